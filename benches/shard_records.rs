@@ -0,0 +1,95 @@
+use std::io::Cursor;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rayon::prelude::*;
+use ungoliant::sources::commoncrawl::Wet;
+use warc::{BufferedBody, Record, WarcWriter};
+
+const NB_RECORDS: usize = 10_000;
+const NB_THREADS: usize = 4;
+
+// bench protocol:
+//
+// Records of an in-memory shard are processed in parallel, either
+// - bridged one by one (par_bridge on the record iterator, as ungoliant used to do), or
+// - by chunks (Wet::into_par_iter).
+//
+// Processing is either cheap (records are dropped right away, as when most of them are filtered out)
+// or heavier (hashing every line of the record several times),
+// so that the cost of pulling records from the shard dominates or not.
+
+/// Serialize a shard of `NB_RECORDS` records, with a few lines each.
+fn gen_shard() -> Vec<u8> {
+    let mut raw = Vec::new();
+    let mut writer = WarcWriter::new(&mut raw);
+    for i in 0..NB_RECORDS {
+        let body: String = (0..10)
+            .map(|line| format!("record {i}, line {line} lorem ipsum dolor sit amet\n"))
+            .collect();
+        let record: Record<BufferedBody> = Record::default().add_body(body);
+        writer.write(&record).unwrap();
+    }
+    raw
+}
+
+/// Cheap or heavy processing of a record body.
+fn process(body: &[u8], rounds: u64) -> u64 {
+    (0..rounds)
+        .map(|round| {
+            body.split(|b| *b == b'\n')
+                .map(|line| {
+                    line.iter()
+                        .fold(round, |h, b| h.wrapping_mul(31).wrapping_add(*b as u64))
+                })
+                .sum::<u64>()
+        })
+        .sum()
+}
+
+fn bridged(raw: &[u8], rounds: u64) -> Vec<(usize, u64)> {
+    Wet::new(Cursor::new(raw))
+        .iter
+        .enumerate()
+        .par_bridge()
+        .map(|(idx, record)| (idx, process(record.unwrap().body(), rounds)))
+        .collect()
+}
+
+fn chunked(raw: &[u8], rounds: u64) -> Vec<(usize, u64)> {
+    Wet::new(Cursor::new(raw))
+        .into_par_iter()
+        .map(|(idx, record)| (idx, process(record.unwrap().body(), rounds)))
+        .collect()
+}
+
+fn bench_records(c: &mut Criterion) {
+    let raw = gen_shard();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(NB_THREADS)
+        .build()
+        .unwrap();
+
+    // both ways process the same records
+    let (mut expected, mut actual) = pool.install(|| (bridged(&raw, 1), chunked(&raw, 1)));
+    expected.sort_unstable();
+    actual.sort_unstable();
+    assert_eq!(expected.len(), NB_RECORDS);
+    assert_eq!(expected, actual);
+
+    let mut group = c.benchmark_group("Shard records");
+    group.sample_size(20);
+    for rounds in [0, 20] {
+        group.bench_with_input(
+            BenchmarkId::new("par_bridge", rounds),
+            &rounds,
+            |b, rounds| b.iter(|| pool.install(|| bridged(black_box(&raw), *rounds))),
+        );
+        group.bench_with_input(BenchmarkId::new("chunked", rounds), &rounds, |b, rounds| {
+            b.iter(|| pool.install(|| chunked(black_box(&raw), *rounds)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_records);
+criterion_main!(benches);
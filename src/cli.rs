@@ -1,26 +1,79 @@
 //! Command line arguments and parameters management/parsing.
 use std::path::PathBuf;
 
+use oxilangtag::LanguageTag;
 use structopt::StructOpt;
 
+use crate::filtering::record::HeaderRule;
+use crate::identifiers::UnknownLabels;
+use crate::io::{Compression, NamingScheme};
+use crate::logging::LogFormat;
+use crate::pipelines::oscardoc::{DocLangStrategy, Utf8Mode};
+use crate::processing::wordcount::TokenizerKind;
+use crate::transformers::{CaseMode, Category, DedupLinesMode, PiiMode, TrimMode};
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "ungoliant", about = "corpus generation tool.")]
+/// Global options, along with the command to run.
+pub struct Ungoliant {
+    #[structopt(
+        long = "log-format",
+        help = "Format of log lines: text or json (one object per line, with level, target, message, shard and lang fields)",
+        default_value = "text",
+        global = true
+    )]
+    pub log_format: LogFormat,
+    #[structopt(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, StructOpt)]
 /// Holds every command that is callable by the `oscar-tools` command.
-pub enum Ungoliant {
+// parsed once at startup, boxing the pipeline options is not worth it.
+#[allow(clippy::large_enum_variant)]
+pub enum Command {
     #[structopt(about = "Download a CommonCrawl release")]
     Download(Download),
     #[structopt(about = "Run pipeline")]
     Pipeline(Pipeline),
-    // #[structopt(about = "Deduplicate a generated, not split corpus.")]
-    // Dedup(Dedup),
-    // #[structopt(about = "Split a not split corpus")]
-    // Split(Split),
+    #[structopt(about = "Deduplicate a generated corpus.")]
+    Dedup(Dedup),
+    #[structopt(about = "Merge corpora generated on different shards into one.")]
+    Merge(Merge),
+    #[structopt(
+        about = "Apply transforms and annotations to a generated corpus, without identifying it again."
+    )]
+    Reprocess(Reprocess),
+    #[structopt(
+        about = "Split a generated corpus into train/validation corpora, or into parts of equal sizes."
+    )]
+    Split(Split),
     // #[structopt(about = "Compress")]
     // Compress(Compress),
     // #[structopt(about = "package")]
     // Package(Package),
     #[structopt(about = "Rebuild the corpus for a given language.")]
     Rebuild(Rebuild),
+    #[structopt(
+        about = "Check a generated corpus for consistency (e.g. after an interrupted run)."
+    )]
+    Validate(Validate),
+    #[structopt(
+        about = "Recompute content counts and byte offsets of a generated corpus in place, e.g. after removing documents by hand."
+    )]
+    Reindex(Reindex),
+    #[structopt(
+        about = "Convert a v1 corpus (text and metadata files) into a corpus of documents."
+    )]
+    Convert(Convert),
+    #[structopt(
+        about = "Report the languages present in a sample of shards, without writing a corpus."
+    )]
+    Scan(Scan),
+    #[structopt(
+        about = "Write the most frequent tokens of each language of a generated corpus, with their counts."
+    )]
+    Wordcount(Wordcount),
     //#[structopt(about = "check for corpus validity. This is under construction and shouldn't be used. ")]
     //Check(Check),
 }
@@ -57,6 +110,148 @@ pub struct Dedup {
         short = "s"
     )]
     pub bufsize: usize,
+    #[structopt(
+        long = "normalize",
+        help = "consider documents that only differ in whitespace as duplicates"
+    )]
+    pub normalize: bool,
+}
+
+#[derive(Debug, StructOpt)]
+/// Reprocess command and parameters.
+pub struct Reprocess {
+    #[structopt(parse(from_os_str), help = "source corpus location")]
+    pub src: PathBuf,
+    #[structopt(parse(from_os_str), help = "destination corpus location")]
+    pub dst: PathBuf,
+    #[structopt(
+        help = "number of records in a bulk write.",
+        long = "chunk_size",
+        default_value = "500",
+        short = "s"
+    )]
+    pub bufsize: usize,
+    #[structopt(
+        long = "min-sentence-length",
+        help = "Remove short sentences at the start/end of documents (in Unicode codepoints)"
+    )]
+    pub min_sentence_length: Option<usize>,
+    #[structopt(
+        long = "keep-document-language-lines",
+        help = "Remove the lines of documents that are not identified in the document language"
+    )]
+    pub keep_document_language_lines: bool,
+    #[structopt(
+        long = "blocklist-path",
+        parse(from_os_str),
+        help = "Path to the UT1 blocklist, to categorize documents again"
+    )]
+    pub blocklist: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+/// Validate command and parameters.
+pub struct Validate {
+    #[structopt(parse(from_os_str), help = "corpus location")]
+    pub src: PathBuf,
+    #[structopt(
+        long = "repair",
+        help = "Remove partially written last documents (e.g. after a crash) before validating"
+    )]
+    pub repair: bool,
+}
+
+#[derive(Debug, StructOpt)]
+/// Reindex command and parameters.
+pub struct Reindex {
+    #[structopt(parse(from_os_str), help = "corpus location")]
+    pub src: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+/// Wordcount command and parameters.
+pub struct Wordcount {
+    #[structopt(parse(from_os_str), help = "corpus location")]
+    pub src: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        help = "destination location (one <lang>.tsv file per language)"
+    )]
+    pub dst: PathBuf,
+    #[structopt(
+        long = "top",
+        help = "number of tokens to keep per language",
+        default_value = "1000"
+    )]
+    pub top: usize,
+    #[structopt(
+        long = "capacity",
+        help = "maximum number of tokens counted at once per language. Counts are approximate for languages with more distinct tokens",
+        default_value = "1000000"
+    )]
+    pub capacity: usize,
+    #[structopt(
+        long = "tokenizer",
+        help = "how documents are split into tokens (whitespace or unicode-words)",
+        default_value = "whitespace"
+    )]
+    pub tokenizer: TokenizerKind,
+}
+
+#[derive(Debug, StructOpt)]
+/// Convert command and parameters.
+pub struct Convert {
+    #[structopt(parse(from_os_str), help = "v1 corpus location")]
+    pub src: PathBuf,
+    #[structopt(parse(from_os_str), help = "destination corpus location")]
+    pub dst: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+/// Scan command and parameters.
+pub struct Scan {
+    #[structopt(parse(from_os_str), help = "source shards directory")]
+    pub src: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        long = "lid-path",
+        help = "Path to the fastText language identification model",
+        default_value = "lid.176.bin"
+    )]
+    pub lid_path: PathBuf,
+    #[structopt(
+        long = "shards",
+        help = "Number of shards to sample",
+        default_value = "10"
+    )]
+    pub nb_shards: usize,
+    #[structopt(
+        long = "seed",
+        help = "Seed used to sample shards",
+        default_value = "0"
+    )]
+    pub seed: u64,
+}
+
+#[derive(Debug, StructOpt)]
+/// Merge command and parameters.
+pub struct Merge {
+    #[structopt(parse(from_os_str), help = "destination corpus location")]
+    pub dst: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        help = "source corpora locations",
+        required = true,
+        min_values = 1
+    )]
+    pub srcs: Vec<PathBuf>,
+    #[structopt(
+        help = "number of records in a bulk write.",
+        long = "chunk_size",
+        default_value = "500",
+        short = "s"
+    )]
+    pub bufsize: usize,
 }
 
 #[derive(Debug, StructOpt)]
@@ -84,14 +279,27 @@ pub struct Package {
     pub move_files: bool,
 }
 #[derive(Debug, StructOpt)]
-/// Dedup command and parameters.
+/// Split command and parameters.
 pub struct Split {
     #[structopt(parse(from_os_str), help = "source corpus location")]
     pub src: PathBuf,
-    #[structopt(parse(from_os_str), help = "destination corpus location")]
+    #[structopt(
+        parse(from_os_str),
+        help = "destination location (one corpus per split)"
+    )]
     pub dst: PathBuf,
-    #[structopt(help = "size of each part (in MBytes)", default_value = "500")]
-    pub part_size: u64,
+    #[structopt(
+        long = "val-ratio",
+        help = "share (between 0 and 1) of documents going to the validation corpus",
+        conflicts_with = "parts",
+        required_unless = "parts"
+    )]
+    pub val_ratio: Option<f32>,
+    #[structopt(
+        long = "parts",
+        help = "split documents round-robin into this number of parts instead"
+    )]
+    pub parts: Option<usize>,
     #[structopt(
         help = "number of records in a bulk write.",
         long = "chunk_size",
@@ -164,6 +372,58 @@ pub struct Pipeline {
         default_value = "lid.176.bin"
     )]
     pub lid_path: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        long = "label-map",
+        help = "Tab-separated file mapping model labels (without __label__) to language tags, for custom models"
+    )]
+    pub label_map: Option<PathBuf>,
+    #[structopt(
+        long = "unknown-labels",
+        help = "What to do with model labels that aren't language tags: error, discard or private (kept as x-<label>)",
+        default_value = "error"
+    )]
+    pub unknown_labels: UnknownLabels,
+    #[structopt(
+        long = "lid-k",
+        help = "Number of predictions kept for each line",
+        default_value = "1"
+    )]
+    pub lid_k: i32,
+    #[structopt(
+        long = "lid-threshold",
+        help = "Minimal prediction confidence for a line to be identified",
+        default_value = "0.8"
+    )]
+    pub lid_threshold: f32,
+    #[structopt(
+        long = "doc-lang-strategy",
+        help = "How the document language is chosen: most-bytes or weighted-prob (bytes weighted by identification probability)",
+        default_value = "most-bytes"
+    )]
+    pub doc_lang_strategy: DocLangStrategy,
+    #[structopt(
+        long = "lang-distribution",
+        help = "Store the share of bytes of each identified language in documents metadata"
+    )]
+    pub lang_distribution: bool,
+    #[structopt(
+        long = "lid-sample-above",
+        help = "Only identify a sample of the lines of documents with more lines than this (the first --lid-sample-head ones and --lid-sample-lines random ones)"
+    )]
+    pub lid_sample_above: Option<usize>,
+    #[structopt(
+        long = "lid-sample-head",
+        help = "Number of first lines identified in sampled documents",
+        default_value = "100"
+    )]
+    pub lid_sample_head: usize,
+    #[structopt(
+        long = "lid-sample-lines",
+        help = "Number of random lines identified in sampled documents, besides the first ones",
+        default_value = "400"
+    )]
+    pub lid_sample_lines: usize,
     #[structopt(
         parse(from_os_str),
         long = "blocklist-path",
@@ -184,4 +444,485 @@ pub struct Pipeline {
         help = "Optional path to kenlm folder. for the language xx, you have to have a xx.binary file."
     )]
     pub kenlms_path: Option<PathBuf>,
+
+    #[structopt(
+        long = "dry-run",
+        help = "Only print the number of documents and their size per language, without writing anything"
+    )]
+    pub dry_run: bool,
+
+    #[structopt(long = "quiet", help = "Don't log the progress of the run")]
+    pub quiet: bool,
+
+    #[structopt(
+        long = "warc",
+        help = "Read raw WARC files rather than WET files, extracting the text of HTML responses"
+    )]
+    pub warc: bool,
+
+    #[structopt(
+        long = "deterministic",
+        help = "Write documents in shard and record order, so that builds over the same shards are identical. Slower"
+    )]
+    pub deterministic: bool,
+
+    #[structopt(
+        long = "quality-scores",
+        help = "Keep documents failing the quality filter, storing its score in their metadata (quality_score)"
+    )]
+    pub quality_scores: bool,
+
+    #[structopt(
+        long = "generated-threshold",
+        help = "Score documents on repeated phrases (likely machine-generated text) in their metadata (generated_score), annotating those scoring at least this (between 0 and 1) as generated"
+    )]
+    pub generated_threshold: Option<f32>,
+
+    #[structopt(
+        long = "line-ids",
+        help = "Also write line identifications in <lang>_lines.jsonl files, aligned with documents by record id and line"
+    )]
+    pub line_ids: bool,
+
+    #[structopt(
+        long = "rejected",
+        help = "Keep records failing the quality filter or with no language in <dst>/rejected, with the rejection reason"
+    )]
+    pub rejected: bool,
+
+    #[structopt(
+        long = "raw-bodies",
+        help = "Keep the original bodies of the records that yield documents in <dst>/raw, byte for byte"
+    )]
+    pub raw_bodies: bool,
+
+    #[structopt(
+        long = "strict",
+        help = "Warn about shards that yield no document, and fail if there are too many of them (see --max-empty-shards)"
+    )]
+    pub strict: bool,
+
+    #[structopt(
+        long = "seen-hashes",
+        parse(from_os_str),
+        help = "Skip documents whose hash is in this file (e.g. the hashes.bin of a previous build), appending the hashes of new ones"
+    )]
+    pub seen_hashes: Option<PathBuf>,
+
+    #[structopt(
+        long = "near-dup-threshold",
+        help = "Skip documents whose estimated Jaccard similarity with a previous document of the same language is at least this (between 0 and 1)"
+    )]
+    pub near_dup_threshold: Option<f32>,
+
+    #[structopt(
+        long = "near-dup-capacity",
+        help = "Maximum number of documents kept over all languages for near-duplicate detection (about 1KiB each)"
+    )]
+    pub near_dup_capacity: Option<usize>,
+
+    #[structopt(
+        long = "max-empty-shards",
+        help = "Maximum share (between 0 and 1) of shards yielding no document in strict mode",
+        default_value = "0.1"
+    )]
+    pub max_empty_shards: f32,
+
+    #[structopt(
+        long = "max-corrupt-ratio",
+        help = "Abort shards whose share (between 0 and 1) of corrupt records exceeds this ratio, e.g. truncated downloads"
+    )]
+    pub max_corrupt_ratio: Option<f32>,
+
+    #[structopt(
+        long = "io-retries",
+        help = "Number of times opening or reading a shard is retried on IO errors (e.g. on network filesystems)",
+        default_value = "0"
+    )]
+    pub io_retries: u32,
+
+    #[structopt(
+        long = "io-backoff",
+        help = "Delay (in milliseconds) before the first IO retry, doubled after each retry",
+        default_value = "500"
+    )]
+    pub io_backoff: u64,
+
+    #[structopt(
+        long = "lang",
+        help = "Only write documents in this language. Can be repeated"
+    )]
+    pub langs: Vec<LanguageTag<String>>,
+
+    #[structopt(
+        long = "lang-budget",
+        help = "Stop collecting a language once its content reaches a size (lang=MBytes, e.g. en=50000). Can be repeated",
+        parse(try_from_str = parse_lang_budget)
+    )]
+    pub lang_budgets: Vec<(LanguageTag<String>, u64)>,
+
+    #[structopt(
+        long = "lang-min-bytes",
+        help = "Discard identified documents smaller than a size for their language (lang=bytes, e.g. en=500). Can be repeated",
+        parse(try_from_str = parse_lang_min_bytes)
+    )]
+    pub lang_min_bytes: Vec<(LanguageTag<String>, usize)>,
+
+    #[structopt(
+        long = "default-min-bytes",
+        help = "Minimum size of identified documents in languages that have no --lang-min-bytes",
+        default_value = "0"
+    )]
+    pub default_min_bytes: usize,
+
+    #[structopt(
+        long = "secondary-lang-share",
+        help = "Attribute documents of a non-allowed language to the main allowed one, if it makes for at least this share (0-1) of the document"
+    )]
+    pub secondary_lang_share: Option<f32>,
+
+    #[structopt(
+        long = "multi-threshold",
+        help = "Label documents as multilingual (multi) if their language makes for less than this share (0-1) of their bytes"
+    )]
+    pub multi_threshold: Option<f32>,
+
+    #[structopt(
+        long = "doc-threshold",
+        help = "Minimum confidence (0-1) of documents, below which they are discarded (or labelled as und with --undetermined)",
+        default_value = "0.6"
+    )]
+    pub doc_threshold: f32,
+
+    #[structopt(
+        long = "undetermined",
+        help = "Label documents below the document threshold as undetermined (und) rather than discarding them, for later reprocessing"
+    )]
+    pub undetermined: bool,
+
+    #[structopt(
+        long = "min-identified-bytes",
+        help = "Discard documents with less than this many bytes in identified lines, whatever their confidence",
+        default_value = "0"
+    )]
+    pub min_identified_bytes: usize,
+
+    #[structopt(
+        long = "record-type",
+        help = "Only process records of this WARC-Type (default: conversion, the type of WET records). Can be repeated"
+    )]
+    pub record_types: Vec<String>,
+
+    #[structopt(
+        long = "header-allow",
+        help = "Only keep records whose WARC header matches (header=regex, e.g. content-type=^text/plain). Can be repeated"
+    )]
+    pub header_allow: Vec<HeaderRule>,
+
+    #[structopt(
+        long = "header-deny",
+        help = "Discard records whose WARC header matches (header=regex, e.g. warc-target-uri=^https?://spam\\.com/). Can be repeated"
+    )]
+    pub header_deny: Vec<HeaderRule>,
+
+    #[structopt(
+        long = "exclude-body-regex",
+        help = "Discard records whose body matches this regex (e.g. boilerplate), before identification. Can be repeated"
+    )]
+    pub exclude_body_regex: Vec<String>,
+
+    #[structopt(
+        long = "exclude-body-regex-case-insensitive",
+        help = "Match --exclude-body-regex patterns case-insensitively"
+    )]
+    pub exclude_body_regex_case_insensitive: bool,
+
+    #[structopt(
+        long = "min-doc-chars",
+        help = "Discard records shorter than this number of characters, before identification",
+        default_value = "0"
+    )]
+    pub min_doc_chars: usize,
+
+    #[structopt(
+        long = "min-doc-bytes",
+        help = "Discard records shorter than this number of bytes, before identification",
+        default_value = "0"
+    )]
+    pub min_doc_bytes: usize,
+
+    #[structopt(
+        long = "min-sentence-length",
+        help = "Minimum length (in codepoints) of sentences kept at the start/end of documents",
+        default_value = "100"
+    )]
+    pub min_sentence_length: usize,
+
+    #[structopt(
+        long = "trim-mode",
+        help = "Which short sentences are removed: edges (at the start/end of documents), all or none",
+        default_value = "edges"
+    )]
+    pub trim_mode: TrimMode,
+
+    #[structopt(
+        long = "keep-document-language-lines",
+        help = "Remove the lines of documents that are not identified in the document language"
+    )]
+    pub keep_document_language_lines: bool,
+
+    #[structopt(
+        long = "segment-sentences",
+        help = "Split lines into sentences before identification (documents get one sentence per line)"
+    )]
+    pub segment_sentences: bool,
+
+    #[structopt(
+        long = "max-sentence-chars",
+        help = "When segmenting, cut sentences longer than this (in codepoints), for scripts that don't mark sentence ends",
+        default_value = "500"
+    )]
+    pub max_sentence_chars: usize,
+
+    #[structopt(
+        long = "no-content-detector",
+        help = "Do not tag documents using the blocklist, even if one is provided"
+    )]
+    pub no_content_detector: bool,
+
+    #[structopt(
+        long = "blocklist-category",
+        help = "Only tag documents with this blocklist category (e.g. adult, phishing, malware), also matching its keywords. Can be repeated"
+    )]
+    pub blocklist_categories: Vec<Category>,
+
+    #[structopt(
+        long = "compression",
+        help = "Language files compression (none, gzip or zstd)",
+        default_value = "none"
+    )]
+    pub compression: Compression,
+
+    #[structopt(
+        long = "naming",
+        help = "Language files layout: flat (<lang>_meta.jsonl) or subdir (<lang>/text.jsonl)",
+        default_value = "flat"
+    )]
+    pub naming: NamingScheme,
+
+    #[structopt(
+        long = "part-size",
+        help = "Rotate language files when they would exceed this size (in MBytes)"
+    )]
+    pub part_size: Option<u64>,
+
+    #[structopt(
+        long = "threads",
+        help = "Number of threads. 0 uses a thread per core",
+        default_value = "0"
+    )]
+    pub threads: usize,
+
+    #[structopt(
+        long = "workers",
+        help = "Number of shards processed concurrently. Defaults to the number of threads"
+    )]
+    pub workers: Option<usize>,
+
+    #[structopt(
+        long = "max-pending-shards",
+        help = "Maximum number of processed shards waiting to be written. Defaults to the number of workers"
+    )]
+    pub max_pending_shards: Option<usize>,
+
+    #[structopt(
+        long = "write-queue",
+        help = "Maximum number of documents waiting to be written by each writer thread",
+        default_value = "1024"
+    )]
+    pub write_queue: usize,
+
+    #[structopt(
+        long = "merge-same-uri",
+        help = "Merge adjacent records of a shard that have the same target URI before processing them"
+    )]
+    pub merge_same_uri: bool,
+
+    #[structopt(
+        long = "content-hash",
+        help = "Store a hash of document contents in their metadata (stored as content_hash)"
+    )]
+    pub content_hash: bool,
+
+    #[structopt(
+        long = "normalize-content-hash",
+        help = "Hash document contents with whitespace normalized (stored as normalized_content_hash). Implies --content-hash"
+    )]
+    pub normalize_content_hash: bool,
+
+    #[structopt(
+        long = "doc-lid-only",
+        help = "Identify documents with a single prediction on their whole content, skipping line identification"
+    )]
+    pub doc_lid_only: bool,
+
+    #[structopt(
+        long = "nfc",
+        help = "Normalize document contents to Unicode NFC (after language identification)"
+    )]
+    pub nfc: bool,
+
+    #[structopt(
+        long = "case",
+        help = "Lowercase (lower) or case-fold (fold, e.g. ß becomes ss) document contents (after language identification)"
+    )]
+    pub case: Option<CaseMode>,
+
+    #[structopt(
+        long = "pii",
+        help = "Scrub email addresses and phone numbers from document contents: mask, hash or remove them"
+    )]
+    pub pii: Option<PiiMode>,
+
+    #[structopt(
+        long = "dedup-lines",
+        help = "Remove repeated lines of documents, keeping their first occurrence: consecutive (lines repeating the previous one) or all"
+    )]
+    pub dedup_lines: Option<DedupLinesMode>,
+
+    #[structopt(
+        long = "utf8",
+        help = "How records that are not valid UTF-8 are handled: lossy (invalid sequences are replaced), count (same, storing the number of replacement characters in metadata) or strict (records are dropped)",
+        default_value = "lossy"
+    )]
+    pub utf8_mode: Utf8Mode,
+
+    #[structopt(
+        long = "snapshot-id",
+        help = "Snapshot id (e.g. CC-MAIN-2023-06) stamped in the metadata of every document, to keep track of it after merging corpora"
+    )]
+    pub snapshot_id: Option<String>,
+
+    #[structopt(
+        long = "languages-csv",
+        help = "Also save the sizes of the written languages in <dst>/languages.csv"
+    )]
+    pub languages_csv: bool,
+
+    #[structopt(
+        long = "content-counts",
+        help = "Store the size in bytes and the number of lines of document contents in their metadata"
+    )]
+    pub content_counts: bool,
+
+    #[structopt(
+        long = "crawl-info",
+        help = "Store the crawl date and target URI of documents in their metadata"
+    )]
+    pub crawl_info: bool,
+
+    #[structopt(
+        long = "lang-hint",
+        help = "Store the language identified by the crawler, and whether it agrees with the document one, in document metadata"
+    )]
+    pub lang_hint: bool,
+
+    #[structopt(
+        long = "byte-offsets",
+        help = "Store the byte offset of each document in its (uncompressed) language file in its metadata"
+    )]
+    pub byte_offsets: bool,
+
+    #[structopt(
+        long = "atomic-writes",
+        help = "Write language files to .tmp files, renamed into place once complete, so that readers never see partial files"
+    )]
+    pub atomic_writes: bool,
+
+    #[structopt(
+        long = "limit",
+        help = "Stop once this number of documents has been written, over all languages. Handy for quick tests"
+    )]
+    pub limit: Option<usize>,
+
+    #[structopt(
+        long = "max-duration",
+        help = "Stop processing new shards after this number of seconds. Shards being processed are finished and checkpointed, so that the run can be resumed"
+    )]
+    pub max_duration: Option<u64>,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "shard-list",
+        help = "Only process the shards listed in this file (one path per line, relative to the file) rather than every shard of the source directory"
+    )]
+    pub shard_list: Option<PathBuf>,
+
+    #[structopt(
+        parse(from_os_str),
+        long = "shard-urls",
+        help = "Download the shards listed in this file (one URL or CommonCrawl path per line, e.g. a wet.paths file) as they get processed, deleting them afterwards. The source directory is ignored"
+    )]
+    pub shard_urls: Option<PathBuf>,
+
+    #[structopt(
+        long = "download-tasks",
+        help = "Number of concurrent shard downloads (see --shard-urls)",
+        default_value = "4"
+    )]
+    pub download_tasks: usize,
+
+    #[structopt(
+        long = "min-lang-documents",
+        help = "Once the run is done, move the files of languages with fewer documents than this to <dst>/discarded"
+    )]
+    pub min_lang_documents: Option<usize>,
+
+    #[structopt(
+        long = "resume",
+        alias = "append",
+        help = "Resume an interrupted run or append to an existing corpus, skipping shards recorded in <dst>/checkpoint.json"
+    )]
+    pub resume: bool,
+
+    #[structopt(
+        long = "incremental",
+        help = "Only process shards modified after the start of the last run, recorded in <dst>/.last_run. Requires --resume, new documents being appended to the existing corpus"
+    )]
+    pub incremental: bool,
+
+    #[structopt(
+        long = "incremental-grace",
+        help = "Also process shards modified up to this number of seconds before the last run, e.g. when the storage clock is behind",
+        default_value = "0"
+    )]
+    pub incremental_grace: u64,
+}
+
+/// Parse a `lang=MBytes` language budget.
+fn parse_lang_budget(s: &str) -> Result<(LanguageTag<String>, u64), String> {
+    let (lang, mb) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid language budget {s:?} (expected lang=MBytes)"))?;
+    let lang = LanguageTag::parse(lang.trim().to_string())
+        .map_err(|e| format!("invalid language in budget {s:?}: {e}"))?;
+    let mb = mb
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid size in budget {s:?}: {e}"))?;
+    Ok((lang, mb))
+}
+
+/// Parse a `lang=bytes` minimum document size.
+fn parse_lang_min_bytes(s: &str) -> Result<(LanguageTag<String>, usize), String> {
+    let (lang, bytes) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid minimum size {s:?} (expected lang=bytes)"))?;
+    let lang = LanguageTag::parse(lang.trim().to_string())
+        .map_err(|e| format!("invalid language in minimum size {s:?}: {e}"))?;
+    let bytes = bytes
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid size in minimum size {s:?}: {e}"))?;
+    Ok((lang, bytes))
 }
@@ -1,7 +1,7 @@
 //! Error enum
 use std::string::FromUtf8Error;
 
-use oxilangtag::LanguageTagParseError;
+use oxilangtag::{LanguageTag, LanguageTagParseError};
 
 use crate::pipelines::oscardoc::types::IncompleteLocation;
 
@@ -24,6 +24,49 @@ pub enum Error {
     Avro(avro_rs::Error),
     Csv(csv::Error),
     OscarIo(oscar_io::Error),
+    Regex(regex::Error),
+    /// Error writing the documents of a language (e.g. disk full).
+    Write(LanguageTag<String>, Box<Error>),
+}
+
+impl Error {
+    /// Short name of the error kind, used to count errors (see [crate::pipelines::oscardoc::types::ShardStats]).
+    ///
+    /// WARC errors are detailed, since they tell corrupt records apart.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Io(_) => "io",
+            Error::Warc(e) => match e {
+                warc::Error::ParseHeaders(_) => "warc_parse_headers",
+                warc::Error::MissingHeader(_) => "warc_missing_header",
+                warc::Error::MalformedHeader(_, _) => "warc_malformed_header",
+                warc::Error::ReadData(_) => "warc_read_data",
+                warc::Error::ReadOverflow => "warc_read_overflow",
+                warc::Error::UnexpectedEOB => "warc_unexpected_eob",
+            },
+            Error::UnknownLang(_) => "unknown_lang",
+            Error::MetadataConversion(_) => "utf8",
+            Error::Custom(_) => "custom",
+            Error::Serde(_) => "serde",
+            Error::Glob(_) | Error::GlobPattern(_) => "glob",
+            Error::Ut1(_) => "ut1",
+            Error::FastText(_) => "fasttext",
+            Error::Languagetag(_) => "language_tag",
+            Error::IncompleteLocation(_) => "incomplete_location",
+            Error::Avro(_) => "avro",
+            Error::Csv(_) => "csv",
+            Error::OscarIo(_) => "oscar_io",
+            Error::Regex(_) => "regex",
+            Error::Write(_, _) => "write",
+        }
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+impl From<regex::Error> for Error {
+    fn from(v: regex::Error) -> Self {
+        Self::Regex(v)
+    }
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -1,7 +1,10 @@
 //! Filtering traits.
 
 /// immutable, pure filter (2 successive equal inputs -> 2 equal outputs)
-pub trait Filter<T>: Default {
+///
+/// Filters can be used as trait objects (see [super::record::CustomFilter]),
+/// so unlike [FilterMut] this doesn't require [Default].
+pub trait Filter<T> {
     fn detect(&self, item: T) -> bool;
 }
 
@@ -0,0 +1,87 @@
+//! Language-aware filtering.
+//!
+//! Those filters take an identified [Document] as a parameter,
+//! and run after language identification, unlike [super::record] ones.
+use std::collections::HashMap;
+
+use oxilangtag::LanguageTag;
+
+use super::Filter;
+use crate::pipelines::oscardoc::types::Document;
+
+/// Filters out documents whose content is shorter than the minimum size (in bytes) of their language,
+/// falling back to a default one for languages that don't have their own.
+///
+/// High-resource languages usually warrant bigger thresholds than low-resource ones,
+/// whose short documents are too rare to be discarded.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PerLangMinBytes {
+    thresholds: HashMap<LanguageTag<String>, usize>,
+    default: usize,
+}
+
+impl PerLangMinBytes {
+    pub fn new(thresholds: HashMap<LanguageTag<String>, usize>, default: usize) -> Self {
+        Self {
+            thresholds,
+            default,
+        }
+    }
+
+    /// Minimum size of documents in `lang`.
+    pub fn threshold(&self, lang: &LanguageTag<String>) -> usize {
+        self.thresholds.get(lang).copied().unwrap_or(self.default)
+    }
+}
+
+impl Filter<&Document> for PerLangMinBytes {
+    fn detect(&self, doc: &Document) -> bool {
+        doc.content().len() >= self.threshold(doc.identification().label())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oscar_io::common::Identification;
+    use oxilangtag::LanguageTag;
+
+    use crate::filtering::Filter;
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+
+    use super::PerLangMinBytes;
+
+    fn gen_doc(content: &str, lang: &str) -> Document {
+        let id = Identification::new(LanguageTag::parse(lang.to_string()).unwrap(), 1.0);
+        let ids = vec![Some(id.clone()); content.lines().count()];
+        Document::new(
+            content.to_string(),
+            HashMap::new(),
+            Metadata::new(&id, &ids),
+        )
+    }
+
+    #[test]
+    fn test_per_lang_min_bytes() {
+        let thresholds = [("en", 100), ("br", 10)]
+            .into_iter()
+            .map(|(lang, min)| (LanguageTag::parse(lang.to_string()).unwrap(), min))
+            .collect();
+        let filter = PerLangMinBytes::new(thresholds, 50);
+        let short = "a".repeat(30);
+        let long = "a".repeat(100);
+
+        assert!(!filter.detect(&gen_doc(&short, "en")));
+        assert!(filter.detect(&gen_doc(&long, "en")));
+        assert!(filter.detect(&gen_doc(&short, "br")));
+        // default threshold
+        assert!(!filter.detect(&gen_doc(&short, "fr")));
+        assert!(filter.detect(&gen_doc(&long, "fr")));
+    }
+
+    #[test]
+    fn test_default() {
+        assert!(PerLangMinBytes::default().detect(&gen_doc("", "en")));
+    }
+}
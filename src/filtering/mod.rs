@@ -1,6 +1,6 @@
 /*! Filtering utilities
 
-Filters can operate on sentence or record level.
+Filters can operate on sentence or record level, or on documents once their language is identified.
 
 Filters implement [filter::Filter], [filter::FilterMut] or both:
 - [filter::Filter] is implemented for filters that do not have state (see [sentence::Length] for example)
@@ -10,6 +10,7 @@ Both can be implemented for a given filter,
 in order to provide a mutable detection that could be used to "train" the filter, then an immutable one to effectively filter content.
 !*/
 mod filter;
+pub mod lang;
 pub mod record;
 pub mod sentence;
 
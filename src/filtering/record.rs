@@ -1,15 +1,110 @@
 //! Document-level filtering.
 //!
 //! Those filters take a WARC [warc::Record] as a parameter.
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
+use std::str::FromStr;
 
-use warc::{BufferedBody, Record};
+use regex::{Regex, RegexSet, RegexSetBuilder};
+use unicode_script::{Script, UnicodeScript};
+use warc::{BufferedBody, Record, WarcHeader};
 
 use super::sentence::Length;
 use super::Filter;
+use crate::error::Error;
 use std::cmp::Ordering;
+
+/// User-provided record filter.
+///
+/// Filters run concurrently on shards, so they have to be [Send] and [Sync].
+pub type CustomFilter = Box<dyn for<'a> Filter<&'a Record<BufferedBody>> + Send + Sync>;
+
 pub enum FilterKind {
     PFilter(PFilter),
+    MinLength(MinLength),
+    MeanLineLength(MeanLineLength),
+    LetterRatio(LetterRatio),
+    Script(ScriptFilter),
+    BodyRegexExclude(BodyRegexExclude),
+    /// Keeps records that pass every filter, stopping at the first one that fails.
+    /// Empty combinators keep everything.
+    All(Vec<FilterKind>),
+    /// Keeps records that pass any filter, stopping at the first one that passes.
+    /// Empty combinators keep everything.
+    Any(Vec<FilterKind>),
+    /// Filter that ungoliant doesn't ship (see [CustomFilter]).
+    Custom(CustomFilter),
+}
+
+impl FilterKind {
+    /// Wrap a filter that ungoliant doesn't ship.
+    pub fn custom<F>(filter: F) -> Self
+    where
+        F: for<'a> Filter<&'a Record<BufferedBody>> + Send + Sync + 'static,
+    {
+        Self::Custom(Box::new(filter))
+    }
+
+    /// Filter name, used in reports.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PFilter(_) => "pfilter",
+            Self::MinLength(_) => "min_length",
+            Self::MeanLineLength(_) => "mean_line_length",
+            Self::LetterRatio(_) => "letter_ratio",
+            Self::Script(_) => "script",
+            Self::BodyRegexExclude(_) => "body_regex",
+            Self::All(_) => "all",
+            Self::Any(_) => "any",
+            Self::Custom(_) => "custom",
+        }
+    }
+
+    /// Get the filter that drops the record, if it is dropped.
+    ///
+    /// This is the first failing filter of [FilterKind::All] combinators,
+    /// and the combinator itself for [FilterKind::Any] ones, since all of their filters fail.
+    pub fn rejecting(&self, reader: &Record<BufferedBody>) -> Option<&FilterKind> {
+        match self {
+            Self::All(filters) => filters.iter().find_map(|f| f.rejecting(reader)),
+            _ if self.detect(reader) => None,
+            _ => Some(self),
+        }
+    }
+
+    /// Reason given to the records dropped by the filter, when they are kept aside.
+    ///
+    /// [PFilter] drops records with too many short lines, hence `filtered_short`.
+    /// Other filters give `filtered_<name>` (see [FilterKind::name]).
+    pub fn rejection_reason(&self) -> String {
+        match self {
+            Self::PFilter(_) => "filtered_short".to_string(),
+            other => format!("filtered_{}", other.name()),
+        }
+    }
+
+    /// Quality score of the record, if the filter computes one (see [PFilter::score]).
+    ///
+    /// Combinators give the lowest ([FilterKind::All]) or highest ([FilterKind::Any]) score of their filters.
+    pub fn score(&self, reader: &Record<BufferedBody>) -> Option<f32> {
+        match self {
+            Self::PFilter(p) => Some(p.score(reader)),
+            Self::MinLength(_)
+            | Self::MeanLineLength(_)
+            | Self::LetterRatio(_)
+            | Self::Script(_)
+            | Self::BodyRegexExclude(_)
+            | Self::Custom(_) => None,
+            Self::All(filters) => filters
+                .iter()
+                .filter_map(|f| f.score(reader))
+                .reduce(f32::min),
+            Self::Any(filters) => filters
+                .iter()
+                .filter_map(|f| f.score(reader))
+                .reduce(f32::max),
+        }
+    }
 }
 
 impl Default for FilterKind {
@@ -22,6 +117,14 @@ impl Filter<&Record<BufferedBody>> for FilterKind {
     fn detect(&self, reader: &Record<BufferedBody>) -> bool {
         match self {
             Self::PFilter(p) => p.detect(reader),
+            Self::MinLength(m) => m.detect(reader),
+            Self::MeanLineLength(m) => m.detect(reader),
+            Self::LetterRatio(l) => l.detect(reader),
+            Self::Script(s) => s.detect(reader),
+            Self::BodyRegexExclude(b) => b.detect(reader),
+            Self::All(filters) => filters.iter().all(|f| f.detect(reader)),
+            Self::Any(filters) => filters.is_empty() || filters.iter().any(|f| f.detect(reader)),
+            Self::Custom(c) => c.detect(reader),
         }
     }
 }
@@ -44,10 +147,19 @@ impl PFilter {
             sentence_filter,
         }
     }
+
+    /// Create a new PFilter with default threshold and a custom long sentence length.
+    pub fn with_min_length(min_length: usize) -> Self {
+        PFilter {
+            sentence_filter: Length::with_min_size(min_length),
+            ..Default::default()
+        }
+    }
 }
 
-impl Filter<&Record<BufferedBody>> for PFilter {
-    fn detect(&self, reader: &Record<BufferedBody>) -> bool {
+impl PFilter {
+    /// Get the size (in codepoints) of short and long lines of the record body, in that order.
+    fn buckets(&self, reader: &Record<BufferedBody>) -> (u32, u32) {
         // get newline-separated lines
         let body = String::from_utf8_lossy(reader.body());
         let lines = body.lines();
@@ -69,6 +181,28 @@ impl Filter<&Record<BufferedBody>> for PFilter {
             }
         }
 
+        (bucket_lower, bucket_upper)
+    }
+
+    /// Share of the content that is in long lines, between `0` and `1`.
+    ///
+    /// Documents are kept by the filter when their score is at least the sentence threshold.
+    /// Empty documents have a score of `0`.
+    pub fn score(&self, reader: &Record<BufferedBody>) -> f32 {
+        let (bucket_lower, bucket_upper) = self.buckets(reader);
+        let total = bucket_lower + bucket_upper;
+        if total == 0 {
+            return 0.0;
+        }
+
+        (f64::from(bucket_upper) / f64::from(total)) as f32
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for PFilter {
+    fn detect(&self, reader: &Record<BufferedBody>) -> bool {
+        let (bucket_lower, bucket_upper) = self.buckets(reader);
+
         // get threshold in bytes
         let threshold = self.sentence_threshold * f64::from(bucket_lower + bucket_upper);
 
@@ -96,13 +230,433 @@ impl Default for PFilter {
     }
 }
 
+/// Filters out documents whose body is shorter than `chars` characters or `bytes` bytes.
+///
+/// Characters are Unicode scalar values.
+/// Invalid UTF-8 sequences are not counted as characters (but their bytes are counted).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MinLength {
+    chars: usize,
+    bytes: usize,
+}
+
+impl MinLength {
+    pub fn new(chars: usize, bytes: usize) -> Self {
+        Self { chars, bytes }
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for MinLength {
+    fn detect(&self, reader: &Record<BufferedBody>) -> bool {
+        let body = reader.body();
+        if body.len() < self.bytes {
+            return false;
+        }
+
+        // no need to decode if there can't be enough characters
+        if body.len() < self.chars {
+            return false;
+        }
+
+        let nb_chars = String::from_utf8_lossy(body)
+            .chars()
+            .filter(|c| *c != char::REPLACEMENT_CHARACTER)
+            .take(self.chars)
+            .count();
+        nb_chars >= self.chars
+    }
+}
+
+/// Filters out documents whose lines are too short on average, such as navigation or list pages
+/// that have many short lines but still enough content in long ones to pass [PFilter].
+///
+/// Lengths are in Unicode codepoints. Blank lines are not counted, and bodies with no line at all are filtered out.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MeanLineLength {
+    min: f32,
+}
+
+impl MeanLineLength {
+    pub fn new(min: f32) -> Self {
+        Self { min }
+    }
+
+    /// Mean length of the non-blank lines of `body`, or [None] if there's none.
+    fn mean(body: &str) -> Option<f32> {
+        let (nb_lines, nb_chars) = body
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .fold((0usize, 0usize), |(nb_lines, nb_chars), line| {
+                (nb_lines + 1, nb_chars + line.chars().count())
+            });
+        (nb_lines > 0).then(|| nb_chars as f32 / nb_lines as f32)
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for MeanLineLength {
+    fn detect(&self, reader: &Record<BufferedBody>) -> bool {
+        let body = String::from_utf8_lossy(reader.body());
+        Self::mean(&body).is_some_and(|mean| mean >= self.min)
+    }
+}
+
+/// Filters out documents that are mostly made of non-letter characters (numbers, punctuation, symbols, code...),
+/// which language identification labels spuriously.
+///
+/// The ratio is the number of alphabetic characters (ideographs included) over the number of non-whitespace characters.
+/// Bodies with no such character are filtered out.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LetterRatio {
+    min: f32,
+}
+
+impl LetterRatio {
+    pub fn new(min: f32) -> Self {
+        Self { min }
+    }
+
+    /// Share of letters in the non-whitespace characters of `body`, or [None] if there's none.
+    fn ratio(body: &str) -> Option<f32> {
+        let (nb_letters, nb_chars) = body
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .fold((0usize, 0usize), |(nb_letters, nb_chars), c| {
+                (nb_letters + usize::from(c.is_alphabetic()), nb_chars + 1)
+            });
+        (nb_chars > 0).then(|| nb_letters as f32 / nb_chars as f32)
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for LetterRatio {
+    fn detect(&self, reader: &Record<BufferedBody>) -> bool {
+        let body = String::from_utf8_lossy(reader.body());
+        Self::ratio(&body).is_some_and(|ratio| ratio >= self.min)
+    }
+}
+
+/// Keeps documents whose dominant script is one of the allowed ones (e.g. to build a Latin-only subset).
+///
+/// The dominant script is the one of the most characters. Characters shared by scripts
+/// (punctuation, digits, combining marks...) are not counted, and bodies with no other character are filtered out.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ScriptFilter {
+    scripts: HashSet<Script>,
+}
+
+impl ScriptFilter {
+    pub fn new(scripts: HashSet<Script>) -> Self {
+        Self { scripts }
+    }
+
+    /// Get the dominant script of `body`, if any. Ties are broken by script name.
+    fn dominant(body: &str) -> Option<Script> {
+        let mut counts: HashMap<Script, usize> = HashMap::new();
+        for script in body.chars().map(|c| c.script()) {
+            if !matches!(script, Script::Common | Script::Inherited | Script::Unknown) {
+                *counts.entry(script).or_default() += 1;
+            }
+        }
+        counts
+            .into_iter()
+            .max_by(|(a, count_a), (b, count_b)| {
+                count_a
+                    .cmp(count_b)
+                    .then_with(|| b.full_name().cmp(a.full_name()))
+            })
+            .map(|(script, _)| script)
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for ScriptFilter {
+    fn detect(&self, reader: &Record<BufferedBody>) -> bool {
+        let body = String::from_utf8_lossy(reader.body());
+        Self::dominant(&body).is_some_and(|script| self.scripts.contains(&script))
+    }
+}
+
+/// Filters out documents whose body matches any of the patterns (e.g. boilerplate, lorem ipsum).
+///
+/// Patterns are compiled once, and are matched against the body decoded as UTF-8
+/// (invalid sequences being replaced).
+#[derive(Debug, Clone)]
+pub struct BodyRegexExclude {
+    patterns: RegexSet,
+}
+
+impl BodyRegexExclude {
+    /// Compile `patterns`, optionally matching case-insensitively. Errors if a pattern is not a valid regex.
+    pub fn new<S: AsRef<str>>(patterns: &[S], case_insensitive: bool) -> Result<Self, Error> {
+        let patterns = RegexSetBuilder::new(patterns)
+            .case_insensitive(case_insensitive)
+            .build()?;
+        Ok(Self { patterns })
+    }
+}
+
+impl Default for BodyRegexExclude {
+    /// No pattern: nothing is filtered out.
+    fn default() -> Self {
+        Self {
+            patterns: RegexSet::empty(),
+        }
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for BodyRegexExclude {
+    fn detect(&self, reader: &Record<BufferedBody>) -> bool {
+        !self
+            .patterns
+            .is_match(&String::from_utf8_lossy(reader.body()))
+    }
+}
+
+/// A WARC header and a pattern its value is matched against.
+///
+/// Can be parsed from `header=regex` strings (e.g. `content-type=^text/plain`).
+#[derive(Debug, Clone)]
+pub struct HeaderRule {
+    header: WarcHeader,
+    pattern: Regex,
+}
+
+impl HeaderRule {
+    /// Create a new rule. Errors if `pattern` is not a valid regex.
+    pub fn new(header: WarcHeader, pattern: &str) -> Result<Self, Error> {
+        Ok(Self {
+            header,
+            pattern: Regex::new(pattern)?,
+        })
+    }
+
+    /// Does the record have the header, with a value matching the pattern?
+    fn matches(&self, record: &Record<BufferedBody>) -> bool {
+        record
+            .header(self.header.clone())
+            .is_some_and(|value| self.pattern.is_match(&value))
+    }
+}
+
+impl FromStr for HeaderRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (header, pattern) = s
+            .split_once('=')
+            .ok_or_else(|| format!("invalid header rule {s:?} (expected header=regex)"))?;
+        Self::new(WarcHeader::from(header.trim()), pattern)
+            .map_err(|e| format!("invalid header rule {s:?}: {e:?}"))
+    }
+}
+
+/// Filters out records depending on their WARC headers.
+///
+/// A record is kept if it matches every allow rule and no deny rule.
+/// A record that lacks the header of an allow rule is filtered out.
+///
+/// This filter is cheap and is meant to be run before identification.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderFilter {
+    allow: Vec<HeaderRule>,
+    deny: Vec<HeaderRule>,
+}
+
+impl HeaderFilter {
+    /// Add an allow rule.
+    pub fn with_allow(mut self, rule: HeaderRule) -> Self {
+        self.allow.push(rule);
+        self
+    }
+
+    /// Add a deny rule.
+    pub fn with_deny(mut self, rule: HeaderRule) -> Self {
+        self.deny.push(rule);
+        self
+    }
+
+    /// Are there no rules?
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+}
+
+impl Filter<&Record<BufferedBody>> for HeaderFilter {
+    fn detect(&self, reader: &Record<BufferedBody>) -> bool {
+        self.allow.iter().all(|rule| rule.matches(reader))
+            && !self.deny.iter().any(|rule| rule.matches(reader))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use warc::Record;
+    use unicode_script::Script;
+    use warc::{BufferedBody, Record, WarcHeader};
 
     use crate::filtering::Filter;
 
-    use super::PFilter;
+    use super::{
+        BodyRegexExclude, FilterKind, HeaderFilter, HeaderRule, LetterRatio, MeanLineLength,
+        MinLength, PFilter, ScriptFilter,
+    };
+
+    fn gen_record(content_type: &str, uri: &str) -> Record<BufferedBody> {
+        let mut r = Record::default();
+        r.set_header(WarcHeader::ContentType, content_type).unwrap();
+        r.set_header(WarcHeader::TargetURI, uri).unwrap();
+        r.add_body("foo")
+    }
+
+    #[test]
+    fn test_min_length_bytes() {
+        let f = MinLength::new(0, 4);
+        assert!(!f.detect(&Record::default().add_body("foo")));
+        assert!(f.detect(&Record::default().add_body("fooo")));
+    }
+
+    #[test]
+    fn test_min_length_chars() {
+        // 3 characters, 6 bytes
+        let body = "éàè";
+        let f = MinLength::new(3, 0);
+        assert!(f.detect(&Record::default().add_body(body)));
+        let f = MinLength::new(4, 0);
+        assert!(!f.detect(&Record::default().add_body(body)));
+        let f = MinLength::new(3, 7);
+        assert!(!f.detect(&Record::default().add_body(body)));
+        let f = MinLength::new(3, 6);
+        assert!(f.detect(&Record::default().add_body(body)));
+    }
+
+    #[test]
+    fn test_min_length_invalid_utf8() {
+        // "foo" followed by an invalid byte
+        let body: Vec<u8> = vec![b'f', b'o', b'o', 0xff];
+        let r = Record::default().add_body(body);
+        assert!(MinLength::new(3, 4).detect(&r));
+        assert!(!MinLength::new(4, 0).detect(&r));
+    }
+
+    #[test]
+    fn test_mean_line_length() {
+        let f = FilterKind::MeanLineLength(MeanLineLength::new(20.0));
+
+        let prose = "Ceci est un premier paragraphe, assez long pour passer.\n\n\
+                     Et en voici un second, tout aussi long que le premier.";
+        assert!(f.detect(&Record::default().add_body(prose)));
+
+        // blank lines don't lower the mean
+        let spaced = prose.replace("\n\n", "\n\n   \n\n\n");
+        assert!(f.detect(&Record::default().add_body(spaced)));
+
+        let menu = "Accueil\nActualités\nSport\nCulture\nContact\n\
+                    Ceci est un premier paragraphe, assez long pour passer.";
+        assert!(!f.detect(&Record::default().add_body(menu)));
+
+        assert!(!f.detect(&Record::default().add_body("\n  \n")));
+        assert_eq!(MeanLineLength::mean("ab\n\néèêë"), Some(3.0));
+    }
+
+    #[test]
+    fn test_letter_ratio() {
+        let f = FilterKind::LetterRatio(LetterRatio::new(0.6));
+
+        let prose = "Ceci est un document en prose, avec quelques chiffres (1, 2, 3) et de la ponctuation !";
+        assert!(f.detect(&Record::default().add_body(prose)));
+
+        let numbers = "12.5 | 13.0 | 14.2\n$ 1,000.00 -- 2,000.00\n{ [x] = 42; } // 0x1f";
+        assert!(!f.detect(&Record::default().add_body(numbers)));
+
+        // ideographs are letters
+        assert!(f.detect(&Record::default().add_body("这是一个中文文档。")));
+
+        assert!(!f.detect(&Record::default().add_body(" \n ")));
+        assert_eq!(LetterRatio::ratio("ab 12"), Some(0.5));
+    }
+
+    #[test]
+    fn test_script() {
+        let f = FilterKind::Script(ScriptFilter::new([Script::Latin].into_iter().collect()));
+
+        assert!(f.detect(&Record::default().add_body("Ceci est un document en français.")));
+        assert!(!f.detect(&Record::default().add_body("Это документ на русском языке.")));
+
+        // mixed documents are classified by majority
+        let mostly_latin = "This is mostly English, with a бит of Russian.";
+        assert!(f.detect(&Record::default().add_body(mostly_latin)));
+        let mostly_cyrillic = "Это в основном русский текст, with some English.";
+        assert!(!f.detect(&Record::default().add_body(mostly_cyrillic)));
+        assert_eq!(
+            ScriptFilter::dominant(mostly_cyrillic),
+            Some(Script::Cyrillic)
+        );
+
+        // punctuation and digits only
+        assert!(!f.detect(&Record::default().add_body("1, 2, 3 !")));
+    }
+
+    #[test]
+    fn test_body_regex_exclude() {
+        let f = FilterKind::BodyRegexExclude(
+            BodyRegexExclude::new(&[r"lorem ipsum dolor", r"we use cookies"], true).unwrap(),
+        );
+
+        let lorem =
+            Record::default().add_body("Lorem ipsum dolor sit amet, consectetur adipiscing elit.");
+        assert!(!f.detect(&lorem));
+
+        let real = Record::default().add_body("Ceci est un vrai document, sans remplissage.");
+        assert!(f.detect(&real));
+
+        // case-sensitive matching
+        let f = BodyRegexExclude::new(&["lorem ipsum dolor"], false).unwrap();
+        assert!(f.detect(&lorem));
+
+        assert!(BodyRegexExclude::new(&["("], false).is_err());
+    }
+
+    #[test]
+    fn test_header_rule_parse() {
+        assert!("content-type=^text/plain".parse::<HeaderRule>().is_ok());
+        assert!("content-type".parse::<HeaderRule>().is_err());
+        assert!("content-type=(".parse::<HeaderRule>().is_err());
+    }
+
+    #[test]
+    fn test_header_filter_allow() {
+        let f = HeaderFilter::default().with_allow("Content-Type=^text/plain".parse().unwrap());
+
+        assert!(f.detect(&gen_record("text/plain", "https://example.com")));
+        assert!(!f.detect(&gen_record("text/html", "https://example.com")));
+    }
+
+    #[test]
+    fn test_header_filter_allow_missing_header() {
+        let f = HeaderFilter::default()
+            .with_allow("warc-identified-content-language=fra".parse().unwrap());
+
+        assert!(!f.detect(&gen_record("text/plain", "https://example.com")));
+    }
+
+    #[test]
+    fn test_header_filter_deny() {
+        let f = HeaderFilter::default().with_deny(
+            r"warc-target-uri=^https?://([^/]+\.)?(spam\.com|ads\.net)/"
+                .parse()
+                .unwrap(),
+        );
+
+        assert!(f.detect(&gen_record("text/plain", "https://example.com/spam.com")));
+        assert!(!f.detect(&gen_record("text/plain", "https://spam.com/page")));
+        assert!(!f.detect(&gen_record("text/plain", "http://www.ads.net/")));
+        // deny rules don't filter out records that lack the header
+        assert!(f.detect(&Record::default().add_body("foo")));
+    }
+
+    #[test]
+    fn test_header_filter_empty() {
+        let f = HeaderFilter::default();
+        assert!(f.is_empty());
+        assert!(f.detect(&Record::default().add_body("foo")));
+    }
 
     #[test]
     fn test_pfilter_fail() {
@@ -146,4 +700,114 @@ mod tests {
         let f = PFilter::default();
         assert_eq!(f.detect(&r), true);
     }
+
+    #[test]
+    fn test_pfilter_score() {
+        let f = PFilter::default();
+        let long = "long line ".repeat(20);
+        // 10 lines, `nb_long` of them being long
+        let gen_lines_record = |nb_long: usize| {
+            let body = (0..10)
+                .map(|i| {
+                    if i < nb_long {
+                        long.as_str()
+                    } else {
+                        "short line"
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            Record::default().add_body(body)
+        };
+
+        let records: Vec<_> = (0..=10).map(gen_lines_record).collect();
+        let scores: Vec<f32> = records.iter().map(|r| f.score(r)).collect();
+
+        assert_eq!(scores[0], 0.0);
+        assert_eq!(scores[10], 1.0);
+        assert!(scores.windows(2).all(|w| w[0] < w[1]));
+
+        // detect agrees with the score
+        for (r, score) in records.iter().zip(scores) {
+            assert_eq!(f.detect(r), score >= 0.6);
+        }
+    }
+
+    #[test]
+    fn test_all() {
+        let f = FilterKind::All(vec![
+            FilterKind::MinLength(MinLength::new(5, 0)),
+            FilterKind::BodyRegexExclude(BodyRegexExclude::new(&["lorem"], false).unwrap()),
+        ]);
+
+        assert!(f.detect(&Record::default().add_body("a real document")));
+        // dropped by either filter
+        assert!(!f.detect(&Record::default().add_body("foo")));
+        assert!(!f.detect(&Record::default().add_body("lorem ipsum dolor")));
+        assert_eq!(f.name(), "all");
+    }
+
+    #[test]
+    fn test_any() {
+        let f = FilterKind::Any(vec![
+            FilterKind::MinLength(MinLength::new(20, 0)),
+            FilterKind::BodyRegexExclude(BodyRegexExclude::new(&["lorem"], false).unwrap()),
+        ]);
+
+        // kept by either filter
+        assert!(f.detect(&Record::default().add_body("foo")));
+        assert!(f.detect(&Record::default().add_body("lorem ipsum dolor sit amet")));
+        // dropped by both
+        assert!(!f.detect(&Record::default().add_body("lorem ipsum dolor")));
+        assert_eq!(f.name(), "any");
+    }
+
+    #[test]
+    fn test_rejecting() {
+        let min_length = FilterKind::MinLength(MinLength::new(5, 0));
+        let regex = FilterKind::BodyRegexExclude(BodyRegexExclude::new(&["lorem"], false).unwrap());
+        let all = FilterKind::All(vec![min_length, regex]);
+        let kept = Record::default().add_body("a real document");
+        let short = Record::default().add_body("foo");
+        let lorem = Record::default().add_body("lorem ipsum dolor");
+
+        assert!(all.rejecting(&kept).is_none());
+        let reason = |r| all.rejecting(r).map(FilterKind::rejection_reason);
+        assert_eq!(reason(&short).as_deref(), Some("filtered_min_length"));
+        assert_eq!(reason(&lorem).as_deref(), Some("filtered_body_regex"));
+
+        let pfilter = FilterKind::default();
+        let rejecting = pfilter.rejecting(&short).unwrap();
+        assert_eq!(rejecting.rejection_reason(), "filtered_short");
+    }
+
+    #[test]
+    fn test_empty_combinators() {
+        let r = Record::default().add_body("");
+        assert!(FilterKind::All(Vec::new()).detect(&r));
+        assert!(FilterKind::Any(Vec::new()).detect(&r));
+        assert_eq!(FilterKind::All(Vec::new()).score(&r), None);
+    }
+
+    #[test]
+    fn test_combinator_score() {
+        let long = "long line ".repeat(20);
+        let r = Record::default().add_body(format!("{long}\nshort line"));
+        let strict = PFilter::with_min_length(500);
+        let lax = PFilter::with_min_length(10);
+        let (low, high) = (strict.score(&r), lax.score(&r));
+        assert!(low < high);
+
+        let all = FilterKind::All(vec![
+            FilterKind::PFilter(PFilter::with_min_length(500)),
+            FilterKind::PFilter(PFilter::with_min_length(10)),
+            FilterKind::MinLength(MinLength::new(1, 0)),
+        ]);
+        assert_eq!(all.score(&r), Some(low));
+        let any = FilterKind::Any(vec![
+            FilterKind::PFilter(PFilter::with_min_length(500)),
+            FilterKind::PFilter(PFilter::with_min_length(10)),
+        ]);
+        assert_eq!(any.score(&r), Some(high));
+    }
 }
@@ -1,6 +1,6 @@
 /*! Language identification models
 
-Holds an [Identifier] trait for implementing other ones.
+Holds a [model::Predict] trait for implementing other ones.
 
 The current identifier used is [fasttext](https://fasttext.cc) !*/
 pub(crate) mod identification;
@@ -10,3 +10,4 @@ mod tag_convert;
 
 pub use multilingual::Multilingual;
 pub use multilingual::StrictMultilingual;
+pub use tag_convert::{LabelMap, UnknownLabels};
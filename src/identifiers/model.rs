@@ -3,6 +3,9 @@
 * !*/
 use std::{
     collections::{HashMap, HashSet},
+    fs::File,
+    hash::Hash,
+    io::Read,
     ops::Deref,
     path::Path,
     str::Lines,
@@ -14,7 +17,7 @@ use oxilangtag::LanguageTag;
 
 use crate::error::Error;
 
-use super::{identification::Identification, tag_convert::Tag};
+use super::{identification::Identification, tag_convert::LabelMap};
 
 /// Covers individual sentence identifications, lang bins and total size of document in bytes
 #[derive(Debug)]
@@ -36,6 +39,19 @@ impl<T: Deref<Target = str> + Clone> DocIdentification<T> {
     pub fn total_size(&self) -> usize {
         self.total_size
     }
+
+    /// Spread the identifications of a sample of lines over the `nb_lines` lines of the document,
+    /// `sampled` being the (increasing) indices of the sampled lines. Unsampled lines get no identification.
+    ///
+    /// Language bins are kept as is, so that language shares extrapolate to the whole document.
+    pub fn spread(mut self, sampled: &[usize], nb_lines: usize) -> Self {
+        let mut line_ids = vec![None; nb_lines];
+        for (idx, id) in sampled.iter().zip(self.line_ids) {
+            line_ids[*idx] = id;
+        }
+        self.line_ids = line_ids;
+        self
+    }
 }
 
 pub trait ModelKind {
@@ -49,6 +65,111 @@ pub trait Predict<T: Deref<Target = str> + Clone> {
     fn predict_one(&self, line: &str) -> Result<Option<Identification<T>>, Error>;
     fn predict(&self, line: &str) -> Result<Option<Vec<Identification<T>>>, Error>;
     fn weighted_ids(&self, lines: Lines) -> Result<DocIdentification<T>, Error>;
+
+    /// Same as [Predict::weighted_ids], but using the top-k predictions of each line.
+    ///
+    /// The bytes of each line are split between its predictions proportionally to their probabilities,
+    /// so that a line that is borderline between two languages contributes to both.
+    /// The top-1 prediction is kept as the line identification.
+    ///
+    /// Blank (empty or whitespace-only) lines are not identified nor counted.
+    fn weighted_ids_topk(&self, lines: Lines) -> Result<DocIdentification<T>, Error>
+    where
+        T: Hash + Eq,
+    {
+        let mut lang_count = HashMap::new();
+        let mut total_count = 0;
+
+        let lines = lines.map(|l| l.replace(char::from(0), ""));
+
+        let mut ids = Vec::new();
+        for line in lines {
+            if is_blank(&line) {
+                ids.push(None);
+                continue;
+            }
+            let predictions = self.predict(&line)?.unwrap_or_default();
+            let byte_count = line.len();
+
+            for (label, bytes, prob) in weighted_shares(&predictions, byte_count) {
+                let (count, count_times_prob) = lang_count.entry(label).or_insert((0, 0f32));
+                *count += bytes;
+                *count_times_prob += bytes as f32 * prob;
+            }
+
+            total_count += byte_count;
+            ids.push(predictions.into_iter().next());
+        }
+
+        // divide by total count to get probs between 0 and 1.
+        for (_, count_times_prob) in lang_count.values_mut() {
+            *count_times_prob /= total_count as f32;
+        }
+
+        Ok(DocIdentification {
+            line_ids: ids,
+            lang_bins: lang_count,
+            total_size: total_count,
+        })
+    }
+
+    /// Identify the lines as a whole, with a single prediction on their (space-joined) non-blank lines.
+    ///
+    /// Lines get no identification, and the document language gets every (non-blank) byte,
+    /// with the prediction probability.
+    fn document_id(&self, lines: Lines) -> Result<DocIdentification<T>, Error>
+    where
+        T: Hash + Eq,
+    {
+        let lines: Vec<String> = lines
+            .filter(|line| !is_blank(line))
+            .map(|line| line.replace(char::from(0), ""))
+            .collect();
+        let total_count = lines.iter().map(String::len).sum();
+
+        let mut lang_count = HashMap::new();
+        if total_count > 0 {
+            let id = self.predict_one(&lines.join(" "))?;
+            let prob = id.as_ref().map_or(0.0, |id| *id.prob());
+            lang_count.insert(id.map(|id| id.label().clone()), (total_count, prob));
+        }
+
+        Ok(DocIdentification {
+            line_ids: Vec::new(),
+            lang_bins: lang_count,
+            total_size: total_count,
+        })
+    }
+}
+
+/// Is the line empty or whitespace-only?
+///
+/// Such lines are not worth identifying: they get no identification (keeping line identifications
+/// aligned with document lines), and their bytes are not counted in document identification.
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// Split `byte_count` between `predictions`, proportionally to their probabilities.
+///
+/// Returns `(label, bytes, prob)` triplets. No prediction yields a single `(None, byte_count, 1.0)` triplet,
+/// to be consistent with [Predict::weighted_ids] that accounts for unidentified bytes.
+fn weighted_shares<T: Deref<Target = str> + Clone>(
+    predictions: &[Identification<T>],
+    byte_count: usize,
+) -> Vec<(Option<LanguageTag<T>>, usize, f32)> {
+    let prob_sum: f32 = predictions.iter().map(|p| *p.prob()).sum();
+    if predictions.is_empty() || prob_sum <= 0.0 {
+        return vec![(None, byte_count, 1.0)];
+    }
+
+    predictions
+        .iter()
+        .map(|p| {
+            let bytes = (byte_count as f32 * p.prob() / prob_sum).round() as usize;
+            (Some(p.label().clone()), bytes, *p.prob())
+        })
+        .collect()
 }
 
 /// FastTextModel.
@@ -58,6 +179,39 @@ pub struct FastText {
     inner: FastTextLib,
     pub k: i32,
     pub threshold: f32,
+    labels: LabelMap,
+}
+
+/// Magic number at the start of fastText model files (both `.bin` and quantized `.ftz`).
+const FASTTEXT_MAGIC: i32 = 793712314;
+
+impl FastText {
+    /// Check that `path` is a readable fastText model, so that a wrong path is reported before any work is done.
+    ///
+    /// Only the magic number is checked: loading may still fail if the model is corrupted.
+    pub fn check_model(path: &Path) -> Result<(), Error> {
+        if !path.is_file() {
+            return Err(Error::Custom(format!(
+                "fastText model not found at {:?}",
+                path
+            )));
+        }
+
+        let mut magic = [0u8; 4];
+        File::open(path)
+            .and_then(|mut f| f.read_exact(&mut magic))
+            .map_err(|e| {
+                Error::Custom(format!("could not read fastText model {:?}: {}", path, e))
+            })?;
+        if i32::from_le_bytes(magic) != FASTTEXT_MAGIC {
+            return Err(Error::Custom(format!(
+                "{:?} is not a fastText model (.bin or .ftz)",
+                path
+            )));
+        }
+
+        Ok(())
+    }
 }
 
 /// Prediction for new tags/model
@@ -72,17 +226,11 @@ impl Predict<String> for FastText {
             // The idea is to move out of pred, since we won't need it afterwards.
             let pred = pred.into_iter().next().unwrap();
             // convert prediction to newtag
-            let pred_to_languagetag: Result<LanguageTag<String>, _> =
-                Tag::new(&pred.label).try_into();
-            match pred_to_languagetag {
-                Ok(label) => {
-                    let id = Identification::new(label, pred.prob);
-
-                    Ok(Some(id))
-                }
+            match self.labels.convert(&pred.label) {
+                Ok(label) => Ok(label.map(|label| Identification::new(label, pred.prob))),
                 Err(e) => {
                     error!("Couldn't parse label {}: {e:?}", &pred.label);
-                    Err(e.into())
+                    Err(e)
                 }
             }
         }
@@ -97,16 +245,13 @@ impl Predict<String> for FastText {
                 .into_iter()
                 //TODO: try_into coerces into OldTag?
                 .map(|pred| {
-                    let label: Result<LanguageTag<String>, _> = Tag::new(&pred.label).try_into();
-                    match label {
-                        Ok(l) => Ok(Identification::new(l, pred.prob)),
-                        Err(e) => Err(e),
-                    }
+                    let label = self.labels.convert(&pred.label)?;
+                    Ok(label.map(|l| Identification::new(l, pred.prob)))
                 })
-                .filter_map(|pred_result| match pred_result {
-                    Ok(p) => Some(p),
+                .filter_map(|pred_result: Result<_, Error>| match pred_result {
+                    Ok(p) => p,
                     Err(e) => {
-                        error!("Error with tag: {e}");
+                        error!("Error with tag: {e:?}");
                         None
                     }
                 })
@@ -130,6 +275,11 @@ impl Predict<String> for FastText {
 
         let ids: Vec<Option<Identification<_>>> = lines
             .map(|line| {
+                // don't bother identifying blank lines
+                if is_blank(&line) {
+                    return Ok(None);
+                }
+
                 // identify
                 let id = self.predict_one(&line);
 
@@ -173,6 +323,7 @@ pub struct FastTextBuilder<'a> {
     path: Option<&'a Path>,
     k: Option<i32>,
     threshold: Option<f32>,
+    labels: LabelMap,
 }
 
 impl<'a> FastTextBuilder<'a> {
@@ -203,6 +354,7 @@ impl<'a> FastTextBuilder<'a> {
             inner,
             k,
             threshold,
+            labels: self.labels.clone(),
         })
     }
 
@@ -230,6 +382,7 @@ impl<'a> FastTextBuilder<'a> {
             inner: Self::init_fasttextlib(path)?,
             k: self.k.unwrap(),
             threshold: self.threshold.unwrap(),
+            labels: self.labels.clone(),
         })
     }
     pub fn path<'b>(&'b mut self, path: &'a Path) -> &'b mut FastTextBuilder<'a> {
@@ -246,6 +399,12 @@ impl<'a> FastTextBuilder<'a> {
         self.threshold = Some(threshold);
         self
     }
+
+    /// Use a custom label conversion, for models with non-standard labels (see [LabelMap]).
+    pub fn labels<'b>(&'b mut self, labels: LabelMap) -> &'b mut FastTextBuilder<'a> {
+        self.labels = labels;
+        self
+    }
 }
 
 impl<'a> Default for FastTextBuilder<'a> {
@@ -254,6 +413,7 @@ impl<'a> Default for FastTextBuilder<'a> {
             path: Some(Path::new("lid.176.bin")),
             k: Some(1),
             threshold: Some(0.8),
+            labels: LabelMap::default(),
         }
     }
 }
@@ -262,7 +422,110 @@ impl<'a> Default for FastTextBuilder<'a> {
 mod tests {
     use std::path::Path;
 
-    use super::{FastText, FastTextBuilder, Predict};
+    use oxilangtag::LanguageTag;
+
+    use crate::error::Error;
+    use crate::identifiers::identification::Identification;
+
+    use super::{
+        weighted_shares, DocIdentification, FastText, FastTextBuilder, Predict, FASTTEXT_MAGIC,
+    };
+
+    /// Identifies lines containing `norsk` as both Bokmål and Nynorsk, and the others as English.
+    struct MockPredict;
+
+    impl Predict<String> for MockPredict {
+        fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+            Ok(self.predict(line)?.and_then(|ids| ids.into_iter().next()))
+        }
+
+        fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+            let id = |label: &str, prob| {
+                Identification::new(LanguageTag::parse(label.to_string()).unwrap(), prob)
+            };
+            if line.contains("norsk") {
+                Ok(Some(vec![id("nb", 0.5), id("nn", 0.4)]))
+            } else {
+                Ok(Some(vec![id("en", 0.9)]))
+            }
+        }
+
+        fn weighted_ids(&self, lines: std::str::Lines) -> Result<DocIdentification<String>, Error> {
+            self.weighted_ids_topk(lines)
+        }
+    }
+
+    #[test]
+    fn test_weighted_shares() {
+        let ids = [
+            Identification::new(LanguageTag::parse("nb".to_string()).unwrap(), 0.6),
+            Identification::new(LanguageTag::parse("nn".to_string()).unwrap(), 0.4),
+        ];
+        let shares = weighted_shares(&ids, 100);
+        assert_eq!(shares.len(), 2);
+        assert_eq!(shares[0].1, 60);
+        assert_eq!(shares[1].1, 40);
+
+        let shares = weighted_shares::<String>(&[], 100);
+        assert_eq!(shares, vec![(None, 100, 1.0)]);
+    }
+
+    #[test]
+    fn test_weighted_ids_topk() {
+        let body = "hello, this is english
+dette er norsk";
+        let ids = MockPredict.weighted_ids_topk(body.lines()).unwrap();
+
+        // top-1 is kept as line identification
+        assert_eq!(ids.line_ids().len(), 2);
+        assert_eq!(ids.line_ids()[1].as_ref().unwrap().label(), "nb");
+
+        // the ambiguous line contributes to both languages
+        let nb = LanguageTag::parse("nb".to_string()).unwrap();
+        let nn = LanguageTag::parse("nn".to_string()).unwrap();
+        assert!(ids.lang_bins().contains_key(&Some(nb)));
+        assert!(ids.lang_bins().contains_key(&Some(nn)));
+        assert_eq!(ids.total_size(), body.lines().map(str::len).sum::<usize>());
+    }
+
+    #[test]
+    fn test_document_id() {
+        let body = "hello, this is english\n\n   \ndette er norsk";
+        let ids = MockPredict.document_id(body.lines()).unwrap();
+
+        // a single prediction on the whole text, and no line identification
+        assert!(ids.line_ids().is_empty());
+        let nb = LanguageTag::parse("nb".to_string()).unwrap();
+        assert_eq!(ids.lang_bins().len(), 1);
+        assert_eq!(ids.lang_bins()[&Some(nb)], (36, 0.5));
+        assert_eq!(ids.total_size(), 36);
+
+        let ids = MockPredict.document_id("\n  \n".lines()).unwrap();
+        assert!(ids.lang_bins().is_empty());
+        assert_eq!(ids.total_size(), 0);
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        let body = "hello, this is english
+
+dette er norsk
+ \t
+";
+        let ids = MockPredict.weighted_ids_topk(body.lines()).unwrap();
+
+        // one identification per line, none for blank lines
+        assert_eq!(ids.line_ids().len(), body.lines().count());
+        let identified: Vec<bool> = ids.line_ids().iter().map(Option::is_some).collect();
+        assert_eq!(identified, vec![true, false, true, false]);
+
+        // blank lines are not counted
+        assert!(!ids.lang_bins().contains_key(&None));
+        assert_eq!(
+            ids.total_size(),
+            "hello, this is english".len() + "dette er norsk".len()
+        );
+    }
 
     #[test]
     fn test_new_one_sentence() {
@@ -310,4 +573,29 @@ mod tests {
 
         assert_eq!(old_pred.unwrap().label(), new_pred.unwrap().label());
     }
+
+    #[test]
+    fn test_check_model() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let missing = dir.path().join("missing.bin");
+        assert!(FastText::check_model(&missing).is_err());
+
+        let garbage = dir.path().join("garbage.bin");
+        std::fs::write(&garbage, b"not a model").unwrap();
+        assert!(FastText::check_model(&garbage).is_err());
+
+        let empty = dir.path().join("empty.bin");
+        std::fs::write(&empty, b"").unwrap();
+        assert!(FastText::check_model(&empty).is_err());
+
+        // magic number, then version
+        let model = dir.path().join("model.bin");
+        let header = [FASTTEXT_MAGIC.to_le_bytes(), 12i32.to_le_bytes()].concat();
+        std::fs::write(&model, header).unwrap();
+        assert!(FastText::check_model(&model).is_ok());
+
+        // directories aren't models
+        assert!(FastText::check_model(dir.path()).is_err());
+    }
 }
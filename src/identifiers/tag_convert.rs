@@ -1,9 +1,11 @@
 //! Conversion utilities or fasttext tags to standardized BCP47.
-use std::{borrow::Cow, collections::HashMap, convert::TryFrom};
+use std::{borrow::Cow, collections::HashMap, convert::TryFrom, path::Path, str::FromStr};
 
 use lazy_static::lazy_static;
 use oxilangtag::{LanguageTag, LanguageTagParseError};
 
+use crate::error::Error;
+
 lazy_static! {
     pub static ref NEW_TAG_REPLACE: HashMap<&'static str, &'static str> = [
         ("abk", "ab"),
@@ -207,6 +209,99 @@ impl<'a> TryFrom<Tag<'a>> for LanguageTag<String> {
         LanguageTag::parse(tag.inner.into_owned())
     }
 }
+/// What to do with model labels that aren't valid language tags (see [LabelMap]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownLabels {
+    /// Fail, the record then gets an identification error.
+    #[default]
+    Error,
+    /// Consider the prediction as no identification.
+    Discard,
+    /// Keep the raw label as a private use tag (`x-<label>`).
+    PrivateUse,
+}
+
+impl FromStr for UnknownLabels {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Self::Error),
+            "discard" => Ok(Self::Discard),
+            "private" => Ok(Self::PrivateUse),
+            other => Err(format!(
+                "unknown label policy {other:?} (expected error, discard or private)"
+            )),
+        }
+    }
+}
+
+/// Conversion of model labels to language tags, for models that don't use (ISO 639 or BCP47) language codes.
+///
+/// Labels (without `__label__`) are first looked up in a custom mapping, then converted as usual (see [Tag]).
+/// Labels that still aren't valid language tags are handled according to [UnknownLabels].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LabelMap {
+    map: HashMap<String, String>,
+    unknown: UnknownLabels,
+}
+
+impl LabelMap {
+    /// Map `label` (without `__label__`) to `tag`.
+    pub fn with_label(mut self, label: String, tag: String) -> Self {
+        self.map.insert(label, tag);
+        self
+    }
+
+    pub fn with_unknown_labels(mut self, unknown: UnknownLabels) -> Self {
+        self.unknown = unknown;
+        self
+    }
+
+    /// Load a mapping from a file with one tab-separated `label tag` pair per line.
+    ///
+    /// Blank lines and lines starting with `#` are ignored.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path)?;
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .try_fold(Self::default(), |map, (idx, line)| {
+                match line.split_once('\t') {
+                    Some((label, tag)) => {
+                        Ok(map.with_label(label.trim().to_string(), tag.trim().to_string()))
+                    }
+                    None => Err(Error::Custom(format!(
+                        "{:?}, line {}: expected a tab-separated label and tag",
+                        path,
+                        idx + 1
+                    ))),
+                }
+            })
+    }
+
+    /// Convert a model label (e.g. `__label__en_US`) to a language tag.
+    ///
+    /// Returns [None] if the label is unknown and discarded.
+    pub fn convert(&self, label: &str) -> Result<Option<LanguageTag<String>>, Error> {
+        let raw = label.strip_prefix("__label__").unwrap_or(label);
+        let tag: Result<LanguageTag<String>, _> = match self.map.get(raw) {
+            Some(tag) => LanguageTag::parse(tag.clone()),
+            None => Tag::new(label).try_into(),
+        };
+        match (tag, self.unknown) {
+            (Ok(tag), _) => Ok(Some(tag)),
+            (Err(e), UnknownLabels::Error) => Err(e.into()),
+            (Err(_), UnknownLabels::Discard) => Ok(None),
+            (Err(e), UnknownLabels::PrivateUse) => {
+                let private = format!("x-{}", raw.replace('_', "-"));
+                LanguageTag::parse(private).map(Some).map_err(|_| e.into())
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -214,6 +309,8 @@ mod tests {
 
     use crate::identifiers::tag_convert::Tag;
 
+    use super::{LabelMap, UnknownLabels};
+
     // use super::{NewTag, OldTag};
 
     #[test]
@@ -250,4 +347,22 @@ mod tests {
             assert_eq!(erroneous, correct);
         }
     }
+
+    #[test]
+    fn test_label_map() {
+        let map = LabelMap::default().with_label("english".to_string(), "en".to_string());
+        assert_eq!(map.convert("__label__english").unwrap().unwrap(), "en");
+
+        // regular labels still work
+        assert_eq!(map.convert("__label__en_US").unwrap().unwrap(), "en-US");
+        assert_eq!(map.convert("__label__fra").unwrap().unwrap(), "fr");
+
+        // not a language tag
+        let label = "__label__lang_01";
+        assert!(map.convert(label).is_err());
+        let discard = map.clone().with_unknown_labels(UnknownLabels::Discard);
+        assert_eq!(discard.convert(label).unwrap(), None);
+        let private = map.with_unknown_labels(UnknownLabels::PrivateUse);
+        assert_eq!(private.convert(label).unwrap().unwrap(), "x-lang-01");
+    }
 }
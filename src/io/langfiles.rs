@@ -1,14 +1,27 @@
 /*! Thread-safe language-separated text/metadata writer.
 
-Each language (provided by [crate::lang::LANG]) is given a [self::Writer] wrapped into an [Arc<Mutex<Writer>>].
+Each language is given a [self::Writer] wrapped into an [Arc<Mutex<Writer>>].
+Writers are created lazily, the first time a language is written (see [LangFilesDoc::writer]),
+and writers don't create files until their first write: `dst` only holds files of languages that have documents.
+
+The position of each writer (see [Position]) can be queried while writing, e.g. by applications
+indexing documents as they are written (see [LangFilesDoc::position]).
+Positions of every writer can be recorded (see [LangFilesDoc::positions]),
+so that files can later be truncated back to them (see [LangFilesDoc::truncate]).
+
+Languages can be given a byte budget (see [Budgets] and [LangFilesDoc::with_budgets]) that caps
+the amount of content written for them over the whole run, regardless of file rotation.
 
 ## Warning
 
 !*/
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
     path::{Path, PathBuf},
-    sync::{Arc, Mutex, RwLock},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
 };
 
 use log::info;
@@ -18,18 +31,70 @@ use oxilangtag::LanguageTag;
 use crate::error;
 use crate::error::Error;
 
-// use super::writer::{WriterDoc, WriterTrait};
-use oscar_io::v3::{Writer, WriterTrait};
+use super::writer::{Compression, NamingScheme, WriterDoc as Writer};
+use super::{CorpusReader, Position};
+use oscar_io::v3::WriterTrait;
 /// Holds references to [Writer].
 // pub struct LangFiles {
 //     writers: HashMap<&'static str, Arc<Mutex<Writer>>>,
 // }
 
 type LanguageMap = HashMap<LanguageTag<String>, Arc<Mutex<Writer>>>;
+
+/// Byte budget of a language, and bytes used so far.
+#[derive(Debug)]
+struct Budget {
+    limit: u64,
+    used: AtomicU64,
+}
+
+/// Byte budgets of languages, capping the content kept for them over a whole run.
+/// Languages that have no budget are unbounded.
+#[derive(Debug, Default)]
+pub struct Budgets(HashMap<LanguageTag<String>, Budget>);
+
+impl Budgets {
+    /// Create budgets of `limit` bytes per language.
+    pub fn new(budgets: HashMap<LanguageTag<String>, u64>) -> Self {
+        Self(
+            budgets
+                .into_iter()
+                .map(|(lang, limit)| {
+                    let budget = Budget {
+                        limit,
+                        used: AtomicU64::new(0),
+                    };
+                    (lang, budget)
+                })
+                .collect(),
+        )
+    }
+
+    /// Consume `bytes` of the budget of `lang`.
+    ///
+    /// Returns `false` if the budget had already been reached, meaning that the document should be dropped.
+    /// The document that reaches the budget is still allowed, so a language can slightly exceed its budget.
+    /// Budgets are shared between threads, and are not persisted when resuming.
+    pub fn consume(&self, lang: &LanguageTag<String>, bytes: u64) -> bool {
+        match self.0.get(lang) {
+            Some(budget) => budget.used.fetch_add(bytes, Ordering::Relaxed) < budget.limit,
+            None => true,
+        }
+    }
+}
+
 pub struct LangFilesDoc {
     writers: Arc<RwLock<LanguageMap>>,
     dst: PathBuf,
     part_size_bytes: Option<u64>,
+    append: bool,
+    compression: Compression,
+    naming: NamingScheme,
+    sorted_keys: bool,
+    byte_offsets: bool,
+    atomic_writes: bool,
+    allowed_langs: Option<HashSet<LanguageTag<String>>>,
+    budgets: Budgets,
 }
 
 // impl LangFiles {
@@ -81,17 +146,100 @@ impl LangFilesDoc {
             writers: Arc::new(RwLock::new(HashMap::new())),
             dst: dst.to_path_buf(),
             part_size_bytes,
+            append: false,
+            compression: Compression::None,
+            naming: NamingScheme::Flat,
+            sorted_keys: false,
+            byte_offsets: false,
+            atomic_writes: false,
+            allowed_langs: None,
+            budgets: Budgets::default(),
         }
     }
 
-    fn new_writer(
-        dst: &Path,
-        lang: LanguageTag<String>,
-        part_size_bytes: Option<u64>,
-    ) -> Result<Arc<Mutex<Writer>>, Error> {
-        let w = Writer::new(dst, lang, part_size_bytes)?;
+    /// Only allow writers for the provided languages.
+    ///
+    /// Attempting to insert a writer for another language is an error.
+    pub fn with_allowed_langs(mut self, allowed_langs: HashSet<LanguageTag<String>>) -> Self {
+        self.allowed_langs = Some(allowed_langs);
+        self
+    }
+
+    /// Cap the content bytes written for some languages over the whole run.
+    /// Languages that have no budget are unbounded.
+    ///
+    /// See [Self::consume_budget].
+    pub fn with_budgets(mut self, budgets: HashMap<LanguageTag<String>, u64>) -> Self {
+        self.budgets = Budgets::new(budgets);
+        self
+    }
 
-        Ok(Arc::new(Mutex::new(w)))
+    /// Consume `bytes` of the budget of `lang` (see [Budgets::consume]).
+    pub fn consume_budget(&self, lang: &LanguageTag<String>, bytes: u64) -> bool {
+        self.budgets.consume(lang, bytes)
+    }
+
+    /// Compress language files.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Lay language files out following `naming` (see [NamingScheme]).
+    pub fn with_naming_scheme(mut self, naming: NamingScheme) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// Serialize documents with sorted keys (see [Writer::with_sorted_keys]).
+    pub fn with_sorted_keys(mut self, sorted_keys: bool) -> Self {
+        self.sorted_keys = sorted_keys;
+        self
+    }
+
+    /// Store the byte offset of each document in its file (see [Writer::with_byte_offsets]).
+    pub fn with_byte_offsets(mut self, byte_offsets: bool) -> Self {
+        self.byte_offsets = byte_offsets;
+        self
+    }
+
+    /// Only make language files visible once complete (see [Writer::with_atomic_writes]).
+    pub fn with_atomic_writes(mut self, atomic_writes: bool) -> Self {
+        self.atomic_writes = atomic_writes;
+        self
+    }
+
+    /// Append to existing language files rather than truncating them.
+    ///
+    /// Writers continue the last existing file (and part numbering, when rotating).
+    /// Used when resuming a run, or when adding documents to an existing corpus.
+    pub fn appending(mut self) -> Self {
+        self.append = true;
+        self
+    }
+
+    fn new_writer(&self, lang: LanguageTag<String>) -> Result<Arc<Mutex<Writer>>, Error> {
+        Ok(Arc::new(Mutex::new(self.build_writer(lang)?)))
+    }
+
+    fn build_writer(&self, lang: LanguageTag<String>) -> Result<Writer, Error> {
+        let w = if self.append {
+            let w = Writer::appending(&self.dst, lang);
+            match self.part_size_bytes {
+                Some(size_limit) => w.with_size_limit(size_limit),
+                None => w,
+            }
+        } else {
+            Writer::new(&self.dst, lang, self.part_size_bytes)?
+        };
+        let w = w
+            .with_compression(self.compression)
+            .with_naming_scheme(self.naming)
+            .with_sorted_keys(self.sorted_keys)
+            .with_byte_offsets(self.byte_offsets)
+            .with_atomic_writes(self.atomic_writes);
+
+        Ok(w)
     }
 
     pub fn contains(&self, k: &LanguageTag<String>) -> bool {
@@ -102,6 +250,12 @@ impl LangFilesDoc {
     }
 
     pub fn insert_writer(&self, k: LanguageTag<String>) -> Result<(), Error> {
+        if let Some(allowed_langs) = &self.allowed_langs {
+            if !allowed_langs.contains(&k) {
+                return Err(Error::Custom(format!("{k} is not an allowed language")));
+            }
+        }
+
         info!("Creating writer {k}");
         info!("{k}: Waiting for lock");
         let mut writer = self
@@ -109,17 +263,91 @@ impl LangFilesDoc {
             .write()
             .expect("Problem with locking writers (in write)");
 
-        // we use the entry API rather than insert to keep the
-        // old writer if the lang already exists
-        writer.entry(k.clone()).or_insert(Self::new_writer(
-            &self.dst,
-            k.clone(),
-            self.part_size_bytes,
-        )?);
+        // another thread may have inserted the writer while we were waiting for the lock:
+        // keep the old writer if the lang already exists
+        if let Entry::Vacant(entry) = writer.entry(k.clone()) {
+            entry.insert(self.new_writer(k.clone())?);
+        }
 
         info!("{k}: Done");
         Ok(())
     }
+
+    /// Get the writer of a language, creating it if it's the first time the language is written.
+    ///
+    /// Safe to call concurrently: the writer is created only once,
+    /// and the writers map is only locked for writing when creating a writer.
+    pub fn writer(&self, lang: &LanguageTag<String>) -> Result<Arc<Mutex<Writer>>, Error> {
+        if let Some(writer) = self.writers().get(lang) {
+            return Ok(writer.clone());
+        }
+
+        self.insert_writer(lang.clone())?;
+        Ok(self.writers().get(lang).unwrap().clone())
+    }
+
+    /// Get the position of the next document written in `lang`,
+    /// or [None] if nothing has been written in it yet (see [Writer::position]).
+    ///
+    /// This locks the writer of the language, waiting for an ongoing write to complete.
+    pub fn position(&self, lang: &LanguageTag<String>) -> Option<Position> {
+        let writer = self.writers().get(lang)?.clone();
+        let position = writer
+            .lock()
+            .expect("Problem with locking writer (in position)")
+            .position();
+        position
+    }
+
+    /// Get the positions of the languages that have been written, keyed by language.
+    ///
+    /// This locks every writer in turn, waiting for ongoing writes to complete.
+    pub fn positions(&self) -> BTreeMap<String, Position> {
+        self.writers()
+            .iter()
+            .filter_map(|(lang, writer)| {
+                let position = writer
+                    .lock()
+                    .expect("Problem with locking writer (in positions)")
+                    .position();
+                position.map(|position| (lang.to_string(), position))
+            })
+            .collect()
+    }
+
+    /// Truncate the files of every language in `dst` back to their recorded position,
+    /// removing those of languages that have none (see [Writer::truncate]).
+    ///
+    /// Used when resuming, before any write, to drop documents that were written after the positions were recorded.
+    pub fn truncate(&self, positions: &BTreeMap<String, Position>) -> Result<(), Error> {
+        for lang in CorpusReader::languages(&self.dst)? {
+            let position = positions.get(lang.as_str());
+            self.build_writer(lang)?.truncate(position)?;
+        }
+        Ok(())
+    }
+
+    /// Flush, sync and close every writer.
+    ///
+    /// Errors if a writer is still referenced elsewhere.
+    pub fn close(self) -> Result<(), Error> {
+        let writers = Arc::try_unwrap(self.writers)
+            .map_err(|_| Error::Custom("writers are still in use".to_string()))?
+            .into_inner()
+            .expect("Problem with locking writers (in close)");
+
+        for (lang, writer) in writers {
+            info!("{lang}: closing writer");
+            Arc::try_unwrap(writer)
+                .map_err(|_| Error::Custom(format!("{lang}: writer is still in use")))?
+                .into_inner()
+                .expect("Problem with locking writer (in close)")
+                .close()?;
+        }
+
+        Ok(())
+    }
+
     /// Get a non-mutable reference to the writers.
     // pub fn writers(&self) -> Arc<HashMap<LanguageTag<String>, Arc<Mutex<WriterDoc>>>> {
     pub fn writers(
@@ -162,6 +390,19 @@ mod tests {
         assert!(lf.contains(&language));
     }
 
+    #[test]
+    fn test_allowed_langs() {
+        let dst = tempdir().unwrap();
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        let en = LanguageTag::parse("en".to_string()).unwrap();
+        let lf = LangFilesDoc::new(dst.path(), None).with_allowed_langs([fr.clone()].into());
+
+        assert!(lf.insert_writer(fr.clone()).is_ok());
+        assert!(lf.insert_writer(en.clone()).is_err());
+        assert!(lf.contains(&fr));
+        assert!(!lf.contains(&en));
+    }
+
     #[test]
     fn write_one_doc() {
         let dst = tempdir().unwrap();
@@ -204,4 +445,181 @@ mod tests {
 
         assert_eq!(doc_from_file, docs[0]);
     }
+
+    #[test]
+    fn test_close() {
+        let dst = tempdir().unwrap();
+        let lf = LangFilesDoc::new(dst.path(), None);
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        let en = LanguageTag::parse("en".to_string()).unwrap();
+        lf.insert_writer(fr.clone()).unwrap();
+        lf.insert_writer(en).unwrap();
+
+        let doc = Document::new("Bonjour".to_string(), HashMap::new(), Metadata::default());
+        lf.writers()
+            .get(&fr)
+            .unwrap()
+            .lock()
+            .unwrap()
+            .write_single(&doc)
+            .unwrap();
+
+        // the unused en writer is closed without error nor file
+        lf.close().unwrap();
+        let content = std::fs::read_to_string(dst.path().join("fr_meta.jsonl")).unwrap();
+        assert!(content.ends_with('\n'));
+        assert!(!dst.path().join("en_meta.jsonl").exists());
+    }
+
+    #[test]
+    fn test_budgets() {
+        let dst = tempdir().unwrap();
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        let en = LanguageTag::parse("en".to_string()).unwrap();
+        let lf = LangFilesDoc::new(dst.path(), None).with_budgets([(fr.clone(), 10)].into());
+
+        assert!(lf.consume_budget(&fr, 6));
+        // reaches the budget
+        assert!(lf.consume_budget(&fr, 6));
+        assert!(!lf.consume_budget(&fr, 1));
+        assert!(lf.consume_budget(&en, 1_000));
+    }
+
+    #[test]
+    fn test_naming_scheme() {
+        let dst = tempdir().unwrap();
+        let lf =
+            LangFilesDoc::new(dst.path(), Some(1)).with_naming_scheme(NamingScheme::Subdirectory);
+        let en = LanguageTag::parse("en".to_string()).unwrap();
+        lf.insert_writer(en.clone()).unwrap();
+
+        let docs: Vec<Document> = ["Hello", "World"]
+            .iter()
+            .map(|c| Document::new(c.to_string(), HashMap::new(), Metadata::default()))
+            .collect();
+        lf.writers()
+            .get(&en)
+            .unwrap()
+            .lock()
+            .unwrap()
+            .write(docs)
+            .unwrap();
+        lf.close().unwrap();
+
+        assert!(dst.path().join("en/text_1.jsonl").exists());
+        assert!(dst.path().join("en/text_2.jsonl").exists());
+        assert!(!dst.path().join("en_meta.jsonl").exists());
+    }
+
+    #[test]
+    fn test_position() {
+        let dst = tempdir().unwrap();
+        let en = LanguageTag::parse("en".to_string()).unwrap();
+        let lf = LangFilesDoc::new(dst.path(), None);
+        assert_eq!(lf.position(&en), None);
+
+        let writer = lf.writer(&en).unwrap();
+        for batch in [vec!["one", "two"], vec!["three"]] {
+            let docs = batch
+                .into_iter()
+                .map(|c| Document::new(c.to_string(), HashMap::new(), Metadata::default()))
+                .collect();
+            writer.lock().unwrap().write(docs).unwrap();
+        }
+        drop(writer);
+
+        // the next document is on the fourth line
+        let position = lf.position(&en).unwrap();
+        assert_eq!(position.line, 3);
+        lf.close().unwrap();
+        let content = std::fs::read_to_string(&position.path).unwrap();
+        assert_eq!(content.lines().count() as u64, position.line);
+        assert_eq!(content.len() as u64, position.byte);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let dst = tempdir().unwrap();
+        let en = LanguageTag::parse("en".to_string()).unwrap();
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        let write = |lf: &LangFilesDoc, lang: &LanguageTag<String>, content: &str| {
+            let doc = Document::new(content.to_string(), HashMap::new(), Metadata::default());
+            lf.writer(lang)
+                .unwrap()
+                .lock()
+                .unwrap()
+                .write(vec![doc])
+                .unwrap();
+        };
+
+        let lf = LangFilesDoc::new(dst.path(), None);
+        write(&lf, &en, "one");
+        let positions = lf.positions();
+        assert_eq!(positions.keys().collect::<Vec<_>>(), ["en"]);
+        write(&lf, &en, "two");
+        write(&lf, &fr, "un");
+        lf.close().unwrap();
+
+        // documents written after the positions are removed, along with languages that had none
+        LangFilesDoc::new(dst.path(), None)
+            .appending()
+            .truncate(&positions)
+            .unwrap();
+        let contents: Vec<String> = CorpusReader::new(dst.path(), &en)
+            .unwrap()
+            .map(|doc| doc.unwrap().content().to_string())
+            .collect();
+        assert_eq!(contents, vec!["one"]);
+        assert!(CorpusReader::new(dst.path(), &fr).is_err());
+    }
+
+    #[test]
+    fn test_appending() {
+        let dst = tempdir().unwrap();
+        let en = LanguageTag::parse("en".to_string()).unwrap();
+        let gen_docs = |contents: &[&str]| -> Vec<Document> {
+            contents
+                .iter()
+                .map(|c| Document::new(c.to_string(), HashMap::new(), Metadata::default()))
+                .collect()
+        };
+        let write = |lf: LangFilesDoc, docs: Vec<Document>| {
+            lf.insert_writer(en.clone()).unwrap();
+            lf.writers()
+                .get(&en)
+                .unwrap()
+                .lock()
+                .unwrap()
+                .write(docs)
+                .unwrap();
+            lf.close().unwrap();
+        };
+
+        // one document per file
+        write(
+            LangFilesDoc::new(dst.path(), Some(1)),
+            gen_docs(&["one", "two"]),
+        );
+        write(
+            LangFilesDoc::new(dst.path(), Some(1)).appending(),
+            gen_docs(&["three", "four"]),
+        );
+
+        // parts are numbered without gaps, and documents are kept in order
+        for part in 1..=4 {
+            assert!(dst
+                .path()
+                .join(format!("en_meta_part_{part}.jsonl"))
+                .exists());
+        }
+        assert!(!dst.path().join("en_meta_part_5.jsonl").exists());
+        let contents: Vec<String> = crate::io::CorpusReader::new(dst.path(), &en)
+            .unwrap()
+            .map(|doc| doc.unwrap().content().to_string())
+            .collect();
+        assert_eq!(contents, vec!["one", "two", "three", "four"]);
+        assert!(crate::processing::validate::validate(dst.path())
+            .unwrap()
+            .is_empty());
+    }
 }
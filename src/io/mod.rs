@@ -3,9 +3,14 @@
 
 Textual/contextual data saving and loading.
 
-Currently only saving is implemented but loading is planned in order to facilitate operations on already generated corpora.
+Corpora can be saved with [LangFilesDoc] and read back with [CorpusReader].
 !*/
 mod langfiles;
+mod reader;
 // pub use langfiles::LangFiles;
-pub use langfiles::LangFilesDoc;
-// pub use writer::Writer;
+pub use langfiles::{Budgets, LangFilesDoc};
+pub use reader::CorpusReader;
+#[cfg(test)]
+pub(crate) mod test_utils;
+mod writer;
+pub use writer::{Compression, NamingScheme, Position, WriterDoc};
@@ -0,0 +1,247 @@
+/*! Corpus reader for a given language.
+
+Reads back [Document]s written by [super::writer::WriterDoc] (or [oscar_io::v3::Writer]).
+
+Documents of a language are either in `<dst>/<lang>_meta.jsonl`,
+or in rotated files `<dst>/<lang>_meta_part_1.jsonl`, `<dst>/<lang>_meta_part_2.jsonl`...
+Compressed files (see [super::Compression]) are decompressed transparently,
+and both [NamingScheme]s are supported.
+!*/
+use std::{
+    collections::BTreeSet,
+    io::{BufRead, Lines},
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+use oscar_io::v3::Document;
+use oxilangtag::LanguageTag;
+
+use crate::error::Error;
+
+use super::{Compression, NamingScheme};
+
+type DocLines = Lines<Box<dyn BufRead + Send>>;
+
+/// Streaming reader over the documents of a language.
+pub struct CorpusReader {
+    files: std::vec::IntoIter<PathBuf>,
+    compression: Compression,
+    current: Option<DocLines>,
+}
+
+impl CorpusReader {
+    /// Create a new reader over the documents of `lang` in `dst`.
+    ///
+    /// Errors if there's no file for `lang`.
+    pub fn new(dst: &Path, lang: &LanguageTag<String>) -> Result<Self, Error> {
+        for naming in [NamingScheme::Flat, NamingScheme::Subdirectory] {
+            for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+                let files = Self::files(dst, lang, naming, compression);
+                if !files.is_empty() {
+                    return Ok(Self {
+                        files: files.into_iter(),
+                        compression,
+                        current: None,
+                    });
+                }
+            }
+        }
+
+        Err(Error::Custom(format!(
+            "no files for language {lang} in {dst:?}"
+        )))
+    }
+
+    /// List the languages that have files in `dst`.
+    pub fn languages(dst: &Path) -> Result<BTreeSet<LanguageTag<String>>, Error> {
+        let mut langs = BTreeSet::new();
+        for entry in std::fs::read_dir(dst)? {
+            let entry = entry?;
+            let filename = entry.file_name();
+            let filename = filename.to_string_lossy();
+            let lang = if entry.path().is_dir() {
+                // language subdirectories (see [NamingScheme::Subdirectory])
+                Self::has_text_files(&entry.path())?.then_some(filename.as_ref())
+            } else {
+                filename.split_once("_meta").map(|(lang, _)| lang)
+            };
+            if let Some(lang) = lang {
+                match LanguageTag::parse(lang.to_string()) {
+                    Ok(lang) => {
+                        langs.insert(lang);
+                    }
+                    Err(e) => warn!("skipping {:?}: {:?}", filename, e),
+                }
+            }
+        }
+
+        Ok(langs)
+    }
+
+    /// Check if a language subdirectory holds documents.
+    fn has_text_files(dir: &Path) -> Result<bool, Error> {
+        for entry in std::fs::read_dir(dir)? {
+            if entry?.file_name().to_string_lossy().starts_with("text") {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Files that are left to be read, in order.
+    pub fn paths(&self) -> &[PathBuf] {
+        self.files.as_slice()
+    }
+
+    /// Compression of the read files.
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// List existing files for a language, a naming scheme and a compression, in order.
+    fn files(
+        dst: &Path,
+        lang: &LanguageTag<String>,
+        naming: NamingScheme,
+        compression: Compression,
+    ) -> Vec<PathBuf> {
+        let ext = compression.extension();
+        let single = naming.path(dst, lang, None, ext);
+        if single.exists() {
+            return vec![single];
+        }
+
+        (1..)
+            .map(|part| naming.path(dst, lang, Some(part), ext))
+            .take_while(|path| path.exists())
+            .collect()
+    }
+
+    /// Open the next file, if any.
+    fn next_file(&mut self) -> Option<Result<DocLines, Error>> {
+        let path = self.files.next()?;
+        Some(
+            self.compression
+                .reader(&path)
+                .map(BufRead::lines)
+                .map_err(Error::from),
+        )
+    }
+}
+
+impl Iterator for CorpusReader {
+    type Item = Result<Document, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(lines) = &mut self.current {
+                match lines.next() {
+                    Some(Ok(line)) if line.is_empty() => continue,
+                    Some(Ok(line)) => {
+                        return Some(serde_json::from_str(&line).map_err(Error::from));
+                    }
+                    Some(Err(e)) => return Some(Err(e.into())),
+                    // current file is exhausted
+                    None => (),
+                }
+            }
+
+            match self.next_file()? {
+                Ok(lines) => self.current = Some(lines),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use oscar_io::v3::{Document, WriterTrait};
+    use oxilangtag::LanguageTag;
+    use tempfile::tempdir;
+
+    use super::CorpusReader;
+    use crate::io::test_utils::gen_docs;
+    use crate::io::{writer::WriterDoc, Compression, NamingScheme};
+
+    fn read_all(reader: CorpusReader) -> Vec<Document> {
+        reader.map(|doc| doc.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_missing() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        assert!(CorpusReader::new(dst.path(), &lang).is_err());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let docs = gen_docs(&["foo", "bar\nbaz", "quux"]);
+
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), None).unwrap();
+        w.write(docs[..2].to_vec()).unwrap();
+        w.write_single(&docs[2]).unwrap();
+
+        let r = CorpusReader::new(dst.path(), &lang).unwrap();
+        assert_eq!(read_all(r), docs);
+    }
+
+    #[test]
+    fn test_roundtrip_compressed() {
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let dst = tempdir().unwrap();
+            let lang = LanguageTag::parse("fr".to_string()).unwrap();
+            let docs = gen_docs(&["foo", "bar\nbaz", "quux"]);
+
+            let mut w = WriterDoc::new(dst.path(), lang.clone(), None)
+                .unwrap()
+                .with_compression(compression);
+            w.write(docs[..2].to_vec()).unwrap();
+            w.write_single(&docs[2]).unwrap();
+
+            let r = CorpusReader::new(dst.path(), &lang).unwrap();
+            assert_eq!(read_all(r), docs);
+        }
+    }
+
+    #[test]
+    fn test_rotated() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let docs = gen_docs(&["foo", "bar", "baz"]);
+
+        // write each document in its own part
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), None)
+            .unwrap()
+            .with_size_limit(1);
+        w.write(docs.clone()).unwrap();
+        assert!(dst.path().join("fr_meta_part_3.jsonl").exists());
+
+        let r = CorpusReader::new(dst.path(), &lang).unwrap();
+        assert_eq!(read_all(r), docs);
+    }
+
+    #[test]
+    fn test_subdirectory() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let docs = gen_docs(&["foo", "bar"]);
+
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), None)
+            .unwrap()
+            .with_naming_scheme(NamingScheme::Subdirectory)
+            .with_size_limit(1);
+        w.write(docs.clone()).unwrap();
+
+        assert_eq!(
+            CorpusReader::languages(dst.path()).unwrap(),
+            [lang.clone()].into()
+        );
+        let r = CorpusReader::new(dst.path(), &lang).unwrap();
+        assert_eq!(read_all(r), docs);
+    }
+}
@@ -0,0 +1,68 @@
+/*! Test helpers to write corpora and read them back.
+!*/
+use std::{collections::HashMap, path::Path};
+
+use oscar_io::common::Identification;
+use oscar_io::v3::{Document, Metadata, WriterTrait};
+use oxilangtag::LanguageTag;
+
+use super::{Compression, CorpusReader, WriterDoc};
+
+/// Generate a document with default metadata.
+pub fn gen_doc(content: &str) -> Document {
+    Document::new(content.to_string(), HashMap::new(), Metadata::default())
+}
+
+/// Generate documents with default metadata.
+pub fn gen_docs(contents: &[&str]) -> Vec<Document> {
+    contents.iter().map(|content| gen_doc(content)).collect()
+}
+
+/// Generate a document identified as `lang`, as are all of its lines.
+pub fn gen_identified_doc(content: &str, lang: &str) -> Document {
+    let id = Identification::new(LanguageTag::parse(lang.to_string()).unwrap(), 1.0);
+    let ids = vec![Some(id.clone()); content.lines().count()];
+    Document::new(
+        content.to_string(),
+        HashMap::new(),
+        Metadata::new(&id, &ids),
+    )
+}
+
+/// Write `docs` in the `lang` file of `dst`.
+pub fn write_docs(dst: &Path, lang: &str, docs: Vec<Document>) {
+    write_parts(dst, lang, docs, None, Compression::None);
+}
+
+/// Write `docs` in the `lang` files of `dst`, rotating them every `size_limit` bytes.
+pub fn write_parts(
+    dst: &Path,
+    lang: &str,
+    docs: Vec<Document>,
+    size_limit: Option<u64>,
+    compression: Compression,
+) {
+    let lang = LanguageTag::parse(lang.to_string()).unwrap();
+    let mut w = WriterDoc::new(dst, lang, size_limit)
+        .unwrap()
+        .with_compression(compression);
+    w.write(docs).unwrap();
+    w.close().unwrap();
+}
+
+/// Read the documents of `lang` in `dst`.
+pub fn read_docs(dst: &Path, lang: &str) -> Vec<Document> {
+    let lang = LanguageTag::parse(lang.to_string()).unwrap();
+    CorpusReader::new(dst, &lang)
+        .unwrap()
+        .map(|doc| doc.unwrap())
+        .collect()
+}
+
+/// Read the contents of the documents of `lang` in `dst`.
+pub fn read_contents(dst: &Path, lang: &str) -> Vec<String> {
+    read_docs(dst, lang)
+        .into_iter()
+        .map(|doc| doc.content().to_string())
+        .collect()
+}
@@ -0,0 +1,1395 @@
+/*! Document writer for a given language.
+
+Writes [Document]s as JSON lines into `<dst>/<lang>_meta.jsonl`, the same layout as [oscar_io::v3::Writer].
+Each line holds a whole document (content, WARC headers and metadata):
+newlines of the content are escaped in the JSON string, so that a document is always a single line.
+Files can also be laid out in per-language subdirectories (see [NamingScheme]).
+
+Contrary to [oscar_io::v3::Writer], which overwrites existing files from their start,
+[WriterDoc] either truncates or appends to an existing file, so that resumed runs keep previously written documents.
+
+Output can be compressed (see [Compression]).
+Each write is compressed independently and appended as a gzip member/zstd frame,
+so that compressed files can be appended to and are always complete after a write.
+
+A run that dies mid-write leaves a partial last line (or member/frame) in a file:
+[Compression::repair] truncates it to its last complete document, and appending writers repair the file they continue.
+Documents written after a known [Position] (e.g. those of a shard that wasn't completely written)
+can also be removed before appending (see [WriterDoc::truncate]).
+
+A failed write (e.g. on a full disk) may also leave a partial document in the file:
+the writer is then poisoned, and fails every subsequent write rather than appending after it.
+
+The byte offset of each document in its file can be stored in its metadata (see [WriterDoc::with_byte_offsets]).
+The [Position] where the next documents will be written can also be queried (see [WriterDoc::position]),
+e.g. to index documents while they are written.
+
+Files are written in place by default, so that readers can follow them while they grow.
+With [WriterDoc::with_atomic_writes], they are written to a `.tmp` file instead,
+renamed into place once complete (on rotation or close), so that readers never see partial files.
+!*/
+use std::{
+    ffi::OsString,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use flate2::{bufread::GzDecoder, read::MultiGzDecoder, write::GzEncoder};
+use log::warn;
+use oscar_io::v3::{Document, WriterTrait};
+use oxilangtag::LanguageTag;
+use serde::{Deserialize, Serialize};
+
+use crate::pipelines::oscardoc::types::{set_byte_offset, ByteOffsetMetadata};
+
+/// Output compression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// File extension, including the leading `.`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Gzip => ".gz",
+            Self::Zstd => ".zst",
+        }
+    }
+
+    /// Compress a buffer.
+    pub(crate) fn compress(&self, buf: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+        match self {
+            Self::None => Ok(buf.to_vec()),
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(buf)?;
+                encoder.finish()
+            }
+            Self::Zstd => zstd::encode_all(buf, zstd::DEFAULT_COMPRESSION_LEVEL),
+        }
+    }
+
+    /// Open a file, decompressing it.
+    pub(crate) fn reader(&self, path: &Path) -> Result<Box<dyn BufRead + Send>, std::io::Error> {
+        let file = File::open(path)?;
+        Ok(match self {
+            Self::None => Box::new(BufReader::new(file)),
+            Self::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+            Self::Zstd => Box::new(BufReader::new(zstd::Decoder::new(file)?)),
+        })
+    }
+
+    /// Get the uncompressed size of a file, and its number of documents (lines).
+    fn uncompressed_counts(&self, path: &Path) -> Result<(u64, u64), std::io::Error> {
+        let mut reader = self.reader(path)?;
+        let (mut len, mut nb_lines) = (0, 0);
+        loop {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            nb_lines += buf.iter().filter(|b| **b == b'\n').count() as u64;
+            let n = buf.len();
+            len += n as u64;
+            reader.consume(n);
+        }
+        Ok((len, nb_lines))
+    }
+
+    /// Truncate a file to its last complete document, returning the number of removed bytes.
+    ///
+    /// For uncompressed files, the last line is removed if it isn't valid JSON.
+    /// For compressed files, the first member/frame that can't be decompressed is removed, along with what follows:
+    /// since writes are compressed independently, complete ones only hold complete documents.
+    pub fn repair(&self, path: &Path) -> Result<u64, std::io::Error> {
+        let len = std::fs::metadata(path)?.len();
+        let valid_len = match self {
+            Self::None => Self::last_line_end(path, len)?,
+            Self::Gzip | Self::Zstd => self.last_frame_end(path)?,
+        };
+
+        if valid_len < len {
+            OpenOptions::new()
+                .write(true)
+                .open(path)?
+                .set_len(valid_len)?;
+        }
+        Ok(len - valid_len)
+    }
+
+    /// Get the end of the last valid line of an uncompressed file of length `len`.
+    ///
+    /// The last line is valid if it's valid JSON followed by a newline,
+    /// so that appends never glue two documents together.
+    fn last_line_end(path: &Path, len: u64) -> Result<u64, std::io::Error> {
+        let mut file = File::open(path)?;
+
+        // look for the start of the last line (ignoring a trailing newline), reading backwards
+        let mut end = len.saturating_sub(1);
+        let mut start = 0;
+        let mut block = vec![0; 64 * 1024];
+        while end > 0 {
+            let block_start = end.saturating_sub(block.len() as u64);
+            let block = &mut block[..(end - block_start) as usize];
+            file.seek(SeekFrom::Start(block_start))?;
+            file.read_exact(block)?;
+            if let Some(idx) = block.iter().rposition(|b| *b == b'\n') {
+                start = block_start + idx as u64 + 1;
+                break;
+            }
+            end = block_start;
+        }
+
+        let mut last_line = Vec::new();
+        file.seek(SeekFrom::Start(start))?;
+        file.read_to_end(&mut last_line)?;
+        let complete = last_line
+            .strip_suffix(b"\n")
+            .is_some_and(|line| serde_json::from_slice::<serde_json::Value>(line).is_ok());
+        Ok(if complete { len } else { start })
+    }
+
+    /// Get the end of the last complete member/frame of a compressed file.
+    fn last_frame_end(&self, path: &Path) -> Result<u64, std::io::Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut end = 0;
+        while !reader.fill_buf()?.is_empty() {
+            let complete = match self {
+                Self::Gzip => {
+                    std::io::copy(&mut GzDecoder::new(&mut reader), &mut std::io::sink()).is_ok()
+                }
+                Self::Zstd => zstd::Decoder::with_buffer(&mut reader).is_ok_and(|decoder| {
+                    std::io::copy(&mut decoder.single_frame(), &mut std::io::sink()).is_ok()
+                }),
+                Self::None => unreachable!("uncompressed files have no frames"),
+            };
+            if !complete {
+                break;
+            }
+            end = reader.stream_position()?;
+        }
+        Ok(end)
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "gzip" => Ok(Self::Gzip),
+            "zstd" => Ok(Self::Zstd),
+            other => Err(format!(
+                "unknown compression {other:?} (expected none, gzip or zstd)"
+            )),
+        }
+    }
+}
+
+/// Output file layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NamingScheme {
+    /// `<dst>/<lang>_meta.jsonl`, rotated into `<dst>/<lang>_meta_part_<n>.jsonl`.
+    #[default]
+    Flat,
+    /// `<dst>/<lang>/text.jsonl`, rotated into `<dst>/<lang>/text_<n>.jsonl`.
+    Subdirectory,
+}
+
+impl NamingScheme {
+    /// Path of a file. `part` is [None] for the first file when there has been no rotation.
+    ///
+    /// `ext` is appended as is, and should include the leading `.`.
+    pub fn path(
+        &self,
+        dst: &Path,
+        lang: &LanguageTag<String>,
+        part: Option<u64>,
+        ext: &str,
+    ) -> PathBuf {
+        match (self, part) {
+            (Self::Flat, None) => dst.join(format!("{lang}_meta.jsonl{ext}")),
+            (Self::Flat, Some(part)) => dst.join(format!("{lang}_meta_part_{part}.jsonl{ext}")),
+            (Self::Subdirectory, None) => dst.join(lang.as_str()).join(format!("text.jsonl{ext}")),
+            (Self::Subdirectory, Some(part)) => dst
+                .join(lang.as_str())
+                .join(format!("text_{part}.jsonl{ext}")),
+        }
+    }
+}
+
+impl FromStr for NamingScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(Self::Flat),
+            "subdir" => Ok(Self::Subdirectory),
+            other => Err(format!(
+                "unknown naming scheme {other:?} (expected flat or subdir)"
+            )),
+        }
+    }
+}
+
+/// Where the next documents of a [WriterDoc] will be written, unless the writer rotates first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    /// final path of the current file (documents are in its `.tmp` file until it's published with atomic writes)
+    pub path: PathBuf,
+    /// number of the current file, starting at 1, incremented on each rotation
+    pub part: u64,
+    /// number of documents in the current file, i.e. the (0-based) line of the next document
+    pub line: u64,
+    /// uncompressed size of the current file, i.e. the byte offset of the next document
+    pub byte: u64,
+    /// size of the current file on disk, which is `byte` unless compressing
+    pub len: u64,
+}
+
+pub struct WriterDoc {
+    dst: PathBuf,
+    lang: LanguageTag<String>,
+    append: bool,
+    compression: Compression,
+    naming: NamingScheme,
+    size_limit: Option<u64>,
+    sorted_keys: bool,
+    byte_offsets: bool,
+    atomic: bool,
+    file: Option<File>,
+    /// number of files created so far (0 if nothing has been written yet)
+    nb_files: u64,
+    /// uncompressed size of the current file
+    current_size: u64,
+    /// number of documents in the current file
+    current_documents: u64,
+    /// size of the current file on disk
+    current_len: u64,
+    /// set when a write fails, leaving the file in an unknown state
+    poisoned: bool,
+}
+
+impl WriterDoc {
+    fn with_dst(dst: &Path, lang: LanguageTag<String>, append: bool) -> Self {
+        Self {
+            dst: dst.to_path_buf(),
+            lang,
+            append,
+            compression: Compression::None,
+            naming: NamingScheme::Flat,
+            size_limit: None,
+            sorted_keys: false,
+            byte_offsets: false,
+            atomic: false,
+            file: None,
+            nb_files: 0,
+            current_size: 0,
+            current_documents: 0,
+            current_len: 0,
+            poisoned: false,
+        }
+    }
+
+    /// Create a new writer that appends to `<dst>/<lang>_meta.jsonl` (or to the last part) if it already exists.
+    ///
+    /// Note that nothing is created/written unless a write is performed.
+    pub fn appending(dst: &Path, lang: LanguageTag<String>) -> Self {
+        Self::with_dst(dst, lang, true)
+    }
+
+    /// Compress output, writing into `<dst>/<lang>_meta.jsonl.{gz,zst}`.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Lay files out following `naming` (see [NamingScheme]).
+    ///
+    /// File names given in this documentation are the [NamingScheme::Flat] ones.
+    pub fn with_naming_scheme(mut self, naming: NamingScheme) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// Rotate files when they would exceed `size_limit` bytes.
+    ///
+    /// Rotation is done at document boundaries, including in bulk writes.
+    /// The first file is `<dst>/<lang>_meta.jsonl`, and is renamed `<dst>/<lang>_meta_part_1.jsonl` on the first rotation.
+    /// Subsequent files are `<dst>/<lang>_meta_part_<n>.jsonl`.
+    ///
+    /// A document that is larger than `size_limit` is written alone in its file.
+    /// When compressing, the limit applies to uncompressed sizes.
+    pub fn with_size_limit(mut self, size_limit: u64) -> Self {
+        self.size_limit = Some(size_limit);
+        self
+    }
+
+    /// Serialize documents with sorted object keys, so that identical documents are serialized identically.
+    ///
+    /// WARC headers are stored in a hash map, whose order changes between instances.
+    /// This is slower, since documents go through an intermediate [serde_json::Value].
+    pub fn with_sorted_keys(mut self, sorted_keys: bool) -> Self {
+        self.sorted_keys = sorted_keys;
+        self
+    }
+
+    /// Store the byte offset of each document in its (uncompressed) file in its metadata
+    /// (see [crate::pipelines::oscardoc::types::ByteOffsetMetadata]), replacing any previous one.
+    ///
+    /// Offsets restart at 0 in each rotated part.
+    /// Seeking to the offset of a document lands on the first character of its line.
+    ///
+    /// Documents that already have an offset (e.g. read from a corpus written with offsets)
+    /// get it updated even when this is disabled, so that copied documents never keep a stale one.
+    pub fn with_byte_offsets(mut self, byte_offsets: bool) -> Self {
+        self.byte_offsets = byte_offsets;
+        self
+    }
+
+    /// Write files to a `.tmp` file (e.g. `<dst>/<lang>_meta.jsonl.tmp`), renamed to their final name
+    /// once complete, i.e. on rotation or close: files are never seen partially written under their final name.
+    ///
+    /// Stale `.tmp` files of interrupted runs are removed on the first write, unless appending:
+    /// the last one is then repaired (see [Compression::repair]) and continued, since it holds documents of previous runs.
+    /// When appending, the file that is continued is moved back to its `.tmp` file until close.
+    ///
+    /// Dropping the writer without closing it leaves the current file unpublished.
+    pub fn with_atomic_writes(mut self, atomic: bool) -> Self {
+        self.atomic = atomic;
+        self
+    }
+
+    /// Path of a file. `part` is [None] for the first file when there has been no rotation.
+    fn part_path(&self, part: Option<u64>) -> PathBuf {
+        self.naming
+            .path(&self.dst, &self.lang, part, self.compression.extension())
+    }
+
+    /// Path of the current file.
+    pub fn path(&self) -> PathBuf {
+        if self.nb_files <= 1 {
+            self.part_path(None)
+        } else {
+            self.part_path(Some(self.nb_files))
+        }
+    }
+
+    /// Temporary path of a file being written atomically.
+    fn tmp_path(path: &Path) -> PathBuf {
+        let mut tmp = OsString::from(path.as_os_str());
+        tmp.push(".tmp");
+        PathBuf::from(tmp)
+    }
+
+    /// Path the current file is written to.
+    fn write_path(&self) -> PathBuf {
+        if self.atomic {
+            Self::tmp_path(&self.path())
+        } else {
+            self.path()
+        }
+    }
+
+    /// Get the temporary files left by interrupted atomic writes, along with their final paths.
+    ///
+    /// Those are either the first file, or the part following the last existing one.
+    fn stale_tmps(&self, nb_parts: usize) -> Vec<(PathBuf, PathBuf)> {
+        std::iter::once(None)
+            .chain((1..=nb_parts as u64 + 1).map(Some))
+            .map(|part| self.part_path(part))
+            .map(|path| (Self::tmp_path(&path), path))
+            .filter(|(tmp, _)| tmp.exists())
+            .collect()
+    }
+
+    /// Get the existing parts, in order.
+    fn existing_parts(&self) -> Vec<PathBuf> {
+        (1..)
+            .map(|part| self.part_path(Some(part)))
+            .take_while(|path| path.exists())
+            .collect()
+    }
+
+    /// Set up the writer state on first write.
+    ///
+    /// When appending, the writer continues the last existing file.
+    /// Otherwise, previously existing files are truncated/removed.
+    /// The language subdirectory is created if needed.
+    fn init(&mut self) -> Result<(), std::io::Error> {
+        if self.naming == NamingScheme::Subdirectory {
+            std::fs::create_dir_all(self.dst.join(self.lang.as_str()))?;
+        }
+
+        let mut parts = self.existing_parts();
+        if self.append {
+            self.recover_tmps()?;
+            parts = self.existing_parts();
+
+            let (nb_files, last) = if parts.is_empty() {
+                (1, self.part_path(None))
+            } else {
+                (parts.len() as u64, parts[parts.len() - 1].clone())
+            };
+            self.nb_files = nb_files;
+            (self.current_size, self.current_documents) = if last.exists() {
+                let removed = self.compression.repair(&last)?;
+                if removed > 0 {
+                    warn!(
+                        "{:?}: removed {} bytes of a partially written document",
+                        last, removed
+                    );
+                }
+                self.current_len = std::fs::metadata(&last)?.len();
+                self.compression.uncompressed_counts(&last)?
+            } else {
+                self.current_len = 0;
+                (0, 0)
+            };
+        } else {
+            for (tmp, _) in self.stale_tmps(parts.len()) {
+                std::fs::remove_file(tmp)?;
+            }
+            for part in parts {
+                std::fs::remove_file(part)?;
+            }
+            self.nb_files = 1;
+            self.current_size = 0;
+            self.current_documents = 0;
+            self.current_len = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Publish the temporary files left by interrupted atomic writes,
+    /// which are complete up to their last write.
+    fn recover_tmps(&self) -> Result<(), std::io::Error> {
+        for (tmp, path) in self.stale_tmps(self.existing_parts().len()) {
+            if path.exists() {
+                warn!("{:?}: removing stale temporary file", tmp);
+                std::fs::remove_file(tmp)?;
+            } else {
+                warn!("{:?}: recovering interrupted write", tmp);
+                self.compression.repair(&tmp)?;
+                std::fs::rename(tmp, path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Truncate existing files back to `position`, removing the documents that were written after it
+    /// (e.g. by a run that died before recording them), along with later parts.
+    /// Every file is removed if `position` is [None], i.e. if nothing was written at that point.
+    ///
+    /// `position` should have been given by a writer with the same settings, in a previous run.
+    /// This can only be done before the first write.
+    pub fn truncate(&mut self, position: Option<&Position>) -> Result<(), std::io::Error> {
+        if self.nb_files > 0 {
+            return Err(std::io::Error::other(
+                "files can only be truncated before writing",
+            ));
+        }
+
+        self.recover_tmps()?;
+        let parts = self.existing_parts();
+        let (kept, later) = match position {
+            None => {
+                let first = self.part_path(None);
+                if first.exists() {
+                    std::fs::remove_file(first)?;
+                }
+                (None, parts)
+            }
+            // the first file got rotated since
+            Some(position) if position.part == 1 => {
+                let first = self.part_path(None);
+                if let Some(part_1) = parts.first() {
+                    std::fs::rename(part_1, &first)?;
+                }
+                (
+                    Some((first, position.len)),
+                    parts.into_iter().skip(1).collect(),
+                )
+            }
+            Some(position) => {
+                let kept = self.part_path(Some(position.part));
+                let later = parts.into_iter().skip(position.part as usize).collect();
+                (Some((kept, position.len)), later)
+            }
+        };
+
+        for part in later {
+            warn!(
+                "{:?}: removing file written after the last checkpoint",
+                part
+            );
+            std::fs::remove_file(part)?;
+        }
+        if let Some((path, len)) = kept {
+            let actual_len = if path.exists() {
+                std::fs::metadata(&path)?.len()
+            } else {
+                0
+            };
+            if actual_len < len {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{path:?} is shorter ({actual_len} bytes) than its recorded position ({len} bytes)"),
+                ));
+            }
+            if actual_len > len {
+                warn!(
+                    "{:?}: removing {} bytes written after the last checkpoint",
+                    path,
+                    actual_len - len
+                );
+                OpenOptions::new().write(true).open(&path)?.set_len(len)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the opened file, opening it if it's the first write.
+    fn file(&mut self) -> Result<&mut File, std::io::Error> {
+        if self.file.is_none() {
+            let first_write = self.nb_files == 0;
+            if first_write {
+                self.init()?;
+            }
+
+            let mut options = OpenOptions::new();
+            options.create(true);
+            if self.append || !first_write {
+                options.append(true);
+            } else {
+                options.write(true).truncate(true);
+            }
+            // a complete file is continued (or replaced) out of sight
+            if self.atomic && self.path().exists() {
+                std::fs::rename(self.path(), self.write_path())?;
+            }
+            self.file = Some(options.open(self.write_path())?);
+        }
+
+        // file is necessarily opened at this point
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    /// Flush and sync the current file to disk, if any, and close it.
+    /// With atomic writes, the file is then renamed to its final name.
+    ///
+    /// This is a no-op if nothing has been written.
+    pub fn close(mut self) -> Result<(), std::io::Error> {
+        self.finish()
+    }
+
+    /// Sync and close the current file, if any, publishing it with atomic writes.
+    ///
+    /// Files of poisoned writers are not published, since they may end with a partial document.
+    fn finish(&mut self) -> Result<(), std::io::Error> {
+        let opened = self.file.is_some();
+        self.sync()?;
+        if self.atomic && opened {
+            if self.poisoned {
+                warn!(
+                    "{:?}: not publishing file after a failed write",
+                    self.write_path()
+                );
+            } else {
+                std::fs::rename(self.write_path(), self.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush, sync and close the current file, if any.
+    fn sync(&mut self) -> Result<(), std::io::Error> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Close the current file and open the next one.
+    fn rotate(&mut self) -> Result<(), std::io::Error> {
+        self.sync()?;
+
+        // the first file gets renamed as the first part
+        if self.nb_files == 1 {
+            std::fs::rename(self.write_path(), self.part_path(Some(1)))?;
+        } else if self.atomic {
+            std::fs::rename(self.write_path(), self.path())?;
+        }
+        self.nb_files += 1;
+        self.current_size = 0;
+        self.current_documents = 0;
+        self.current_len = 0;
+
+        let mut options = OpenOptions::new();
+        options.create(true).write(true).truncate(true);
+        self.file = Some(options.open(self.write_path())?);
+        Ok(())
+    }
+
+    /// Serialize a document as a line (followed by a newline), setting its byte offset if enabled
+    /// or if it already has one.
+    fn serialize(&self, doc: &mut Document, offset: u64) -> Result<String, serde_json::Error> {
+        // an offset from the file the document was read from would be stale
+        if self.byte_offsets || doc.metadata().byte_offset().is_some() {
+            set_byte_offset(doc, offset);
+        }
+
+        // serde_json objects are ordered by key
+        let mut line = if self.sorted_keys {
+            serde_json::to_string(&serde_json::to_value(&*doc)?)?
+        } else {
+            serde_json::to_string(doc)?
+        };
+        line.push('\n');
+        Ok(line)
+    }
+
+    /// Write a chunk of `nb_documents` serialized documents in the current file.
+    fn write_chunk(&mut self, chunk: &str, nb_documents: u64) -> Result<(), std::io::Error> {
+        let buf = self.compression.compress(chunk.as_bytes())?;
+        let file = self.file()?;
+        file.write_all(&buf)?;
+        file.flush()?;
+        self.current_size += chunk.len() as u64;
+        self.current_documents += nb_documents;
+        self.current_len += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Has a write failed? Poisoned writers fail every subsequent write.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Write documents, in a single write if no rotation is needed.
+    fn write_documents(&mut self, vals: Vec<Document>) -> Result<(), oscar_io::Error> {
+        // ensure that the writer state is set up
+        self.file()?;
+
+        let mut chunk = String::new();
+        let mut nb_documents = 0;
+        for mut doc in vals {
+            let size = self.current_size + chunk.len() as u64;
+            let mut line = self.serialize(&mut doc, size)?;
+
+            if let Some(size_limit) = self.size_limit {
+                if size > 0 && size + line.len() as u64 > size_limit {
+                    if !chunk.is_empty() {
+                        self.write_chunk(&chunk, nb_documents)?;
+                        chunk.clear();
+                        nb_documents = 0;
+                    }
+                    self.rotate()?;
+
+                    // the document now starts the new file
+                    if doc.metadata().byte_offset().is_some() {
+                        line = self.serialize(&mut doc, 0)?;
+                    }
+                }
+            }
+
+            chunk += &line;
+            nb_documents += 1;
+        }
+
+        if !chunk.is_empty() {
+            self.write_chunk(&chunk, nb_documents)?;
+        }
+        Ok(())
+    }
+
+    /// Get the position of the next written document.
+    ///
+    /// Returns [None] until the first write, since appending writers only look at existing files then.
+    pub fn position(&self) -> Option<Position> {
+        (self.nb_files > 0).then(|| Position {
+            path: self.path(),
+            part: self.nb_files,
+            line: self.current_documents,
+            byte: self.current_size,
+            len: self.current_len,
+        })
+    }
+}
+
+impl WriterTrait for WriterDoc {
+    type Item = Document;
+
+    /// Create a new writer for provided language.
+    ///
+    /// `<dst>/<lang>_meta.jsonl` is truncated on first write.
+    /// If set, `max_file_size` enables rotation (see [WriterDoc::with_size_limit]).
+    fn new(
+        dst: &Path,
+        lang: LanguageTag<String>,
+        max_file_size: Option<u64>,
+    ) -> Result<Self, oscar_io::Error> {
+        let w = Self::with_dst(dst, lang, false);
+        Ok(match max_file_size {
+            Some(size_limit) => w.with_size_limit(size_limit),
+            None => w,
+        })
+    }
+
+    /// Write documents, in a single write if no rotation is needed.
+    ///
+    /// The writer is poisoned if the write fails (see [WriterDoc::is_poisoned]).
+    fn write(&mut self, vals: Vec<Document>) -> Result<(), oscar_io::Error> {
+        if self.poisoned {
+            return Err(std::io::Error::other(format!(
+                "{:?}: a previous write failed, and may have left a partial document",
+                self.path()
+            ))
+            .into());
+        }
+
+        let result = self.write_documents(vals);
+        self.poisoned = result.is_err();
+        result
+    }
+
+    fn write_single(&mut self, val: &Document) -> Result<(), oscar_io::Error> {
+        self.write(vec![val.clone()])
+    }
+
+    /// Close the current file, publishing it with atomic writes.
+    fn close_meta(&mut self) -> Result<(), oscar_io::Error> {
+        if self.atomic {
+            self.finish()?;
+        } else {
+            self.file = None;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::MultiGzDecoder;
+    use oscar_io::v3::{Document, Metadata, WriterTrait};
+    use oxilangtag::LanguageTag;
+    use tempfile::tempdir;
+    use warc::WarcHeader;
+
+    use super::{Compression, NamingScheme, WriterDoc};
+    use crate::io::test_utils::{gen_doc, gen_docs, read_contents, write_docs};
+    use crate::io::CorpusReader;
+    use crate::pipelines::oscardoc::types::ByteOffsetMetadata;
+
+    fn nb_lines(dst: &std::path::Path) -> usize {
+        std::fs::read_to_string(dst.join("fr_meta.jsonl"))
+            .unwrap()
+            .lines()
+            .count()
+    }
+
+    fn read_docs(content: &str) -> Vec<Document> {
+        content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_truncate() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), None).unwrap();
+        w.write(vec![gen_doc("foo"), gen_doc("bar")]).unwrap();
+        assert_eq!(nb_lines(dst.path()), 2);
+
+        let mut w = WriterDoc::new(dst.path(), lang, None).unwrap();
+        w.write_single(&gen_doc("baz")).unwrap();
+        assert_eq!(nb_lines(dst.path()), 1);
+    }
+
+    #[test]
+    fn test_position() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), None)
+            .unwrap()
+            .with_compression(Compression::Gzip);
+        assert_eq!(w.position(), None);
+        w.write(vec![gen_doc("foo"), gen_doc("bar")]).unwrap();
+        w.write(vec![gen_doc("baz")]).unwrap();
+        let position = w.position().unwrap();
+        assert_eq!(position.path, dst.path().join("fr_meta.jsonl.gz"));
+        assert_eq!((position.part, position.line), (1, 3));
+        w.close().unwrap();
+
+        // appending writers count existing documents
+        let mut w = WriterDoc::appending(dst.path(), lang).with_compression(Compression::Gzip);
+        w.write(vec![gen_doc("quux")]).unwrap();
+        let position = w.position().unwrap();
+        assert_eq!(position.line, 4);
+        w.close().unwrap();
+
+        let mut content = String::new();
+        MultiGzDecoder::new(std::fs::File::open(&position.path).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(position.byte, content.len() as u64);
+        assert_eq!(read_docs(&content)[2].content(), "baz");
+    }
+
+    #[test]
+    fn test_truncate_position() {
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let doc_size = serde_json::to_string(&gen_doc("foo")).unwrap().len() as u64 + 1;
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let dst = tempdir().unwrap();
+            // two documents per file
+            let writer = || {
+                WriterDoc::appending(dst.path(), lang.clone())
+                    .with_compression(compression)
+                    .with_size_limit(doc_size * 2)
+            };
+            let contents = || -> Vec<String> {
+                CorpusReader::new(dst.path(), &lang)
+                    .unwrap()
+                    .map(|doc| doc.unwrap().content().to_string())
+                    .collect()
+            };
+
+            let mut w = writer();
+            w.write(vec![gen_doc("foo")]).unwrap();
+            let first = w.position();
+            w.write(vec![gen_doc("bar"), gen_doc("baz")]).unwrap();
+            let second = w.position();
+            assert_eq!(second.as_ref().unwrap().part, 2);
+            w.write(vec![gen_doc("qux")]).unwrap();
+            w.close().unwrap();
+
+            writer().truncate(second.as_ref()).unwrap();
+            assert_eq!(contents(), ["foo", "bar", "baz"], "{compression:?}");
+
+            // the first file is moved back from its first part
+            writer().truncate(first.as_ref()).unwrap();
+            assert_eq!(contents(), ["foo"], "{compression:?}");
+            assert_eq!(
+                std::fs::metadata(&first.as_ref().unwrap().path)
+                    .unwrap()
+                    .len(),
+                first.as_ref().unwrap().len
+            );
+
+            // and can be appended to
+            let mut w = writer();
+            w.write(vec![gen_doc("new")]).unwrap();
+            w.close().unwrap();
+            assert_eq!(contents(), ["foo", "new"], "{compression:?}");
+
+            // files can't be shorter than their position
+            let mut w = writer();
+            assert!(w.truncate(second.as_ref()).is_err());
+
+            writer().truncate(None).unwrap();
+            assert!(CorpusReader::new(dst.path(), &lang).is_err());
+        }
+    }
+
+    #[test]
+    fn test_poisoned() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let path = dst.path().join("fr_meta.jsonl");
+
+        // the file can't be opened
+        std::fs::create_dir(&path).unwrap();
+        let mut w = WriterDoc::new(dst.path(), lang, None).unwrap();
+        assert!(w.write_single(&gen_doc("foo")).is_err());
+        assert!(w.is_poisoned());
+
+        // even once the cause is gone
+        std::fs::remove_dir(&path).unwrap();
+        assert!(w.write_single(&gen_doc("bar")).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_multiline_roundtrip() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let docs = vec![gen_doc("foo\nbar\r\n\nbaz\n"), gen_doc("quux")];
+
+        let mut w = WriterDoc::new(dst.path(), lang, None).unwrap();
+        w.write(docs.clone()).unwrap();
+
+        // one line per document
+        let content = std::fs::read_to_string(dst.path().join("fr_meta.jsonl")).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert_eq!(read_docs(&content), docs);
+        assert_eq!(read_docs(&content)[0].content(), "foo\nbar\r\n\nbaz\n");
+    }
+
+    #[test]
+    fn test_sorted_keys() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+
+        // same headers, in many maps
+        let docs: Vec<Document> = (0..10)
+            .map(|_| {
+                let headers = (0..10)
+                    .map(|i| (WarcHeader::from(format!("header-{i}").as_str()), vec![b'x']))
+                    .collect();
+                Document::new("foo".to_string(), headers, Metadata::default())
+            })
+            .collect();
+
+        let mut w = WriterDoc::new(dst.path(), lang, None)
+            .unwrap()
+            .with_sorted_keys(true);
+        w.write(docs.clone()).unwrap();
+
+        let content = std::fs::read_to_string(dst.path().join("fr_meta.jsonl")).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert!(lines.iter().all(|line| *line == lines[0]));
+        assert_eq!(read_docs(&content), docs);
+    }
+
+    #[test]
+    fn test_append() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), None).unwrap();
+        w.write(vec![gen_doc("foo"), gen_doc("bar")]).unwrap();
+
+        let mut w = WriterDoc::appending(dst.path(), lang);
+        w.write_single(&gen_doc("baz")).unwrap();
+        assert_eq!(nb_lines(dst.path()), 3);
+    }
+
+    #[test]
+    fn test_repair() {
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let dst = tempdir().unwrap();
+            let mut w = WriterDoc::new(dst.path(), lang.clone(), None)
+                .unwrap()
+                .with_compression(compression);
+            w.write(vec![gen_doc("foo"), gen_doc("bar")]).unwrap();
+            w.write_single(&gen_doc("baz")).unwrap();
+            let path = w.path();
+            w.close().unwrap();
+
+            // nothing to repair
+            assert_eq!(compression.repair(&path).unwrap(), 0);
+
+            // cut the last write in half
+            let content = std::fs::read(&path).unwrap();
+            let cut = content.len() - 20;
+            std::fs::write(&path, &content[..cut]).unwrap();
+
+            let removed = compression.repair(&path).unwrap();
+            assert!(removed > 0, "{compression:?}");
+            let docs: Vec<Document> = CorpusReader::new(dst.path(), &lang)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(
+                docs,
+                vec![gen_doc("foo"), gen_doc("bar")],
+                "{compression:?}"
+            );
+
+            // appending repairs the file first
+            std::fs::write(&path, &content[..cut]).unwrap();
+            let mut w =
+                WriterDoc::appending(dst.path(), lang.clone()).with_compression(compression);
+            w.write_single(&gen_doc("qux")).unwrap();
+            w.close().unwrap();
+            assert_eq!(
+                CorpusReader::new(dst.path(), &lang).unwrap().count(),
+                3,
+                "{compression:?}"
+            );
+        }
+
+        // a document missing its newline is partially written
+        let dst = tempdir().unwrap();
+        write_docs(dst.path(), "fr", gen_docs(&["foo", "bar"]));
+        let path = dst.path().join("fr_meta.jsonl");
+        let content = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &content[..content.len() - 1]).unwrap();
+        assert!(Compression::None.repair(&path).unwrap() > 0);
+        assert_eq!(read_contents(dst.path(), "fr"), ["foo"]);
+    }
+
+    #[test]
+    fn test_close() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+
+        let mut w = WriterDoc::new(dst.path(), lang, None).unwrap();
+        w.write_single(&gen_doc("foo")).unwrap();
+        w.close().unwrap();
+
+        let content = std::fs::read_to_string(dst.path().join("fr_meta.jsonl")).unwrap();
+        assert!(content.ends_with("}\n"));
+        assert_eq!(read_docs(&content), vec![gen_doc("foo")]);
+    }
+
+    #[test]
+    fn test_close_unused() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+
+        let w = WriterDoc::new(dst.path(), lang, None).unwrap();
+        w.close().unwrap();
+        assert!(!dst.path().join("fr_meta.jsonl").exists());
+    }
+
+    #[test]
+    fn test_atomic_writes() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let path = |name: &str| dst.path().join(name);
+        let doc_size = serde_json::to_string(&gen_doc("foo")).unwrap().len() as u64 + 1;
+
+        // two documents per file
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), None)
+            .unwrap()
+            .with_size_limit(doc_size * 2)
+            .with_atomic_writes(true);
+        w.write(vec![gen_doc("foo"), gen_doc("bar")]).unwrap();
+        assert!(!path("fr_meta.jsonl").exists());
+        assert!(path("fr_meta.jsonl.tmp").exists());
+
+        // rotated parts are complete
+        w.write_single(&gen_doc("baz")).unwrap();
+        assert!(!path("fr_meta.jsonl.tmp").exists());
+        let part_1 = std::fs::read_to_string(path("fr_meta_part_1.jsonl")).unwrap();
+        assert_eq!(read_docs(&part_1), vec![gen_doc("foo"), gen_doc("bar")]);
+        assert!(!path("fr_meta_part_2.jsonl").exists());
+
+        w.close().unwrap();
+        let part_2 = std::fs::read_to_string(path("fr_meta_part_2.jsonl")).unwrap();
+        assert_eq!(read_docs(&part_2), vec![gen_doc("baz")]);
+        assert!(!path("fr_meta_part_2.jsonl.tmp").exists());
+
+        // the continued file is hidden until close
+        let mut w = WriterDoc::appending(dst.path(), lang)
+            .with_size_limit(doc_size * 2)
+            .with_atomic_writes(true);
+        w.write_single(&gen_doc("qux")).unwrap();
+        assert!(!path("fr_meta_part_2.jsonl").exists());
+        w.close().unwrap();
+        let part_2 = std::fs::read_to_string(path("fr_meta_part_2.jsonl")).unwrap();
+        assert_eq!(read_docs(&part_2), vec![gen_doc("baz"), gen_doc("qux")]);
+    }
+
+    #[test]
+    fn test_stale_tmps() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let path = dst.path().join("fr_meta.jsonl");
+        let tmp = dst.path().join("fr_meta.jsonl.tmp");
+
+        // interrupted run, with a partially written document
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), None)
+            .unwrap()
+            .with_atomic_writes(true);
+        w.write(vec![gen_doc("foo"), gen_doc("bar")]).unwrap();
+        drop(w);
+        let content = std::fs::read_to_string(&tmp).unwrap();
+        std::fs::write(&tmp, &content[..content.len() - 5]).unwrap();
+
+        // appending recovers complete documents
+        let mut w = WriterDoc::appending(dst.path(), lang.clone()).with_atomic_writes(true);
+        w.write_single(&gen_doc("baz")).unwrap();
+        w.close().unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(read_docs(&content), vec![gen_doc("foo"), gen_doc("baz")]);
+        assert!(!tmp.exists());
+
+        // new runs remove them
+        std::fs::write(&tmp, "stale").unwrap();
+        let mut w = WriterDoc::new(dst.path(), lang, None)
+            .unwrap()
+            .with_atomic_writes(true);
+        w.write_single(&gen_doc("qux")).unwrap();
+        w.close().unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(read_docs(&content), vec![gen_doc("qux")]);
+        assert!(!tmp.exists());
+    }
+
+    #[test]
+    fn test_compression_from_str() {
+        assert_eq!("none".parse(), Ok(Compression::None));
+        assert_eq!("gzip".parse(), Ok(Compression::Gzip));
+        assert_eq!("zstd".parse(), Ok(Compression::Zstd));
+        assert!("lzma".parse::<Compression>().is_err());
+    }
+
+    #[test]
+    fn test_gzip_roundtrip() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let docs = vec![gen_doc("foo"), gen_doc("bar"), gen_doc("baz")];
+
+        // two writes make two gzip members
+        let mut w = WriterDoc::new(dst.path(), lang, None)
+            .unwrap()
+            .with_compression(Compression::Gzip);
+        w.write(docs[..2].to_vec()).unwrap();
+        w.write_single(&docs[2]).unwrap();
+
+        let path = dst.path().join("fr_meta.jsonl.gz");
+        let mut content = String::new();
+        MultiGzDecoder::new(std::fs::File::open(path).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+
+        assert_eq!(read_docs(&content), docs);
+    }
+
+    #[test]
+    fn test_zstd_roundtrip() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let docs = vec![gen_doc("foo"), gen_doc("bar"), gen_doc("baz")];
+
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), None)
+            .unwrap()
+            .with_compression(Compression::Zstd);
+        w.write(docs[..2].to_vec()).unwrap();
+
+        // appending adds a new frame
+        let mut w = WriterDoc::appending(dst.path(), lang).with_compression(Compression::Zstd);
+        w.write_single(&docs[2]).unwrap();
+
+        let path = dst.path().join("fr_meta.jsonl.zst");
+        let content = zstd::decode_all(std::fs::File::open(path).unwrap()).unwrap();
+
+        assert_eq!(read_docs(&String::from_utf8(content).unwrap()), docs);
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let docs = vec![
+            gen_doc("foo"),
+            gen_doc("bar"),
+            gen_doc("baz"),
+            gen_doc("quu"),
+            gen_doc("qux"),
+        ];
+
+        // two documents per file
+        let doc_size = serde_json::to_string(&docs[0]).unwrap().len() as u64 + 1;
+        let size_limit = doc_size * 2 + doc_size / 2;
+
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), None)
+            .unwrap()
+            .with_size_limit(size_limit);
+        w.write(docs[..3].to_vec()).unwrap();
+        w.write(docs[3..].to_vec()).unwrap();
+
+        assert!(!dst.path().join("fr_meta.jsonl").exists());
+        let parts: Vec<String> = (1..=3)
+            .map(|part| {
+                std::fs::read_to_string(dst.path().join(format!("fr_meta_part_{part}.jsonl")))
+                    .unwrap()
+            })
+            .collect();
+        assert!(!dst.path().join("fr_meta_part_4.jsonl").exists());
+
+        for part in &parts {
+            assert!(part.len() as u64 <= size_limit);
+            assert!(part.ends_with('\n'));
+        }
+        assert_eq!(read_docs(&parts.concat()), docs);
+
+        // resuming continues the last part, then rotates
+        let mut w = WriterDoc::appending(dst.path(), lang).with_size_limit(size_limit);
+        w.write(docs[..2].to_vec()).unwrap();
+        assert_eq!(
+            read_docs(&std::fs::read_to_string(dst.path().join("fr_meta_part_3.jsonl")).unwrap()),
+            docs[4..=4]
+                .iter()
+                .chain(&docs[..1])
+                .cloned()
+                .collect::<Vec<_>>()
+        );
+        assert!(dst.path().join("fr_meta_part_4.jsonl").exists());
+    }
+
+    #[test]
+    fn test_size_limit_truncates_parts() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let docs = vec![gen_doc("foo"), gen_doc("bar")];
+
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), Some(1)).unwrap();
+        w.write(docs.clone()).unwrap();
+        assert!(dst.path().join("fr_meta_part_2.jsonl").exists());
+
+        // a new run without rotation removes previous parts
+        let mut w = WriterDoc::new(dst.path(), lang, None).unwrap();
+        w.write(docs).unwrap();
+        assert!(!dst.path().join("fr_meta_part_1.jsonl").exists());
+        assert!(!dst.path().join("fr_meta_part_2.jsonl").exists());
+        assert_eq!(nb_lines(dst.path()), 2);
+    }
+
+    #[test]
+    fn test_byte_offsets() {
+        use std::io::{BufRead, BufReader, Seek, SeekFrom};
+
+        use crate::pipelines::oscardoc::types::ByteOffsetMetadata;
+
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let docs: Vec<_> = ["foo", "bär", "bäz", "quu", "qux"]
+            .iter()
+            .map(|content| gen_doc(content))
+            .collect();
+
+        // roughly two documents per file
+        let size_limit = 2 * (serde_json::to_string(&docs[0]).unwrap().len() as u64 + 30);
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), Some(size_limit))
+            .unwrap()
+            .with_byte_offsets(true);
+        w.write(docs[..3].to_vec()).unwrap();
+        w.write(docs[3..].to_vec()).unwrap();
+        w.close().unwrap();
+
+        let parts: Vec<_> = (1..)
+            .map(|part| dst.path().join(format!("fr_meta_part_{part}.jsonl")))
+            .take_while(|path| path.exists())
+            .collect();
+        assert!(parts.len() > 1);
+
+        // seeking to the offset of a document lands on its line, in its part
+        let mut contents = Vec::new();
+        for part in &parts {
+            let lines: Vec<String> = std::fs::read_to_string(part)
+                .unwrap()
+                .lines()
+                .map(String::from)
+                .collect();
+            for (idx, line) in lines.iter().enumerate() {
+                let doc: Document = serde_json::from_str(line).unwrap();
+                let offset = doc.metadata().byte_offset().unwrap();
+                if idx == 0 {
+                    assert_eq!(offset, 0);
+                }
+
+                let mut reader = BufReader::new(std::fs::File::open(part).unwrap());
+                reader.seek(SeekFrom::Start(offset)).unwrap();
+                let mut seeked = String::new();
+                reader.read_line(&mut seeked).unwrap();
+                assert_eq!(seeked.trim_end(), line);
+                contents.push(doc.content().to_string());
+            }
+        }
+        assert_eq!(contents, vec!["foo", "bär", "bäz", "quu", "qux"]);
+
+        // offsets of appended documents follow the uncompressed size of compressed files
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), None)
+            .unwrap()
+            .with_compression(Compression::Gzip)
+            .with_byte_offsets(true);
+        w.write(docs[..2].to_vec()).unwrap();
+        w.close().unwrap();
+        let mut w = WriterDoc::appending(dst.path(), lang)
+            .with_compression(Compression::Gzip)
+            .with_byte_offsets(true);
+        w.write(docs[2..3].to_vec()).unwrap();
+        w.close().unwrap();
+
+        let mut content = String::new();
+        MultiGzDecoder::new(std::fs::File::open(dst.path().join("fr_meta.jsonl.gz")).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+        let last: Document = serde_json::from_str(content.lines().last().unwrap()).unwrap();
+        let offset = last.metadata().byte_offset().unwrap() as usize;
+        assert_eq!(
+            offset,
+            content.len() - content.lines().last().unwrap().len() - 1
+        );
+    }
+
+    #[test]
+    fn test_stale_byte_offsets() {
+        use crate::pipelines::oscardoc::types::set_byte_offset;
+
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let mut docs = vec![gen_doc("foo"), gen_doc("bar")];
+        set_byte_offset(&mut docs[1], 1234);
+
+        // offsets are updated without byte offsets enabled, but not added
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), None).unwrap();
+        w.write(docs.clone()).unwrap();
+        w.close().unwrap();
+
+        let written: Vec<_> = CorpusReader::new(dst.path(), &lang)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let first_len = serde_json::to_string(&docs[0]).unwrap().len() as u64 + 1;
+        assert_eq!(written[0].metadata().byte_offset(), None);
+        assert_eq!(written[1].metadata().byte_offset(), Some(first_len));
+    }
+
+    #[test]
+    fn test_naming_scheme_from_str() {
+        assert_eq!("flat".parse(), Ok(NamingScheme::Flat));
+        assert_eq!("subdir".parse(), Ok(NamingScheme::Subdirectory));
+        assert!("nested".parse::<NamingScheme>().is_err());
+    }
+
+    #[test]
+    fn test_subdirectory() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("en".to_string()).unwrap();
+        let docs = vec![gen_doc("foo"), gen_doc("bar"), gen_doc("baz")];
+
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), None)
+            .unwrap()
+            .with_naming_scheme(NamingScheme::Subdirectory);
+        w.write(docs[..1].to_vec()).unwrap();
+        assert!(dst.path().join("en/text.jsonl").exists());
+
+        // rotated files stay in the language subdirectory
+        let mut w = WriterDoc::new(dst.path(), lang, Some(1))
+            .unwrap()
+            .with_naming_scheme(NamingScheme::Subdirectory);
+        w.write(docs.clone()).unwrap();
+
+        assert!(!dst.path().join("en/text.jsonl").exists());
+        assert!(!dst.path().join("en_meta.jsonl").exists());
+        let parts: Vec<String> = (1..=3)
+            .map(|part| {
+                std::fs::read_to_string(dst.path().join(format!("en/text_{part}.jsonl"))).unwrap()
+            })
+            .collect();
+        assert_eq!(read_docs(&parts.concat()), docs);
+    }
+}
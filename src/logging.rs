@@ -0,0 +1,149 @@
+/*! Log output.
+
+Logs are written on stderr, either as text (the default, see [env_logger]) or as JSON lines,
+for ingestion in log aggregators (see [LogFormat]).
+
+Each JSON line holds the `timestamp`, `level`, `target` and `message` of the log, along with:
+
+- `shard`: the shard that the logging thread is processing, if any (see [with_shard]),
+- `lang`: the language that the message is about, for messages following the `[<lang>]: ...` convention.
+
+!*/
+use std::{
+    cell::RefCell,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::Utc;
+use log::{LevelFilter, Record};
+use oxilangtag::LanguageTag;
+use serde::Serialize;
+
+/// Format of log lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human-readable text.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "unknown log format {other:?} (expected text or json)"
+            )),
+        }
+    }
+}
+
+thread_local! {
+    static SHARD: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Run `f`, tagging the logs of the current thread with `shard`.
+///
+/// Logs of other threads (e.g. of records processed in parallel) are not tagged.
+pub fn with_shard<R>(shard: &Path, f: impl FnOnce() -> R) -> R {
+    let previous = SHARD.with(|current| current.replace(Some(shard.to_path_buf())));
+    let result = f();
+    SHARD.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// A log, as a JSON line.
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    timestamp: String,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shard: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lang: Option<String>,
+}
+
+/// Get the language of messages starting with `[<lang>]`.
+fn message_lang(message: &str) -> Option<String> {
+    let (lang, _) = message.strip_prefix('[')?.split_once(']')?;
+    LanguageTag::parse(lang.to_string())
+        .ok()
+        .map(LanguageTag::into_inner)
+}
+
+/// Format a log as a JSON line (without the trailing newline).
+fn json_line(record: &Record) -> String {
+    let message = record.args().to_string();
+    let line = JsonLine {
+        timestamp: Utc::now().to_rfc3339(),
+        level: record.level().as_str(),
+        target: record.target(),
+        lang: message_lang(&message),
+        shard: SHARD.with(|shard| shard.borrow().clone()),
+        message,
+    };
+
+    // a struct of strings always serializes
+    serde_json::to_string(&line).unwrap_or_default()
+}
+
+/// Set the global logger up, logging at the info level unless overridden by `RUST_LOG`.
+pub fn init(format: LogFormat) {
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(LevelFilter::Info);
+    builder.parse_env("RUST_LOG");
+    if format == LogFormat::Json {
+        builder.format(|buf, record| writeln!(buf, "{}", json_line(record)));
+    }
+    builder.init();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use log::{Level, Record};
+
+    use super::{json_line, with_shard};
+
+    #[test]
+    fn test_json_line() {
+        let line = with_shard(Path::new("shards/0.txt.gz"), || {
+            json_line(
+                &Record::builder()
+                    .level(Level::Warn)
+                    .target("ungoliant::pipelines")
+                    .args(format_args!("[fr]: {} documents over budget", 3))
+                    .build(),
+            )
+        });
+
+        let line: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["target"], "ungoliant::pipelines");
+        assert_eq!(line["message"], "[fr]: 3 documents over budget");
+        assert_eq!(line["shard"], "shards/0.txt.gz");
+        assert_eq!(line["lang"], "fr");
+        assert!(line["timestamp"].is_string());
+
+        // no shard nor language
+        let line = json_line(
+            &Record::builder()
+                .level(Level::Info)
+                .args(format_args!("[0] done"))
+                .build(),
+        );
+        let line: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert!(line.get("shard").is_none());
+        assert!(line.get("lang").is_none());
+    }
+}
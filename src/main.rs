@@ -1,6 +1,6 @@
 #![doc = include_str!("../README.md")]
 use download::Downloader;
-use log::LevelFilter;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Write;
 use structopt::StructOpt;
@@ -16,6 +16,7 @@ mod error;
 mod filtering;
 mod identifiers;
 mod io;
+mod logging;
 mod pipelines;
 mod processing;
 mod sources;
@@ -24,17 +25,12 @@ mod transformers;
 #[tokio::main]
 #[cfg(not(tarpaulin_include))]
 async fn main() -> Result<(), error::Error> {
-    // set devault log level to info
-    let mut builder = env_logger::Builder::new();
-    builder.filter_level(LevelFilter::Info);
-    builder.parse_env("RUST_LOG");
-    builder.init();
-
     let opt = cli::Ungoliant::from_args();
+    logging::init(opt.log_format);
     debug!("cli args\n{:#?}", opt);
 
-    match opt {
-        cli::Ungoliant::Download(e) => {
+    match opt.command {
+        cli::Command::Download(e) => {
             let paths = File::open(e.paths_file)?;
             let mut dl = Downloader::from_paths_file(&paths, e.n_tasks.unwrap_or(4))?;
             let results = dl.download(&e.dst, e.offset).await;
@@ -56,35 +52,361 @@ async fn main() -> Result<(), error::Error> {
             }
         }
 
-        cli::Ungoliant::Pipeline(p) => {
+        cli::Command::Pipeline(p) => {
             let mut schema_filepath = p.dst.clone();
-            let p =
-                pipelines::OscarDocNew::new(p.src, p.dst, p.lid_path, p.blocklist, p.kenlms_path);
-            p.run()?;
+            let dry_run = p.dry_run;
+            let lid_params = pipelines::oscardoc::LidParams::new(p.lid_k, p.lid_threshold);
+            let lid_params = if p.lang_distribution {
+                lid_params.with_lang_distribution()
+            } else {
+                lid_params
+            };
+            let lid_params = match p.lid_sample_above {
+                Some(min_lines) => {
+                    lid_params.with_line_sampling(pipelines::oscardoc::LineSampling::new(
+                        min_lines,
+                        p.lid_sample_head,
+                        p.lid_sample_lines,
+                    ))
+                }
+                None => lid_params,
+            };
+            let allowed_langs: HashSet<_> = p.langs.into_iter().collect();
+            let lang_budgets: HashMap<_, _> = p
+                .lang_budgets
+                .into_iter()
+                .map(|(lang, mb)| (lang, mb * 1_000_000))
+                .collect();
+            let lang_min_bytes =
+                (!p.lang_min_bytes.is_empty() || p.default_min_bytes > 0).then(|| {
+                    filtering::lang::PerLangMinBytes::new(
+                        p.lang_min_bytes.into_iter().collect(),
+                        p.default_min_bytes,
+                    )
+                });
+            let secondary_lang_share = p.secondary_lang_share;
+            let multi_threshold = p.multi_threshold;
+            let max_empty_shards = p.strict.then_some(p.max_empty_shards);
+            let max_corrupt_ratio = p.max_corrupt_ratio;
+            let limit = p.limit;
+            let max_duration = p.max_duration;
+            let (incremental, incremental_grace) = (p.incremental, p.incremental_grace);
+            let shard_list = p.shard_list;
+            let min_lang_documents = p.min_lang_documents;
+            let pii = p.pii;
+            let dedup_lines = p.dedup_lines;
+            let case = p.case;
+            let snapshot_id = p.snapshot_id;
+            let record_types: Vec<warc::RecordType> =
+                p.record_types.iter().map(warc::RecordType::from).collect();
+            let generated_threshold = p.generated_threshold;
+            let blocklist_categories = p.blocklist_categories;
+            let (segment_sentences, max_sentence_chars) =
+                (p.segment_sentences, p.max_sentence_chars);
+            let (min_doc_chars, min_doc_bytes) = (p.min_doc_chars, p.min_doc_bytes);
+            let body_regex_exclude = if p.exclude_body_regex.is_empty() {
+                None
+            } else {
+                Some(filtering::record::BodyRegexExclude::new(
+                    &p.exclude_body_regex,
+                    p.exclude_body_regex_case_insensitive,
+                )?)
+            };
+            let label_map = match &p.label_map {
+                Some(path) => identifiers::LabelMap::from_path(path)?,
+                None => identifiers::LabelMap::default(),
+            }
+            .with_unknown_labels(p.unknown_labels);
+            let header_filter = p
+                .header_allow
+                .into_iter()
+                .fold(filtering::record::HeaderFilter::default(), |f, rule| {
+                    f.with_allow(rule)
+                });
+            let header_filter = p
+                .header_deny
+                .into_iter()
+                .fold(header_filter, |f, rule| f.with_deny(rule));
+            let output = pipelines::oscardoc::OutputOptions::default()
+                .with_compression(p.compression)
+                .with_naming_scheme(p.naming)
+                .with_part_size(p.part_size.map(|mb| mb * 1_000_000))
+                .with_byte_offsets(p.byte_offsets)
+                .with_atomic_writes(p.atomic_writes)
+                .with_line_ids(p.line_ids)
+                .with_languages_csv(p.languages_csv)
+                .with_raw_bodies(p.raw_bodies);
+            let dedup = pipelines::oscardoc::DedupOptions::default();
+            let dedup = match p.seen_hashes {
+                Some(path) => dedup.with_seen_hashes(path),
+                None => dedup,
+            };
+            let dedup = match p.near_dup_threshold {
+                Some(threshold) => dedup.with_near_duplicates(threshold),
+                None => dedup,
+            };
+            let dedup = match p.near_dup_capacity {
+                Some(capacity) => dedup.with_near_duplicates_capacity(capacity),
+                None => dedup,
+            };
+            let remote =
+                pipelines::oscardoc::RemoteOptions::default().with_download_tasks(p.download_tasks);
+            let remote = match p.shard_urls {
+                Some(shard_urls) => remote.with_shard_urls(shard_urls),
+                None => remote,
+            };
+            let rejected = pipelines::oscardoc::RejectedOptions::default()
+                .with_harvest(p.rejected)
+                .with_quality_scores(p.quality_scores)
+                .with_undetermined(p.undetermined);
+            let p = pipelines::OscarDocNew::new(
+                p.src,
+                p.dst,
+                p.lid_path,
+                Some(lid_params),
+                p.blocklist,
+                p.kenlms_path,
+            )
+            .with_resume(p.resume)
+            .with_min_sentence_length(p.min_sentence_length)
+            .with_trim_mode(p.trim_mode)
+            .with_doc_lang_strategy(p.doc_lang_strategy)
+            .with_utf8_mode(p.utf8_mode)
+            .with_content_detector(!p.no_content_detector)
+            .with_keep_document_language_lines(p.keep_document_language_lines)
+            .with_content_counts(p.content_counts)
+            .with_crawl_info(p.crawl_info)
+            .with_lang_hint(p.lang_hint)
+            .with_content_hash(p.content_hash)
+            .with_normalized_content_hash(p.normalize_content_hash)
+            .with_doc_lid_only(p.doc_lid_only)
+            .with_nfc(p.nfc)
+            .with_min_identified_bytes(p.min_identified_bytes)
+            .with_doc_threshold(p.doc_threshold)
+            .with_label_map(label_map)
+            .with_retry(sources::commoncrawl::Retry::new(
+                p.io_retries,
+                std::time::Duration::from_millis(p.io_backoff),
+            ))
+            .with_output(output)
+            .with_dedup(dedup)
+            .with_remote(remote)
+            .with_rejected(rejected)
+            .with_threads(p.threads)
+            .with_workers(p.workers)
+            .with_max_pending_shards(p.max_pending_shards)
+            .with_write_queue(p.write_queue)
+            .with_deterministic(p.deterministic)
+            .with_dry_run(dry_run)
+            .with_quiet(p.quiet)
+            .with_warc_input(p.warc)
+            .with_merge_same_uri(p.merge_same_uri);
+            let p = if header_filter.is_empty() {
+                p
+            } else {
+                p.with_header_filter(header_filter)
+            };
+            let p = if allowed_langs.is_empty() {
+                p
+            } else {
+                p.with_allowed_langs(allowed_langs)
+            };
+            let p = if blocklist_categories.is_empty() {
+                p
+            } else {
+                p.with_blocklist_categories(blocklist_categories)
+            };
+            let p = if segment_sentences {
+                p.with_sentence_segmentation(max_sentence_chars)
+            } else {
+                p
+            };
+            let p = if lang_budgets.is_empty() {
+                p
+            } else {
+                p.with_lang_budgets(lang_budgets)
+            };
+            let p = if min_doc_chars > 0 || min_doc_bytes > 0 {
+                p.with_min_doc_length(min_doc_chars, min_doc_bytes)
+            } else {
+                p
+            };
+            let p = match body_regex_exclude {
+                Some(filter) => p.with_body_regex_exclude(filter),
+                None => p,
+            };
+            let p = match lang_min_bytes {
+                Some(filter) => p.with_lang_min_bytes(filter),
+                None => p,
+            };
+            let p = match secondary_lang_share {
+                Some(share) => p.with_secondary_lang_share(share),
+                None => p,
+            };
+            let p = match multi_threshold {
+                Some(threshold) => p.with_multi_threshold(threshold),
+                None => p,
+            };
+            let p = match max_empty_shards {
+                Some(max_empty_shards) => p.with_strict(max_empty_shards),
+                None => p,
+            };
+            let p = match max_corrupt_ratio {
+                Some(ratio) => p.with_max_corrupt_ratio(ratio),
+                None => p,
+            };
+            let p = match limit {
+                Some(limit) => p.with_limit(limit),
+                None => p,
+            };
+            let p = match max_duration {
+                Some(secs) => p.with_max_duration(std::time::Duration::from_secs(secs)),
+                None => p,
+            };
+            let p = if incremental {
+                p.with_incremental(std::time::Duration::from_secs(incremental_grace))
+            } else {
+                p
+            };
+            let p = match shard_list {
+                Some(shard_list) => p.with_shard_list(shard_list),
+                None => p,
+            };
+            let p = match min_lang_documents {
+                Some(min_documents) => p.with_min_lang_documents(min_documents),
+                None => p,
+            };
+            let p = if record_types.is_empty() {
+                p
+            } else {
+                p.with_record_types(record_types)
+            };
+            let p = match pii {
+                Some(mode) => p.with_pii(mode),
+                None => p,
+            };
+            let p = match dedup_lines {
+                Some(mode) => p.with_dedup_lines(mode),
+                None => p,
+            };
+            let p = match case {
+                Some(mode) => p.with_case(mode),
+                None => p,
+            };
+            let p = match snapshot_id {
+                Some(snapshot_id) => p.with_snapshot_id(snapshot_id),
+                None => p,
+            };
+            let p = match generated_threshold {
+                Some(threshold) => p.with_generated_threshold(threshold),
+                None => p,
+            };
+            if let Some(summary) = p.run()? {
+                println!("{summary}");
+            }
 
-            schema_filepath.push("metadata_schema.json");
-            info!("creating json schema file {:?}", schema_filepath);
-            let _f = File::create(schema_filepath)?;
-            // f.write_all(Document::get_schema().unwrap().as_bytes())?;
-            // f.write_all(Metadata::get_schema()?.as_bytes())?;
+            if !dry_run {
+                schema_filepath.push("metadata_schema.json");
+                info!("creating json schema file {:?}", schema_filepath);
+                let _f = File::create(schema_filepath)?;
+                // f.write_all(Document::get_schema().unwrap().as_bytes())?;
+                // f.write_all(Metadata::get_schema()?.as_bytes())?;
+            }
         }
-        // cli::Ungoliant::Dedup(d) => {
-        //     processing::dedup::dedup(&d.src, &d.dst, Some(d.bufsize))?;
-        // }
-        // cli::Ungoliant::Split(s) => {
-        //     processing::split::split(&s.src, &s.dst, s.part_size, Some(s.bufsize));
-        // }
-        // cli::Ungoliant::Compress(c) => {
+        cli::Command::Dedup(d) => {
+            processing::dedup::dedup(&d.src, &d.dst, Some(d.bufsize), d.normalize)?;
+        }
+        cli::Command::Merge(m) => {
+            processing::merge::merge(&m.srcs, &m.dst, Some(m.bufsize))?;
+        }
+        cli::Command::Reprocess(r) => {
+            let mut transforms: processing::reprocess::Transforms = Vec::new();
+            if let Some(min_length) = r.min_sentence_length {
+                transforms.push(Box::new(
+                    transformers::RemoveShortSentences::with_min_length(min_length),
+                ));
+            }
+            if r.keep_document_language_lines {
+                transforms.push(Box::new(transformers::KeepDocumentLanguageLines::default()));
+            }
+            let mut annotator = transformers::Annotator::default();
+            if let Some(blocklist) = &r.blocklist {
+                annotator.add(Box::new(transformers::ContentDetector::from_path(
+                    blocklist,
+                )?));
+            }
+            processing::reprocess::reprocess(
+                &r.src,
+                &r.dst,
+                &transforms,
+                &annotator,
+                Some(r.bufsize),
+            )?;
+        }
+        cli::Command::Validate(v) => {
+            if v.repair {
+                for (path, removed) in processing::validate::repair(&v.src)? {
+                    println!("{path:?}: removed {removed} bytes");
+                }
+            }
+            let problems = processing::validate::validate(&v.src)?;
+            for (lang, lang_problems) in &problems {
+                for problem in lang_problems {
+                    println!("[{lang}]: {problem}");
+                }
+            }
+            if !problems.is_empty() {
+                return Err(error::Error::Custom(format!(
+                    "{} languages have problems",
+                    problems.len()
+                )));
+            }
+        }
+        cli::Command::Reindex(r) => {
+            processing::reindex::reindex(&r.src)?;
+        }
+        cli::Command::Wordcount(w) => {
+            processing::wordcount::wordcount(&w.src, &w.dst, w.top, w.capacity, &w.tokenizer)?;
+        }
+        cli::Command::Convert(c) => {
+            processing::convert::convert(&c.src, &c.dst)?;
+        }
+        cli::Command::Scan(s) => {
+            let p = pipelines::OscarDocNew::new(
+                s.src,
+                std::path::PathBuf::new(),
+                s.lid_path,
+                None,
+                None,
+                None,
+            )
+            .with_sample(s.nb_shards, s.seed);
+            let summary = p.scan()?;
+            println!("{:<12}{:>16}{:>10}", "lang", "bytes", "share");
+            for (lang, share) in summary.shares() {
+                let bytes = summary.langs()[lang].bytes;
+                println!("{:<12}{:>16}{:>9.2}%", lang, bytes, share * 100.0);
+            }
+        }
+        cli::Command::Split(s) => {
+            let kind = match (s.val_ratio, s.parts) {
+                (_, Some(parts)) => processing::split::SplitKind::Parts(parts),
+                (Some(ratio), None) => processing::split::SplitKind::Ratio(ratio),
+                (None, None) => unreachable!("structopt requires one of them"),
+            };
+            processing::split::split(&s.src, &s.dst, kind, Some(s.bufsize))?;
+        }
+        // cli::Command::Compress(c) => {
         //     processing::compress::compress_corpus(&c.src, &c.dst)?;
         // }
-        // cli::Ungoliant::Package(p) => {
+        // cli::Command::Package(p) => {
         //     processing::package::package(&p.src, p.dst.as_deref(), p.move_files)?;
         // }
-        cli::Ungoliant::Rebuild(r) => {
+        cli::Command::Rebuild(r) => {
             let l = r.lang.parse().expect("unexpected language");
             let rb = processing::rebuild::Rebuilder::new(&r.src_rebuild, &r.src_shards, &r.dst, l);
             rb.run()?;
-        } //cli::Ungoliant::Check(c) => processing::check::check(c.src, c.dst)?,
+        } //cli::Command::Check(c) => processing::check::check(c.src, c.dst)?,
     };
     Ok(())
 }
@@ -2,6 +2,8 @@
 mod pipeline;
 pub mod types;
 
-pub use pipeline::OscarDoc;
+pub use pipeline::{DedupOptions, OutputOptions, RejectedOptions, RemoteOptions};
+pub use pipeline::{DocLangStrategy, LidParams, LineSampling, Utf8Mode, SEGMENTED_KEY};
+pub use pipeline::{OscarDoc, OscarDocBuilder};
 // pub use types::Document;
 // pub use types::Metadata;
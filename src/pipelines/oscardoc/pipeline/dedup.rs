@@ -0,0 +1,289 @@
+//! Document deduplication (see [DedupOptions]).
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use oscar_io::v3::Document;
+use oxilangtag::LanguageTag;
+use rayon::prelude::*;
+
+use crate::error::Error;
+use crate::pipelines::oscardoc::types::Location;
+use crate::pipelines::oscardoc::types::NearDuplicates;
+use crate::pipelines::oscardoc::types::SeenHashes;
+
+use super::OscarDoc;
+
+/// Document deduplication options (see [OscarDoc::with_dedup]).
+///
+/// Documents are deduplicated once identified, within their language, keeping the first one.
+/// Skipped documents are counted as `seen` and `near_duplicates` in [crate::pipelines::oscardoc::types::Stats].
+///
+/// # Near-duplicates
+/// Exact deduplication (see [DedupOptions::with_seen_hashes]) misses documents that only differ by a few sentences.
+/// With [DedupOptions::with_near_duplicates], documents whose estimated Jaccard similarity with a previous document
+/// of the same language reaches a threshold are skipped (see [NearDuplicates]).
+/// Indexes hold a bounded number of documents over all languages (see [DedupOptions::with_near_duplicates_capacity]).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DedupOptions {
+    /// file of the hashes of seen documents
+    pub seen_hashes: Option<PathBuf>,
+    /// near-duplicates similarity threshold
+    pub near_duplicates: Option<f32>,
+    /// maximum number of documents kept in near-duplicate indexes
+    pub near_duplicates_capacity: Option<usize>,
+}
+
+impl DedupOptions {
+    /// Skip documents whose content has already been seen, according to the hashes stored in `path`
+    /// (see [SeenHashes]). Hashes of written documents are appended to `path`, which is created if needed.
+    ///
+    /// Pointing every build to the same file (e.g. the `hashes.bin` of the previous build)
+    /// avoids writing documents that are already in a previous corpus.
+    /// Documents that are duplicated within a run are skipped too.
+    ///
+    /// Hashes are only recorded once the documents of a shard are written, and resumed runs drop
+    /// those recorded after the last checkpoint: documents that were not written are not skipped when resuming.
+    pub fn with_seen_hashes(mut self, path: PathBuf) -> Self {
+        self.seen_hashes = Some(path);
+        self
+    }
+
+    /// Skip documents that are near-duplicates of a previous document of the same language,
+    /// i.e. whose estimated Jaccard similarity with it is at least `threshold` (between 0 and 1).
+    ///
+    /// Unlike [DedupOptions::with_seen_hashes], this only applies within a run.
+    pub fn with_near_duplicates(mut self, threshold: f32) -> Self {
+        self.near_duplicates = Some(threshold);
+        self
+    }
+
+    /// Maximum number of documents kept in near-duplicate indexes, over all languages
+    /// (see [NearDuplicates::with_capacity]). Each one takes about 1KiB.
+    pub fn with_near_duplicates_capacity(mut self, capacity: usize) -> Self {
+        self.near_duplicates_capacity = Some(capacity);
+        self
+    }
+}
+
+impl OscarDoc {
+    /// Open the seen hashes file, if any.
+    ///
+    /// When resuming, hashes recorded after the last recorded shard are dropped:
+    /// `size` is the size of the file when it was recorded (see [crate::pipelines::oscardoc::types::Checkpoint]).
+    pub(super) fn open_seen_hashes(&self, size: Option<u64>) -> Result<Option<SeenHashes>, Error> {
+        match &self.dedup.seen_hashes {
+            Some(path) => {
+                // drop the hashes of the documents that were truncated
+                if let Some(size) = size {
+                    if self.resume {
+                        SeenHashes::truncate(path, size)?;
+                    }
+                }
+                Ok(Some(SeenHashes::open(path)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Build the near-duplicates index, if enabled.
+    pub(super) fn near_duplicates(&self) -> Result<Option<NearDuplicates>, Error> {
+        match self.dedup.near_duplicates {
+            Some(threshold) => {
+                let near_duplicates = NearDuplicates::new(threshold)?;
+                Ok(Some(match self.dedup.near_duplicates_capacity {
+                    Some(capacity) => near_duplicates.with_capacity(capacity),
+                    None => near_duplicates,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Remove documents that have already been seen, along with repeated ones (keeping the first).
+    ///
+    /// Documents are not recorded as seen: this is done once they are written (see [Self::content_hashes]).
+    /// Contents are hashed concurrently. Languages with no document left are removed.
+    /// Returns the number of removed documents.
+    pub(super) fn remove_seen(
+        seen_hashes: &SeenHashes,
+        documents: &mut HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+    ) -> usize {
+        let mut shard_hashes = HashSet::new();
+        let mut nb_removed = 0;
+        for docs in documents.values_mut() {
+            let nb_docs = docs.len();
+            let mut hashes = docs
+                .par_iter()
+                .map(|(doc, _)| SeenHashes::hash(doc.content()))
+                .collect::<Vec<_>>()
+                .into_iter();
+            // documents are visited in order
+            docs.retain(|_| {
+                let hash = hashes.next().unwrap();
+                !seen_hashes.contains(hash) && shard_hashes.insert(hash)
+            });
+            nb_removed += nb_docs - docs.len();
+        }
+        documents.retain(|_, docs| !docs.is_empty());
+
+        nb_removed
+    }
+
+    /// Get the hashes of documents, to record them as seen (see [SeenHashes]).
+    pub(super) fn content_hashes(
+        documents: &HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+    ) -> Vec<u128> {
+        documents
+            .par_iter()
+            .flat_map_iter(|(_, docs)| docs.iter().map(|(doc, _)| SeenHashes::hash(doc.content())))
+            .collect()
+    }
+
+    /// Remove near-duplicate documents (see [NearDuplicates]), returning the number of removed ones.
+    ///
+    /// Signatures are computed in parallel, but documents are recorded in order
+    /// so that the first of near-duplicates is kept.
+    pub(super) fn remove_near_duplicates(
+        near_duplicates: &mut NearDuplicates,
+        documents: &mut HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+    ) -> usize {
+        let mut nb_removed = 0;
+        for (lang, docs) in documents.iter_mut() {
+            let signatures: Vec<_> = docs
+                .par_iter()
+                .map(|(doc, _)| near_duplicates.signature(doc.content()))
+                .collect();
+            let mut signatures = signatures.into_iter();
+            let nb_docs = docs.len();
+            // retain visits documents in order, exactly once
+            docs.retain(|_| near_duplicates.insert(lang, signatures.next().unwrap()));
+            nb_removed += nb_docs - docs.len();
+        }
+        documents.retain(|_, docs| !docs.is_empty());
+
+        nb_removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use oxilangtag::LanguageTag;
+
+    use crate::io::CorpusReader;
+    use crate::pipelines::oscardoc::types::Stats;
+
+    use super::super::tests::{gen_pipeline_at, write_shard, MockPredict};
+    use super::DedupOptions;
+
+    #[test]
+    fn seen_hashes() {
+        let hashes = tempfile::tempdir().unwrap();
+        let hashes_path = hashes.path().join("hashes.bin");
+        let body = |doc_id: usize| format!("Dette er dokument {doc_id}. {}", "x".repeat(100));
+
+        // documents 0 to 2, then 2 to 4 (with 4 twice)
+        let mut runs = Vec::new();
+        for bodies in [
+            (0..3).map(body).collect::<Vec<_>>(),
+            [2, 3, 4, 4].into_iter().map(body).collect(),
+        ] {
+            let src = tempfile::tempdir().unwrap();
+            let dst = tempfile::tempdir().unwrap();
+            write_shard(&src.path().join("0.txt.gz"), &bodies);
+
+            let p = gen_pipeline_at(src.path(), dst.path())
+                .with_dedup(DedupOptions::default().with_seen_hashes(hashes_path.clone()));
+            p.run_with(&MockPredict).unwrap();
+
+            let lang = LanguageTag::parse("nb".to_string()).unwrap();
+            let contents: Vec<String> = CorpusReader::new(dst.path(), &lang)
+                .unwrap()
+                .map(|doc| doc.unwrap().content().to_string())
+                .collect();
+            let stats = Stats::load(dst.path()).unwrap();
+            runs.push((contents, stats.total().seen));
+        }
+
+        assert_eq!(runs[0], ((0..3).map(body).collect(), 0));
+        assert_eq!(runs[1], (vec![body(3), body(4)], 2));
+        assert_eq!(std::fs::metadata(&hashes_path).unwrap().len(), 5 * 16);
+    }
+
+    #[test]
+    fn seen_hashes_write_error() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let hashes_path = dst.path().join("hashes.bin");
+        let long = "x".repeat(100);
+        write_shard(
+            &src.path().join("0.txt.gz"),
+            &[
+                format!("Dette er bokmål. {long}"),
+                format!("Dette er ikkje bokmål. {long}"),
+            ],
+        );
+        let pipeline = |resume: bool| {
+            gen_pipeline_at(src.path(), dst.path())
+                .with_dedup(DedupOptions::default().with_seen_hashes(hashes_path.clone()))
+                .with_resume(resume)
+                .with_quiet(true)
+        };
+        let count = |lang: &str| {
+            let lang = LanguageTag::parse(lang.to_string()).unwrap();
+            CorpusReader::new(dst.path(), &lang).map_or(0, |reader| reader.count())
+        };
+
+        // the nb file can't be opened for writing
+        let nb_path = dst.path().join("nb_meta.jsonl");
+        std::fs::create_dir(&nb_path).unwrap();
+        assert!(pipeline(false).run_with(&MockPredict).is_err());
+        assert_eq!(std::fs::metadata(&hashes_path).unwrap().len(), 0);
+
+        // documents are not seen when resuming
+        std::fs::remove_dir(&nb_path).unwrap();
+        pipeline(true).run_with(&MockPredict).unwrap();
+        assert_eq!((count("nb"), count("nn")), (1, 1));
+        assert_eq!(Stats::load(dst.path()).unwrap().total().seen, 0);
+        assert_eq!(std::fs::metadata(&hashes_path).unwrap().len(), 2 * 16);
+    }
+
+    #[test]
+    fn near_duplicates() {
+        // 20 lines of 20 distinct words, with line `changed` replaced
+        let body = |first_word: usize, changed: Option<usize>| {
+            (0..20)
+                .map(|line| {
+                    (0..20)
+                        .map(|word| match changed {
+                            Some(changed) if changed == line => format!("endret{word}"),
+                            _ => format!("ord{}", first_word + line * 20 + word),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let bodies = [body(0, None), body(0, Some(3)), body(1000, None)];
+
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        write_shard(&src.path().join("0.txt.gz"), &bodies);
+
+        let p = gen_pipeline_at(src.path(), dst.path())
+            .with_dedup(DedupOptions::default().with_near_duplicates(0.8));
+        p.run_with(&MockPredict).unwrap();
+
+        let lang = LanguageTag::parse("nb".to_string()).unwrap();
+        let mut contents: Vec<String> = CorpusReader::new(dst.path(), &lang)
+            .unwrap()
+            .map(|doc| doc.unwrap().content().to_string())
+            .collect();
+        contents.sort();
+        assert_eq!(contents, [bodies[0].clone(), bodies[2].clone()]);
+
+        let stats = Stats::load(dst.path()).unwrap();
+        assert_eq!(stats.total().near_duplicates, 1);
+        assert_eq!(stats.total().excluded_lang, 0);
+    }
+}
@@ -0,0 +1,5933 @@
+//! OSCAR Schema v2 generation pipeline
+//!
+//! OSCAR Schema v2 is a document-oriented corpus schema
+//! enhanced with metadata coming from CommonCrawl.
+//!
+//! The CommonCrawl dump is composed of shards,
+//! Each shard is composed of records,
+//! Each record is composed of a metadata header and a body containing sentences.
+//!
+//! # Processing
+//! 1. Records that are not conversions (e.g. the `warcinfo` record that starts WET files) are skipped (see [OscarDoc::with_record_types]).
+//! 1. Bodies are decoded lossily, unless records that are not valid UTF-8 are dropped (see [Utf8Mode]).
+//! 1. Each record passes through a quality filter that by default checks the content distribution between
+//!   short and long sentences, discarding records where the content is primarly in short sentences. (sentence = newline-separated string)
+//! 1. The remaining ones get identified both by line and as a whole (we keep the language that has the most information (=bytes)).
+//! 1. We pass the records in the adult content annotator
+//! 1. We remove remaining short sentences at start/end[^1]
+//! 1. We then write documents in files.
+//!
+//! [^1]: We should do this after step 1: better efficiency.
+//!
+//! # Resuming
+//! Shards are recorded in a [Checkpoint] once their documents are written.
+//! When resuming (see [OscarDoc::with_resume]), recorded shards are skipped, language files are appended to
+//! and new rebuild files are created alongside existing ones.
+//! Shards that were being written when the run was interrupted are not recorded and will be processed again.
+//! Some of their documents may already be written though: the checkpoint also records where language, rejected
+//! and rebuild files were when the last shard was recorded, and they are truncated back there when resuming,
+//! so that documents aren't written twice. Checkpoints saved by previous versions don't record files,
+//! and resuming from them may duplicate the documents of the shards that were being written.
+//! Neither are shards whose documents could not be written (e.g. when the disk is full):
+//! some of their documents are already written then, and the writer that failed is poisoned
+//! (see [crate::io::WriterDoc::is_poisoned]). Later shards can't be recorded either, so the run stops:
+//! shards being processed are skipped, and the run fails (without writing the manifest).
+//! Resuming truncates files back to the last recorded shard, and processes the others again.
+//! Stats of the previous runs are kept and added to.
+//!
+//! Resuming also appends to an existing corpus when processing new shards:
+//! language files continue their last part, with part numbering following the existing ones.
+//!
+//! # Deterministic output
+//! Shards are processed concurrently and their records in parallel, so documents land in language files
+//! in no particular order. With [OscarDoc::with_deterministic], shards are written in shard number order
+//! and documents in record order (shard results that arrive early are held until the previous shards are written),
+//! so that two runs over the same shards produce identical language files, rotated parts included.
+//! Documents are then serialized with sorted keys, since WARC headers are not ordered.
+//! This reduces parallelism of the write stage and holds more shards in memory, so it is opt-in.
+//!
+//! # Statistics
+//! Counts of records dropped at each step and of documents written per language are saved
+//! in `<dst>/stats.json` at the end of the run (see [Stats]), along with histograms of document identification probabilities.
+//! Line identifications can also be written in separate files, and language sizes in a CSV file
+//! (see [OutputOptions]). Records and documents that are discarded can be kept as well (see [RejectedOptions]).
+//! Language files are then listed with their sizes, line counts and checksums in `<dst>/manifest.json` (see [Manifest]).
+//! Runs using the FastText model also record its SHA-256 and the identification parameters
+//! in `<dst>/provenance.json` (see [Provenance]), so that corpora built with different models can be told apart.
+//!
+//! # Memory
+//! Shards are processed concurrently by workers (see [OscarDoc::with_workers]), and handed whole to a single
+//! writing loop, that selects their documents (see [OscarDoc::select]) and writes shards one at a time.
+//! Processed shards wait to be written in a bounded channel, whose capacity is set by
+//! [OscarDoc::with_max_pending_shards]: when writing is slower than processing (e.g. on a slow disk),
+//! workers block until there's room in the channel instead of piling shards up in memory.
+//! At most `workers + max_pending_shards + 1` processed shards are held at once, the last one being written.
+//! With deterministic output, shards that are processed before the ones preceding them are also held
+//! until those are written. Shards are then only handed to workers when they are less than
+//! `workers + max_pending_shards` shards after the next one to write, which bounds the shards held
+//! the same way while a slow shard is being processed.
+//!
+//! The writing loop streams selected documents one by one to dedicated writer threads, as many as workers,
+//! each language being always written by the same writer so that its documents stay in order.
+//! Each writer has a bounded queue of documents (see [OscarDoc::with_write_queue]): when a disk is slow,
+//! the writing loop blocks on a full queue, which in turn fills the channel of pending shards and blocks workers.
+//! Documents are freed as soon as they are written, so a shard being written doesn't have to fit in memory twice
+//! (once processed, once serialized).
+//!
+//! Processing itself still produces whole shards: a shard is only recorded in the [Checkpoint] once all of its
+//! documents are written, and seen hashes, near-duplicates, budgets and limits are applied to whole shards.
+//! The writing loop waits for writers to be done with a shard before recording it and going on with the next one,
+//! so that recorded file positions never include part of another shard.
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::str::{FromStr, Lines};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, SystemTime};
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+};
+
+use crate::error::Error;
+use crate::filtering::lang::PerLangMinBytes;
+use crate::filtering::{record, Filter};
+use crate::identifiers::identification::Identification;
+use crate::identifiers::model::{DocIdentification, FastText, FastTextBuilder, Predict};
+use crate::identifiers::{LabelMap, StrictMultilingual};
+use crate::pipelines::oscardoc::types::set_content_counts;
+use crate::pipelines::oscardoc::types::set_content_hash;
+use crate::pipelines::oscardoc::types::Checkpoint;
+use crate::pipelines::oscardoc::types::ExtraMetadata;
+use crate::pipelines::oscardoc::types::LangHintMetadata;
+use crate::pipelines::oscardoc::types::LastRun;
+use crate::pipelines::oscardoc::types::LineIds;
+use crate::pipelines::oscardoc::types::Location;
+use crate::pipelines::oscardoc::types::Manifest;
+use crate::pipelines::oscardoc::types::NearDuplicates;
+use crate::pipelines::oscardoc::types::Progress;
+use crate::pipelines::oscardoc::types::Provenance;
+use crate::pipelines::oscardoc::types::QualityMetadata;
+use crate::pipelines::oscardoc::types::RawBodies;
+use crate::pipelines::oscardoc::types::RebuildWriters;
+use crate::pipelines::oscardoc::types::SeenHashes;
+use crate::pipelines::oscardoc::types::WarcMetadata;
+use crate::pipelines::oscardoc::types::{LangCounts, ShardCounters, ShardStats, Stats, Summary};
+use oscar_io::v3::{Document, Metadata, WriterTrait};
+
+use crate::pipelines::oscardoc::types::{LocationBuilder, ShardResult};
+use crate::pipelines::pipeline::Pipeline;
+use crate::sources::commoncrawl::{IndexedRecord, MergeSameUri, ParRecords, Retry, Warc, Wet};
+
+use crate::transformers::{
+    self, rebuild_metadata, Annotate, Annotator, Category, ContentDetector, Header, Noisy,
+    ShortSentences, TinyDocument, Transform, LSH,
+};
+#[cfg(feature = "kenlm")]
+use crate::transformers::{AdultDetector, AdultDetectorBuilder, Models};
+use itertools::Itertools;
+use log::{debug, error, info, log_enabled, warn};
+use oxilangtag::LanguageTag;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use rayon::prelude::*;
+use warc::BufferedBody;
+use warc::{Record, RecordType, WarcHeader};
+
+use crate::io::{Budgets, CorpusReader};
+use crate::logging;
+
+mod dedup;
+mod output;
+mod rejected;
+mod remote;
+
+pub use dedup::DedupOptions;
+pub use output::OutputOptions;
+use rejected::Rejected;
+pub use rejected::RejectedOptions;
+pub use remote::RemoteOptions;
+
+/// Default document confidence threshold (see [OscarDoc::with_doc_threshold]).
+const DOC_THRESHOLD: f32 = 0.6f32;
+
+/// Multilingual documents language.
+const MULTI_LANG: &str = "multi";
+
+/// Extra metadata key of segmented documents (see [OscarDoc::with_sentence_segmentation]).
+pub const SEGMENTED_KEY: &str = "segmented";
+
+/// Extra metadata key of the snapshot documents come from (see [OscarDoc::with_snapshot_id]).
+pub const SNAPSHOT_KEY: &str = "snapshot";
+
+/// Rejected and low-confidence documents language (undetermined).
+const REJECTED_LANG: &str = "und";
+
+/// Extra metadata key of the number of replacement characters (see [Utf8Mode::Count]).
+const REPLACEMENT_CHARS_KEY: &str = "replacement_chars";
+
+/// Default number of documents waiting to be written by each document writer (see [OscarDoc::with_write_queue]).
+const WRITE_QUEUE: usize = 1024;
+
+/// Shard id, documents with their location, shard stats, rejected records and original bodies.
+type ShardOutput = (
+    usize,
+    Vec<(Document, Location)>,
+    ShardStats,
+    Rejected,
+    RawBodies,
+);
+
+/// A processed shard, once its documents went through selection (see [OscarDoc::select]).
+struct SelectedShard {
+    shard_id: usize,
+    /// number of documents before selection
+    nb_documents: usize,
+    /// selected documents, by language
+    documents: HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+    stats: ShardStats,
+    rejected: Rejected,
+    raw_bodies: RawBodies,
+}
+
+/// Number of written documents by language, or the errors of the languages that failed.
+type WrittenShard = Result<Vec<(String, LangCounts)>, Vec<Error>>;
+
+/// Messages to a document writer (see [OscarDoc::write_documents]).
+enum WriteMessage {
+    /// a selected document, written right away
+    Document(Box<(LanguageTag<String>, Document, Location)>),
+    /// all the documents of a shard have been sent: what was written is sent back
+    /// once its rebuild files are written
+    ShardEnd(usize, SyncSender<WrittenShard>),
+}
+
+/// State of document selection (see [OscarDoc::select]), shared by all the shards of a run.
+struct Selection {
+    seen_hashes: Option<SeenHashes>,
+    near_duplicates: Option<NearDuplicates>,
+    #[cfg(feature = "kenlm")]
+    kenlms: Models,
+    budgets: Budgets,
+    /// number of selected documents, counted against the limit (see [OscarDoc::with_limit])
+    written: AtomicUsize,
+}
+
+/// Language identification parameters.
+///
+/// * `k`: number of predictions kept for each line
+/// * `threshold`: minimal prediction confidence. Lines under it are left unidentified.
+/// * `topk_weighting`: split the bytes of each line between its `k` predictions
+///   (see [Predict::weighted_ids_topk]) rather than only counting the top-1.
+///   Document candidates are then stored in the `lid_candidates` extra metadata.
+/// * `lang_distribution`: store the share of bytes of each identified language
+///   in the `lang_distribution` extra metadata. Unidentified bytes make for the remaining share.
+/// * `line_sampling`: only identify a sample of the lines of long documents (see [LineSampling]).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LidParams {
+    pub k: i32,
+    pub threshold: f32,
+    pub topk_weighting: bool,
+    pub lang_distribution: bool,
+    pub line_sampling: Option<LineSampling>,
+}
+
+impl LidParams {
+    pub fn new(k: i32, threshold: f32) -> Self {
+        Self {
+            k,
+            threshold,
+            topk_weighting: false,
+            lang_distribution: false,
+            line_sampling: None,
+        }
+    }
+
+    /// Enable top-k weighting
+    pub fn with_topk_weighting(mut self) -> Self {
+        self.topk_weighting = true;
+        self
+    }
+
+    /// Enable storing the language distribution
+    pub fn with_lang_distribution(mut self) -> Self {
+        self.lang_distribution = true;
+        self
+    }
+
+    /// Enable line sampling
+    pub fn with_line_sampling(mut self, line_sampling: LineSampling) -> Self {
+        self.line_sampling = Some(line_sampling);
+        self
+    }
+}
+
+/// Identification of a sample of the lines of very long documents, trading a little accuracy for speed.
+///
+/// Documents with more than `min_lines` lines only get their first `head` lines identified,
+/// along with `sample` lines picked at random among the remaining ones.
+/// Unsampled lines get no identification, and the document language is chosen from the sampled lines:
+/// their byte shares stand for the whole document.
+///
+/// Lines are picked with a seed derived from the document content, so that a document is always sampled the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineSampling {
+    pub min_lines: usize,
+    pub head: usize,
+    pub sample: usize,
+}
+
+impl LineSampling {
+    pub fn new(min_lines: usize, head: usize, sample: usize) -> Self {
+        Self {
+            min_lines,
+            head,
+            sample,
+        }
+    }
+
+    /// Pick the (increasing) indices of the lines of `body` to identify,
+    /// or [None] if it is short enough to identify every line.
+    fn pick(&self, body: &str, nb_lines: usize) -> Option<Vec<usize>> {
+        if nb_lines <= self.min_lines {
+            return None;
+        }
+
+        let head = self.head.min(nb_lines);
+        let mut rng = StdRng::seed_from_u64(twox_hash::xxh3::hash64(body.as_bytes()));
+        let mut sampled: Vec<usize> =
+            rand::seq::index::sample(&mut rng, nb_lines - head, self.sample.min(nb_lines - head))
+                .into_iter()
+                .map(|idx| idx + head)
+                .collect();
+        sampled.sort_unstable();
+
+        Some((0..head).chain(sampled).collect())
+    }
+}
+
+/// How the document language is chosen from its line identifications.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DocLangStrategy {
+    /// The language with the most bytes.
+    /// Its confidence is the sum of its lines' bytes weighted by their probabilities, over the document bytes.
+    #[default]
+    MostBytes,
+    /// The language with the highest sum of its lines' bytes weighted by their probabilities,
+    /// favouring confident identifications over long but uncertain ones.
+    /// Its confidence is its share of the weighted sums of every language (unidentified bytes weighing fully).
+    WeightedProb,
+}
+
+impl DocLangStrategy {
+    /// Choose the document language from `lang_count`, that maps languages to their byte count
+    /// and their weighted byte count (divided by the document bytes).
+    ///
+    /// Returns the language, its byte count and its confidence.
+    fn choose<'a, L>(
+        &self,
+        lang_count: &'a HashMap<Option<L>, (usize, f32)>,
+    ) -> Option<(&'a Option<L>, usize, f32)> {
+        match self {
+            Self::MostBytes => lang_count
+                .iter()
+                .max_by_key(|(_, (v, _))| *v)
+                .map(|(lang, (bytes, confidence))| (lang, *bytes, *confidence)),
+            Self::WeightedProb => {
+                let total_weight: f32 = lang_count.values().map(|(_, w)| w).sum();
+                lang_count
+                    .iter()
+                    .max_by(|(_, (_, w1)), (_, (_, w2))| w1.total_cmp(w2))
+                    .map(|(lang, (bytes, weight))| {
+                        let confidence = if total_weight > 0.0 {
+                            weight / total_weight
+                        } else {
+                            0.0
+                        };
+                        (lang, *bytes, confidence)
+                    })
+            }
+        }
+    }
+}
+
+impl FromStr for DocLangStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "most-bytes" => Ok(Self::MostBytes),
+            "weighted-prob" => Ok(Self::WeightedProb),
+            other => Err(format!(
+                "unknown document language strategy {other:?} (expected most-bytes or weighted-prob)"
+            )),
+        }
+    }
+}
+
+/// How record bodies that are not valid UTF-8 are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Utf8Mode {
+    /// Invalid sequences are replaced by U+FFFD (see [String::from_utf8_lossy]).
+    #[default]
+    Lossy,
+    /// Same as [Utf8Mode::Lossy], storing the number of U+FFFD characters of documents
+    /// in their `replacement_chars` extra metadata (when there are some).
+    Count,
+    /// Records that are not valid UTF-8 are dropped, and counted as `invalid_utf8` filtered records.
+    Strict,
+}
+
+impl FromStr for Utf8Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lossy" => Ok(Self::Lossy),
+            "count" => Ok(Self::Count),
+            "strict" => Ok(Self::Strict),
+            other => Err(format!(
+                "unknown UTF-8 mode {other:?} (expected lossy, count or strict)"
+            )),
+        }
+    }
+}
+
+impl Default for LidParams {
+    /// Default values are `k=1`, `threshold=0.8`, no top-k weighting, no language distribution and no line sampling.
+    fn default() -> Self {
+        Self::new(1, 0.8)
+    }
+}
+
+// TODO: Implement structopt directly here.
+pub struct OscarDoc {
+    src: PathBuf,
+    dst: PathBuf,
+    lid_path: PathBuf,
+    lid_params: LidParams,
+    doc_lang_strategy: DocLangStrategy,
+    segmenter: Option<transformers::SentenceSegmenter>,
+    blocklist: Option<PathBuf>,
+    kenlms_path: Option<PathBuf>,
+    resume: bool,
+    incremental: Option<Duration>,
+    min_sentence_length: Option<usize>,
+    trim_mode: transformers::TrimMode,
+    content_detector: bool,
+    blocklist_categories: Option<Vec<Category>>,
+    workers: Option<usize>,
+    threads: usize,
+    max_pending_shards: Option<usize>,
+    write_queue: usize,
+    record_types: Vec<RecordType>,
+    header_filter: Option<record::HeaderFilter>,
+    allowed_langs: Option<HashSet<LanguageTag<String>>>,
+    lang_budgets: HashMap<LanguageTag<String>, u64>,
+    limit: Option<usize>,
+    max_duration: Option<Duration>,
+    min_lang_documents: Option<usize>,
+    secondary_lang_share: Option<f32>,
+    multi_threshold: Option<f32>,
+    doc_threshold: f32,
+    min_identified_bytes: usize,
+    dry_run: bool,
+    sample: Option<(usize, u64)>,
+    shard_list: Option<PathBuf>,
+    utf8_mode: Utf8Mode,
+    snapshot_id: Option<String>,
+    lang_min_bytes: Option<PerLangMinBytes>,
+    quiet: bool,
+    warc_input: bool,
+    merge_same_uri: bool,
+    deterministic: bool,
+    keep_document_language_lines: bool,
+    output: OutputOptions,
+    dedup: DedupOptions,
+    remote: RemoteOptions,
+    rejected: RejectedOptions,
+    identifier: Option<Box<dyn Predict<String> + Sync>>,
+    label_map: LabelMap,
+    min_doc_length: Option<record::MinLength>,
+    body_regex_exclude: Option<record::FilterKind>,
+    filter: Option<record::FilterKind>,
+    max_empty_shards: Option<f32>,
+    max_corrupt_ratio: Option<f32>,
+    retry: Retry,
+    generated_threshold: Option<f32>,
+    doc_lid_only: bool,
+    nfc: bool,
+    case: Option<transformers::CaseNormalizer>,
+    pii: Option<transformers::PiiScrubber>,
+    dedup_lines: Option<transformers::DedupLines>,
+    content_hash: bool,
+    normalized_content_hash: bool,
+    content_counts: bool,
+    crawl_info: bool,
+    lang_hint: bool,
+}
+
+impl OscarDoc {
+    /// Create a new pipeline.
+    ///
+    /// If `lid_params` is [None], [LidParams::default] is used.
+    pub fn new(
+        src: PathBuf,
+        dst: PathBuf,
+        lid_path: PathBuf,
+        lid_params: Option<LidParams>,
+        blocklist: Option<PathBuf>,
+        kenlms_path: Option<PathBuf>,
+    ) -> Self {
+        if blocklist.is_none() {
+            warn!("No blocklist folder specified! No adult content tagging will be done.");
+        }
+
+        debug!("using blocklist {:?}", blocklist);
+        let lid_params = lid_params.unwrap_or_default();
+        debug!("using lid parameters {:?}", lid_params);
+        Self {
+            lid_params,
+            blocklist,
+            kenlms_path,
+            ..Self::defaults(src, dst, lid_path)
+        }
+    }
+
+    /// Start building a pipeline with [OscarDocBuilder], rather than chaining `with_*` calls.
+    pub fn builder(src: PathBuf, dst: PathBuf, lid_path: PathBuf) -> OscarDocBuilder {
+        OscarDocBuilder {
+            doc: Self::defaults(src, dst, lid_path),
+        }
+    }
+
+    /// Pipeline with every option left to its default.
+    fn defaults(src: PathBuf, dst: PathBuf, lid_path: PathBuf) -> Self {
+        Self {
+            src,
+            dst,
+            lid_path,
+            lid_params: LidParams::default(),
+            doc_lang_strategy: DocLangStrategy::default(),
+            segmenter: None,
+            blocklist: None,
+            kenlms_path: None,
+            resume: false,
+            incremental: None,
+            min_sentence_length: None,
+            trim_mode: transformers::TrimMode::default(),
+            content_detector: true,
+            blocklist_categories: None,
+            workers: None,
+            threads: 0,
+            max_pending_shards: None,
+            write_queue: WRITE_QUEUE,
+            record_types: vec![RecordType::Conversion],
+            header_filter: None,
+            allowed_langs: None,
+            lang_budgets: HashMap::new(),
+            limit: None,
+            max_duration: None,
+            min_lang_documents: None,
+            secondary_lang_share: None,
+            multi_threshold: None,
+            doc_threshold: DOC_THRESHOLD,
+            min_identified_bytes: 0,
+            dry_run: false,
+            sample: None,
+            shard_list: None,
+            utf8_mode: Utf8Mode::Lossy,
+            snapshot_id: None,
+            lang_min_bytes: None,
+            quiet: false,
+            warc_input: false,
+            merge_same_uri: false,
+            deterministic: false,
+            keep_document_language_lines: false,
+            output: OutputOptions::default(),
+            dedup: DedupOptions::default(),
+            remote: RemoteOptions::default(),
+            rejected: RejectedOptions::default(),
+            identifier: None,
+            label_map: LabelMap::default(),
+            min_doc_length: None,
+            body_regex_exclude: None,
+            filter: None,
+            max_empty_shards: None,
+            max_corrupt_ratio: None,
+            retry: Retry::none(),
+            generated_threshold: None,
+            doc_lid_only: false,
+            nfc: false,
+            case: None,
+            pii: None,
+            dedup_lines: None,
+            content_hash: false,
+            normalized_content_hash: false,
+            content_counts: false,
+            crawl_info: false,
+            lang_hint: false,
+        }
+    }
+
+    /// Set how language files are laid out, and which files are written alongside them (see [OutputOptions]).
+    pub fn with_output(mut self, output: OutputOptions) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Skip duplicate documents (see [DedupOptions]).
+    pub fn with_dedup(mut self, dedup: DedupOptions) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Download shards as they get processed (see [RemoteOptions]).
+    pub fn with_remote(mut self, remote: RemoteOptions) -> Self {
+        self.remote = remote;
+        self
+    }
+
+    /// Keep records and documents that would otherwise be discarded (see [RejectedOptions]).
+    pub fn with_rejected(mut self, rejected: RejectedOptions) -> Self {
+        self.rejected = rejected;
+        self
+    }
+
+    /// Score documents on repeated phrases, a sign of machine-generated or machine-translated text
+    /// (see [transformers::GeneratedText]), storing the score in their metadata
+    /// and annotating those scoring at least `threshold` (between 0 and 1) as `generated`.
+    ///
+    /// The score is the share of the word n-grams of documents that repeat previous ones.
+    /// Documents are kept whatever their score, so that they can be filtered downstream.
+    pub fn with_generated_threshold(mut self, threshold: f32) -> Self {
+        self.generated_threshold = Some(threshold);
+        self
+    }
+
+    /// Identify documents as a whole rather than line by line.
+    ///
+    /// By default each line is identified, and the document language is the one that gets the most bytes
+    /// (see [DocLangStrategy]). Documents are then identified with a single prediction
+    /// on their whole content (see [Predict::document_id]), which is much faster on long documents.
+    /// Documents have no line identifications, so they can't be labelled as multilingual
+    /// and options relying on line identifications (line filtering, multilingual threshold, line identification files)
+    /// are rejected.
+    /// A middle ground for outliers (e.g. scraped dumps) is to only identify a sample of the lines of very long documents
+    /// (see [LineSampling]), unsampled lines then getting no identification.
+    pub fn with_doc_lid_only(mut self, doc_lid_only: bool) -> Self {
+        self.doc_lid_only = doc_lid_only;
+        self
+    }
+
+    /// Normalize document contents to NFC (see [transformers::NfcNormalizer]) before hashing and writing them.
+    ///
+    /// This is done after language identification, so that it sees the original text.
+    /// Line counts don't change, but rebuilding documents yields the original (non-normalized) text.
+    pub fn with_nfc(mut self, nfc: bool) -> Self {
+        self.nfc = nfc;
+        self
+    }
+
+    /// Lowercase or case-fold document contents, following `mode` (see [transformers::CaseNormalizer]).
+    ///
+    /// As with NFC (see [OscarDoc::with_nfc]), this is done after language identification,
+    /// since identification can be case-sensitive, and before hashing.
+    /// Folding can change the length of lines (`ß` becomes `ss`), but not their number.
+    pub fn with_case(mut self, mode: transformers::CaseMode) -> Self {
+        self.case = Some(transformers::CaseNormalizer::new(mode));
+        self
+    }
+
+    /// Mask, hash or remove email addresses and phone numbers from document contents, following `mode`
+    /// (see [transformers::PiiScrubber]).
+    ///
+    /// This is done after language identification, so that it is not affected.
+    /// Documents get the number of scrubbed matches in their metadata (`pii_emails`, `pii_phones`).
+    /// As with normalization, rebuilding documents yields the original text.
+    pub fn with_pii(mut self, mode: transformers::PiiMode) -> Self {
+        self.pii = Some(transformers::PiiScrubber::new(mode));
+        self
+    }
+
+    /// Remove lines that are repeated within a document (menu items, disclaimers...), following `mode`,
+    /// along with their identifications (see [transformers::DedupLines]).
+    ///
+    /// This is done after language identification and line filtering, so that repeated lines still count
+    /// in the document language. As with line filtering, rebuilt documents still have every line.
+    pub fn with_dedup_lines(mut self, mode: transformers::DedupLinesMode) -> Self {
+        self.dedup_lines = Some(transformers::DedupLines::new(mode));
+        self
+    }
+
+    /// Store a hash of the content of documents in their metadata (`content_hash`),
+    /// so that downstream tools can deduplicate documents across corpora without reading their content.
+    ///
+    /// Hashes are 128-bit XXH3 hashes of the UTF-8 content, in hexadecimal
+    /// (see [crate::pipelines::oscardoc::types::ContentHashMetadata]).
+    pub fn with_content_hash(mut self, content_hash: bool) -> Self {
+        self.content_hash = content_hash;
+        self
+    }
+
+    /// Hash normalized contents rather than raw ones, implying [OscarDoc::with_content_hash]:
+    /// whitespace runs are collapsed and blank lines removed before hashing,
+    /// and the hash is stored as `normalized_content_hash` instead.
+    pub fn with_normalized_content_hash(mut self, normalized_content_hash: bool) -> Self {
+        self.normalized_content_hash = normalized_content_hash;
+        self
+    }
+
+    /// Store the size in bytes and the number of lines of the content of documents in their metadata
+    /// (`content_bytes`, `content_lines`, see [crate::pipelines::oscardoc::types::ContentMetadata]),
+    /// so that corpus statistics can be built without reading contents.
+    pub fn with_content_counts(mut self, content_counts: bool) -> Self {
+        self.content_counts = content_counts;
+        self
+    }
+
+    /// Store the crawl date and target URI of documents in their metadata
+    /// (`warc_date`, `url`, see [WarcMetadata]).
+    ///
+    /// As with other extras, these are stored as `key:value` annotations, so they are opt-in.
+    pub fn with_crawl_info(mut self, crawl_info: bool) -> Self {
+        self.crawl_info = crawl_info;
+        self
+    }
+
+    /// Store the language identified by the crawler, if any, in document metadata,
+    /// along with whether it agrees with the document language (see [LangHintMetadata]).
+    pub fn with_lang_hint(mut self, lang_hint: bool) -> Self {
+        self.lang_hint = lang_hint;
+        self
+    }
+
+    /// Enable strict mode: warn about shards that had records but yielded no document,
+    /// and make the run fail if the share of such shards exceeds `max_empty_shards` (between 0 and 1).
+    ///
+    /// Stats are still saved when the run fails.
+    pub fn with_strict(mut self, max_empty_shards: f32) -> Self {
+        self.max_empty_shards = Some(max_empty_shards);
+        self
+    }
+
+    /// Abort shards whose share of corrupt records exceeds `max_corrupt_ratio` (between 0 and 1).
+    ///
+    /// Corrupt records are otherwise skipped and counted as `record_errors` in the shard [Stats].
+    /// Records failing identification are skipped too, and counted as `identification_errors`.
+    /// Both are also counted by error kind in `errors` (see [Error::kind]), e.g. to tell parsing errors from model errors.
+    /// Shards where they are too frequent are usually truncated downloads: aborted shards are not recorded
+    /// in the [Checkpoint], so that they are processed again when resuming (once downloaded again).
+    pub fn with_max_corrupt_ratio(mut self, max_corrupt_ratio: f32) -> Self {
+        self.max_corrupt_ratio = Some(max_corrupt_ratio);
+        self
+    }
+
+    /// Handle record bodies that are not valid UTF-8 following `utf8_mode` (see [Utf8Mode]).
+    ///
+    /// Bodies are decoded lossily by default, which may pollute the corpus with replacement characters.
+    pub fn with_utf8_mode(mut self, utf8_mode: Utf8Mode) -> Self {
+        self.utf8_mode = utf8_mode;
+        self
+    }
+
+    /// Discard documents that are smaller than the minimum size of their language (see [PerLangMinBytes]),
+    /// once identified and transformed, e.g. to keep short documents of low-resource languages only.
+    ///
+    /// Record filters run before identification, so they apply the same thresholds to every language.
+    /// Discarded documents are counted as `lang_min_bytes` in the filtered records of [Stats].
+    pub fn with_lang_min_bytes(mut self, lang_min_bytes: PerLangMinBytes) -> Self {
+        self.lang_min_bytes = Some(lang_min_bytes);
+        self
+    }
+
+    /// Stamp `snapshot_id` (e.g. `CC-MAIN-2023-06`) in the metadata of every document (see [SNAPSHOT_KEY]).
+    ///
+    /// Corpora built from several CommonCrawl snapshots in separate runs can then be merged
+    /// while keeping track of where documents come from.
+    pub fn with_snapshot_id(mut self, snapshot_id: String) -> Self {
+        self.snapshot_id = Some(snapshot_id);
+        self
+    }
+
+    /// Only process the shards listed in `shard_list` (one path per line) rather than every gzip
+    /// or zstd-compressed file of the source directory, e.g. a curated subset of a crawl mirror.
+    ///
+    /// Relative paths are relative to the list file, and missing shards are skipped with a warning.
+    pub fn with_shard_list(mut self, shard_list: PathBuf) -> Self {
+        self.shard_list = Some(shard_list);
+        self
+    }
+
+    /// Retry to open and read shards on transient IO errors (see [Retry]),
+    /// e.g. for shards stored on network filesystems. Remote shards downloads are retried as well (see [RemoteOptions]).
+    ///
+    /// Decoding errors (e.g. a corrupt gzip stream) are not retried.
+    pub fn with_retry(mut self, retry: Retry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Discard records whose body is shorter than `chars` characters or `bytes` bytes (see [record::MinLength]),
+    /// before any other content processing.
+    pub fn with_min_doc_length(mut self, chars: usize, bytes: usize) -> Self {
+        self.min_doc_length = Some(record::MinLength::new(chars, bytes));
+        self
+    }
+
+    /// Discard records whose body matches any of the patterns of `filter` (see [record::BodyRegexExclude]),
+    /// before any other content processing.
+    pub fn with_body_regex_exclude(mut self, filter: record::BodyRegexExclude) -> Self {
+        self.body_regex_exclude = Some(record::FilterKind::BodyRegexExclude(filter));
+        self
+    }
+
+    /// Use a custom quality filter rather than the default [record::PFilter].
+    ///
+    /// Filters can be composed with [record::FilterKind::All] and [record::FilterKind::Any],
+    /// and crates embedding ungoliant can provide their own with [record::FilterKind::custom].
+    pub fn with_filter(mut self, filter: record::FilterKind) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Use a custom language identifier rather than the FastText model at `lid_path`.
+    ///
+    /// [LidParams::k] and [LidParams::threshold] are then up to the identifier.
+    pub fn with_identifier(mut self, identifier: Box<dyn Predict<String> + Sync>) -> Self {
+        self.identifier = Some(identifier);
+        self
+    }
+
+    /// Convert the labels of the FastText model at `lid_path` with a custom mapping,
+    /// for models whose labels aren't language codes (see [LabelMap]).
+    pub fn with_label_map(mut self, label_map: LabelMap) -> Self {
+        self.label_map = label_map;
+        self
+    }
+
+    /// Remove the lines that are not identified in the document language (see [transformers::KeepDocumentLanguageLines]).
+    ///
+    /// Documents that are left with no line longer than the minimum sentence length are discarded.
+    pub fn with_keep_document_language_lines(mut self, keep_document_language_lines: bool) -> Self {
+        self.keep_document_language_lines = keep_document_language_lines;
+        self
+    }
+
+    /// Only report the number of documents and their size per language, without writing anything:
+    /// runs then return a [Summary].
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Only process `nb_shards` shards, picked at random with `seed` (see [OscarDoc::scan]).
+    ///
+    /// The same shards are picked for a given seed and source directory.
+    pub fn with_sample(mut self, nb_shards: usize, seed: u64) -> Self {
+        self.sample = Some((nb_shards, seed));
+        self
+    }
+
+    /// Read shards as raw WARC files, extracting the text of HTML responses (see [Warc]),
+    /// rather than as WET files. Other records are skipped.
+    ///
+    /// Record locations then refer to the converted records, so such runs can't be rebuilt from the shards.
+    pub fn with_warc_input(mut self, warc_input: bool) -> Self {
+        self.warc_input = warc_input;
+        self
+    }
+
+    /// Merge adjacent records that have the same target URI before any other processing (see [MergeSameUri]),
+    /// so that pages split by the crawler are identified as a single document.
+    ///
+    /// Merged records count as a single read record, and their rebuild location only points to the first one.
+    pub fn with_merge_same_uri(mut self, merge_same_uri: bool) -> Self {
+        self.merge_same_uri = merge_same_uri;
+        self
+    }
+
+    /// Don't log the progress of the run (see [Progress]).
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Write documents in shard and record order (see [the module documentation](self#deterministic-output)).
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Only write documents identified in one of `allowed_langs`.
+    ///
+    /// Multilingual documents are kept only if `multi` is allowed.
+    pub fn with_allowed_langs(mut self, allowed_langs: HashSet<LanguageTag<String>>) -> Self {
+        self.allowed_langs = Some(allowed_langs);
+        self
+    }
+
+    /// Stop writing documents of a language once its content reaches a byte budget, over the whole run.
+    ///
+    /// Languages that have no budget are unbounded. See [crate::io::LangFilesDoc::with_budgets].
+    pub fn with_lang_budgets(mut self, lang_budgets: HashMap<LanguageTag<String>, u64>) -> Self {
+        self.lang_budgets = lang_budgets;
+        self
+    }
+
+    /// Stop the run once `limit` documents have been written, over all languages,
+    /// which is handy to try a configuration quickly. Language files, stats and the manifest are written as usual.
+    ///
+    /// Room for documents is reserved before writing them so the limit itself isn't exceeded,
+    /// but shards that were being processed when it was reached still are: the run overshoots in time (and in stats)
+    /// by up to the number of workers and pending shards, not in written documents.
+    /// Documents beyond the limit are dropped before language budgets are consumed (see [OscarDoc::with_lang_budgets]),
+    /// and are counted in the `over_limit` field of the [Stats].
+    /// Shards are recorded in the [Checkpoint] anyway, so they are not processed again when resuming.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Stop processing new shards once `max_duration` has elapsed since the start of the run,
+    /// e.g. to fit in a time-limited job.
+    ///
+    /// Shards being processed are finished and written, and the run ends as usual (stats, manifest),
+    /// so that it can be resumed later (see [OscarDoc::with_resume]).
+    /// As with limits (see [OscarDoc::with_limit]), the run overshoots the deadline
+    /// by the time it takes to process in-flight shards.
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Move the files of languages that have fewer than `min_documents` documents to `<dst>/discarded`
+    /// at the end of the run, since languages with only a handful of documents are usually identification noise.
+    ///
+    /// Counts are those of the run [Stats], once every shard has been processed, and rotated parts are moved too.
+    /// Discarded languages are then not listed in the manifest, but are still counted in the stats.
+    /// Runs that stopped early or that have shards left to resume don't discard anything,
+    /// since counts are not final yet.
+    pub fn with_min_lang_documents(mut self, min_documents: usize) -> Self {
+        self.min_lang_documents = Some(min_documents);
+        self
+    }
+
+    /// Reconsider documents whose language is not allowed (see [OscarDoc::with_allowed_langs]).
+    ///
+    /// Such a document is attributed the allowed language that got the most bytes,
+    /// provided that it makes for at least `share` (between 0 and 1) of the document bytes.
+    /// That share then stands for the document confidence threshold.
+    /// Has no effect if there's no allowed languages.
+    pub fn with_secondary_lang_share(mut self, share: f32) -> Self {
+        self.secondary_lang_share = Some(share);
+        self
+    }
+
+    /// Label documents whose language makes for less than `threshold` (between 0 and 1) of their bytes
+    /// as multilingual (`multi`), rather than discarding them or keeping their main language.
+    ///
+    /// This replaces the document confidence threshold (see [OscarDoc::with_doc_threshold]).
+    pub fn with_multi_threshold(mut self, threshold: f32) -> Self {
+        self.multi_threshold = Some(threshold);
+        self
+    }
+
+    /// Set the minimum confidence (between 0 and 1) of documents. Default is 0.6.
+    ///
+    /// Documents below it are discarded, unless they are labelled as undetermined (see [RejectedOptions::with_undetermined]).
+    pub fn with_doc_threshold(mut self, threshold: f32) -> Self {
+        self.doc_threshold = threshold;
+        self
+    }
+
+    /// Discard documents with less than `min_bytes` bytes in identified lines, whatever their confidence:
+    /// a single identified token among empty or unidentified lines is not enough to label a document.
+    ///
+    /// Those documents are counted as having no language. Default is 0 (no minimum).
+    pub fn with_min_identified_bytes(mut self, min_bytes: usize) -> Self {
+        self.min_identified_bytes = min_bytes;
+        self
+    }
+
+    /// Only process records of these `WARC-Type`s, skipping others (e.g. `warcinfo` or `metadata` records)
+    /// before any other processing. Default is `conversion`, the type of WET records
+    /// (and of the records converted from WARC files, see [OscarDoc::with_warc_input]).
+    pub fn with_record_types(mut self, record_types: Vec<RecordType>) -> Self {
+        self.record_types = record_types;
+        self
+    }
+
+    /// Filter records on their WARC headers before any other processing (see [record::HeaderFilter]).
+    pub fn with_header_filter(mut self, header_filter: record::HeaderFilter) -> Self {
+        self.header_filter = Some(header_filter);
+        self
+    }
+
+    /// Split lines into sentences before identification (see [transformers::SentenceSegmenter]),
+    /// cutting sentences longer than `max_chars` codepoints.
+    ///
+    /// This changes the corpus shape: documents get one sentence per line.
+    /// Documents are tagged with a `segmented` extra metadata, holding `max_chars`,
+    /// so that they can be rebuilt the same way.
+    pub fn with_sentence_segmentation(mut self, max_chars: usize) -> Self {
+        self.segmenter = Some(transformers::SentenceSegmenter::with_max_chars(max_chars));
+        self
+    }
+
+    /// Choose the document language following `doc_lang_strategy` (see [DocLangStrategy]).
+    pub fn with_doc_lang_strategy(mut self, doc_lang_strategy: DocLangStrategy) -> Self {
+        self.doc_lang_strategy = doc_lang_strategy;
+        self
+    }
+
+    /// Run the pipeline in a dedicated rayon thread pool of `threads` threads.
+    ///
+    /// `0` (the default) uses the global thread pool, that has a thread per core.
+    /// Since the number of workers defaults to the number of threads, fewer threads also mean fewer shards in memory.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Set the number of shards processed concurrently.
+    ///
+    /// If [None], defaults to the number of threads (see [OscarDoc::with_threads]).
+    pub fn with_workers(mut self, workers: Option<usize>) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Set the maximum number of processed shards waiting to be written, i.e. the capacity of the channel
+    /// between workers and the writing loop.
+    /// Workers block when it's reached, so that slow writes hold processing back (see the module documentation).
+    ///
+    /// If [None], defaults to the number of workers.
+    pub fn with_max_pending_shards(mut self, max_pending_shards: Option<usize>) -> Self {
+        self.max_pending_shards = max_pending_shards;
+        self
+    }
+
+    /// Set the maximum number of documents waiting to be written by each document writer,
+    /// i.e. the capacity of the channel between the writing loop and the writer (see the module documentation).
+    /// The writing loop blocks when it's reached, so that slow writes hold processing back.
+    ///
+    /// Defaults to 1024.
+    pub fn with_write_queue(mut self, write_queue: usize) -> Self {
+        self.write_queue = write_queue;
+        self
+    }
+
+    /// Enable or disable the content detector (enabled by default).
+    ///
+    /// When disabled, the blocklist is ignored and documents have no categories.
+    pub fn with_content_detector(mut self, content_detector: bool) -> Self {
+        self.content_detector = content_detector;
+        self
+    }
+
+    /// Only tag documents with the provided blocklist categories, also matching their keywords
+    /// (see [ContentDetector::from_categories]).
+    pub fn with_blocklist_categories(mut self, categories: Vec<Category>) -> Self {
+        self.blocklist_categories = Some(categories);
+        self
+    }
+
+    /// Set the minimum length (in Unicode codepoints) of the sentences kept at the start/end of documents.
+    ///
+    /// Defaults to [transformers::RemoveShortSentences::default]'s one.
+    /// Documents that have no sentence longer than that are discarded.
+    ///
+    /// The default quality filter ([record::PFilter]) uses the same length to tell short and long sentences apart.
+    pub fn with_min_sentence_length(mut self, min_sentence_length: usize) -> Self {
+        self.min_sentence_length = Some(min_sentence_length);
+        self
+    }
+
+    /// Choose which short sentences are removed (see [transformers::TrimMode]). Default is at the start/end of documents.
+    ///
+    /// With [transformers::TrimMode::All], rebuild locations span from the first to the last kept sentence,
+    /// so rebuilt documents keep the short sentences in between.
+    pub fn with_trim_mode(mut self, trim_mode: transformers::TrimMode) -> Self {
+        self.trim_mode = trim_mode;
+        self
+    }
+
+    /// Resume from the checkpoint stored in `dst`, if any.
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Only process shards modified after the start of the previous run, e.g. the shards added to a mirror since last month.
+    ///
+    /// The start time of a run is recorded in `<dst>/.last_run` (see [LastRun]) once every shard has been processed,
+    /// and every shard is processed when there's none yet.
+    /// Incremental runs require [OscarDoc::with_resume], so that new documents are appended
+    /// to the existing corpus rather than replacing it.
+    /// The `grace` window moves the recorded time back, for storage whose clock is behind the local one:
+    /// shards in the window are processed again, unless they are in the [Checkpoint].
+    /// Remote shards (see [RemoteOptions]) have no modification time, so they can't be processed incrementally.
+    pub fn with_incremental(mut self, grace: Duration) -> Self {
+        self.incremental = Some(grace);
+        self
+    }
+
+    /// Check that the options make sense together.
+    ///
+    /// Shares and ratios have to be between 0 and 1, the allowlist can't be empty
+    /// and there has to be at least one worker and one pending shard.
+    /// Document-level identification can't be used with options relying on line identifications,
+    /// and line sampling can't be used with document-level identification nor line filtering
+    /// (which would remove unsampled lines).
+    /// This is done by [OscarDocBuilder::build] and before running the pipeline.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self
+            .allowed_langs
+            .as_ref()
+            .is_some_and(|langs| langs.is_empty())
+        {
+            return Err(Error::Custom(
+                "allowed languages can't be empty".to_string(),
+            ));
+        }
+
+        let shares = [
+            ("lid threshold", Some(self.lid_params.threshold)),
+            ("secondary language share", self.secondary_lang_share),
+            ("multilingual threshold", self.multi_threshold),
+            ("document threshold", Some(self.doc_threshold)),
+            ("max empty shards share", self.max_empty_shards),
+            ("max corrupt ratio", self.max_corrupt_ratio),
+            ("near-duplicate threshold", self.dedup.near_duplicates),
+            ("generated text threshold", self.generated_threshold),
+        ];
+        for (name, share) in shares {
+            if let Some(share) = share.filter(|share| !(0.0..=1.0).contains(share)) {
+                return Err(Error::Custom(format!(
+                    "{name} should be between 0 and 1 (got {share})"
+                )));
+            }
+        }
+
+        if self.remote.shard_urls.is_some() && self.shard_list.is_some() {
+            return Err(Error::Custom(
+                "shards can't be both listed and downloaded".to_string(),
+            ));
+        }
+
+        if self.remote.shard_urls.is_some() && self.incremental.is_some() {
+            return Err(Error::Custom(
+                "remote shards can't be processed incrementally".to_string(),
+            ));
+        }
+
+        // a fresh run would truncate the corpus, and then record that every shard is in it
+        if self.incremental.is_some() && !self.resume {
+            return Err(Error::Custom(
+                "incremental runs append to the corpus, and need resuming".to_string(),
+            ));
+        }
+
+        if self.remote.download_tasks == 0 {
+            return Err(Error::Custom(
+                "number of download tasks should be at least 1".to_string(),
+            ));
+        }
+
+        if self.workers == Some(0) || self.max_pending_shards == Some(0) || self.write_queue == 0 {
+            return Err(Error::Custom(
+                "number of workers, of pending shards and of queued documents should be at least 1"
+                    .to_string(),
+            ));
+        }
+
+        if self.lid_params.line_sampling.is_some()
+            && (self.doc_lid_only || self.keep_document_language_lines)
+        {
+            return Err(Error::Custom(
+                "line sampling can't be used with document-level identification or line filtering"
+                    .to_string(),
+            ));
+        }
+
+        if self.doc_lid_only
+            && (self.keep_document_language_lines
+                || self.multi_threshold.is_some()
+                || self.output.line_ids)
+        {
+            return Err(Error::Custom(
+                "document-level identification can't be used with options relying on line identifications"
+                    .to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Build the language identifier, using the pipeline's [LidParams].
+    ///
+    /// Fails right away if the model path is wrong (see [FastText::check_model]).
+    fn identifier(&self) -> Result<FastText, Error> {
+        FastText::check_model(&self.lid_path)?;
+        FastTextBuilder::default()
+            .path(&self.lid_path)
+            .k(self.lid_params.k)
+            .threshold(self.lid_params.threshold)
+            .labels(self.label_map.clone())
+            .build()
+    }
+
+    /// Build the document annotator.
+    ///
+    /// The [ContentDetector] is only added if a blocklist is provided and it is not disabled.
+    fn annotator(&self) -> Result<Annotator<Document>, Error> {
+        let mut annotator = Annotator::default();
+        annotator
+            .add(Box::new(TinyDocument::default()))
+            .add(Box::new(ShortSentences::default()))
+            .add(Box::new(Header::default()))
+            .add(Box::new(LSH::default()))
+            .add(Box::new(Noisy::default()));
+
+        if let Some(threshold) = self.generated_threshold {
+            annotator.add(Box::new(
+                transformers::GeneratedText::default().with_threshold(threshold),
+            ));
+        }
+
+        // add ut1 blocklists for categories
+        match &self.blocklist {
+            Some(path) if self.content_detector => {
+                let content_detector = match &self.blocklist_categories {
+                    Some(categories) => ContentDetector::from_categories(path, categories.clone())?,
+                    None => ContentDetector::from_path(path)?,
+                };
+                annotator.add(Box::new(content_detector));
+            }
+            Some(_) => info!("Content detector disabled, blocklist is ignored."),
+            None => (),
+        }
+
+        Ok(annotator)
+    }
+
+    /// Read the shards listed in `shard_list`, one per line (see [OscarDoc::with_shard_list]).
+    ///
+    /// Blank lines are ignored, and missing shards are skipped with a warning.
+    fn read_shard_list(shard_list: &Path) -> Result<Vec<PathBuf>, Error> {
+        let base = shard_list.parent().unwrap_or_else(|| Path::new(""));
+        let mut shards = Vec::new();
+        for line in BufReader::new(File::open(shard_list)?).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            // absolute paths are kept as is
+            let shard = base.join(line);
+            if shard.is_file() {
+                shards.push(shard);
+            } else {
+                warn!("skipping missing shard {:?}", shard);
+            }
+        }
+
+        Ok(shards)
+    }
+
+    /// list files in source folder (or in the shard list, see [OscarDoc::with_shard_list]),
+    /// filter out errors from fs and from gzip/wet.
+    ///
+    /// This means that invalid gz files and invalid
+    /// wet files are discarded silently.
+    /// Only gzip and zstd-compressed files (see [Wet::EXTENSIONS]) are kept.
+    fn get_paths_iter(&self) -> Result<impl Iterator<Item = PathBuf>, Error> {
+        let shards: Vec<PathBuf> = match (&self.remote.shard_urls, &self.shard_list) {
+            (Some(shard_urls), _) => self
+                .remote_shards(shard_urls)?
+                .into_iter()
+                .map(|(shard, _)| shard)
+                .collect(),
+            (None, Some(shard_list)) => Self::read_shard_list(shard_list)?,
+            (None, None) => std::fs::read_dir(&self.src)?
+                .filter_map(|shard| {
+                    shard.map_or_else(
+                        |e| {
+                            error!("error reading shard directory: {}", e);
+                            None
+                        },
+                        Some,
+                    )
+                })
+                .map(|shard| shard.path())
+                .collect(),
+        };
+        let mut results: Vec<PathBuf> = shards
+            .into_iter()
+            .filter(|shard| {
+                shard
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| Wet::EXTENSIONS.contains(&ext))
+            })
+            .collect();
+
+        if let Some(grace) = self.incremental {
+            if let Some(last_run) = LastRun::load(&self.dst)? {
+                results.retain(|shard| match shard.metadata().and_then(|m| m.modified()) {
+                    Ok(mtime) => last_run.is_newer(mtime, grace),
+                    Err(e) => {
+                        // let processing report the error
+                        warn!("could not get modification time of {:?}: {}", shard, e);
+                        true
+                    }
+                });
+            }
+        }
+
+        // directory listing order is arbitrary, sort before sampling
+        if let Some((nb_shards, seed)) = self.sample {
+            results.sort();
+            results.shuffle(&mut StdRng::seed_from_u64(seed));
+            results.truncate(nb_shards);
+        }
+        Ok(results.into_iter())
+    }
+
+    /// list shards that are not in the provided [Checkpoint].
+    fn get_remaining_paths_iter<'a>(
+        &self,
+        checkpoint: &'a Checkpoint,
+    ) -> Result<impl Iterator<Item = PathBuf> + 'a, Error> {
+        let results = self.get_paths_iter()?.filter(|shard| {
+            if checkpoint.contains(shard) {
+                info!("skipping completed shard {:?}", shard);
+                false
+            } else {
+                true
+            }
+        });
+        Ok(results)
+    }
+
+    /// Extract shard number from a CC shard path.
+    fn get_shard_number(shard_path: &Path) -> Result<usize, Error> {
+        let shard_number = shard_path.file_stem();
+        let shard_number = shard_number
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.split('.').next())
+            .map(|s| s.parse::<usize>());
+
+        match shard_number {
+            Some(Ok(sn)) => Ok(sn),
+            Some(Err(e)) => Err(Error::Custom(format!("{:?}", e))),
+            None => Err(Error::Custom(format!(
+                "Couldn't extract shard number from {:?}",
+                shard_path
+            ))),
+        }
+    }
+
+    /// Process a shard.
+    ///
+    /// This opens the shard, filters/identifies all documents and then
+    /// returns the shard id, along with a [Vec] of documents and their relative location (for rebuilding)
+    /// and the counts of records dropped at each step.
+    fn process_shard(
+        &self,
+        shard_path: &Path,
+        identifier: &(dyn Predict<String> + Sync),
+        filter: Option<&record::FilterKind>,
+        annotator: &Annotator<Document>,
+    ) -> Result<ShardOutput, Error> {
+        info!("working on shard: {:?}", shard_path);
+
+        // get shard number
+        let shard_id = Self::get_shard_number(shard_path)?;
+
+        let counters = ShardCounters::default();
+        let rejected = Mutex::new(Rejected::default());
+        let raw_bodies = Mutex::new(RawBodies::default());
+        let und = LanguageTag::parse(REJECTED_LANG.to_string())?;
+        let reject = |record: Record<BufferedBody>, reason: &str| {
+            if self.rejected.harvest {
+                let doc = Self::rejected_document(record, &und, reason);
+                rejected.lock().unwrap().documents.push(doc);
+            }
+        };
+
+        let records: Box<dyn Iterator<Item = _> + Send> = if self.warc_input {
+            Box::new(Warc::from_path_with_retry(shard_path, self.retry)?)
+        } else {
+            Box::new(Wet::from_path_with_retry(shard_path, self.retry)?.iter)
+        };
+        let records = records.enumerate();
+        let records: Box<dyn Iterator<Item = IndexedRecord> + Send> = if self.merge_same_uri {
+            Box::new(MergeSameUri::new(records))
+        } else {
+            Box::new(records)
+        };
+        let record_iter = ParRecords::indexed(records);
+
+        // only get valid records, print errors
+        let record_iter = record_iter.filter_map(|(idx, record)| {
+            counters.incr_records_read();
+            match record {
+                Ok(r) => {
+                    if self.output.raw_bodies {
+                        raw_bodies
+                            .lock()
+                            .unwrap()
+                            .push(r.warc_id().to_string(), r.body().to_vec());
+                    }
+                    Some((idx, r))
+                }
+                Err(e) => {
+                    error!("{:?}", e);
+                    counters.incr_record_errors();
+                    if self.rejected.harvest {
+                        rejected
+                            .lock()
+                            .unwrap()
+                            .unparseable
+                            .push(format!("{idx}\t{e}"));
+                    }
+                    counters.count_error(&Error::Warc(e));
+                    None
+                }
+            }
+        });
+
+        // skip other record types (e.g. warcinfo), before any content processing
+        let record_iter = record_iter.filter(|(_, record)| {
+            if self.record_types.contains(record.warc_type()) {
+                true
+            } else {
+                debug!(
+                    "record {} skipped: type {}",
+                    record.warc_id(),
+                    record.warc_type().to_string()
+                );
+                counters.incr_record_type_filtered();
+                false
+            }
+        });
+
+        // drop invalid UTF-8 rather than decoding it lossily, before any content processing
+        let record_iter = record_iter.filter(|(_, record)| {
+            if self.utf8_mode == Utf8Mode::Strict && std::str::from_utf8(record.body()).is_err() {
+                debug!("record {} skipped: invalid UTF-8", record.warc_id());
+                counters.incr_invalid_utf8();
+                false
+            } else {
+                true
+            }
+        });
+
+        // filter on WARC headers, before any content processing
+        let record_iter = record_iter.filter(|(_, record)| match &self.header_filter {
+            Some(header_filter) if !header_filter.detect(record) => {
+                counters.incr_header_filtered();
+                false
+            }
+            _ => true,
+        });
+
+        // filter out near-empty records
+        let min_length_filter = self.min_doc_length.map(record::FilterKind::MinLength);
+        let record_iter = record_iter.filter(|(_, record)| match &min_length_filter {
+            Some(filter) if !filter.detect(record) => {
+                counters.incr_min_length_filtered();
+                false
+            }
+            _ => true,
+        });
+
+        // filter out boilerplate
+        let record_iter = record_iter.filter(|(_, record)| match &self.body_regex_exclude {
+            Some(filter) if !filter.detect(record) => {
+                counters.incr_body_regex_filtered();
+                false
+            }
+            _ => true,
+        });
+
+        // begin creation of location
+        // We fill what we can fill now: shard_id, location_in_shard and record_id.
+        let record_iter = record_iter.map(|(idx, record)| {
+            let mut loc = LocationBuilder::default();
+            loc.set_shard_id(shard_id);
+            loc.set_loc_in_shard(idx);
+            loc.set_record_id(record.warc_id().to_string());
+
+            (loc, record)
+        });
+
+        // remove short sentences, discarding documents that only have short sentences
+        let length_filter = match self.min_sentence_length {
+            Some(min_length) => transformers::RemoveShortSentences::with_min_length(min_length),
+            None => transformers::RemoveShortSentences::default(),
+        }
+        .with_trim_mode(self.trim_mode);
+        let record_iter = record_iter.filter_map(|(mut loc, mut record)| {
+            let bounds = length_filter.transform(&mut record);
+            match bounds.len() {
+                0 => {
+                    debug!("record {} has no sentences kept", record.warc_id());
+                    counters.incr_no_sentences();
+                    None
+                }
+                1 => {
+                    loc.set_line_start(*bounds[0].start());
+                    loc.set_line_end(*bounds[0].end());
+                    Some((loc, record))
+                }
+                // interior short sentences were removed (see [OscarDoc::with_trim_mode])
+                _ => {
+                    debug!(
+                        "record {} has more than one chunk of sentences kept",
+                        record.warc_id()
+                    );
+                    loc.set_line_start(*bounds[0].start());
+                    loc.set_line_end(*bounds[bounds.len() - 1].end());
+                    Some((loc, record))
+                }
+            }
+        });
+
+        // get specified filter or resort to default filter kind
+        let default_filter = match self.min_sentence_length {
+            Some(min_length) => {
+                record::FilterKind::PFilter(record::PFilter::with_min_length(min_length))
+            }
+            None => record::FilterKind::default(),
+        };
+        let f = filter.unwrap_or(&default_filter);
+
+        // get iterator on filtered records.
+        // only get records that are valid *and* pass the filter,
+        // unless quality scores are kept instead.
+        let record_iter = record_iter.filter_map(|(idx, record)| {
+            let score = if self.rejected.quality_scores {
+                f.score(&record)
+            } else {
+                None
+            };
+            if score.is_some() || f.detect(&record) {
+                Some((idx, record, score))
+            } else {
+                counters.incr_filtered();
+                if self.rejected.harvest {
+                    // tagged by the filter that actually dropped the record
+                    let reason = f.rejecting(&record).unwrap_or(f).rejection_reason();
+                    reject(record, &reason);
+                }
+                None
+            }
+        });
+
+        // split lines into sentences
+        let record_iter = record_iter.map(|(loc, mut record, score)| {
+            if let Some(segmenter) = &self.segmenter {
+                segmenter.transform(&mut record);
+            }
+            (loc, record, score)
+        });
+
+        // identify
+        let record_iter = record_iter
+            .map(|(loc, record, score)| {
+                // keep a copy of the record in case it has no language
+                let copy = self.rejected.harvest.then(|| record.clone());
+                (loc, self.process_record(record, identifier), score, copy)
+            })
+            .filter_map(|(loc, res, score, copy)| match res {
+                Ok(Some(mut res)) => {
+                    if let Some(score) = score {
+                        res.metadata_mut().set_quality_score(score);
+                    }
+                    if let Some(segmenter) = &self.segmenter {
+                        res.metadata_mut()
+                            .add_extra(SEGMENTED_KEY, &segmenter.max_chars().to_string());
+                    }
+                    Some((loc, res))
+                }
+                Ok(None) => {
+                    counters.incr_no_language();
+                    if let Some(record) = copy {
+                        reject(record, "no_language");
+                    }
+                    None
+                }
+                Err(e) => {
+                    error!("{:?}", e);
+                    counters.incr_identification_errors();
+                    counters.count_error(&e);
+                    None
+                }
+            });
+
+        // remove lines that are not in the document language, discarding documents that have no long lines left
+        let lang_lines = match self.min_sentence_length {
+            Some(min_length) => {
+                transformers::KeepDocumentLanguageLines::with_min_length(min_length)
+            }
+            None => transformers::KeepDocumentLanguageLines::default(),
+        };
+        let record_iter = record_iter.filter_map(|(loc, mut doc)| {
+            if self.keep_document_language_lines && lang_lines.transform(&mut doc).is_empty() {
+                counters.incr_no_lang_lines();
+                None
+            } else {
+                Some((loc, doc))
+            }
+        });
+
+        // remove repeated lines, normalize content and case and scrub personal information, after identification
+        let record_iter = record_iter.map(|(loc, mut doc)| {
+            if let Some(dedup_lines) = &self.dedup_lines {
+                dedup_lines.transform(&mut doc);
+            }
+            if self.nfc {
+                transformers::NfcNormalizer.transform(&mut doc);
+            }
+            if let Some(case) = &self.case {
+                case.transform(&mut doc);
+            }
+            if let Some(pii) = &self.pii {
+                pii.transform(&mut doc);
+            }
+            if self.utf8_mode == Utf8Mode::Count {
+                let nb_replacements = doc.content().matches(char::REPLACEMENT_CHARACTER).count();
+                if nb_replacements > 0 {
+                    doc.metadata_mut()
+                        .add_extra(REPLACEMENT_CHARS_KEY, &nb_replacements.to_string());
+                }
+            }
+            (loc, doc)
+        });
+
+        // discard documents that are too small for their language, once the content is final
+        let record_iter = record_iter.filter(|(_, doc)| match &self.lang_min_bytes {
+            Some(lang_min_bytes) if !lang_min_bytes.detect(doc) => {
+                counters.incr_lang_min_bytes_filtered();
+                false
+            }
+            _ => true,
+        });
+
+        // annotate, once the content is final
+        let record_iter = record_iter.map(|(loc, mut r)| {
+            if let Some(snapshot_id) = &self.snapshot_id {
+                r.metadata_mut().add_extra(SNAPSHOT_KEY, snapshot_id);
+            }
+            if self.content_counts {
+                set_content_counts(&mut r);
+            }
+            if self.content_hash || self.normalized_content_hash {
+                set_content_hash(&mut r, self.normalized_content_hash);
+            }
+            annotator.annotate(&mut r);
+            (r, loc.build().unwrap())
+        });
+
+        // remove documents that are both tiny and noisy
+        let record_iter = record_iter.filter_map(|(r, loc): (Document, Location)| {
+            // opt-in extras (counts, hashes...) are annotations too
+            if r.metadata().tags() == ["noisy", "tiny"] {
+                debug!("removed document {:?} for noisy+tiny", r.warc_id());
+                counters.incr_noisy_tiny();
+                None
+            } else {
+                Some((r, loc))
+            }
+        });
+
+        let mut records: Vec<(_, _)> = record_iter.collect();
+        info!("Shard {}: Got {} documents", shard_id, records.len());
+
+        let mut rejected = rejected.into_inner().unwrap();
+        if self.deterministic {
+            records.sort_by_key(|(_, loc)| loc.loc_in_shard());
+            rejected
+                .documents
+                .sort_by_cached_key(|doc| doc.warc_id().into_owned());
+            rejected.unparseable.sort();
+        }
+
+        // only keep the bodies of the records that made it
+        let mut raw_bodies = raw_bodies.into_inner().unwrap();
+        if self.output.raw_bodies {
+            let kept: HashSet<String> = records
+                .iter()
+                .map(|(doc, _)| doc.warc_id().into_owned())
+                .collect();
+            raw_bodies.retain(|warc_id| kept.contains(warc_id));
+            raw_bodies.sort();
+        }
+
+        let stats = counters.into_stats(f.name());
+        if let Some(max_corrupt_ratio) = self.max_corrupt_ratio {
+            let ratio = stats.record_errors as f32 / stats.records_read.max(1) as f32;
+            if ratio > max_corrupt_ratio {
+                return Err(Error::Custom(format!(
+                    "{:?}: {} corrupt records out of {} (max ratio: {}), shard may be truncated",
+                    shard_path, stats.record_errors, stats.records_read, max_corrupt_ratio
+                )));
+            }
+        }
+
+        Ok((shard_id, records, stats, rejected, raw_bodies))
+    }
+
+    /// Add crawl date, target URI and language hint extras, if enabled.
+    fn add_crawl_extras(
+        &self,
+        metadata: &mut Metadata,
+        headers: &HashMap<WarcHeader, Vec<u8>>,
+        lang: &LanguageTag<String>,
+    ) {
+        if self.crawl_info {
+            metadata.set_warc_info(headers);
+        }
+        if self.lang_hint {
+            metadata.set_lang_hint(headers, lang);
+        }
+    }
+
+    /// Get the id for each line, the byte/prob count and the total byte count of `lines`,
+    /// as configured (see [OscarDoc::with_doc_lid_only] and [LidParams]).
+    fn identify_lines(
+        &self,
+        identifier: &dyn Predict<String>,
+        lines: Lines,
+    ) -> Result<DocIdentification<String>, Error> {
+        if self.doc_lid_only {
+            identifier.document_id(lines)
+        } else if self.lid_params.topk_weighting {
+            identifier.weighted_ids_topk(lines)
+        } else {
+            identifier.weighted_ids(lines)
+        }
+    }
+
+    /// process a record
+    /// identify each line of the document
+    /// then compute the most present identification
+    fn process_record(
+        &self,
+        record: Record<BufferedBody>,
+        identifier: &dyn Predict<String>,
+    ) -> Result<Option<Document>, Error> {
+        // get lines
+        let (headers, body) = record.into_raw_parts();
+        let body = String::from_utf8_lossy(&body);
+        let lines = body.lines();
+
+        // get the id for each line, the byte/prob count and the total byte count of the document
+        // lines are only counted when sampling, to keep a single pass over the body otherwise
+        let sampled = self.lid_params.line_sampling.and_then(|sampling| {
+            let nb_lines = body.lines().count();
+            sampling
+                .pick(&body, nb_lines)
+                .map(|sampled| (sampled, nb_lines))
+        });
+        let w_ids = match sampled {
+            Some((sampled, nb_lines)) => {
+                let lines: Vec<&str> = lines.collect();
+                let sampled_lines: Vec<&str> = sampled.iter().map(|idx| lines[*idx]).collect();
+                self.identify_lines(identifier, sampled_lines.join("\n").lines())?
+                    .spread(&sampled, nb_lines)
+            }
+            None => self.identify_lines(identifier, lines)?,
+        };
+        let ids = w_ids.line_ids();
+        let lang_count = w_ids.lang_bins();
+        let total_count = w_ids.total_size();
+
+        // too little identified text for the document confidence to mean anything
+        let identified_count: usize = lang_count
+            .iter()
+            .filter(|(lang, _)| lang.is_some())
+            .map(|(_, (count, _))| count)
+            .sum();
+        if identified_count < self.min_identified_bytes {
+            debug!(
+                "{:?}: {} identified bytes",
+                headers.headers.get(&WarcHeader::RecordID),
+                identified_count
+            );
+            return Ok(None);
+        }
+
+        //TODO fix multilingual
+        // see if the record meets multilingual criteria
+        let multilingual = StrictMultilingual::default().detect(ids);
+
+        let ids: Vec<_> = ids
+            .into_iter()
+            .map(|id| id.clone().map(|_id| _id.into_inner()))
+            .collect();
+
+        if multilingual {
+            //TODO: fix prob on multilingual documents
+            let document_identification =
+                Identification::new(LanguageTag::parse(MULTI_LANG.to_string())?, 0.5);
+
+            let mut metadata = Metadata::new(&document_identification, ids.as_slice());
+            self.add_lid_extras(&mut metadata, lang_count, total_count);
+            self.add_crawl_extras(
+                &mut metadata,
+                &headers.headers,
+                document_identification.label(),
+            );
+            let doc = Document::new(body.into_owned(), headers.headers, metadata);
+
+            return Ok(Some(doc));
+        }
+
+        // figure out document language (by default, the language that got most bytes)
+        let document_language = self.doc_lang_strategy.choose(lang_count);
+        let und = if self.rejected.undetermined {
+            Some(LanguageTag::parse(REJECTED_LANG.to_string())?)
+        } else {
+            None
+        };
+
+        // build a document and return it if the document language is not the unknown one.
+        if let Some((Some(id), lang_byte_count, confidence)) = document_language {
+            // build an Identification with prob = number of bytes from most identified language / total number of bytes
+            debug!(
+                "{:?}: {}/{} (c:{})",
+                id, lang_byte_count, total_count, confidence
+            );
+
+            // fall back on an allowed language if the document one isn't.
+            // The secondary language share then stands for the confidence threshold.
+            let secondary = if self.is_allowed(id) {
+                None
+            } else {
+                self.secondary_language(lang_count, total_count)
+            };
+            let (id, confidence) = match secondary {
+                Some((secondary_id, secondary_confidence)) => {
+                    debug!("reconsidering {:?} as {:?}", id, secondary_id);
+                    (secondary_id, secondary_confidence)
+                }
+                // documents under the multilingual threshold are kept, to be labelled as multilingual.
+                None if confidence < self.doc_threshold && self.multi_threshold.is_none() => {
+                    match &und {
+                        Some(und) => {
+                            debug!("labelling {:?} as undetermined", id);
+                            (und, confidence)
+                        }
+                        None => return Ok(None),
+                    }
+                }
+                None => (id, confidence),
+            };
+
+            // create id
+            let document_identification = Identification::new(id.clone(), confidence);
+
+            // create doc and metadata
+            let mut metadata = Metadata::new(&document_identification, ids.as_slice());
+            self.add_lid_extras(&mut metadata, lang_count, total_count);
+            self.add_crawl_extras(
+                &mut metadata,
+                &headers.headers,
+                document_identification.label(),
+            );
+            let doc = Document::new(body.into_owned(), headers.headers, metadata);
+
+            debug!("{} : {:?}", doc.warc_id(), doc.identification());
+            Ok(Some(doc))
+        } else {
+            if log_enabled!(log::Level::Debug) {
+                debug!(
+                    "{:?} : NONE",
+                    headers
+                        .headers
+                        .get(&WarcHeader::RecordID)
+                        .map(|x| Some(String::from_utf8_lossy(x)))
+                );
+                debug!("{:?}", &lang_count);
+                debug!("{}", &body);
+            }
+            Ok(None)
+        }
+    }
+
+    /// Is the language allowed? Always true if there are no allowed languages.
+    fn is_allowed(&self, lang: &LanguageTag<String>) -> bool {
+        self.allowed_langs
+            .as_ref()
+            .is_none_or(|allowed_langs| allowed_langs.contains(lang))
+    }
+
+    /// Get the allowed language that got the most bytes and its confidence,
+    /// if it makes for at least [OscarDoc::with_secondary_lang_share] of the document.
+    fn secondary_language<'a>(
+        &self,
+        lang_count: &'a HashMap<Option<LanguageTag<String>>, (usize, f32)>,
+        total_count: usize,
+    ) -> Option<(&'a LanguageTag<String>, f32)> {
+        let (share, allowed_langs) = self.secondary_lang_share.zip(self.allowed_langs.as_ref())?;
+        lang_count
+            .iter()
+            .filter_map(|(lang, counts)| lang.as_ref().map(|lang| (lang, counts)))
+            .filter(|(lang, _)| allowed_langs.contains(*lang))
+            .max_by_key(|(_, (v, _))| *v)
+            .filter(|(_, (v, _))| total_count > 0 && *v as f32 / total_count as f32 >= share)
+            .map(|(lang, (_, confidence))| (lang, *confidence))
+    }
+
+    /// Add language identification extra metadata, depending on [LidParams].
+    fn add_lid_extras(
+        &self,
+        metadata: &mut Metadata,
+        lang_count: &HashMap<Option<LanguageTag<String>>, (usize, f32)>,
+        total_count: usize,
+    ) {
+        if self.lid_params.topk_weighting {
+            metadata.add_extra("lid_candidates", &Self::lid_candidates(lang_count));
+        }
+        if self.lid_params.lang_distribution {
+            metadata.add_extra(
+                "lang_distribution",
+                &Self::lang_distribution(lang_count, total_count),
+            );
+        }
+    }
+
+    /// Format document language candidates as `label=prob` pairs, ordered by decreasing byte count.
+    fn lid_candidates(lang_count: &HashMap<Option<LanguageTag<String>>, (usize, f32)>) -> String {
+        lang_count
+            .iter()
+            .filter_map(|(lang, counts)| lang.as_ref().map(|lang| (lang, counts)))
+            .sorted_by(|(_, (a, _)), (_, (b, _))| b.cmp(a))
+            .map(|(lang, (_, prob))| format!("{lang}={prob:.4}"))
+            .join(",")
+    }
+
+    /// Format the share of bytes of each identified language as `label=share` pairs, ordered by decreasing share.
+    fn lang_distribution(
+        lang_count: &HashMap<Option<LanguageTag<String>>, (usize, f32)>,
+        total_count: usize,
+    ) -> String {
+        lang_count
+            .iter()
+            .filter_map(|(lang, (count, _))| lang.as_ref().map(|lang| (lang, count)))
+            .sorted_by(|(_, a), (_, b)| b.cmp(a))
+            .map(|(lang, count)| {
+                let share = if total_count > 0 {
+                    *count as f32 / total_count as f32
+                } else {
+                    0.0
+                };
+                format!("{lang}={share:.4}")
+            })
+            .join(",")
+    }
+
+    /// Share of the bytes of `document` that are in lines identified in the document language.
+    fn lang_share(document: &Document) -> f32 {
+        let label = document.identification().label();
+        let ids = document.metadata().sentence_identifications();
+        let (lang_bytes, total_bytes) = document.content().lines().zip(ids).fold(
+            (0, 0),
+            |(lang_bytes, total_bytes), (line, id)| {
+                if id.as_ref().map(|id| id.label()) == Some(label) {
+                    (lang_bytes + line.len(), total_bytes + line.len())
+                } else {
+                    (lang_bytes, total_bytes + line.len())
+                }
+            },
+        );
+        if total_bytes == 0 {
+            0.0
+        } else {
+            lang_bytes as f32 / total_bytes as f32
+        }
+    }
+
+    /// Gets a vector of documents and outputs a hashmap listing the documents per language
+    ///
+    /// If `allowed_langs` is provided, documents of other languages are discarded.
+    /// If `multi_threshold` is provided, documents whose language makes for less than this share of their bytes
+    /// are labelled as multilingual (see [OscarDoc::with_multi_threshold]).
+    fn sort_by_lang(
+        documents: Vec<(Document, Location)>,
+        allowed_langs: Option<&HashSet<LanguageTag<String>>>,
+        multi_threshold: Option<f32>,
+    ) -> HashMap<LanguageTag<String>, Vec<(Document, Location)>> {
+        let multi = LanguageTag::parse(MULTI_LANG.to_string()).expect("invalid multilingual tag");
+        let mut ret = HashMap::new();
+        for (mut document, location) in documents.into_iter() {
+            if let Some(threshold) = multi_threshold {
+                let share = Self::lang_share(&document);
+                if *document.identification().label() != multi && share < threshold {
+                    let id = oscar_io::common::Identification::new(multi.clone(), share);
+                    let ids = document.metadata().sentence_identifications().to_vec();
+                    let metadata = rebuild_metadata(&id, document.metadata(), &ids);
+                    *document.metadata_mut() = metadata;
+                }
+            }
+            if let Some(allowed_langs) = allowed_langs {
+                if !allowed_langs.contains(document.identification().label()) {
+                    continue;
+                }
+            }
+            let e = ret
+                .entry(document.identification().label().clone()) //TODO: since we take ownership of documents, we could avoid cloning and taking value itself.
+                .or_insert_with(Vec::new);
+            e.push((document, location));
+        }
+
+        ret
+    }
+
+    /// run kenlm models on data, adding perplexity.
+    #[cfg(feature = "kenlm")]
+    fn run_kenlms(
+        models: &Models,
+        base_model_path: &Path,
+        documents: &mut HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+    ) {
+        debug!("Running kenlms");
+        for (lang, docs) in documents {
+            // attempt to load model for provided lang.
+            // It's okay if it's not possible.
+            if !models.is_loaded(lang) {
+                if let Err(e) = models.load(lang) {
+                    debug!("Couldn't load model for lang {lang:?}: {e:?}");
+                }
+            }
+
+            // TODO: Possible problem here, if between load and get the HM is modified.
+            // Add a way of dealing with that?
+            // possibly creating a scope and then using "direct" method calls rather than
+            // calls that use read/write locks internally.
+            if let Some(model) = models.models().get(lang.as_ref()) {
+                let model = model.read().unwrap();
+                for (doc, _) in docs {
+                    model.annotate(doc);
+                }
+            } else {
+                error!("Could not annotate using model {lang}: No model");
+            }
+        }
+    }
+
+    /// Drop the documents beyond the `limit` and those of languages that reached their budget
+    /// (see [OscarDoc::with_lang_budgets]).
+    ///
+    /// If there's a `limit`, room is reserved in `written` for the kept documents, so that concurrent shards
+    /// don't go past it. The limit is applied first, so that budgets are only consumed by written documents.
+    /// Returns the number of documents dropped because of budgets and because of the limit.
+    fn drop_over_limits(
+        budgets: &Budgets,
+        documents: &mut HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+        written: &AtomicUsize,
+        limit: Option<usize>,
+    ) -> (usize, usize) {
+        let mut over_budget = 0;
+        let mut over_limit = 0;
+        for (lang, docs) in documents.iter_mut() {
+            let mut reserved = docs.len();
+            if let Some(limit) = limit {
+                let before = written
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |written| {
+                        Some(written + docs.len().min(limit.saturating_sub(written)))
+                    })
+                    .unwrap_or_else(|before| before);
+                reserved = docs.len().min(limit.saturating_sub(before));
+                let nb_over_limit = docs.len() - reserved;
+                if nb_over_limit > 0 {
+                    debug!("[{}]: {} documents over the limit", lang, nb_over_limit);
+                    docs.truncate(reserved);
+                    over_limit += nb_over_limit;
+                }
+            }
+
+            docs.retain(|(doc, _)| budgets.consume(lang, doc.content().len() as u64));
+            let nb_over_budget = reserved - docs.len();
+            if nb_over_budget > 0 {
+                debug!("[{}]: {} documents over budget", lang, nb_over_budget);
+                // give back the room of dropped documents
+                if limit.is_some() {
+                    written.fetch_sub(nb_over_budget, Ordering::SeqCst);
+                }
+                over_budget += nb_over_budget;
+            }
+        }
+        documents.retain(|_, docs| !docs.is_empty());
+
+        (over_budget, over_limit)
+    }
+
+    /// Build the selection state of a run (see [OscarDoc::select]), checking seen documents against `seen_hashes`.
+    fn selection(&self, seen_hashes: Option<SeenHashes>) -> Result<Selection, Error> {
+        let near_duplicates = self.near_duplicates()?;
+        #[cfg(feature = "kenlm")]
+        let kenlms = if let Some(kenlms_path) = &self.kenlms_path {
+            if !kenlms_path.is_dir() {
+                panic!("KenLMs path must exist and be a dir! {kenlms_path:?}");
+            }
+            Models::from_dir(kenlms_path)?
+        } else {
+            /*  TODO: Remove panic here.
+                We should either:
+                    - Have an "appendable" switch which enables Models to find binaries at runtime (with write lock cost)
+                    - Crash on no kenlms provided OR have a warning to indicate that no kenlm annotations will be done.
+            */
+            panic!("No kenlms path provided but feature turned on!");
+        };
+
+        Ok(Selection {
+            seen_hashes,
+            near_duplicates,
+            #[cfg(feature = "kenlm")]
+            kenlms,
+            budgets: Budgets::new(self.lang_budgets.clone()),
+            written: AtomicUsize::new(0),
+        })
+    }
+
+    /// Select the documents of a processed shard, sorting them by language (see [OscarDoc::sort_by_lang]):
+    /// near-duplicate and seen documents are removed, kenlm annotations are added,
+    /// and documents over budgets and the limit are dropped. The number of dropped documents is set in `stats`.
+    ///
+    /// Shards are selected one at a time, in the order they are consumed, so that the first of duplicates is kept.
+    /// Selected documents are not recorded as seen: see [OscarDoc::content_hashes].
+    fn select(
+        &self,
+        selection: &mut Selection,
+        documents: Vec<(Document, Location)>,
+        stats: &mut ShardStats,
+    ) -> HashMap<LanguageTag<String>, Vec<(Document, Location)>> {
+        let nb_documents = documents.len();
+        let mut hm =
+            Self::sort_by_lang(documents, self.allowed_langs.as_ref(), self.multi_threshold);
+        // before recording hashes, so that near-duplicates aren't recorded as seen
+        if let Some(near_duplicates) = &mut selection.near_duplicates {
+            stats.near_duplicates = Self::remove_near_duplicates(near_duplicates, &mut hm);
+        }
+        if let Some(seen_hashes) = &selection.seen_hashes {
+            stats.seen = Self::remove_seen(seen_hashes, &mut hm);
+        }
+        stats.excluded_lang = nb_documents
+            - stats.seen
+            - stats.near_duplicates
+            - hm.values().map(|docs| docs.len()).sum::<usize>();
+
+        // run kenlms after identification so that shard results are already
+        // sorted by language.
+        #[cfg(feature = "kenlm")]
+        if let Some(kenlms_path) = &self.kenlms_path {
+            Self::run_kenlms(&selection.kenlms, kenlms_path, &mut hm);
+        }
+
+        // before hashing, so that dropped documents aren't recorded as seen
+        let (over_budget, over_limit) =
+            Self::drop_over_limits(&selection.budgets, &mut hm, &selection.written, self.limit);
+        stats.over_budget = over_budget;
+        stats.over_limit = over_limit;
+
+        hm
+    }
+
+    /// Index of the document writer of `lang` among `nb_writers` (see [OscarDoc::write_documents]).
+    ///
+    /// A language is always written by the same writer, so that its documents are written in order.
+    fn writer_idx(lang: &LanguageTag<String>, nb_writers: usize) -> usize {
+        let mut hasher = DefaultHasher::new();
+        lang.as_str().hash(&mut hasher);
+        (hasher.finish() % nb_writers as u64) as usize
+    }
+
+    /// Write the documents received from `documents` as they come, with `write`, until the channel is closed.
+    /// Run by dedicated writer threads (see [OscarDoc::send_documents]).
+    ///
+    /// The locations of the documents of a shard are kept until the end of the shard (see [WriteMessage::ShardEnd]),
+    /// when they are appended to rebuild files and the number of written documents by language is sent back.
+    /// A language whose write failed skips the remaining documents of the shard, and its error is sent back instead.
+    fn write_documents<'a, W>(
+        avrowriters: &'a RebuildWriters<'a, File>,
+        rebuild_root_dir: &Path,
+        documents: Receiver<WriteMessage>,
+        mut write: W,
+    ) where
+        W: FnMut(&LanguageTag<String>, Document) -> Result<(), Error>,
+    {
+        // locations, metadata and counts of written documents by language, or the write error
+        type Written = Result<(Vec<Location>, Vec<Metadata>, LangCounts), Error>;
+        let mut shard: HashMap<LanguageTag<String>, Written> = HashMap::new();
+
+        for message in documents {
+            match message {
+                WriteMessage::Document(document) => {
+                    let (lang, doc, location) = *document;
+                    let written = shard
+                        .entry(lang.clone())
+                        .or_insert_with(|| Ok(Default::default()));
+                    if let Ok((locations, metadata, counts)) = written {
+                        counts.add_document(&doc);
+                        metadata.push(doc.metadata().clone());
+                        locations.push(location);
+                        if let Err(e) = write(&lang, doc) {
+                            *written = Err(Error::Write(lang, Box::new(e)));
+                        }
+                    }
+                }
+                WriteMessage::ShardEnd(shard_id, done) => {
+                    let mut nb_written = Vec::new();
+                    let mut errors = Vec::new();
+                    for (lang, written) in shard.drain() {
+                        let rebuild = |locations, metadata| -> Result<(), Error> {
+                            if !avrowriters.contains(&lang) {
+                                avrowriters.insert(rebuild_root_dir, &lang)?;
+                            }
+                            let avrowriters_lock = avrowriters.writers();
+                            let avrowriter = avrowriters_lock.get(&lang).ok_or_else(|| {
+                                Error::Custom(format!("no rebuild writer for {}", lang))
+                            })?;
+                            let mut avrowriter_lock = avrowriter.lock().unwrap();
+                            let mut sr = ShardResult::new(shard_id as i64, locations, metadata);
+                            sr.sort();
+                            avrowriter_lock.append_ser(sr)?;
+
+                            //TODO: not sure that we need the flush
+                            avrowriter_lock.flush()?;
+                            Ok(())
+                        };
+                        match written.and_then(|(locations, metadata, counts)| {
+                            info!("[{}]: {} documents", lang, locations.len());
+                            rebuild(locations, metadata)
+                                .map_err(|e| Error::Write(lang.clone(), Box::new(e)))?;
+                            Ok(counts)
+                        }) {
+                            Ok(counts) => nb_written.push((lang.to_string(), counts)),
+                            Err(e) => errors.push(e),
+                        }
+                    }
+                    let result = if errors.is_empty() {
+                        Ok(nb_written)
+                    } else {
+                        Err(errors)
+                    };
+                    // the writing loop only stops waiting once every writer answered
+                    let _ = done.send(result);
+                }
+            }
+        }
+    }
+
+    /// Send the documents of a shard to the document `writers` (see [OscarDoc::write_documents]), one by one,
+    /// and wait for them to be written.
+    ///
+    /// Sending blocks while the queue of a writer is full, holding the writing loop back (see [OscarDoc::with_write_queue]).
+    /// Returns the number of written documents by language.
+    /// Errors are logged, and an error is returned if at least one language failed.
+    fn send_documents(
+        writers: &[SyncSender<WriteMessage>],
+        shard_id: usize,
+        documents: HashMap<LanguageTag<String>, Vec<(Document, Location)>>,
+    ) -> Result<Vec<(String, LangCounts)>, Error> {
+        let stopped = || Error::Custom("document writers stopped".to_string());
+        for (lang, docs) in documents.into_iter().sorted_by(|(a, _), (b, _)| a.cmp(b)) {
+            let writer = &writers[Self::writer_idx(&lang, writers.len())];
+            for (doc, location) in docs {
+                writer
+                    .send(WriteMessage::Document(Box::new((
+                        lang.clone(),
+                        doc,
+                        location,
+                    ))))
+                    .map_err(|_| stopped())?;
+            }
+        }
+
+        let (done, results) = std::sync::mpsc::sync_channel(writers.len());
+        for writer in writers {
+            writer
+                .send(WriteMessage::ShardEnd(shard_id, done.clone()))
+                .map_err(|_| stopped())?;
+        }
+        drop(done);
+
+        let mut nb_written = Vec::new();
+        let mut errors = Vec::new();
+        for result in results {
+            match result {
+                Ok(lang_written) => nb_written.extend(lang_written),
+                Err(lang_errors) => errors.extend(lang_errors),
+            }
+        }
+
+        match errors.len() {
+            0 => return Ok(nb_written),
+            // a single failing language is reported as is
+            1 => return Err(errors.remove(0)),
+            _ => (),
+        }
+
+        for error in &errors {
+            error!("{:?}", error);
+        }
+        Err(Error::Custom(format!(
+            "{} errors while writing shard {}",
+            errors.len(),
+            shard_id
+        )))
+    }
+
+    /// Number of shards processed concurrently (see [OscarDoc::with_workers]).
+    fn nb_workers(&self) -> usize {
+        self.workers.unwrap_or(match self.threads {
+            0 => rayon::current_num_threads(),
+            threads => threads,
+        })
+    }
+
+    /// Run `op` in `pool`, or in the current thread pool if there's none.
+    fn install<R: Send>(pool: Option<&rayon::ThreadPool>, op: impl FnOnce() -> R + Send) -> R {
+        match pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+
+    /// Process `shards` concurrently (see [OscarDoc::with_workers]),
+    /// passing each shard result to `consume` on the calling thread, in completion order.
+    /// Shards are counted in `progress` once consumed, and no new shard is processed once `progress` is stopped.
+    ///
+    /// Both processing and `consume` run in the pipeline's thread pool (see [OscarDoc::with_threads]).
+    fn process_shards<F>(
+        &self,
+        shards: Vec<PathBuf>,
+        identifier: &(dyn Predict<String> + Sync),
+        annotator: &Annotator<Document>,
+        progress: &Progress,
+        mut consume: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(usize, PathBuf, Result<ShardOutput, Error>) + Send,
+    {
+        let mut shards = shards;
+        if self.deterministic {
+            shards.sort_by_cached_key(|shard| (Self::get_shard_number(shard).ok(), shard.clone()));
+        }
+
+        let pool = if self.threads > 0 {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(self.threads)
+                .build()
+                .map_err(|e| Error::Custom(format!("could not build thread pool: {e}")))?;
+            Some(pool)
+        } else {
+            None
+        };
+        let pool = pool.as_ref();
+
+        // remote shards get downloaded before being processed
+        let downloads = self.downloads()?;
+        let remote = downloads.is_some();
+        let (download_sender, download_receiver) =
+            std::sync::mpsc::sync_channel(self.remote.download_tasks);
+        let downloaded = Mutex::new(Some(download_receiver));
+
+        let workers = self.nb_workers();
+        let max_pending_shards = self.max_pending_shards.unwrap_or(workers);
+        info!(
+            "processing shards with {} workers, {} pending shards max",
+            workers, max_pending_shards
+        );
+        let (sender, receiver) = std::sync::mpsc::sync_channel(max_pending_shards);
+
+        // with deterministic output, shards are only handed out when they are within a window
+        // of the next one to consume, so that shards processed early don't pile up waiting for it.
+        // Holds the index of the next shard to consume, or None once consuming is over.
+        let window = workers + max_pending_shards;
+        let consumed = (Mutex::new(Some(0)), Condvar::new());
+        let set_consumed = |next_idx| {
+            *consumed.0.lock().unwrap() = next_idx;
+            consumed.1.notify_all();
+        };
+
+        let shards = Mutex::new(shards.into_iter().enumerate());
+        // get the next shard, releasing the lock right away
+        let next_shard = || {
+            if progress.stopped() {
+                return None;
+            }
+            let (idx, shard) = shards.lock().unwrap().next()?;
+            if self.deterministic {
+                let next_idx = consumed
+                    .1
+                    .wait_while(consumed.0.lock().unwrap(), |next_idx| {
+                        matches!(next_idx, Some(next_idx) if idx >= *next_idx + window)
+                            && !progress.stopped()
+                    })
+                    .unwrap();
+                if next_idx.is_none() || progress.stopped() {
+                    return None;
+                }
+            }
+            Some((idx, shard))
+        };
+
+        std::thread::scope(|scope| {
+            // download remote shards
+            if let Some(downloads) = &downloads {
+                self.spawn_downloads(scope, downloads, &next_shard, download_sender);
+            } else {
+                drop(download_sender);
+            }
+
+            // process shards
+            for _ in 0..workers {
+                let sender = sender.clone();
+                let (next_shard, downloaded) = (&next_shard, &downloaded);
+                scope.spawn(move || {
+                    let next = || {
+                        if !remote {
+                            next_shard().map(|(idx, shard)| (idx, shard, Ok(())))
+                        } else if progress.stopped() {
+                            None
+                        } else {
+                            downloaded.lock().unwrap().as_ref()?.recv().ok()
+                        }
+                    };
+                    while let Some((idx, shard, download)) = next() {
+                        let shard_result = download.and_then(|_| {
+                            Self::install(pool, || {
+                                logging::with_shard(&shard, || {
+                                    self.process_shard(
+                                        &shard,
+                                        identifier,
+                                        self.filter.as_ref(),
+                                        annotator,
+                                    )
+                                })
+                            })
+                        });
+                        if remote {
+                            Self::remove_download(&shard);
+                        }
+
+                        // blocks if there are too many pending shards
+                        if sender.send((idx, shard, shard_result)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            if self.deterministic {
+                // hold shard results until the ones before them are consumed
+                let mut pending = BTreeMap::new();
+                let mut next_idx = 0;
+                for (idx, shard, shard_result) in receiver {
+                    pending.insert(idx, (shard, shard_result));
+                    while let Some((shard, shard_result)) = pending.remove(&next_idx) {
+                        Self::install(pool, || consume(next_idx, shard, shard_result));
+                        progress.incr();
+                        next_idx += 1;
+                        set_consumed(Some(next_idx));
+                    }
+                }
+            } else {
+                for (idx, shard, shard_result) in receiver {
+                    Self::install(pool, || consume(idx, shard, shard_result));
+                    progress.incr();
+                }
+            }
+
+            // workers are done: unblock downloads, if they were stopped early
+            set_consumed(None);
+            downloaded.lock().unwrap().take();
+        });
+
+        // downloaded shards that weren't processed are kept for resumed runs
+        if remote {
+            let _ = std::fs::remove_dir(self.download_dir());
+        }
+
+        Ok(())
+    }
+
+    /// Process `shards` (see [OscarDoc::process_shards]), passing each one to `consume`
+    /// once its documents went through selection (see [OscarDoc::select]), along with the selection state.
+    ///
+    /// Runs and document streams both build on this (see [OscarDoc::run_with] and [OscarDoc::documents_with]).
+    /// No new shard is processed once the limit is reached (see [OscarDoc::with_limit]).
+    fn selected_shards<F>(
+        &self,
+        shards: Vec<PathBuf>,
+        identifier: &(dyn Predict<String> + Sync),
+        annotator: &Annotator<Document>,
+        progress: &Progress,
+        selection: &mut Selection,
+        mut consume: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(usize, PathBuf, Result<SelectedShard, Error>, &Selection) + Send,
+    {
+        self.process_shards(
+            shards,
+            identifier,
+            annotator,
+            progress,
+            |idx, shard, shard_result| {
+                let shard_result =
+                    shard_result.map(|(shard_id, documents, mut stats, rejected, raw_bodies)| {
+                        let nb_documents = documents.len();
+                        let documents = self.select(selection, documents, &mut stats);
+                        SelectedShard {
+                            shard_id,
+                            nb_documents,
+                            documents,
+                            stats,
+                            rejected,
+                            raw_bodies,
+                        }
+                    });
+                consume(idx, shard, shard_result, selection);
+
+                if self
+                    .limit
+                    .is_some_and(|limit| selection.written.load(Ordering::SeqCst) >= limit)
+                {
+                    progress.stop();
+                }
+            },
+        )
+    }
+
+    /// Progress of a run over `nb_shards` shards, stopped at the deadline if there's one
+    /// (see [OscarDoc::with_max_duration]).
+    fn progress(&self, nb_shards: usize) -> Progress {
+        let progress = Progress::new(nb_shards, self.quiet);
+        match self.max_duration {
+            Some(max_duration) => progress.with_deadline(max_duration),
+            None => progress,
+        }
+    }
+
+    /// Process shards, passing identified and annotated documents to `consume` instead of writing them.
+    ///
+    /// Documents go through the same steps as in a regular run, selection included (see [OscarDoc::select]):
+    /// `consume` gets the documents a run would write. Shard errors are logged and skipped.
+    /// Documents of a shard are passed language by language.
+    ///
+    /// The checkpoint is honoured if resuming, but isn't updated.
+    /// Seen hashes (see [DedupOptions::with_seen_hashes]) are checked, but passed documents are only recorded in memory.
+    fn documents_with<F>(
+        &self,
+        identifier: &(dyn Predict<String> + Sync),
+        mut consume: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(Document) + Send,
+    {
+        let checkpoint = if self.resume {
+            Checkpoint::load(&self.dst)?
+        } else {
+            Checkpoint::default()
+        };
+        let shards: Vec<PathBuf> = self.get_remaining_paths_iter(&checkpoint)?.collect();
+        let annotator = self.annotator()?;
+        let progress = self.progress(shards.len());
+        let seen_hashes = self
+            .dedup
+            .seen_hashes
+            .as_deref()
+            .map(SeenHashes::read_only)
+            .transpose()?;
+        let mut selection = self.selection(seen_hashes)?;
+
+        self.selected_shards(
+            shards,
+            identifier,
+            &annotator,
+            &progress,
+            &mut selection,
+            |idx, _, shard_result, selection| match shard_result {
+                Ok(shard) => {
+                    let new_hashes = selection
+                        .seen_hashes
+                        .as_ref()
+                        .map(|_| Self::content_hashes(&shard.documents));
+                    for (_, docs) in shard
+                        .documents
+                        .into_iter()
+                        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+                    {
+                        for (doc, _) in docs {
+                            consume(doc);
+                        }
+                    }
+                    if let (Some(seen_hashes), Some(new_hashes)) =
+                        (&selection.seen_hashes, new_hashes)
+                    {
+                        if let Err(e) = seen_hashes.extend(new_hashes) {
+                            error!("Error recording hashes of shard idx {}: {:?}", idx, e);
+                        }
+                    }
+                }
+                Err(e) => error!("Error with shard idx {}:{:?}", idx, e),
+            },
+        )?;
+        progress.finish();
+
+        Ok(())
+    }
+
+    /// Process shards, passing identified and annotated documents to `consume` instead of writing them,
+    /// so that processing can be embedded without going through files.
+    ///
+    /// Nothing is written in the destination folder. See [OscarDoc::documents_with] for details.
+    pub fn for_each_document<F>(&self, consume: F) -> Result<(), Error>
+    where
+        F: FnMut(Document) + Send,
+    {
+        self.validate()?;
+        match &self.identifier {
+            Some(identifier) => self.documents_with(identifier.as_ref(), consume),
+            None => {
+                let cls = self.identifier()?;
+                self.documents_with(&cls, consume)
+            }
+        }
+    }
+
+    /// Process shards without writing anything, and get the number of documents and their size per language.
+    fn dry_run_with(&self, identifier: &(dyn Predict<String> + Sync)) -> Result<Summary, Error> {
+        let mut summary = Summary::default();
+        self.documents_with(identifier, |doc| {
+            // size of the JSONL line that would have been written
+            match serde_json::to_string(&doc) {
+                Ok(line) => summary.add(doc.identification().label().as_str(), line.len() + 1),
+                Err(e) => error!("Error serializing document: {:?}", e),
+            }
+        })?;
+
+        Ok(summary)
+    }
+
+    /// Get the languages present in the source shards and their sizes, without writing anything.
+    ///
+    /// Use [OscarDoc::with_sample] to only process some shards and get an estimate.
+    pub fn scan(&self) -> Result<Summary, Error> {
+        self.validate()?;
+        match &self.identifier {
+            Some(identifier) => self.dry_run_with(identifier.as_ref()),
+            None => self.dry_run_with(&self.identifier()?),
+        }
+    }
+
+    /// Run the pipeline with the provided identifier.
+    ///
+    /// Shards are processed by [OscarDoc::with_workers] workers, that send their results
+    /// to the (unique) writing loop through a bounded channel.
+    /// The writing loop consumes shards once selected (see [OscarDoc::selected_shards]), as document streams do.
+    /// Workers block when [OscarDoc::with_max_pending_shards] shard results are waiting to be written,
+    /// bounding the number of shards held in memory.
+    ///
+    /// Dry runs (see [OscarDoc::with_dry_run]) return the [Summary] of what would have been written.
+    fn run_with(
+        &self,
+        identifier: &(dyn Predict<String> + Sync),
+    ) -> Result<Option<Summary>, Error> {
+        if self.dry_run {
+            return self.dry_run_with(identifier).map(Some);
+        }
+
+        if !self.dst.exists() {
+            warn!("Destination file does not exist. Creating");
+            std::fs::create_dir(&self.dst)?;
+        }
+
+        if !self.dst.is_dir() {
+            panic!("Destination has to be a directory: {:?}", self.dst);
+        }
+        // shards modified during the run are processed by the next one
+        let started = SystemTime::now();
+        let mut checkpoint = if self.resume {
+            info!("resuming from {:?}", Checkpoint::path(&self.dst));
+            Checkpoint::load(&self.dst)?
+        } else {
+            // overwrite any previous checkpoint and stats, since previous files are going to be truncated
+            let checkpoint = Checkpoint::new();
+            checkpoint.save(&self.dst)?;
+            if Stats::path(&self.dst).exists() {
+                std::fs::remove_file(Stats::path(&self.dst))?;
+            }
+            checkpoint
+        };
+        let shards: Vec<PathBuf> = self.get_remaining_paths_iter(&checkpoint)?.collect();
+
+        let langfiles = self.langfiles(&self.dst);
+        let langfiles = if self.resume {
+            langfiles.appending()
+        } else {
+            langfiles
+        };
+        let langfiles = match &self.allowed_langs {
+            Some(allowed_langs) => langfiles.with_allowed_langs(allowed_langs.clone()),
+            None => langfiles,
+        };
+        let annotator = self.annotator()?;
+
+        let mut dst_rebuild = self.dst.clone();
+        dst_rebuild.push("rebuild");
+
+        // drop what was written after the last recorded shard
+        let files = checkpoint.files().cloned();
+        if self.resume {
+            match &files {
+                Some(files) => {
+                    langfiles.truncate(&files.langs)?;
+                    RebuildWriters::truncate(&dst_rebuild, &files.rebuild)?;
+                }
+                None => warn!(
+                    "{:?} doesn't record written files, documents of shards that were being written may be duplicated",
+                    Checkpoint::path(&self.dst)
+                ),
+            }
+        }
+
+        let rebuild_files = if self.resume {
+            RebuildWriters::with_dst_resume(&dst_rebuild)?
+        } else {
+            RebuildWriters::with_dst(&dst_rebuild)?
+        };
+
+        let mut rejected_files =
+            self.rejected_files(files.as_ref().map(|files| &files.rejected))?;
+        let seen_hashes =
+            self.open_seen_hashes(files.as_ref().and_then(|files| files.seen_hashes))?;
+        let mut selection = self.selection(seen_hashes)?;
+
+        // resumed runs add to the stats of the previous ones
+        let mut stats = if self.resume {
+            Stats::load(&self.dst)?
+        } else {
+            Stats::default()
+        };
+        let mut nb_shards = 0;
+        let mut empty_shards = 0;
+        let mut write_errors = 0;
+        // set once files hold part of a shard that could not be written
+        let mut write_failed = false;
+        let progress = self.progress(shards.len());
+
+        // documents are written by dedicated threads, languages being spread between them
+        let nb_writers = self.nb_workers();
+        info!(
+            "writing documents with {} writers, {} queued documents max each",
+            nb_writers, self.write_queue
+        );
+        std::thread::scope(|scope| {
+            let writers: Vec<_> = (0..nb_writers)
+                .map(|_| {
+                    let (sender, receiver) = std::sync::mpsc::sync_channel(self.write_queue);
+                    let (langfiles, rebuild_files, dst_rebuild) =
+                        (&langfiles, &rebuild_files, &dst_rebuild);
+                    scope.spawn(move || {
+                        Self::write_documents(rebuild_files, dst_rebuild, receiver, |lang, doc| {
+                            let writer = langfiles.writer(lang)?;
+                            let mut writer_lock = writer.lock().unwrap();
+                            writer_lock.write(vec![doc])?;
+                            Ok(())
+                        })
+                    });
+                    sender
+                })
+                .collect();
+
+            // for each selected shard, send documents to writers and wait for them to be written
+            let result = self.selected_shards(
+                shards,
+                identifier,
+                &annotator,
+                &progress,
+                &mut selection,
+                |idx, shard, shard_result, selection| {
+                    // positions recorded from now on would keep the documents of the failed shard:
+                    // shards are left for resuming, which truncates files back to the last recorded one
+                    if write_failed {
+                        warn!("skipping shard idx {} after a write error", idx);
+                        return;
+                    }
+                    if let Ok(SelectedShard {
+                        shard_id,
+                        nb_documents,
+                        documents: hm,
+                        stats: mut shard_stats,
+                        rejected,
+                        raw_bodies,
+                    }) = shard_result
+                    {
+                        if let Some(rejected_files) = &mut rejected_files {
+                            if let Err(e) = rejected_files.write(&shard, rejected) {
+                                // don't checkpoint the shard, so that its rejected records are written when resuming
+                                error!(
+                                    "Error writing rejected records of shard idx {}: {:?}",
+                                    idx, e
+                                );
+                                write_errors += 1;
+                                write_failed = true;
+                                progress.stop();
+                                stats.add(shard_id, shard_stats);
+                                return;
+                            }
+                        }
+                        if self.output.raw_bodies {
+                            if let Err(e) = raw_bodies.write(&self.dst, shard_id) {
+                                // don't checkpoint the shard, so that its bodies are written when resuming
+                                error!("Error writing raw bodies of shard idx {}: {:?}", idx, e);
+                                write_errors += 1;
+                                stats.add(shard_id, shard_stats);
+                                return;
+                            }
+                        }
+                        nb_shards += 1;
+                        if self.max_empty_shards.is_some()
+                            && nb_documents == 0
+                            && shard_stats.records_read > 0
+                        {
+                            warn!(
+                                "{:?}: no document out of {} records",
+                                shard, shard_stats.records_read
+                            );
+                            empty_shards += 1;
+                        }
+                        let seen_hashes = &selection.seen_hashes;
+                        let new_hashes = seen_hashes.as_ref().map(|_| Self::content_hashes(&hm));
+                        let result =
+                            Self::send_documents(&writers, shard_id, hm).and_then(|output| {
+                                // once documents are written, so that they are written when processed again after an error
+                                if let (Some(seen_hashes), Some(new_hashes)) =
+                                    (&seen_hashes, new_hashes)
+                                {
+                                    seen_hashes.extend(new_hashes)?;
+                                }
+                                Ok(output)
+                            });
+                        match result {
+                            // only record the shard once everything has been written
+                            Ok(nb_docs) => {
+                                for (lang, counts) in nb_docs {
+                                    shard_stats.add_written(lang, counts);
+                                }
+                                stats.add(shard_id, shard_stats);
+
+                                // along with where files are, to truncate them back there when resuming
+                                match rebuild_files.lengths() {
+                                    Ok(lengths) => {
+                                        checkpoint.add(shard);
+                                        let files = checkpoint.files_mut();
+                                        files.langs.extend(langfiles.positions());
+                                        if let Some(rejected_files) = &rejected_files {
+                                            files.rejected.extend(rejected_files.positions());
+                                        }
+                                        files.rebuild.extend(lengths);
+                                        files.seen_hashes =
+                                            seen_hashes.as_ref().map(SeenHashes::size);
+                                        if let Err(e) = checkpoint.save(&self.dst) {
+                                            error!("Could not save checkpoint: {:?}", e);
+                                        }
+                                    }
+                                    Err(e) => {
+                                        error!(
+                                            "Error getting rebuild sizes of shard idx {}: {:?}",
+                                            idx, e
+                                        );
+                                        write_errors += 1;
+                                        write_failed = true;
+                                        progress.stop();
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                error!("Error writing shard idx {}: {:?}", idx, e);
+                                write_errors += 1;
+                                write_failed = true;
+                                progress.stop();
+                                stats.add(shard_id, shard_stats);
+                            }
+                        }
+                    } else if let Err(e) = shard_result {
+                        error!("Error with shard idx {}:{:?}", idx, e);
+                    }
+                },
+            );
+            // stop writers
+            drop(writers);
+            result
+        })?;
+        progress.finish();
+
+        // make sure that everything is on disk before saving stats
+        langfiles.close()?;
+        if let Some(rejected_files) = rejected_files {
+            rejected_files.close()?;
+        }
+        if let Some(seen_hashes) = selection.seen_hashes {
+            seen_hashes.close()?;
+        }
+
+        info!("writing stats to {:?}", Stats::path(&self.dst));
+        stats.save(&self.dst)?;
+
+        // language files are incomplete, don't list them
+        if write_errors > 0 {
+            return Err(Error::Custom(format!(
+                "{write_errors} shards could not be written (see logs), they will be processed again when resuming"
+            )));
+        }
+
+        if let Some(min_documents) = self.min_lang_documents {
+            if self
+                .get_paths_iter()?
+                .all(|shard| checkpoint.contains(&shard))
+            {
+                self.discard_small_langs(&stats, min_documents)?;
+            } else {
+                warn!("shards are left to process, not discarding small languages");
+            }
+        }
+
+        if self.output.line_ids {
+            info!("writing line identifications");
+            LineIds::generate(&self.dst, self.output.naming)?;
+        }
+
+        info!("writing manifest to {:?}", Manifest::path(&self.dst));
+        Manifest::generate(&self.dst)?.save(&self.dst)?;
+
+        if self.output.languages_csv {
+            info!(
+                "writing language sizes to {:?}",
+                Stats::languages_path(&self.dst)
+            );
+            stats.save_languages(&self.dst, self.output.compression)?;
+        }
+
+        if let Some(max_empty_shards) = self.max_empty_shards {
+            let share = empty_shards as f32 / nb_shards.max(1) as f32;
+            if share > max_empty_shards {
+                return Err(Error::Custom(format!(
+                    "{empty_shards} out of {nb_shards} shards yielded no document (max share: {max_empty_shards})"
+                )));
+            }
+        }
+
+        if self.incremental.is_some() {
+            if self
+                .get_paths_iter()?
+                .all(|shard| checkpoint.contains(&shard))
+            {
+                info!("writing last run to {:?}", LastRun::path(&self.dst));
+                LastRun::new(started).save(&self.dst)?;
+            } else {
+                warn!("shards are left to process, not updating last run");
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Move the files of languages that have fewer than `min_documents` documents in `stats` to `<dst>/discarded`,
+    /// keeping their paths relative to `dst`.
+    fn discard_small_langs(&self, stats: &Stats, min_documents: usize) -> Result<(), Error> {
+        let discarded = self.dst.join("discarded");
+        for (lang, nb_documents) in &stats.total().documents {
+            if *nb_documents >= min_documents {
+                continue;
+            }
+            let lang = LanguageTag::parse(lang.clone())?;
+            let reader = match CorpusReader::new(&self.dst, &lang) {
+                Ok(reader) => reader,
+                // already discarded by a previous run
+                Err(_) => continue,
+            };
+
+            info!(
+                "[{}]: discarding {} documents (min: {})",
+                lang, nb_documents, min_documents
+            );
+            for path in reader.paths() {
+                let discarded_path = discarded.join(path.strip_prefix(&self.dst).unwrap_or(path));
+                if let Some(parent) = discarded_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::rename(path, discarded_path)?;
+            }
+
+            // language subdirectory (see [NamingScheme::Subdirectory])
+            let subdir = self.dst.join(lang.as_str());
+            if subdir.is_dir() && std::fs::read_dir(&subdir)?.next().is_none() {
+                std::fs::remove_dir(subdir)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Builder for [OscarDoc], exposing the most common tuning knobs.
+///
+/// Unset options keep [OscarDoc]'s defaults, and [OscarDocBuilder::build] checks that the options
+/// make sense together (see [OscarDoc::validate]).
+/// Other options can still be set on the built pipeline with the `with_*` methods.
+///
+/// ```ignore
+/// let pipeline = OscarDoc::builder(src, dst, lid_path)
+///     .lid(LidParams::new(3, 0.9))
+///     .allowed_langs(["fr", "br"].map(|l| LanguageTag::parse(l.to_string()).unwrap()))
+///     .output(OutputOptions::default().with_compression(Compression::Zstd))
+///     .threads(8)
+///     .build()?;
+/// ```
+pub struct OscarDocBuilder {
+    doc: OscarDoc,
+}
+
+impl OscarDocBuilder {
+    /// Set the language identification parameters (see [LidParams]).
+    pub fn lid(mut self, lid_params: LidParams) -> Self {
+        self.doc.lid_params = lid_params;
+        self
+    }
+
+    /// See [OscarDoc::with_identifier].
+    pub fn identifier(mut self, identifier: Box<dyn Predict<String> + Sync>) -> Self {
+        self.doc = self.doc.with_identifier(identifier);
+        self
+    }
+
+    /// See [OscarDoc::with_doc_lang_strategy].
+    pub fn doc_lang_strategy(mut self, doc_lang_strategy: DocLangStrategy) -> Self {
+        self.doc = self.doc.with_doc_lang_strategy(doc_lang_strategy);
+        self
+    }
+
+    /// Tag adult content using the blocklist folder at `blocklist`.
+    pub fn blocklist(mut self, blocklist: PathBuf) -> Self {
+        self.doc.blocklist = Some(blocklist);
+        self
+    }
+
+    /// Use the KenLM models of the `kenlms_path` folder.
+    pub fn kenlms(mut self, kenlms_path: PathBuf) -> Self {
+        self.doc.kenlms_path = Some(kenlms_path);
+        self
+    }
+
+    /// See [OscarDoc::with_filter].
+    pub fn filter(mut self, filter: record::FilterKind) -> Self {
+        self.doc = self.doc.with_filter(filter);
+        self
+    }
+
+    /// See [OscarDoc::with_header_filter].
+    pub fn header_filter(mut self, header_filter: record::HeaderFilter) -> Self {
+        self.doc = self.doc.with_header_filter(header_filter);
+        self
+    }
+
+    /// See [OscarDoc::with_min_sentence_length].
+    pub fn min_sentence_length(mut self, min_sentence_length: usize) -> Self {
+        self.doc = self.doc.with_min_sentence_length(min_sentence_length);
+        self
+    }
+
+    /// See [OscarDoc::with_trim_mode].
+    pub fn trim_mode(mut self, trim_mode: transformers::TrimMode) -> Self {
+        self.doc = self.doc.with_trim_mode(trim_mode);
+        self
+    }
+
+    /// Only write documents identified in one of `allowed_langs` (see [OscarDoc::with_allowed_langs]).
+    pub fn allowed_langs<I>(mut self, allowed_langs: I) -> Self
+    where
+        I: IntoIterator<Item = LanguageTag<String>>,
+    {
+        self.doc = self
+            .doc
+            .with_allowed_langs(allowed_langs.into_iter().collect());
+        self
+    }
+
+    /// See [OscarDoc::with_secondary_lang_share].
+    pub fn secondary_lang_share(mut self, share: f32) -> Self {
+        self.doc = self.doc.with_secondary_lang_share(share);
+        self
+    }
+
+    /// See [OscarDoc::with_multi_threshold].
+    pub fn multi_threshold(mut self, threshold: f32) -> Self {
+        self.doc = self.doc.with_multi_threshold(threshold);
+        self
+    }
+
+    /// See [OscarDoc::with_output].
+    pub fn output(mut self, output: OutputOptions) -> Self {
+        self.doc = self.doc.with_output(output);
+        self
+    }
+
+    /// See [OscarDoc::with_dedup].
+    pub fn dedup(mut self, dedup: DedupOptions) -> Self {
+        self.doc = self.doc.with_dedup(dedup);
+        self
+    }
+
+    /// See [OscarDoc::with_remote].
+    pub fn remote(mut self, remote: RemoteOptions) -> Self {
+        self.doc = self.doc.with_remote(remote);
+        self
+    }
+
+    /// See [OscarDoc::with_rejected].
+    pub fn rejected(mut self, rejected: RejectedOptions) -> Self {
+        self.doc = self.doc.with_rejected(rejected);
+        self
+    }
+
+    /// See [OscarDoc::with_threads].
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.doc = self.doc.with_threads(threads);
+        self
+    }
+
+    /// Set the number of shards processed concurrently.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.doc = self.doc.with_workers(Some(workers));
+        self
+    }
+
+    /// Set the maximum number of processed shards waiting to be written.
+    pub fn max_pending_shards(mut self, max_pending_shards: usize) -> Self {
+        self.doc = self.doc.with_max_pending_shards(Some(max_pending_shards));
+        self
+    }
+
+    /// See [OscarDoc::with_write_queue].
+    pub fn write_queue(mut self, write_queue: usize) -> Self {
+        self.doc = self.doc.with_write_queue(write_queue);
+        self
+    }
+
+    /// See [OscarDoc::with_resume].
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.doc = self.doc.with_resume(resume);
+        self
+    }
+
+    /// See [OscarDoc::with_quiet].
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.doc = self.doc.with_quiet(quiet);
+        self
+    }
+
+    /// Build the pipeline, failing if the options don't make sense together (see [OscarDoc::validate]).
+    pub fn build(self) -> Result<OscarDoc, Error> {
+        self.doc.validate()?;
+        if self.doc.blocklist.is_none() && self.doc.content_detector {
+            warn!("No blocklist folder specified! No adult content tagging will be done.");
+        }
+        debug!("using lid parameters {:?}", self.doc.lid_params);
+        Ok(self.doc)
+    }
+}
+
+/// Runs return the [Summary] of dry runs (see [OscarDoc::with_dry_run]), and [None] otherwise.
+impl Pipeline<Option<Summary>> for OscarDoc {
+    fn version() -> &'static str {
+        "2.0.0"
+    }
+
+    fn run(&self) -> Result<Option<Summary>, Error> {
+        self.validate()?;
+        let (provenance, summary) = match &self.identifier {
+            Some(identifier) => {
+                // identification parameters are only used by the FastText model
+                let provenance = Provenance::custom();
+                let summary = self.run_with(identifier.as_ref())?;
+                (provenance, summary)
+            }
+            None => {
+                let cls = self.identifier()?;
+                // hash the model that is loaded, before the run
+                let provenance =
+                    Provenance::new(&self.lid_path, self.lid_params.k, self.lid_params.threshold)?;
+                let summary = self.run_with(&cls)?;
+                (provenance, summary)
+            }
+        };
+
+        if !self.dry_run {
+            info!("writing provenance to {:?}", Provenance::path(&self.dst));
+            provenance.save(&self.dst)?;
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        fs::File,
+        io::Write,
+        path::Path,
+        path::PathBuf,
+        str::Lines,
+        sync::atomic::{AtomicUsize, Ordering},
+        sync::Mutex,
+        time::{Duration, SystemTime},
+    };
+
+    use chrono::{TimeZone, Utc};
+    use flate2::{write::GzEncoder, Compression};
+    use oscar_io::v3::WriterTrait;
+    use oxilangtag::LanguageTag;
+    use warc::{BufferedBody, Record, RecordType, WarcHeader, WarcWriter};
+
+    use crate::error::Error;
+    use crate::filtering::lang::PerLangMinBytes;
+    use crate::filtering::record;
+    use crate::filtering::Filter;
+    use crate::identifiers::identification::Identification;
+    use crate::identifiers::model::{DocIdentification, Predict};
+    use crate::io::test_utils::gen_doc;
+    use crate::io::{Budgets, CorpusReader, WriterDoc};
+    use crate::pipelines::oscardoc::types::hash_content;
+    use crate::pipelines::oscardoc::types::Checkpoint;
+    use crate::pipelines::oscardoc::types::ContentHashMetadata;
+    use crate::pipelines::oscardoc::types::ContentMetadata;
+    use crate::pipelines::oscardoc::types::ExtraMetadata;
+    use crate::pipelines::oscardoc::types::LangHintMetadata;
+    use crate::pipelines::oscardoc::types::LastRun;
+    use crate::pipelines::oscardoc::types::Location;
+    use crate::pipelines::oscardoc::types::Manifest;
+    use crate::pipelines::oscardoc::types::Progress;
+    use crate::pipelines::oscardoc::types::Provenance;
+    use crate::pipelines::oscardoc::types::QualityMetadata;
+    use crate::pipelines::oscardoc::types::RawBodies;
+    use crate::pipelines::oscardoc::types::RebuildWriters;
+    use crate::pipelines::oscardoc::types::ShardResult;
+    use crate::pipelines::oscardoc::types::Stats;
+    use crate::pipelines::oscardoc::types::WarcMetadata;
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+    use crate::transformers::{self, Annotate, Annotator};
+
+    use super::{
+        DedupOptions, DocLangStrategy, LidParams, LineSampling, OscarDoc, OutputOptions,
+        RejectedOptions, RemoteOptions, Utf8Mode, WriteMessage, DOC_THRESHOLD,
+        REPLACEMENT_CHARS_KEY, SEGMENTED_KEY, SNAPSHOT_KEY,
+    };
+    use crate::pipelines::Pipeline;
+
+    /// Identifies lines containing `nynorsk` as both Bokmål and Nynorsk, other lines containing `ikkje` as Nynorsk,
+    /// lines containing `zzz` as nothing, and the others as Bokmål.
+    pub(super) struct MockPredict;
+
+    impl Predict<String> for MockPredict {
+        fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+            Ok(self.predict(line)?.and_then(|ids| ids.into_iter().next()))
+        }
+
+        fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+            let id = |label: &str, prob| {
+                Identification::new(LanguageTag::parse(label.to_string()).unwrap(), prob)
+            };
+            if line.contains("nynorsk") {
+                Ok(Some(vec![id("nb", 0.5), id("nn", 0.5)]))
+            } else if line.contains("ikkje") {
+                Ok(Some(vec![id("nn", 1.0)]))
+            } else if line.contains("zzz") {
+                Ok(None)
+            } else if line.contains("français") {
+                Ok(Some(vec![id("fr", 0.99)]))
+            } else if line.contains("noise") {
+                Ok(Some(vec![id("en", 0.2)]))
+            } else if line.contains("lid_failure") {
+                Err(Error::FastText("mock failure".to_string()))
+            } else {
+                Ok(Some(vec![id("nb", 1.0)]))
+            }
+        }
+
+        // lines that are not ambiguous have a single prediction, making both equivalent.
+        fn weighted_ids(&self, lines: Lines) -> Result<DocIdentification<String>, Error> {
+            self.weighted_ids_topk(lines)
+        }
+    }
+
+    /// Identifies every line as French.
+    struct FrenchPredict;
+
+    impl Predict<String> for FrenchPredict {
+        fn predict_one(&self, _: &str) -> Result<Option<Identification<String>>, Error> {
+            Ok(Some(Identification::new(
+                LanguageTag::parse("fr".to_string()).unwrap(),
+                1.0,
+            )))
+        }
+
+        fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+            Ok(self.predict_one(line)?.map(|id| vec![id]))
+        }
+
+        fn weighted_ids(&self, lines: Lines) -> Result<DocIdentification<String>, Error> {
+            self.weighted_ids_topk(lines)
+        }
+    }
+
+    /// A WET-like record (see [OscarDoc::with_record_types]).
+    fn conversion<B: Into<Vec<u8>>>(body: B) -> Record<BufferedBody> {
+        let mut record = Record::default();
+        record.set_warc_type(RecordType::Conversion);
+        record.add_body(body)
+    }
+
+    /// Write a gzipped shard containing a record for each body.
+    pub(super) fn write_shard(path: &Path, bodies: &[String]) {
+        let gz = GzEncoder::new(File::create(path).unwrap(), Compression::default());
+        let mut writer = WarcWriter::new(gz);
+        for body in bodies {
+            let record: Record<BufferedBody> = conversion(body.as_str());
+            writer.write(&record).unwrap();
+        }
+    }
+
+    /// Pipeline reading shards from `src` and writing the corpus in `dst`.
+    pub(super) fn gen_pipeline_at(src: &Path, dst: &Path) -> OscarDoc {
+        OscarDoc::new(
+            src.to_path_buf(),
+            dst.to_path_buf(),
+            PathBuf::from("lid.176.bin"),
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn gen_pipeline(lid_params: Option<LidParams>) -> OscarDoc {
+        OscarDoc::new(
+            PathBuf::from("res/shards"),
+            PathBuf::from("res/corpus"),
+            PathBuf::from("lid.176.bin"),
+            lid_params,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn sort_by_lang_multi() {
+        let line_id = |label: &str| {
+            Some(oscar_io::common::Identification::new(
+                LanguageTag::parse(label.to_string()).unwrap(),
+                0.9,
+            ))
+        };
+        // as many French bytes as English ones
+        let content = "Ceci est du français.\nThis is some English!!";
+        let gen_doc = || {
+            let doc_id = Identification::new(LanguageTag::parse("fr".to_string()).unwrap(), 0.5);
+            let metadata = Metadata::new(&doc_id, &[line_id("fr"), line_id("en")]);
+            (
+                Document::new(content.to_string(), HashMap::new(), metadata),
+                Location::default(),
+            )
+        };
+        let multi = LanguageTag::parse("multi".to_string()).unwrap();
+
+        let hm = OscarDoc::sort_by_lang(vec![gen_doc()], None, Some(0.6));
+        assert_eq!(hm.keys().collect::<Vec<_>>(), vec![&multi]);
+        let (doc, _) = &hm[&multi][0];
+        assert_eq!(doc.identification().label(), &multi);
+        assert_eq!(*doc.identification().prob(), 0.5);
+        assert_eq!(doc.metadata().sentence_identifications().len(), 2);
+
+        // under the threshold
+        let hm = OscarDoc::sort_by_lang(vec![gen_doc()], None, Some(0.4));
+        assert_eq!(
+            hm.keys().map(|k| k.as_str()).collect::<Vec<_>>(),
+            vec!["fr"]
+        );
+
+        // disabled
+        let hm = OscarDoc::sort_by_lang(vec![gen_doc()], None, None);
+        assert_eq!(
+            hm.keys().map(|k| k.as_str()).collect::<Vec<_>>(),
+            vec!["fr"]
+        );
+    }
+
+    #[test]
+    fn drop_over_limits() {
+        let nb = LanguageTag::parse("nb".to_string()).unwrap();
+        let gen_docs = |nb_docs: usize| {
+            (0..nb_docs)
+                .map(|i| {
+                    let doc = Document::new(format!("{i}"), HashMap::new(), Metadata::default());
+                    (doc, Location::default())
+                })
+                .collect::<Vec<_>>()
+        };
+        // room for 2 documents
+        let budgets = Budgets::new([(nb.clone(), 2)].into());
+        let written = AtomicUsize::new(0);
+
+        // documents over the limit don't consume the budget
+        let mut hm = HashMap::from([(nb.clone(), gen_docs(3))]);
+        assert_eq!(
+            OscarDoc::drop_over_limits(&budgets, &mut hm, &written, Some(1)),
+            (0, 2)
+        );
+        assert_eq!(hm[&nb].len(), 1);
+        assert_eq!(written.load(Ordering::SeqCst), 1);
+
+        // documents over budget give their room back
+        let mut hm = HashMap::from([(nb.clone(), gen_docs(3))]);
+        assert_eq!(
+            OscarDoc::drop_over_limits(&budgets, &mut hm, &written, Some(10)),
+            (2, 0)
+        );
+        assert_eq!(hm[&nb].len(), 1);
+        assert_eq!(written.load(Ordering::SeqCst), 2);
+
+        // languages with no document left are removed
+        let mut hm = HashMap::from([(nb.clone(), gen_docs(1))]);
+        assert_eq!(
+            OscarDoc::drop_over_limits(&budgets, &mut hm, &written, None),
+            (1, 0)
+        );
+        assert!(hm.is_empty());
+    }
+
+    #[test]
+    fn for_each_document() {
+        let src = tempfile::tempdir().unwrap();
+        let long = "x".repeat(100);
+        write_shard(
+            &src.path().join("0.txt.gz"),
+            &[
+                format!("Dette er bokmål. {long}"),
+                format!("Dette er også bokmål. {long}"),
+                format!("Ceci est du français. {long}"),
+                "zzz".repeat(50),
+            ],
+        );
+        let dst = tempfile::tempdir().unwrap();
+        let p = gen_pipeline_at(src.path(), &dst.path().join("corpus"))
+            .with_identifier(Box::new(MockPredict))
+            .with_quiet(true);
+
+        let mut docs = Vec::new();
+        p.for_each_document(|doc| docs.push(doc)).unwrap();
+
+        let mut langs: Vec<_> = docs
+            .iter()
+            .map(|doc| doc.identification().label().to_string())
+            .collect();
+        langs.sort();
+        assert_eq!(langs, vec!["fr", "nb", "nb"]);
+        // nothing is written
+        assert!(!dst.path().join("corpus").exists());
+
+        // options are checked
+        let p = p.with_multi_threshold(1.5);
+        assert!(p.for_each_document(|_| ()).is_err());
+    }
+
+    #[test]
+    fn documents_selection() {
+        let src = tempfile::tempdir().unwrap();
+        let long = "x".repeat(100);
+        let bodies = [
+            format!("Dette er bokmål. {long}"),
+            format!("Dette er også bokmål. {long}"),
+            format!("Eg veit ikkje. {long}"),
+        ];
+        // duplicated over shards
+        for shard_id in 0..2 {
+            write_shard(&src.path().join(format!("{shard_id}.txt.gz")), &bodies);
+        }
+        let dst = tempfile::tempdir().unwrap();
+        let hashes = dst.path().join("hashes.bin");
+        let p = gen_pipeline_at(src.path(), &dst.path().join("corpus"))
+            .with_identifier(Box::new(MockPredict))
+            .with_dedup(DedupOptions::default().with_seen_hashes(hashes.clone()))
+            .with_deterministic(true)
+            .with_workers(Some(1))
+            .with_quiet(true);
+
+        // documents are passed once, and seen hashes aren't written
+        let mut contents = Vec::new();
+        p.for_each_document(|doc| contents.push(doc.content().to_string()))
+            .unwrap();
+        assert_eq!(contents.len(), 3);
+        assert!(!hashes.exists());
+
+        // documents over the limit aren't passed
+        let p = p.with_limit(2);
+        let mut contents = Vec::new();
+        p.for_each_document(|doc| contents.push(doc.content().to_string()))
+            .unwrap();
+        assert_eq!(contents.len(), 2);
+    }
+
+    #[test]
+    fn trim_mode() {
+        let long = format!("Dette er bokmål. {}", "x".repeat(100));
+        let body = format!("kort\n{long}\nkort\n{long}\nkort");
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(&shard_path, &[body]);
+
+        let process = |trim_mode| {
+            let (_, docs, _, _, _) = gen_pipeline(None)
+                .with_trim_mode(trim_mode)
+                .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+                .unwrap();
+            let (doc, loc) = docs.into_iter().next().unwrap();
+            (doc.content().to_string(), loc.line_start(), loc.line_end())
+        };
+
+        assert_eq!(
+            process(transformers::TrimMode::Edges),
+            (format!("{long}\nkort\n{long}"), 1, 3)
+        );
+        assert_eq!(
+            process(transformers::TrimMode::All),
+            (format!("{long}\n{long}"), 1, 3)
+        );
+    }
+
+    #[test]
+    fn merge_same_uri() {
+        let src = tempfile::tempdir().unwrap();
+        let long = "x".repeat(100);
+        let gz = GzEncoder::new(
+            File::create(src.path().join("0.txt.gz")).unwrap(),
+            Compression::default(),
+        );
+        let mut writer = WarcWriter::new(gz);
+        for (uri, body) in [
+            ("https://example.com/a", format!("Første del. {long}")),
+            ("https://example.com/a", format!("Andre del. {long}")),
+            ("https://example.com/b", format!("Noe annet. {long}")),
+        ] {
+            let mut record = conversion(body);
+            record.set_header(WarcHeader::TargetURI, uri).unwrap();
+            writer.write(&record).unwrap();
+        }
+        drop(writer);
+
+        let contents = |merge_same_uri| {
+            let p = gen_pipeline_at(src.path(), Path::new("corpus"))
+                .with_identifier(Box::new(MockPredict))
+                .with_merge_same_uri(merge_same_uri)
+                .with_quiet(true);
+            let mut contents = Vec::new();
+            p.for_each_document(|doc| contents.push(doc.content().to_string()))
+                .unwrap();
+            contents.sort();
+            contents
+        };
+
+        assert_eq!(contents(false).len(), 3);
+        assert_eq!(
+            contents(true),
+            vec![
+                format!("Første del. {long}\nAndre del. {long}"),
+                format!("Noe annet. {long}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn opt_in_extras() {
+        let body = format!("Dette er bokmål. {}", "x".repeat(100));
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(&shard_path, &[body]);
+        let is_extra = |annotation: &String| annotation.contains(':');
+
+        // annotations only hold quality tags by default
+        let (_, docs, _, _, _) = gen_pipeline(None)
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+        let metadata = docs[0].0.metadata();
+        assert!(!metadata.annotation().into_iter().flatten().any(is_extra));
+
+        let (_, docs, _, _, _) = gen_pipeline(None)
+            .with_content_hash(true)
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        let (doc, _) = &docs[0];
+        assert_eq!(
+            doc.metadata().content_hash(),
+            Some(hash_content(doc.content(), false))
+        );
+        assert_eq!(doc.metadata().normalized_content_hash(), None);
+
+        let (_, docs, _, _, _) = gen_pipeline(None)
+            .with_normalized_content_hash(true)
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        let (doc, _) = &docs[0];
+        assert_eq!(doc.metadata().content_hash(), None);
+        assert_eq!(
+            doc.metadata().normalized_content_hash(),
+            Some(hash_content(doc.content(), true))
+        );
+    }
+
+    #[test]
+    fn case() {
+        let long = "x".repeat(100);
+        let body = format!("Dette er en STRAßE. {long}\nOg ΟΔΟΣ til. {long}");
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(&shard_path, &[body]);
+
+        let (_, docs, _, _, _) = gen_pipeline(None)
+            .with_case(transformers::CaseMode::Fold)
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        let doc = &docs[0].0;
+        assert_eq!(
+            doc.content(),
+            &format!("dette er en strasse. {long}\nog οδοσ til. {long}")
+        );
+        // lines are kept, along with their identifications
+        assert_eq!(doc.content().lines().count(), 2);
+        assert_eq!(doc.metadata().sentence_identifications().len(), 2);
+    }
+
+    #[test]
+    fn nfc() {
+        let src = tempfile::tempdir().unwrap();
+        let long = "x".repeat(100);
+        let nfd = format!("Dette er en kafe\u{301}. {long}\nOg en til. {long}");
+        write_shard(&src.path().join("0.txt.gz"), std::slice::from_ref(&nfd));
+
+        let contents = |nfc| {
+            let p = gen_pipeline_at(src.path(), Path::new("corpus"))
+                .with_identifier(Box::new(MockPredict))
+                .with_nfc(nfc)
+                .with_quiet(true);
+            let mut contents = Vec::new();
+            p.for_each_document(|doc| contents.push(doc.content().to_string()))
+                .unwrap();
+            contents
+        };
+
+        assert_eq!(contents(false), vec![nfd.clone()]);
+        let nfc = contents(true);
+        assert_eq!(
+            nfc,
+            vec![format!("Dette er en kaf\u{e9}. {long}\nOg en til. {long}")]
+        );
+        assert_eq!(nfc[0].lines().count(), nfd.lines().count());
+    }
+
+    #[test]
+    fn dedup_lines() {
+        let src = tempfile::tempdir().unwrap();
+        let long = "x".repeat(100);
+        let menu = format!("Meny: hjem, nyheter. {long}");
+        let body = format!(
+            "{menu}\n{menu}\nDette er en setning. {long}\n{menu}\nDette er en annen setning. {long}"
+        );
+        write_shard(&src.path().join("0.txt.gz"), &[body]);
+
+        let p = gen_pipeline_at(src.path(), Path::new("corpus"))
+            .with_identifier(Box::new(MockPredict))
+            .with_dedup_lines(transformers::DedupLinesMode::All)
+            .with_content_counts(true)
+            .with_quiet(true);
+        let mut docs = Vec::new();
+        p.for_each_document(|doc| docs.push(doc)).unwrap();
+
+        assert_eq!(docs.len(), 1);
+        assert_eq!(
+            docs[0].content(),
+            &format!("{menu}\nDette er en setning. {long}\nDette er en annen setning. {long}")
+        );
+        assert_eq!(docs[0].metadata().sentence_identifications().len(), 3);
+        assert_eq!(docs[0].metadata().content_lines(), Some(3));
+    }
+
+    #[test]
+    fn languages_csv() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let long = "x".repeat(100);
+        write_shard(
+            &src.path().join("0.txt.gz"),
+            &[
+                format!("Dette er bokmål. {long}\nEnda en linje. {long}"),
+                format!("Mer bokmål. {long}"),
+                format!("Ceci est du français. {long}"),
+            ],
+        );
+
+        let p = gen_pipeline_at(src.path(), dst.path())
+            .with_output(
+                OutputOptions::default()
+                    .with_compression(crate::io::Compression::Gzip)
+                    .with_languages_csv(true),
+            )
+            .with_quiet(true);
+        p.run_with(&MockPredict).unwrap();
+
+        let mut reader = csv::Reader::from_path(Stats::languages_path(dst.path())).unwrap();
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec![
+                "language",
+                "documents",
+                "lines",
+                "bytes",
+                "compressed_bytes"
+            ]
+        );
+        let rows: Vec<(String, Vec<u64>)> = reader
+            .records()
+            .map(|record| {
+                let record = record.unwrap();
+                let counts = record.iter().skip(1).map(|c| c.parse().unwrap()).collect();
+                (record[0].to_string(), counts)
+            })
+            .collect();
+
+        let langs: Vec<_> = rows.iter().map(|(lang, _)| lang.as_str()).collect();
+        assert_eq!(langs, vec!["fr", "nb", "total"]);
+        assert_eq!(rows[1].1[..2], [2, 3]);
+
+        // totals are the sums of language rows
+        let (_, total) = &rows[2];
+        for column in 0..4 {
+            let sum: u64 = rows[..2].iter().map(|(_, counts)| counts[column]).sum();
+            assert_eq!(total[column], sum);
+        }
+    }
+
+    #[test]
+    fn scan() {
+        let src = tempfile::tempdir().unwrap();
+        let long = "x".repeat(100);
+        write_shard(
+            &src.path().join("0.txt.gz"),
+            &[
+                format!("Dette er bokmål. {long}"),
+                format!("Mer bokmål. {long}"),
+            ],
+        );
+        write_shard(
+            &src.path().join("1.txt.gz"),
+            &[format!("Ceci est du français. {long}")],
+        );
+
+        let scan = |sample| {
+            let p = gen_pipeline_at(src.path(), Path::new("corpus"))
+                .with_identifier(Box::new(MockPredict))
+                .with_quiet(true);
+            let p = match sample {
+                Some((nb_shards, seed)) => p.with_sample(nb_shards, seed),
+                None => p,
+            };
+            p.scan().unwrap()
+        };
+
+        let summary = scan(None);
+        let langs: Vec<_> = summary.shares().into_iter().map(|(lang, _)| lang).collect();
+        assert_eq!(langs, vec!["nb", "fr"]);
+        let total: f32 = summary.shares().iter().map(|(_, share)| share).sum();
+        assert!((total - 1.0).abs() < 1e-6);
+
+        // sampling is deterministic
+        let sampled = scan(Some((1, 42)));
+        assert_eq!(sampled.langs().len(), 1);
+        assert_eq!(scan(Some((1, 42))), sampled);
+        assert_eq!(scan(Some((2, 42))), summary);
+    }
+
+    #[test]
+    fn raw_bodies() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let long = "x".repeat(100);
+
+        // invalid UTF-8 is replaced in the content, but not in the raw body
+        let mut kept = format!("Dette er bokmål. {long}\n").into_bytes();
+        kept.push(0xff);
+        kept.extend_from_slice(format!("\nEnda en linje. {long}").as_bytes());
+        let bodies = [kept, b"short".to_vec()];
+
+        let gz = GzEncoder::new(
+            File::create(src.path().join("0.txt.gz")).unwrap(),
+            Compression::default(),
+        );
+        let mut writer = WarcWriter::new(gz);
+        let mut ids = Vec::new();
+        for body in &bodies {
+            let record: Record<BufferedBody> = conversion(body.clone());
+            ids.push(record.warc_id().to_string());
+            writer.write(&record).unwrap();
+        }
+        drop(writer);
+
+        let p = gen_pipeline_at(src.path(), dst.path())
+            .with_output(OutputOptions::default().with_raw_bodies(true))
+            .with_quiet(true);
+        p.run_with(&MockPredict).unwrap();
+
+        assert_eq!(
+            RawBodies::read(dst.path(), 0, &ids[0]).unwrap(),
+            Some(bodies[0].clone())
+        );
+        // filtered records are not kept
+        assert_eq!(RawBodies::read(dst.path(), 0, &ids[1]).unwrap(), None);
+    }
+
+    #[test]
+    fn write_error() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let long = "x".repeat(100);
+        write_shard(
+            &src.path().join("0.txt.gz"),
+            &[
+                format!("Dokument 0 på bokmål. {long}"),
+                format!("Dokument 0 på ikkje bokmål. {long}"),
+            ],
+        );
+        // that could be written on its own
+        write_shard(
+            &src.path().join("1.txt.gz"),
+            &[format!("Dokument 1 på ikkje bokmål. {long}")],
+        );
+        let pipeline = |resume: bool| {
+            gen_pipeline_at(src.path(), dst.path())
+                .with_workers(Some(1))
+                .with_deterministic(true)
+                .with_resume(resume)
+                .with_quiet(true)
+        };
+        let contents = |lang: &str| -> Vec<String> {
+            let lang = LanguageTag::parse(lang.to_string()).unwrap();
+            CorpusReader::new(dst.path(), &lang).map_or(Vec::new(), |reader| {
+                reader
+                    .map(|doc| doc.unwrap().content().to_string())
+                    .collect()
+            })
+        };
+        let recorded = || {
+            let checkpoint = Checkpoint::load(dst.path()).unwrap();
+            (0..2)
+                .filter(|i| checkpoint.contains(&src.path().join(format!("{i}.txt.gz"))))
+                .count()
+        };
+
+        // the nb file can't be opened for writing
+        let nb_path = dst.path().join("nb_meta.jsonl");
+        std::fs::create_dir(&nb_path).unwrap();
+        match pipeline(false).run_with(&MockPredict) {
+            Err(Error::Custom(msg)) => assert!(msg.starts_with("1 shards could not be written")),
+            res => panic!("unexpected result: {res:?}"),
+        }
+
+        // the nn document of the failed shard is written, but the run stopped there
+        assert_eq!(
+            contents("nn"),
+            [format!("Dokument 0 på ikkje bokmål. {long}")]
+        );
+        assert_eq!(recorded(), 0);
+        assert!(Stats::path(dst.path()).exists());
+        assert!(!Manifest::path(dst.path()).exists());
+
+        // once fixed, resuming writes every document once
+        std::fs::remove_dir(&nb_path).unwrap();
+        pipeline(true).run_with(&MockPredict).unwrap();
+        for (lang, nb_docs) in [("nb", 1), ("nn", 2)] {
+            let contents = contents(lang);
+            let unique: HashSet<&String> = contents.iter().collect();
+            assert_eq!(contents.len(), nb_docs, "{lang}");
+            assert_eq!(unique.len(), nb_docs, "{lang}");
+        }
+        assert_eq!(recorded(), 2);
+        assert!(Manifest::path(dst.path()).exists());
+        let stats = Stats::load(dst.path()).unwrap();
+        assert_eq!(stats.total().documents.get("nn"), Some(&2));
+    }
+
+    #[test]
+    fn doc_lid_only() {
+        let src = tempfile::tempdir().unwrap();
+        let long = "x".repeat(100);
+        // line by line, bokmål gets most bytes. As a whole, the document is nynorsk.
+        write_shard(
+            &src.path().join("0.txt.gz"),
+            &[format!(
+                "Dette er bokmål. {long}\nDette er bokmål. {long}\nDet er ikkje bokmål. {long}"
+            )],
+        );
+
+        let docs = |doc_lid_only| {
+            let p = gen_pipeline_at(src.path(), Path::new("corpus"))
+                .with_identifier(Box::new(MockPredict))
+                .with_doc_lid_only(doc_lid_only)
+                .with_quiet(true);
+            let mut docs = Vec::new();
+            p.for_each_document(|doc| docs.push(doc)).unwrap();
+            docs
+        };
+
+        let by_line = docs(false);
+        assert_eq!(by_line[0].identification().label().as_str(), "nb");
+        assert_eq!(by_line[0].metadata().sentence_identifications().len(), 3);
+
+        let by_doc = docs(true);
+        assert_eq!(by_doc.len(), 1);
+        assert_eq!(by_doc[0].identification().label().as_str(), "nn");
+        assert!(by_doc[0].metadata().sentence_identifications().is_empty());
+
+        // options relying on line identifications are rejected
+        let p = gen_pipeline(None)
+            .with_doc_lid_only(true)
+            .with_keep_document_language_lines(true);
+        assert!(p.validate().is_err());
+    }
+
+    #[test]
+    fn warc_input() {
+        let long = format!("Dette er bokmål. {}", "x".repeat(100));
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.warc.gz");
+        let gz = GzEncoder::new(File::create(&shard_path).unwrap(), Compression::default());
+        let mut writer = WarcWriter::new(gz);
+        let mut request: Record<BufferedBody> = conversion("GET / HTTP/1.1\r\n\r\n");
+        request.set_warc_type(RecordType::Request);
+        writer.write(&request).unwrap();
+        let mut response: Record<BufferedBody> = conversion(format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<html><body><p>{long}</p><p>{long}</p></body></html>"
+        ));
+        response.set_warc_type(RecordType::Response);
+        writer.write(&response).unwrap();
+        drop(writer);
+
+        let (_, docs, stats, _, _) = gen_pipeline(None)
+            .with_warc_input(true)
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert_eq!(stats.records_read, 1);
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].0.content(), &format!("{long}\n{long}"));
+    }
+
+    #[test]
+    fn missing_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let dst = dir.path().join("dst");
+        let p = OscarDoc::new(
+            dir.path().join("no_shards"),
+            dst.clone(),
+            dir.path().join("missing.bin"),
+            None,
+            None,
+            None,
+        );
+
+        // fails on the model rather than on the missing source folder, without creating anything
+        match p.run() {
+            Err(Error::Custom(e)) => assert!(e.starts_with("fastText model not found")),
+            other => panic!("unexpected result: {:?}", other.err()),
+        }
+        assert!(!dst.exists());
+    }
+
+    #[test]
+    fn lid_params_default() {
+        let p = gen_pipeline(None);
+        assert_eq!(p.lid_params, LidParams::new(1, 0.8));
+    }
+
+    #[test]
+    fn lid_params_custom() {
+        let p = gen_pipeline(Some(LidParams::new(3, 0.5)));
+        assert_eq!(p.lid_params.k, 3);
+        assert_eq!(p.lid_params.threshold, 0.5);
+    }
+
+    /// Identifies every line as French with a fixed probability,
+    /// dropping predictions under `threshold` like [FastText] does.
+    struct ThresholdPredict {
+        prob: f32,
+        threshold: f32,
+    }
+
+    impl Predict<String> for ThresholdPredict {
+        fn predict_one(&self, _: &str) -> Result<Option<Identification<String>>, Error> {
+            if self.prob < self.threshold {
+                return Ok(None);
+            }
+            Ok(Some(Identification::new(
+                LanguageTag::parse("fr".to_string()).unwrap(),
+                self.prob,
+            )))
+        }
+
+        fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+            Ok(self.predict_one(line)?.map(|id| vec![id]))
+        }
+
+        fn weighted_ids(&self, lines: Lines) -> Result<DocIdentification<String>, Error> {
+            self.weighted_ids_topk(lines)
+        }
+    }
+
+    #[test]
+    fn lid_threshold() {
+        let process = |threshold| {
+            let p = gen_pipeline(Some(LidParams::new(1, threshold)));
+            let identifier = ThresholdPredict {
+                prob: 0.7,
+                threshold: p.lid_params.threshold,
+            };
+            p.process_record(conversion("Ceci est une phrase en français."), &identifier)
+                .unwrap()
+        };
+
+        let doc = process(0.5).unwrap();
+        assert_eq!(doc.identification().label().as_str(), "fr");
+        assert!(process(0.8).is_none());
+    }
+
+    #[test]
+    fn min_identified_bytes() {
+        // a single word among empty lines, identified with full confidence
+        let near_empty = "Hei\n\n\n\n";
+        let p = gen_pipeline(None);
+        let doc = p
+            .process_record(conversion(near_empty), &MockPredict)
+            .unwrap()
+            .unwrap();
+        assert_eq!(*doc.identification().prob(), 1.0);
+
+        let p = gen_pipeline(None).with_min_identified_bytes(10);
+        assert!(p
+            .process_record(conversion(near_empty), &MockPredict)
+            .unwrap()
+            .is_none());
+        assert!(p
+            .process_record(conversion("Dette er bokmål."), &MockPredict)
+            .unwrap()
+            .is_some());
+        // unidentified lines don't count
+        let unidentified = format!("Hei\n{}", "zzz".repeat(10));
+        assert!(p
+            .process_record(conversion(unidentified), &MockPredict)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn undetermined() {
+        // 30 bytes of bokmål, 25 of nynorsk, 25 of french and 20 unidentified
+        let body = format!(
+            "Hei {}\nikkje {}\nfrançais {}\nzzz {}",
+            "x".repeat(26),
+            "x".repeat(19),
+            "x".repeat(15),
+            "x".repeat(16)
+        );
+        let p = gen_pipeline(None).with_doc_threshold(0.2);
+        let doc = p
+            .process_record(conversion(body.as_str()), &MockPredict)
+            .unwrap()
+            .unwrap();
+        assert_eq!(doc.identification().label(), "nb");
+        assert!((doc.identification().prob() - 0.3).abs() < 1e-6);
+
+        let p = gen_pipeline(None).with_doc_threshold(0.5);
+        assert!(p
+            .process_record(conversion(body.as_str()), &MockPredict)
+            .unwrap()
+            .is_none());
+
+        let p = p.with_rejected(RejectedOptions::default().with_undetermined(true));
+        let doc = p
+            .process_record(conversion(body.as_str()), &MockPredict)
+            .unwrap()
+            .unwrap();
+        assert_eq!(doc.identification().label(), "und");
+        assert!((doc.identification().prob() - 0.3).abs() < 1e-6);
+        // confident documents keep their language
+        let doc = p
+            .process_record(conversion("Dette er bokmål."), &MockPredict)
+            .unwrap()
+            .unwrap();
+        assert_eq!(doc.identification().label(), "nb");
+
+        assert!(gen_pipeline(None)
+            .with_doc_threshold(1.5)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn topk_candidates() {
+        let body = "Dette er en setning på bokmål.
+Dette er en annen setning på bokmål.
+Dette er en tredje setning på bokmål.
+Dette er kanskje nynorsk, kanskje ikkje.";
+        let record: Record<BufferedBody> = conversion(body);
+
+        let p = gen_pipeline(Some(LidParams::new(2, 0.8).with_topk_weighting()));
+        let doc = p.process_record(record, &MockPredict).unwrap().unwrap();
+
+        assert_eq!(doc.identification().label(), "nb");
+        let candidates = doc.metadata().extra("lid_candidates").unwrap();
+        let candidates: Vec<&str> = candidates
+            .split(',')
+            .map(|c| c.split('=').next().unwrap())
+            .collect();
+        assert_eq!(candidates, vec!["nb", "nn"]);
+    }
+
+    #[test]
+    fn line_sampling() {
+        let sampling = LineSampling::new(50, 5, 10);
+        let p = gen_pipeline(Some(LidParams::default().with_line_sampling(sampling)));
+        let body = |nb_lines: usize| {
+            (0..nb_lines)
+                .map(|i| format!("Dette er setning nummer {i} på bokmål."))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        // long documents only get their first lines and a sample identified
+        let doc = p
+            .process_record(conversion(body(200)), &MockPredict)
+            .unwrap()
+            .unwrap();
+        assert_eq!(doc.identification().label(), "nb");
+        let ids = doc.metadata().sentence_identifications();
+        assert_eq!(ids.len(), 200);
+        assert!(ids[..5].iter().all(Option::is_some));
+        assert_eq!(ids.iter().filter(|id| id.is_some()).count(), 15);
+
+        // sampling is deterministic
+        let again = p
+            .process_record(conversion(body(200)), &MockPredict)
+            .unwrap()
+            .unwrap();
+        assert_eq!(again.metadata().sentence_identifications(), ids);
+
+        // short ones are fully identified
+        let doc = p
+            .process_record(conversion(body(50)), &MockPredict)
+            .unwrap()
+            .unwrap();
+        let ids = doc.metadata().sentence_identifications();
+        assert_eq!(ids.len(), 50);
+        assert!(ids.iter().all(Option::is_some));
+    }
+
+    #[test]
+    fn lang_hint() {
+        let hinted = |hint: &str| {
+            let mut record: Record<BufferedBody> = conversion("Ceci est une phrase en français.");
+            record
+                .set_header(
+                    WarcHeader::from("WARC-Identified-Content-Language"),
+                    hint.to_string(),
+                )
+                .unwrap();
+            record
+        };
+        let p = gen_pipeline(None).with_lang_hint(true);
+
+        let doc = p
+            .process_record(hinted("fra"), &FrenchPredict)
+            .unwrap()
+            .unwrap();
+        assert_eq!(doc.metadata().lang_hint(), Some("fra"));
+        assert_eq!(doc.metadata().lang_hint_agreement(), Some(true));
+
+        let doc = p
+            .process_record(hinted("deu"), &FrenchPredict)
+            .unwrap()
+            .unwrap();
+        assert_eq!(doc.metadata().lang_hint(), Some("deu"));
+        assert_eq!(doc.metadata().lang_hint_agreement(), Some(false));
+
+        let record = conversion("Ceci est une phrase en français.");
+        let doc = p.process_record(record, &FrenchPredict).unwrap().unwrap();
+        assert_eq!(doc.metadata().lang_hint(), None);
+        assert_eq!(doc.metadata().lang_hint_agreement(), None);
+    }
+
+    #[test]
+    fn topk_disabled_no_candidates() {
+        let record: Record<BufferedBody> = conversion("Dette er bokmål.");
+        let p = gen_pipeline(None);
+        let doc = p.process_record(record, &MockPredict).unwrap().unwrap();
+        assert_eq!(doc.metadata().extra("lid_candidates"), None);
+    }
+
+    #[test]
+    fn resume_skips_completed_shards() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let shards: Vec<PathBuf> = (0..3)
+            .map(|i| src.path().join(format!("{i}.txt.gz")))
+            .collect();
+        for shard in &shards {
+            std::fs::File::create(shard).unwrap();
+        }
+
+        let mut checkpoint = Checkpoint::default();
+        checkpoint.add(shards[0].clone());
+        checkpoint.add(shards[1].clone());
+        checkpoint.save(dst.path()).unwrap();
+
+        let p = gen_pipeline_at(src.path(), dst.path()).with_resume(true);
+
+        let checkpoint = Checkpoint::load(dst.path()).unwrap();
+        let remaining: Vec<PathBuf> = p.get_remaining_paths_iter(&checkpoint).unwrap().collect();
+        assert_eq!(remaining, vec![shards[2].clone()]);
+    }
+
+    #[test]
+    fn append_run() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let body = |shard_id: usize| format!("Dette er shard {shard_id}. {}", "x".repeat(100));
+        let gen_pipeline = |resume| {
+            gen_pipeline_at(src.path(), dst.path())
+                .with_output(OutputOptions::default().with_part_size(Some(1)))
+                .with_resume(resume)
+        };
+
+        write_shard(&src.path().join("0.txt.gz"), &[body(0)]);
+        gen_pipeline(false).run_with(&MockPredict).unwrap();
+
+        // a new shard is added to the corpus
+        write_shard(&src.path().join("1.txt.gz"), &[body(1)]);
+        gen_pipeline(true).run_with(&MockPredict).unwrap();
+
+        let lang = LanguageTag::parse("nb".to_string()).unwrap();
+        let contents: Vec<String> = CorpusReader::new(dst.path(), &lang)
+            .unwrap()
+            .map(|doc| doc.unwrap().content().to_string())
+            .collect();
+        assert_eq!(contents, vec![body(0), body(1)]);
+
+        let stats = Stats::load(dst.path()).unwrap();
+        assert_eq!(stats.shards().keys().collect::<Vec<_>>(), vec![&0, &1]);
+        assert_eq!(stats.total().documents.get("nb"), Some(&2));
+
+        // a new run starts over
+        gen_pipeline(false).run_with(&MockPredict).unwrap();
+        let stats = Stats::load(dst.path()).unwrap();
+        assert_eq!(stats.total().documents.get("nb"), Some(&2));
+    }
+
+    #[test]
+    fn shard_stats() {
+        let long = |prefix: &str| format!("{prefix} {}", "x".repeat(100));
+        let short = "short line".to_string();
+        let bodies = vec![
+            // kept
+            [long("Dette er bokmål."), long("Dette er bokmål.")].join("\n"),
+            [
+                long("Dette er bokmål."),
+                short.clone(),
+                long("Dette er bokmål."),
+            ]
+            .join("\n"),
+            // no long sentences
+            [short.clone(), short.clone()].join("\n"),
+            // mostly short sentences
+            [
+                vec![long("Dette er bokmål.")],
+                vec![short.repeat(5); 10],
+                vec![long("Dette er bokmål.")],
+            ]
+            .concat()
+            .join("\n"),
+            // no identification
+            [long("zzz"), long("zzz")].join("\n"),
+        ];
+
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(&shard_path, &bodies);
+
+        let p = gen_pipeline(None).with_content_counts(true);
+        let (shard_id, docs, stats, _, _) = p
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+
+        assert_eq!(shard_id, 0);
+        assert_eq!(docs.len(), 2);
+        for (doc, _) in &docs {
+            assert_eq!(doc.metadata().content_bytes(), Some(doc.content().len()));
+            assert_eq!(
+                doc.metadata().content_lines(),
+                Some(doc.metadata().sentence_identifications().len())
+            );
+        }
+        assert_eq!(stats.records_read, 5);
+        assert_eq!(stats.record_errors, 0);
+        assert_eq!(stats.no_sentences, 1);
+        assert_eq!(stats.filtered.get("pfilter"), Some(&1));
+        assert_eq!(stats.no_language, 1);
+        assert_eq!(stats.identification_errors, 0);
+    }
+
+    #[test]
+    fn record_types() {
+        let long = |s: &str| [s; 20].join(" ");
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        let gz = GzEncoder::new(File::create(&shard_path).unwrap(), Compression::default());
+        let mut writer = WarcWriter::new(gz);
+        let mut warcinfo = conversion(long("Dette er bokmål."));
+        warcinfo.set_warc_type(RecordType::WarcInfo);
+        writer.write(&warcinfo).unwrap();
+        writer.write(&conversion(long("Dette er bokmål."))).unwrap();
+        drop(writer);
+
+        let (_, docs, stats, _, _) = gen_pipeline(None)
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(stats.filtered.get("record_type"), Some(&1));
+
+        let (_, docs, _, _, _) = gen_pipeline(None)
+            .with_record_types(vec![RecordType::Conversion, RecordType::WarcInfo])
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert_eq!(docs.len(), 2);
+    }
+
+    #[test]
+    fn identification_errors() {
+        let long = |s: &str| [s; 20].join(" ");
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(
+            &shard_path,
+            &[long("Dette er bokmål."), long("lid_failure")],
+        );
+
+        let p = gen_pipeline(None);
+        let (_, docs, stats, _, _) = p
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(stats.identification_errors, 1);
+        assert_eq!(stats.errors.get("fasttext"), Some(&1));
+        assert_eq!(stats.errors.len(), 1);
+    }
+
+    #[test]
+    fn corrupt_records() {
+        let long = |s: &str| [s; 20].join(" ");
+        let record = |body: String| {
+            let mut raw = Vec::new();
+            let record: Record<BufferedBody> = conversion(body);
+            WarcWriter::new(&mut raw).write(&record).unwrap();
+            raw
+        };
+
+        // a valid record, a record with a malformed header, and another valid record
+        let raw = [
+            record(long("Dette er bokmål.")),
+            b"WARC/1.0\r\nnot a header\r\n\r\n".to_vec(),
+            record(long("Dette er bokmål.")),
+        ]
+        .concat();
+
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        let mut gz = GzEncoder::new(File::create(&shard_path).unwrap(), Compression::default());
+        gz.write_all(&raw).unwrap();
+        gz.finish().unwrap();
+
+        let p = gen_pipeline(None);
+        let (_, docs, stats, _, _) = p
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(stats.records_read, 3);
+        assert_eq!(stats.record_errors, 1);
+
+        assert_eq!(stats.errors.get("warc_parse_headers"), Some(&1));
+
+        // under the ratio
+        let p = gen_pipeline(None).with_max_corrupt_ratio(0.5);
+        assert!(p
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .is_ok());
+
+        // over the ratio: the shard is aborted
+        let p = gen_pipeline(None).with_max_corrupt_ratio(0.1);
+        assert!(p
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .is_err());
+    }
+
+    #[test]
+    fn utf8_mode() {
+        let long = "x".repeat(100);
+        let record = |body: Vec<u8>| {
+            let mut raw = Vec::new();
+            let record: Record<BufferedBody> = conversion(body);
+            WarcWriter::new(&mut raw).write(&record).unwrap();
+            raw
+        };
+
+        // a latin-1 record and a valid one
+        let raw = [
+            record(
+                [
+                    b"Dette er en setning p\xe5 bokm\xe5l. ".as_slice(),
+                    long.as_bytes(),
+                ]
+                .concat(),
+            ),
+            record(format!("Dette er en setning på bokmål. {long}").into_bytes()),
+        ]
+        .concat();
+
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        let mut gz = GzEncoder::new(File::create(&shard_path).unwrap(), Compression::default());
+        gz.write_all(&raw).unwrap();
+        gz.finish().unwrap();
+
+        let process = |mode| {
+            let (_, docs, stats, _, _) = gen_pipeline(None)
+                .with_utf8_mode(mode)
+                .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+                .unwrap();
+            (docs, stats)
+        };
+
+        // lossy: both are kept, the invalid bytes being replaced
+        let (docs, stats) = process(Utf8Mode::Lossy);
+        assert_eq!(docs.len(), 2);
+        assert_eq!(stats.filtered.get("invalid_utf8"), None);
+        assert!(docs[0].0.content().contains(char::REPLACEMENT_CHARACTER));
+        assert_eq!(docs[0].0.metadata().extra(REPLACEMENT_CHARS_KEY), None);
+
+        // count: replacement characters are counted
+        let (docs, _) = process(Utf8Mode::Count);
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0].0.metadata().extra(REPLACEMENT_CHARS_KEY), Some("2"));
+        assert_eq!(docs[1].0.metadata().extra(REPLACEMENT_CHARS_KEY), None);
+
+        // strict: the invalid record is dropped
+        let (docs, stats) = process(Utf8Mode::Strict);
+        assert_eq!(docs.len(), 1);
+        assert!(!docs[0].0.content().contains(char::REPLACEMENT_CHARACTER));
+        assert_eq!(stats.filtered.get("invalid_utf8"), Some(&1));
+    }
+
+    #[test]
+    fn lang_min_bytes() {
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        let short_nb = format!("Dette er en kort setning. {}", "x".repeat(100));
+        let long_nb = format!("Dette er en lang setning. {}", "x".repeat(300));
+        let short_fr = format!("Une phrase en français. {}", "x".repeat(100));
+        write_shard(&shard_path, &[short_nb, long_nb.clone(), short_fr.clone()]);
+
+        let thresholds = [("nb", 200), ("fr", 50)]
+            .into_iter()
+            .map(|(lang, min)| (LanguageTag::parse(lang.to_string()).unwrap(), min))
+            .collect();
+        let (_, docs, stats, _, _) = gen_pipeline(None)
+            .with_lang_min_bytes(PerLangMinBytes::new(thresholds, 1000))
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+
+        let contents: Vec<&str> = docs.iter().map(|(doc, _)| doc.content().as_str()).collect();
+        assert_eq!(contents, vec![long_nb.as_str(), short_fr.as_str()]);
+        assert_eq!(stats.filtered.get("lang_min_bytes"), Some(&1));
+
+        // default threshold
+        let (_, docs, _, _, _) = gen_pipeline(None)
+            .with_lang_min_bytes(PerLangMinBytes::new(HashMap::new(), 200))
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+    }
+
+    #[test]
+    fn snapshot_id() {
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(
+            &shard_path,
+            &[format!(
+                "Dette er en setning på bokmål. {}",
+                "x".repeat(100)
+            )],
+        );
+
+        let (_, docs, _, _, _) = gen_pipeline(None)
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert_eq!(docs[0].0.metadata().extra(SNAPSHOT_KEY), None);
+
+        let (_, docs, _, _, _) = gen_pipeline(None)
+            .with_snapshot_id("CC-MAIN-2023-06".to_string())
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert_eq!(
+            docs[0].0.metadata().extra(SNAPSHOT_KEY),
+            Some("CC-MAIN-2023-06")
+        );
+    }
+
+    #[test]
+    fn combined_filter() {
+        let long = format!("Dette er bokmål. {}", "x".repeat(100));
+        // mostly short lines: fails the default quality filter
+        let mostly_short = format!("{long}\n{}\n{long}", vec!["kort linje"; 20].join("\n"));
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(&shard_path, &[long.clone(), mostly_short]);
+
+        let nb_docs = |p: &OscarDoc, filter: Option<&record::FilterKind>| {
+            let (_, docs, _, _, _) = p
+                .process_shard(&shard_path, &MockPredict, filter, &Annotator::default())
+                .unwrap();
+            docs.len()
+        };
+
+        let p = gen_pipeline(None);
+        assert_eq!(nb_docs(&p, None), 1);
+
+        // empty combinators keep everything
+        assert_eq!(nb_docs(&p, Some(&record::FilterKind::Any(Vec::new()))), 2);
+
+        let all = record::FilterKind::All(vec![
+            record::FilterKind::default(),
+            record::FilterKind::MinLength(record::MinLength::new(1000, 0)),
+        ]);
+        assert_eq!(nb_docs(&p, Some(&all)), 0);
+
+        // the filter set on the pipeline is used for runs
+        let p = gen_pipeline(None).with_filter(record::FilterKind::Any(vec![
+            record::FilterKind::default(),
+            record::FilterKind::MinLength(record::MinLength::new(200, 0)),
+        ]));
+        assert_eq!(nb_docs(&p, p.filter.as_ref()), 2);
+    }
+
+    #[test]
+    fn custom_filter() {
+        /// Keeps records containing a marker word.
+        struct Marker;
+        impl Filter<&Record<BufferedBody>> for Marker {
+            fn detect(&self, record: &Record<BufferedBody>) -> bool {
+                String::from_utf8_lossy(record.body()).contains("markør")
+            }
+        }
+
+        let marked = format!("Dette er en markør. {}", "x".repeat(100));
+        let unmarked = format!("Dette er bokmål. {}", "x".repeat(100));
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(&shard_path, &[unmarked.clone(), marked.clone(), unmarked]);
+
+        // combined with the default quality filter
+        let p = gen_pipeline(None).with_filter(record::FilterKind::All(vec![
+            record::FilterKind::default(),
+            record::FilterKind::custom(Marker),
+        ]));
+        let (_, docs, stats, _, _) = p
+            .process_shard(
+                &shard_path,
+                &MockPredict,
+                p.filter.as_ref(),
+                &Annotator::default(),
+            )
+            .unwrap();
+        let contents: Vec<&str> = docs.iter().map(|(doc, _)| doc.content().as_str()).collect();
+        assert_eq!(contents, [marked.as_str()]);
+        assert_eq!(stats.filtered.get("all"), Some(&2));
+
+        let (_, docs, stats, _, _) = gen_pipeline(None)
+            .process_shard(
+                &shard_path,
+                &MockPredict,
+                Some(&record::FilterKind::custom(Marker)),
+                &Annotator::default(),
+            )
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(stats.filtered.get("custom"), Some(&2));
+    }
+
+    #[test]
+    fn blank_lines_alignment() {
+        let long = format!("Dette er bokmål. {}", "x".repeat(100));
+        let body = format!("{long}\n\n{long}\n   \n{long}");
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(&shard_path, &[body]);
+
+        let (_, docs, _, _, _) = gen_pipeline(None)
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+
+        let doc = &docs[0].0;
+        let ids = doc.metadata().sentence_identifications();
+        assert_eq!(ids.len(), doc.content().lines().count());
+        let identified: Vec<bool> = ids.iter().map(Option::is_some).collect();
+        assert_eq!(identified, vec![true, false, true, false, true]);
+        assert_eq!(doc.identification().label().as_str(), "nb");
+    }
+
+    #[test]
+    fn doc_lang_strategy() {
+        // a short, confidently identified French passage within long, barely identified noise
+        let french = format!("Ceci est du français. {}", "x".repeat(130));
+        let noise = format!("noise {}", "y".repeat(114));
+        let body = format!("{french}\n{noise}\n{noise}\n{noise}");
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(&shard_path, &[body]);
+
+        // noise has the most bytes, but is not confident enough to make a document
+        let (_, docs, _, _, _) = gen_pipeline(None)
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert!(docs.is_empty());
+
+        let (_, docs, _, _, _) = gen_pipeline(None)
+            .with_doc_lang_strategy(DocLangStrategy::WeightedProb)
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+        let doc = &docs[0].0;
+        assert_eq!(doc.identification().label().as_str(), "fr");
+        assert!(*doc.identification().prob() >= DOC_THRESHOLD);
+    }
+
+    #[test]
+    fn sentence_segmentation() {
+        let body = format!(
+            "Dette er den første setningen. Dette er den andre setningen. X{}.",
+            "x".repeat(100)
+        );
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(&shard_path, &[body]);
+
+        let (_, docs, _, _, _) = gen_pipeline(None)
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert_eq!(docs[0].0.content().lines().count(), 1);
+        assert_eq!(docs[0].0.metadata().extra(SEGMENTED_KEY), None);
+
+        let (_, docs, _, _, _) = gen_pipeline(None)
+            .with_sentence_segmentation(500)
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        let doc = &docs[0].0;
+        let lines: Vec<&str> = doc.content().lines().collect();
+        assert_eq!(
+            lines[..2],
+            [
+                "Dette er den første setningen.",
+                "Dette er den andre setningen."
+            ]
+        );
+        assert_eq!(lines.len(), 3);
+        assert_eq!(doc.metadata().sentence_identifications().len(), 3);
+        assert_eq!(doc.metadata().extra(SEGMENTED_KEY), Some("500"));
+        // the location still points to the original line
+        assert_eq!(docs[0].1.line_start(), 0);
+        assert_eq!(docs[0].1.line_end(), 0);
+    }
+
+    #[test]
+    fn min_doc_length() {
+        let bodies = vec![
+            format!("Dette er bokmål. {}", "x".repeat(100)),
+            format!("Dette er bokmål. {}", "x".repeat(200)),
+        ];
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(&shard_path, &bodies);
+
+        let p = gen_pipeline(None).with_min_doc_length(150, 0);
+        let (_, docs, stats, _, _) = p
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+
+        assert_eq!(docs.len(), 1);
+        assert_eq!(stats.filtered.get("min_length"), Some(&1));
+    }
+
+    #[test]
+    fn body_regex_exclude() {
+        let bodies = vec![
+            format!("Lorem ipsum dolor sit amet. {}", "x".repeat(100)),
+            format!("Dette er bokmål. {}", "x".repeat(100)),
+        ];
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(&shard_path, &bodies);
+
+        let filter = record::BodyRegexExclude::new(&["^lorem ipsum"], true).unwrap();
+        let p = gen_pipeline(None).with_body_regex_exclude(filter);
+        let (_, docs, stats, _, _) = p
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+
+        assert_eq!(docs.len(), 1);
+        assert!(docs[0].0.content().starts_with("Dette er bokmål."));
+        assert_eq!(stats.filtered.get("body_regex"), Some(&1));
+    }
+
+    #[test]
+    fn quality_scores() {
+        // mostly short lines between two long ones: fails the default quality filter
+        let long = format!("Dette er bokmål. {}", "x".repeat(10));
+        let body = format!("{long}\n{}{long}", "kort kort\n".repeat(10));
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(&shard_path, &[body]);
+
+        let p = gen_pipeline(None).with_min_sentence_length(20);
+        let (_, docs, stats, _, _) = p
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert!(docs.is_empty());
+        assert_eq!(stats.filtered.get("pfilter"), Some(&1));
+
+        let p = p.with_rejected(RejectedOptions::default().with_quality_scores(true));
+        let (_, docs, stats, _, _) = p
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+        assert!(stats.filtered.is_empty());
+        let score = docs[0].0.metadata().quality_score().unwrap();
+        assert!(score > 0.0 && score < 0.6);
+    }
+
+    #[test]
+    fn strict() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        write_shard(
+            &src.path().join("0.txt.gz"),
+            &[format!("Dette er bokmål. {}", "x".repeat(200))],
+        );
+        // every record of this shard fails the length filter
+        write_shard(
+            &src.path().join("1.txt.gz"),
+            &[format!("Dette er bokmål. {}", "x".repeat(100))],
+        );
+
+        let p = gen_pipeline_at(src.path(), dst.path()).with_min_doc_length(150, 0);
+
+        // not strict
+        assert!(p.run_with(&MockPredict).is_ok());
+
+        let p = p.with_strict(0.5);
+        assert!(p.run_with(&MockPredict).is_ok());
+
+        let p = p.with_strict(0.25);
+        assert!(p.run_with(&MockPredict).is_err());
+        assert_eq!(
+            Stats::load(dst.path())
+                .unwrap()
+                .total()
+                .filtered
+                .get("min_length"),
+            Some(&1)
+        );
+    }
+
+    #[test]
+    fn header_filter() {
+        let body = format!("Dette er bokmål. {}", "x".repeat(100));
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        let gz = GzEncoder::new(File::create(&shard_path).unwrap(), Compression::default());
+        let mut writer = WarcWriter::new(gz);
+        for uri in [
+            "https://example.com/",
+            "https://spam.com/",
+            "https://ads.net/",
+        ] {
+            let mut record: Record<BufferedBody> = conversion(body.as_str());
+            record.set_header(WarcHeader::TargetURI, uri).unwrap();
+            writer.write(&record).unwrap();
+        }
+        drop(writer);
+
+        let header_filter = record::HeaderFilter::default()
+            .with_deny("warc-target-uri=^https://spam\\.com/".parse().unwrap())
+            .with_deny("warc-target-uri=^https://ads\\.net/".parse().unwrap());
+        let p = gen_pipeline(None).with_header_filter(header_filter);
+        let (_, docs, stats, _, _) = p
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+
+        assert_eq!(docs.len(), 1);
+        assert_eq!(stats.filtered.get("header"), Some(&2));
+    }
+
+    #[test]
+    fn min_sentence_length() {
+        let bodies = vec![
+            ["a".repeat(60), "short".to_string(), "b".repeat(60)].join("\n"),
+            ["c".repeat(60), "d".repeat(60)].join("\n"),
+        ];
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(&shard_path, &bodies);
+
+        // 60 codepoints sentences are short by default
+        let p = gen_pipeline(None);
+        let (_, docs, stats, _, _) = p
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert!(docs.is_empty());
+        assert_eq!(stats.no_sentences, 2);
+
+        let p = gen_pipeline(None).with_min_sentence_length(50);
+        let (_, docs, stats, _, _) = p
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(stats.no_sentences, 0);
+    }
+
+    #[test]
+    fn keep_document_language_lines() {
+        let long = |prefix: &str| format!("{prefix} {}", "x".repeat(100));
+        let bodies = vec![[
+            long("Dette er bokmål."),
+            long("Eg veit ikkje."),
+            long("Dette er også bokmål."),
+        ]
+        .join("\n")];
+        let src = tempfile::tempdir().unwrap();
+        let shard_path = src.path().join("0.txt.gz");
+        write_shard(&shard_path, &bodies);
+
+        let (_, docs, _, _, _) = gen_pipeline(None)
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        assert_eq!(docs[0].0.content().lines().count(), 3);
+
+        let (_, docs, stats, _, _) = gen_pipeline(None)
+            .with_keep_document_language_lines(true)
+            .process_shard(&shard_path, &MockPredict, None, &Annotator::default())
+            .unwrap();
+        let doc = &docs[0].0;
+        assert_eq!(
+            doc.content(),
+            &[long("Dette er bokmål."), long("Dette er også bokmål.")].join("\n")
+        );
+        assert_eq!(doc.metadata().sentence_identifications().len(), 2);
+        assert_eq!(stats.no_lang_lines, 0);
+    }
+
+    fn gen_blocklist_pipeline(bl_dir: &Path) -> OscarDoc {
+        let adult_dir = bl_dir.join("adult");
+        std::fs::create_dir(&adult_dir).unwrap();
+        std::fs::write(adult_dir.join("domains"), "foo.bar\n").unwrap();
+
+        OscarDoc::new(
+            PathBuf::from("res/shards"),
+            PathBuf::from("res/corpus"),
+            PathBuf::from("lid.176.bin"),
+            None,
+            Some(bl_dir.to_path_buf()),
+            None,
+        )
+    }
+
+    fn gen_url_document() -> Document {
+        let mut headers = HashMap::new();
+        headers.insert(WarcHeader::TargetURI, b"https://foo.bar".to_vec());
+        Document::new("foo".to_string(), headers, Metadata::default())
+    }
+
+    #[test]
+    fn content_detector_enabled() {
+        let bl_dir = tempfile::tempdir().unwrap();
+        let p = gen_blocklist_pipeline(bl_dir.path());
+
+        let mut doc = gen_url_document();
+        p.annotator().unwrap().annotate(&mut doc);
+        assert_eq!(
+            doc.metadata().categories(),
+            Some(vec!["adult".to_string()]).as_ref()
+        );
+    }
+
+    #[test]
+    fn content_detector_disabled() {
+        let bl_dir = tempfile::tempdir().unwrap();
+        let p = gen_blocklist_pipeline(bl_dir.path()).with_content_detector(false);
+
+        let mut doc = gen_url_document();
+        let mut expected = gen_url_document();
+        p.annotator().unwrap().annotate(&mut doc);
+        gen_pipeline(None)
+            .annotator()
+            .unwrap()
+            .annotate(&mut expected);
+
+        assert!(doc.metadata().categories().is_none());
+        assert_eq!(doc.metadata(), expected.metadata());
+    }
+
+    #[test]
+    fn atomic_writes() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let body = format!("Dette er bokmål. {}", "x".repeat(100));
+        write_shard(&src.path().join("0.txt.gz"), std::slice::from_ref(&body));
+
+        let p = gen_pipeline_at(src.path(), dst.path())
+            .with_output(OutputOptions::default().with_atomic_writes(true));
+        p.run_with(&MockPredict).unwrap();
+
+        // files are published at the end of the run
+        let tmps: Vec<_> = std::fs::read_dir(dst.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "tmp"))
+            .collect();
+        assert!(tmps.is_empty());
+        let lang = LanguageTag::parse("nb".to_string()).unwrap();
+        let contents: Vec<String> = CorpusReader::new(dst.path(), &lang)
+            .unwrap()
+            .map(|doc| doc.unwrap().content().to_string())
+            .collect();
+        assert_eq!(contents, [body]);
+    }
+
+    #[test]
+    fn incremental() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let old = format!("Dette er bokmål. {}", "x".repeat(100));
+        let new = format!("Dette er også bokmål. {}", "x".repeat(100));
+        write_shard(&src.path().join("0.txt.gz"), std::slice::from_ref(&old));
+        write_shard(&src.path().join("1.txt.gz"), std::slice::from_ref(&new));
+
+        let hour = Duration::from_secs(3600);
+        let now = SystemTime::now();
+        File::options()
+            .write(true)
+            .open(src.path().join("0.txt.gz"))
+            .unwrap()
+            .set_modified(now - 2 * hour)
+            .unwrap();
+        let last_run = LastRun::new(now - hour);
+        last_run.save(dst.path()).unwrap();
+
+        let p = gen_pipeline_at(src.path(), dst.path())
+            .with_resume(true)
+            .with_incremental(Duration::ZERO);
+        p.run_with(&MockPredict).unwrap();
+
+        let lang = LanguageTag::parse("nb".to_string()).unwrap();
+        let contents: Vec<String> = CorpusReader::new(dst.path(), &lang)
+            .unwrap()
+            .map(|doc| doc.unwrap().content().to_string())
+            .collect();
+        assert_eq!(contents, [new]);
+        // both shards are older than the new last run
+        assert_ne!(LastRun::load(dst.path()).unwrap(), Some(last_run));
+        assert_eq!(p.get_paths_iter().unwrap().count(), 0);
+
+        // the grace window reaches back to both shards
+        let p = p.with_incremental(3 * hour);
+        assert_eq!(p.get_paths_iter().unwrap().count(), 2);
+
+        assert!(p.with_resume(false).validate().is_err());
+    }
+
+    #[test]
+    fn provenance() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        write_shard(
+            &src.path().join("0.txt.gz"),
+            &[format!("Dette er bokmål. {}", "x".repeat(100))],
+        );
+
+        let p = OscarDoc::new(
+            src.path().to_path_buf(),
+            dst.path().to_path_buf(),
+            PathBuf::from("lid.176.bin"),
+            Some(LidParams::new(2, 0.5)),
+            None,
+            None,
+        )
+        .with_identifier(Box::new(MockPredict))
+        .with_quiet(true);
+        p.run().unwrap();
+
+        // custom identifiers have no model to hash, and don't use identification parameters
+        let provenance: serde_json::Value =
+            serde_json::from_reader(File::open(Provenance::path(dst.path())).unwrap()).unwrap();
+        assert_eq!(
+            provenance,
+            serde_json::json!({"model": null, "k": null, "threshold": null})
+        );
+    }
+
+    /// Run the pipeline on 3 fixture shards, returning the sorted contents of Bokmål documents.
+    fn run_fixture(workers: usize, max_pending_shards: usize) -> Vec<String> {
+        run_fixture_with(|p| {
+            p.with_workers(Some(workers))
+                .with_max_pending_shards(Some(max_pending_shards))
+        })
+    }
+
+    #[test]
+    fn progress() {
+        let src = tempfile::tempdir().unwrap();
+        for shard_id in 0..3 {
+            let body = format!("Dette er shard {shard_id}. {}", "x".repeat(100));
+            write_shard(&src.path().join(format!("{shard_id}.txt.gz")), &[body]);
+        }
+
+        let p = gen_pipeline_at(src.path(), Path::new("dst")).with_quiet(true);
+        let shards: Vec<PathBuf> = p.get_paths_iter().unwrap().collect();
+        let progress = Progress::new(shards.len(), true);
+        let mut nb_consumed = 0;
+        p.process_shards(
+            shards,
+            &MockPredict,
+            &Annotator::default(),
+            &progress,
+            |_, _, _| nb_consumed += 1,
+        )
+        .unwrap();
+
+        assert_eq!(nb_consumed, 3);
+        assert_eq!(progress.done(), 3);
+    }
+
+    /// Records the lines it identifies, identifying them as [MockPredict] does.
+    struct RecordingPredict(Mutex<HashSet<String>>);
+
+    impl Predict<String> for RecordingPredict {
+        fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+            self.0.lock().unwrap().insert(line.to_string());
+            MockPredict.predict_one(line)
+        }
+
+        fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+            self.0.lock().unwrap().insert(line.to_string());
+            MockPredict.predict(line)
+        }
+
+        fn weighted_ids(&self, lines: Lines) -> Result<DocIdentification<String>, Error> {
+            self.weighted_ids_topk(lines)
+        }
+    }
+
+    #[test]
+    fn slow_writes() {
+        let src = tempfile::tempdir().unwrap();
+        let body = |shard_id| format!("Dette er shard {shard_id}. {}", "x".repeat(100));
+        let shards: Vec<PathBuf> = (0..8)
+            .map(|shard_id| {
+                let path = src.path().join(format!("{shard_id}.txt.gz"));
+                write_shard(&path, &[body(shard_id)]);
+                path
+            })
+            .collect();
+
+        let p = gen_pipeline_at(src.path(), Path::new("dst"))
+            .with_quiet(true)
+            .with_workers(Some(2))
+            .with_max_pending_shards(Some(1));
+        let identifier = RecordingPredict(Mutex::new(HashSet::new()));
+        let progress = Progress::new(shards.len(), true);
+        let mut written = Vec::new();
+        let mut max_in_flight = 0;
+        p.process_shards(
+            shards,
+            &identifier,
+            &Annotator::default(),
+            &progress,
+            |_, _, shard_result| {
+                // shards that have been picked up by workers but are not written yet
+                let started = identifier.0.lock().unwrap().len();
+                max_in_flight = max_in_flight.max(started - written.len());
+
+                std::thread::sleep(Duration::from_millis(20));
+                let (_, docs, _, _, _) = shard_result.unwrap();
+                written.extend(docs.into_iter().map(|(doc, _)| doc.content().to_string()));
+            },
+        )
+        .unwrap();
+
+        // workers, pending shards and the shard being written
+        assert!(
+            max_in_flight <= 2 + 1 + 1,
+            "{max_in_flight} shards in flight"
+        );
+        written.sort();
+        assert_eq!(written, (0..8).map(body).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn write_queue() {
+        let dst = tempfile::tempdir().unwrap();
+        let rebuild_dir = dst.path().join("rebuild");
+        let rebuild_files = RebuildWriters::with_dst(&rebuild_dir).unwrap();
+        let nb = LanguageTag::parse("nb".to_string()).unwrap();
+        let written = Mutex::new(Vec::new());
+
+        let (writer, documents) = std::sync::mpsc::sync_channel(1);
+        let result = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                OscarDoc::write_documents(&rebuild_files, &rebuild_dir, documents, |_, doc| {
+                    std::thread::sleep(Duration::from_millis(5));
+                    written.lock().unwrap().push(doc.content().to_string());
+                    Ok(())
+                })
+            });
+
+            for i in 0..20 {
+                let doc = gen_doc(&format!("document {i}"));
+                let message =
+                    WriteMessage::Document(Box::new((nb.clone(), doc, Location::default())));
+                writer.send(message).unwrap();
+                // one document waiting, and one being written
+                let nb_written = written.lock().unwrap().len();
+                assert!(
+                    i + 1 - nb_written <= 2,
+                    "{} documents in flight",
+                    i + 1 - nb_written
+                );
+            }
+
+            let (done, result) = std::sync::mpsc::sync_channel(1);
+            writer.send(WriteMessage::ShardEnd(0, done)).unwrap();
+            let result = result.recv().unwrap();
+            drop(writer);
+            result
+        });
+
+        let written_shard = result.unwrap();
+        assert_eq!(written_shard.len(), 1);
+        assert_eq!(written_shard[0].0, "nb");
+        assert_eq!(written_shard[0].1.documents, 20);
+        let expected: Vec<String> = (0..20).map(|i| format!("document {i}")).collect();
+        assert_eq!(written.into_inner().unwrap(), expected);
+        assert!(rebuild_files.contains(&nb));
+
+        // runs write the same documents through a queue of one
+        assert_eq!(
+            run_fixture_with(|p| p.with_write_queue(1).with_workers(Some(2))),
+            run_fixture(1, 1)
+        );
+    }
+
+    /// Identifies lines as [RecordingPredict] does, taking its time on the first shard.
+    struct SlowFirstShard(RecordingPredict);
+
+    impl Predict<String> for SlowFirstShard {
+        fn predict_one(&self, line: &str) -> Result<Option<Identification<String>>, Error> {
+            let id = self.0.predict_one(line);
+            if line.contains("shard 0") {
+                std::thread::sleep(Duration::from_millis(200));
+            }
+            id
+        }
+
+        fn predict(&self, line: &str) -> Result<Option<Vec<Identification<String>>>, Error> {
+            let ids = self.0.predict(line);
+            if line.contains("shard 0") {
+                std::thread::sleep(Duration::from_millis(200));
+            }
+            ids
+        }
+
+        fn weighted_ids(&self, lines: Lines) -> Result<DocIdentification<String>, Error> {
+            self.weighted_ids_topk(lines)
+        }
+    }
+
+    #[test]
+    fn deterministic_window() {
+        let src = tempfile::tempdir().unwrap();
+        let body = |shard_id| format!("Dette er shard {shard_id}. {}", "x".repeat(100));
+        let shards: Vec<PathBuf> = (0..8)
+            .map(|shard_id| {
+                let path = src.path().join(format!("{shard_id}.txt.gz"));
+                write_shard(&path, &[body(shard_id)]);
+                path
+            })
+            .collect();
+
+        let p = gen_pipeline_at(src.path(), Path::new("dst"))
+            .with_quiet(true)
+            .with_deterministic(true)
+            .with_threads(4)
+            .with_workers(Some(2))
+            .with_max_pending_shards(Some(1));
+        let identifier = SlowFirstShard(RecordingPredict(Mutex::new(HashSet::new())));
+        let progress = Progress::new(shards.len(), true);
+        let mut written = Vec::new();
+        let mut max_in_flight = 0;
+        p.process_shards(
+            shards,
+            &identifier,
+            &Annotator::default(),
+            &progress,
+            |_, _, shard_result| {
+                // shards that have been picked up by workers but are not written yet
+                let started = (identifier.0).0.lock().unwrap().len();
+                max_in_flight = max_in_flight.max(started - written.len());
+
+                let (_, docs, _, _, _) = shard_result.unwrap();
+                written.extend(docs.into_iter().map(|(doc, _)| doc.content().to_string()));
+            },
+        )
+        .unwrap();
+
+        // other shards don't pile up while the first one is processed
+        assert!(max_in_flight <= 2 + 1, "{max_in_flight} shards in flight");
+        assert_eq!(written, (0..8).map(body).collect::<Vec<_>>());
+    }
+
+    /// Run the pipeline configured by `configure` on 3 fixture shards,
+    /// returning the sorted contents of Bokmål documents.
+    fn run_fixture_with(configure: impl FnOnce(OscarDoc) -> OscarDoc) -> Vec<String> {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        for shard_id in 0..3 {
+            let bodies: Vec<String> = (0..4)
+                .map(|doc_id| {
+                    format!(
+                        "Dette er dokument {doc_id} i shard {shard_id}. {}",
+                        "x".repeat(100)
+                    )
+                })
+                .collect();
+            write_shard(&src.path().join(format!("{shard_id}.txt.gz")), &bodies);
+        }
+
+        let p = gen_pipeline_at(src.path(), dst.path());
+        configure(p).run_with(&MockPredict).unwrap();
+
+        let lang = LanguageTag::parse("nb".to_string()).unwrap();
+        let mut contents: Vec<String> = CorpusReader::new(dst.path(), &lang)
+            .unwrap()
+            .map(|doc| doc.unwrap().content().to_string())
+            .collect();
+        contents.sort();
+        contents
+    }
+
+    #[test]
+    fn deterministic() {
+        let src = tempfile::tempdir().unwrap();
+        for shard_id in 0..4 {
+            let bodies: Vec<String> = (0..30)
+                .map(|doc_id| {
+                    format!(
+                        "Dette er dokument {doc_id} i shard {shard_id}. {}",
+                        "x".repeat(100 + doc_id * 10)
+                    )
+                })
+                .collect();
+            write_shard(&src.path().join(format!("{shard_id}.txt.gz")), &bodies);
+        }
+
+        // language files, by name
+        let run = || {
+            let dst = tempfile::tempdir().unwrap();
+            gen_pipeline_at(src.path(), dst.path())
+                .with_workers(Some(3))
+                .with_output(OutputOptions::default().with_part_size(Some(2000)))
+                .with_deterministic(true)
+                .run_with(&MockPredict)
+                .unwrap();
+
+            let mut files: Vec<(String, Vec<u8>)> = std::fs::read_dir(dst.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("jsonl"))
+                .map(|path| {
+                    let name = path.file_name().unwrap().to_str().unwrap().to_string();
+                    (name, std::fs::read(path).unwrap())
+                })
+                .collect();
+            files.sort();
+
+            // documents are in shard then record order, across parts
+            let lang = LanguageTag::parse("nb".to_string()).unwrap();
+            let contents: Vec<String> = CorpusReader::new(dst.path(), &lang)
+                .unwrap()
+                .map(|doc| {
+                    let doc = doc.unwrap();
+                    doc.content().split('.').next().unwrap().to_string()
+                })
+                .collect();
+            (files, contents)
+        };
+
+        let (first, contents) = run();
+        let (second, _) = run();
+        assert!(first.len() > 1, "language files should be rotated");
+        assert!(first == second);
+
+        let expected: Vec<String> = (0..4)
+            .flat_map(|shard_id| {
+                (0..30).map(move |doc_id| format!("Dette er dokument {doc_id} i shard {shard_id}"))
+            })
+            .collect();
+        assert_eq!(contents, expected);
+    }
+
+    #[test]
+    fn lang_budgets() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        for shard_id in 0..3 {
+            let bodies: Vec<String> = (0..2)
+                .map(|i| format!("Dette er dokument {i}. {}", "x".repeat(100)))
+                .chain(std::iter::once(format!(
+                    "Dette er ikkje bokmål. {}",
+                    "x".repeat(100)
+                )))
+                .collect();
+            write_shard(&src.path().join(format!("{shard_id}.txt.gz")), &bodies);
+        }
+
+        // budget reached by the third Bokmål document
+        let nb = LanguageTag::parse("nb".to_string()).unwrap();
+        gen_pipeline_at(src.path(), dst.path())
+            .with_lang_budgets([(nb.clone(), 250)].into())
+            .run_with(&MockPredict)
+            .unwrap();
+
+        assert_eq!(CorpusReader::new(dst.path(), &nb).unwrap().count(), 3);
+        let nn = LanguageTag::parse("nn".to_string()).unwrap();
+        assert_eq!(CorpusReader::new(dst.path(), &nn).unwrap().count(), 3);
+
+        let stats = Stats::load(dst.path()).unwrap();
+        assert_eq!(stats.total().over_budget, 3);
+        assert_eq!(stats.total().documents.get("nb"), Some(&3));
+    }
+
+    #[test]
+    fn limit() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        for shard_id in 0..10 {
+            let bodies: Vec<String> = (0..4)
+                .map(|i| {
+                    let lang = if i % 2 == 0 { "bokmål" } else { "ikkje" };
+                    format!("Dokument {shard_id}-{i} på {lang}. {}", "x".repeat(100))
+                })
+                .collect();
+            write_shard(&src.path().join(format!("{shard_id}.txt.gz")), &bodies);
+        }
+
+        gen_pipeline_at(src.path(), dst.path())
+            .with_workers(Some(2))
+            .with_limit(5)
+            .with_quiet(true)
+            .run_with(&MockPredict)
+            .unwrap();
+
+        let count = |lang: &str| {
+            let lang = LanguageTag::parse(lang.to_string()).unwrap();
+            CorpusReader::new(dst.path(), &lang).map_or(0, |reader| reader.count())
+        };
+        assert_eq!(count("nb") + count("nn"), 5);
+
+        // the run stopped early, and is recorded in stats and manifest
+        let checkpoint = Checkpoint::load(dst.path()).unwrap();
+        let recorded = (0..10)
+            .filter(|i| checkpoint.contains(&src.path().join(format!("{i}.txt.gz"))))
+            .count();
+        assert!(recorded < 10);
+        let stats = Stats::load(dst.path()).unwrap();
+        assert_eq!(stats.total().documents.values().sum::<usize>(), 5);
+        assert!(Manifest::path(dst.path()).exists());
+
+        // the truncated shard is recorded, with its dropped documents
+        assert!(recorded >= 2);
+        assert_eq!(stats.total().over_limit, 4 * recorded - 5);
+    }
+
+    #[test]
+    fn max_duration() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        for shard_id in 0..10 {
+            let bodies = vec![format!(
+                "Dokument {shard_id} på bokmål. {}",
+                "x".repeat(100)
+            )];
+            write_shard(&src.path().join(format!("{shard_id}.txt.gz")), &bodies);
+        }
+        let pipeline = |resume: bool| {
+            gen_pipeline_at(src.path(), dst.path())
+                .with_workers(Some(1))
+                .with_resume(resume)
+                .with_quiet(true)
+        };
+        let recorded = || {
+            let checkpoint = Checkpoint::load(dst.path()).unwrap();
+            (0..10)
+                .filter(|i| checkpoint.contains(&src.path().join(format!("{i}.txt.gz"))))
+                .count()
+        };
+
+        // the deadline is reached before the first shard
+        pipeline(false)
+            .with_max_duration(Duration::ZERO)
+            .run_with(&MockPredict)
+            .unwrap();
+        assert_eq!(recorded(), 0);
+        assert!(Stats::load(dst.path()).is_ok());
+        assert!(Manifest::path(dst.path()).exists());
+
+        // the run can be resumed
+        pipeline(true).run_with(&MockPredict).unwrap();
+        assert_eq!(recorded(), 10);
+    }
+
+    #[test]
+    fn resume_partial_shard() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let write = |shard_id: usize| {
+            let bodies: Vec<String> = (0..4)
+                .map(|i| {
+                    let lang = if i % 2 == 0 { "bokmål" } else { "ikkje" };
+                    format!("Dokument {shard_id}-{i} på {lang}. {}", "x".repeat(100))
+                })
+                .collect();
+            write_shard(&src.path().join(format!("{shard_id}.txt.gz")), &bodies);
+        };
+        let pipeline = |resume: bool| {
+            gen_pipeline_at(src.path(), dst.path())
+                .with_resume(resume)
+                .with_quiet(true)
+        };
+        let contents = |lang: &str| -> Vec<String> {
+            let lang = LanguageTag::parse(lang.to_string()).unwrap();
+            CorpusReader::new(dst.path(), &lang)
+                .unwrap()
+                .map(|doc| doc.unwrap().content().to_string())
+                .collect()
+        };
+        let shard_ids = |lang: &str| -> Vec<i64> {
+            let mut shard_ids: Vec<i64> = std::fs::read_dir(dst.path().join("rebuild"))
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.to_string_lossy().contains(&format!("/{lang}")))
+                .flat_map(|path| {
+                    avro_rs::Reader::new(File::open(path).unwrap())
+                        .unwrap()
+                        .map(|r| avro_rs::from_value::<ShardResult>(&r.unwrap()).unwrap())
+                        .map(|sr| sr.shard_id())
+                        .collect::<Vec<_>>()
+                })
+                .collect();
+            shard_ids.sort();
+            shard_ids
+        };
+
+        write(0);
+        pipeline(false).run_with(&MockPredict).unwrap();
+        let nn_path = dst.path().join("nn_meta.jsonl");
+        let snapshot: Vec<(PathBuf, Vec<u8>)> = [
+            Checkpoint::path(dst.path()),
+            Stats::path(dst.path()),
+            nn_path,
+        ]
+        .into_iter()
+        .map(|path| {
+            let content = std::fs::read(&path).unwrap();
+            (path, content)
+        })
+        .collect();
+
+        // the run dies while writing shard 1, once its nb documents are written:
+        // nn documents, the checkpoint and stats are as they were before
+        write(1);
+        pipeline(true).run_with(&MockPredict).unwrap();
+        assert_eq!(contents("nb").len(), 4);
+        for (path, content) in snapshot {
+            std::fs::write(path, content).unwrap();
+        }
+
+        pipeline(true).run_with(&MockPredict).unwrap();
+        for lang in ["nb", "nn"] {
+            let contents = contents(lang);
+            let unique: HashSet<&String> = contents.iter().collect();
+            assert_eq!(contents.len(), 4, "{lang}");
+            assert_eq!(unique.len(), 4, "{lang}");
+            assert_eq!(shard_ids(lang), [0, 1], "{lang}");
+        }
+        let stats = Stats::load(dst.path()).unwrap();
+        assert_eq!(stats.total().documents.get("nb"), Some(&4));
+    }
+
+    #[test]
+    fn shard_list() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        for shard_id in 0..3 {
+            let bodies = vec![format!(
+                "Dokument {shard_id} på bokmål. {}",
+                "x".repeat(100)
+            )];
+            write_shard(&src.path().join(format!("{shard_id}.txt.gz")), &bodies);
+        }
+        let shard_list = src.path().join("shards.txt");
+        let absolute = src.path().join("2.txt.gz");
+        std::fs::write(
+            &shard_list,
+            format!("0.txt.gz\n\n{}\nmissing.txt.gz\n", absolute.display()),
+        )
+        .unwrap();
+
+        gen_pipeline_at(Path::new("unused"), dst.path())
+            .with_shard_list(shard_list)
+            .with_quiet(true)
+            .run_with(&MockPredict)
+            .unwrap();
+
+        let checkpoint = Checkpoint::load(dst.path()).unwrap();
+        let recorded: Vec<bool> = (0..3)
+            .map(|i| checkpoint.contains(&src.path().join(format!("{i}.txt.gz"))))
+            .collect();
+        assert_eq!(recorded, vec![true, false, true]);
+        let nb = LanguageTag::parse("nb".to_string()).unwrap();
+        assert_eq!(CorpusReader::new(dst.path(), &nb).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn min_lang_documents() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        for shard_id in 0..3 {
+            let mut bodies: Vec<String> = (0..2)
+                .map(|i| format!("Dokument {shard_id}-{i} på bokmål. {}", "x".repeat(100)))
+                .collect();
+            if shard_id < 2 {
+                bodies.push(format!(
+                    "Document {shard_id} en français. {}",
+                    "x".repeat(100)
+                ));
+            }
+            write_shard(&src.path().join(format!("{shard_id}.txt.gz")), &bodies);
+        }
+
+        // one part per shard
+        gen_pipeline_at(src.path(), dst.path())
+            .with_output(OutputOptions::default().with_part_size(Some(1)))
+            .with_min_lang_documents(3)
+            .with_quiet(true)
+            .run_with(&MockPredict)
+            .unwrap();
+
+        let nb = LanguageTag::parse("nb".to_string()).unwrap();
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        assert_eq!(CorpusReader::new(dst.path(), &nb).unwrap().count(), 6);
+        assert!(CorpusReader::new(dst.path(), &fr).is_err());
+
+        // every part is moved
+        let discarded = dst.path().join("discarded");
+        assert_eq!(CorpusReader::new(&discarded, &fr).unwrap().count(), 2);
+        assert_eq!(CorpusReader::new(&discarded, &fr).unwrap().paths().len(), 2);
+
+        let manifest = std::fs::read_to_string(Manifest::path(dst.path())).unwrap();
+        assert!(manifest.contains("nb_meta_part_1.jsonl"));
+        assert!(!manifest.contains("fr_meta"));
+    }
+
+    #[test]
+    fn only_present_languages() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        for shard_id in 0..2 {
+            let bodies: Vec<String> = (0..3)
+                .map(|i| format!("Ceci est du français, numéro {i}. {}", "x".repeat(100)))
+                .collect();
+            write_shard(&src.path().join(format!("{shard_id}.txt.gz")), &bodies);
+        }
+
+        gen_pipeline_at(src.path(), dst.path())
+            .with_quiet(true)
+            .run_with(&MockPredict)
+            .unwrap();
+
+        let list = |dir: &Path| {
+            let mut names: Vec<String> = std::fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            list(dst.path()),
+            vec![
+                "checkpoint.json",
+                "fr_meta.jsonl",
+                "manifest.json",
+                "rebuild",
+                "stats.json"
+            ]
+        );
+        let rebuild = list(&dst.path().join("rebuild"));
+        assert!(!rebuild.is_empty());
+        assert!(rebuild.iter().all(|name| name.starts_with("fr")));
+    }
+
+    #[test]
+    fn manifest() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let bodies: Vec<String> = (0..3)
+            .map(|i| format!("Dette er dokument {i}. {}", "x".repeat(100)))
+            .collect();
+        write_shard(&src.path().join("0.txt.gz"), &bodies);
+
+        gen_pipeline_at(src.path(), dst.path())
+            .with_output(
+                OutputOptions::default()
+                    .with_part_size(Some(1))
+                    .with_compression(crate::io::Compression::Gzip),
+            )
+            .run_with(&MockPredict)
+            .unwrap();
+
+        let manifest: Manifest =
+            serde_json::from_reader(File::open(Manifest::path(dst.path())).unwrap()).unwrap();
+        assert_eq!(manifest.files.len(), 3);
+        assert_eq!(manifest.files.iter().map(|f| f.lines).sum::<u64>(), 3);
+        for entry in &manifest.files {
+            let filename = entry.path.to_str().unwrap();
+            assert!(filename.starts_with("nb_meta_part_") && filename.ends_with(".jsonl.gz"));
+            let path = dst.path().join(&entry.path);
+            assert_eq!(entry.bytes, std::fs::metadata(path).unwrap().len());
+        }
+    }
+
+    #[test]
+    fn threads_output_independent() {
+        let default = run_fixture_with(|p| p);
+        assert_eq!(default.len(), 12);
+        assert_eq!(run_fixture_with(|p| p.with_threads(1)), default);
+        assert_eq!(run_fixture_with(|p| p.with_threads(0)), default);
+    }
+
+    #[test]
+    fn workers_output_independent() {
+        let sequential = run_fixture(1, 1);
+        assert_eq!(sequential.len(), 12);
+        assert_eq!(run_fixture(3, 1), sequential);
+        assert_eq!(run_fixture(4, 8), sequential);
+    }
+
+    #[test]
+    fn lang_distribution() {
+        let body = "Dette er en setning på bokmål.
+Dette er kanskje nynorsk, kanskje ikkje.
+Dette er en annen setning på bokmål.";
+        let record: Record<BufferedBody> = conversion(body);
+
+        let p = gen_pipeline(Some(
+            LidParams::new(2, 0.8)
+                .with_topk_weighting()
+                .with_lang_distribution(),
+        ));
+        let doc = p.process_record(record, &MockPredict).unwrap().unwrap();
+
+        let distribution: HashMap<&str, f32> = doc
+            .metadata()
+            .extra("lang_distribution")
+            .unwrap()
+            .split(',')
+            .map(|pair| {
+                let (lang, share) = pair.split_once('=').unwrap();
+                (lang, share.parse().unwrap())
+            })
+            .collect();
+
+        assert_eq!(distribution.len(), 2);
+        assert!(distribution["nb"] > distribution["nn"]);
+        let total: f32 = distribution.values().sum();
+        assert!((total - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn lang_distribution_disabled() {
+        let record: Record<BufferedBody> = conversion("Dette er bokmål.");
+        let p = gen_pipeline(None);
+        let doc = p.process_record(record, &MockPredict).unwrap().unwrap();
+        assert_eq!(doc.metadata().extra("lang_distribution"), None);
+    }
+
+    fn lang_set(langs: &[&str]) -> HashSet<LanguageTag<String>> {
+        langs
+            .iter()
+            .map(|lang| LanguageTag::parse(lang.to_string()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn allowed_langs() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let bodies = vec![
+            format!("Dette er bokmål. {}", "x".repeat(100)),
+            format!("Eg veit ikkje. {}", "x".repeat(100)),
+        ];
+        write_shard(&src.path().join("0.txt.gz"), &bodies);
+
+        let p = gen_pipeline_at(src.path(), dst.path()).with_allowed_langs(lang_set(&["nb"]));
+        p.run_with(&MockPredict).unwrap();
+
+        let lang = LanguageTag::parse("nb".to_string()).unwrap();
+        assert_eq!(CorpusReader::new(dst.path(), &lang).unwrap().count(), 1);
+
+        // no file at all for excluded languages
+        let nn_files = std::fs::read_dir(dst.path())
+            .unwrap()
+            .filter(|f| {
+                f.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("nn")
+            })
+            .count();
+        assert_eq!(nn_files, 0);
+        assert!(!dst.path().join("rebuild").join("nn.avro").exists());
+    }
+
+    #[test]
+    fn secondary_lang_share() {
+        let body = format!(
+            "Eg veit ikkje. {0}\nEg veit ikkje heller. {0}\nDette er bokmål. {0}",
+            "x".repeat(50)
+        );
+        let label = |p: &OscarDoc| {
+            let record: Record<BufferedBody> = conversion(body.as_str());
+            p.process_record(record, &MockPredict)
+                .unwrap()
+                .unwrap()
+                .identification()
+                .label()
+                .to_string()
+        };
+
+        let p = gen_pipeline(None).with_allowed_langs(lang_set(&["nb"]));
+        assert_eq!(label(&p), "nn");
+
+        // bokmål makes for about a third of the document
+        let p = p.with_secondary_lang_share(0.3);
+        assert_eq!(label(&p), "nb");
+
+        let p = p.with_secondary_lang_share(0.5);
+        assert_eq!(label(&p), "nn");
+    }
+
+    #[test]
+    fn warc_info_roundtrip() {
+        let dst = tempfile::tempdir().unwrap();
+        let mut record: Record<BufferedBody> = conversion("Dette er bokmål.");
+        record
+            .set_header(WarcHeader::TargetURI, "https://example.com/")
+            .unwrap();
+        record
+            .set_header(WarcHeader::Date, "2021-02-24T17:02:28Z")
+            .unwrap();
+
+        let p = gen_pipeline(None).with_crawl_info(true);
+        let doc = p.process_record(record, &MockPredict).unwrap().unwrap();
+
+        let lang = LanguageTag::parse("nb".to_string()).unwrap();
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), None).unwrap();
+        w.write_single(&doc).unwrap();
+        drop(w);
+
+        let doc = CorpusReader::new(dst.path(), &lang)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(doc.metadata().url(), Some("https://example.com/"));
+        assert_eq!(
+            doc.metadata().warc_date(),
+            Some(Utc.with_ymd_and_hms(2021, 2, 24, 17, 2, 28).unwrap())
+        );
+    }
+
+    #[test]
+    fn dry_run() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let bodies = vec![
+            format!("Dette er bokmål. {}", "x".repeat(100)),
+            format!("Dette er også bokmål. {}", "x".repeat(100)),
+            format!("Eg veit ikkje. {}", "x".repeat(100)),
+        ];
+        write_shard(&src.path().join("0.txt.gz"), &bodies);
+
+        let p = gen_pipeline_at(src.path(), dst.path())
+            .with_identifier(Box::new(MockPredict))
+            .with_dry_run(true);
+        let summary = p.run().unwrap().unwrap();
+        assert_eq!(std::fs::read_dir(dst.path()).unwrap().count(), 0);
+        assert_eq!(summary.langs()["nb"].documents, 2);
+        assert_eq!(summary.langs()["nn"].documents, 1);
+        assert_eq!(summary.total().documents, 3);
+
+        // documents that wouldn't be written aren't counted
+        let limited = gen_pipeline_at(src.path(), dst.path())
+            .with_identifier(Box::new(MockPredict))
+            .with_dry_run(true)
+            .with_limit(2);
+        assert_eq!(limited.run().unwrap().unwrap().total().documents, 2);
+
+        // sizes match the files of an actual run
+        assert_eq!(p.with_dry_run(false).run().unwrap(), None);
+        for (lang, size) in summary.langs() {
+            let file_size = std::fs::metadata(dst.path().join(format!("{lang}_meta.jsonl")))
+                .unwrap()
+                .len();
+            assert_eq!(size.bytes as u64, file_size);
+        }
+    }
+
+    #[test]
+    fn custom_identifier() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let bodies = vec![format!("Dette er bokmål. {}", "x".repeat(100))];
+        write_shard(&src.path().join("0.txt.gz"), &bodies);
+
+        // there's no model at lid_path: the custom identifier is used instead
+        let p = OscarDoc::new(
+            src.path().to_path_buf(),
+            dst.path().to_path_buf(),
+            PathBuf::from("does/not/exist.bin"),
+            None,
+            None,
+            None,
+        )
+        .with_identifier(Box::new(FrenchPredict));
+        p.run().unwrap();
+
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let docs: Vec<Document> = CorpusReader::new(dst.path(), &lang)
+            .unwrap()
+            .map(|doc| doc.unwrap())
+            .collect();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].identification().label().as_str(), "fr");
+    }
+
+    #[test]
+    fn builder() {
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        let p = OscarDoc::builder(
+            PathBuf::from("shards"),
+            PathBuf::from("corpus"),
+            PathBuf::from("lid.176.bin"),
+        )
+        .lid(LidParams::new(3, 0.9))
+        .filter(record::FilterKind::MinLength(record::MinLength::new(10, 0)))
+        .allowed_langs([fr.clone()])
+        .output(OutputOptions::default().with_compression(crate::io::Compression::Zstd))
+        .dedup(DedupOptions::default().with_near_duplicates(0.8))
+        .threads(4)
+        .build()
+        .unwrap();
+
+        assert_eq!(p.lid_params.k, 3);
+        assert_eq!(p.lid_params.threshold, 0.9);
+        assert!(p.filter.is_some());
+        assert_eq!(p.allowed_langs, Some(HashSet::from([fr])));
+        assert_eq!(p.output.compression, crate::io::Compression::Zstd);
+        assert_eq!(p.dedup.near_duplicates, Some(0.8));
+        assert_eq!(p.threads, 4);
+
+        // unset options keep their defaults
+        assert_eq!(p.doc_lang_strategy, DocLangStrategy::default());
+        assert_eq!(p.output.naming, crate::io::NamingScheme::Flat);
+        assert_eq!(p.remote, RemoteOptions::default());
+        assert_eq!(p.rejected, RejectedOptions::default());
+        assert!(p.blocklist.is_none());
+        assert!(p.workers.is_none());
+        assert!(p.content_detector);
+        assert!(!p.resume);
+    }
+
+    #[test]
+    fn builder_invalid() {
+        let builder = || {
+            OscarDoc::builder(
+                PathBuf::from("shards"),
+                PathBuf::from("corpus"),
+                PathBuf::from("lid.176.bin"),
+            )
+        };
+        assert!(builder().build().is_ok());
+        assert!(builder().allowed_langs([]).build().is_err());
+        assert!(builder().lid(LidParams::new(1, 1.5)).build().is_err());
+        assert!(builder().multi_threshold(-0.1).build().is_err());
+        assert!(builder().workers(0).build().is_err());
+        assert!(builder()
+            .dedup(DedupOptions::default().with_near_duplicates(1.5))
+            .build()
+            .is_err());
+        assert!(builder()
+            .remote(RemoteOptions::default().with_download_tasks(0))
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! Language files layout, and files written alongside them (see [OutputOptions]).
+use std::path::Path;
+
+use crate::io::{Compression, LangFilesDoc, NamingScheme};
+
+use super::OscarDoc;
+
+/// Output options (see [OscarDoc::with_output]).
+///
+/// Documents are written in a language file per language, as JSON lines (see [LangFilesDoc]).
+/// Files can be compressed, laid out in subdirectories and rotated into parts,
+/// and rejected records (see [super::RejectedOptions]) are written the same way.
+/// Other files can be written alongside them, to describe or rebuild the corpus.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputOptions {
+    /// language files compression
+    pub compression: Compression,
+    /// language files layout
+    pub naming: NamingScheme,
+    /// size of language file parts, in bytes
+    pub part_size: Option<u64>,
+    /// store the byte offset of documents in their metadata
+    pub byte_offsets: bool,
+    /// write language files to temporary files
+    pub atomic_writes: bool,
+    /// write line identifications in separate files
+    pub line_ids: bool,
+    /// write language sizes in a CSV file
+    pub languages_csv: bool,
+    /// keep the original bodies of records
+    pub raw_bodies: bool,
+}
+
+impl OutputOptions {
+    /// Compress language files (see [Compression]).
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Lay language files out following `naming` (see [NamingScheme]).
+    pub fn with_naming_scheme(mut self, naming: NamingScheme) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// Rotate language files when they would exceed `part_size` bytes.
+    pub fn with_part_size(mut self, part_size: Option<u64>) -> Self {
+        self.part_size = part_size;
+        self
+    }
+
+    /// Store the byte offset of each document in its language file in its metadata
+    /// (see [crate::pipelines::oscardoc::types::ByteOffsetMetadata]),
+    /// so that documents can be read without scanning files. Offsets are in uncompressed files.
+    pub fn with_byte_offsets(mut self, byte_offsets: bool) -> Self {
+        self.byte_offsets = byte_offsets;
+        self
+    }
+
+    /// Write language files (rejected ones included) to `.tmp` files, renamed to their final name
+    /// once complete, i.e. when rotated or at the end of the run (see [crate::io::WriterDoc::with_atomic_writes]).
+    ///
+    /// Language files otherwise grow while they are written, which tools watching `dst` during runs may pick up.
+    pub fn with_atomic_writes(mut self, atomic_writes: bool) -> Self {
+        self.atomic_writes = atomic_writes;
+        self
+    }
+
+    /// Also write line identifications in separate files, once the run is done
+    /// (see [crate::pipelines::oscardoc::types::LineIds]).
+    ///
+    /// Documents identified as a whole have no line identifications (see [OscarDoc::with_doc_lid_only]).
+    pub fn with_line_ids(mut self, line_ids: bool) -> Self {
+        self.line_ids = line_ids;
+        self
+    }
+
+    /// Also save the sizes of the written languages in `<dst>/languages.csv`, once the run is done
+    /// (see [crate::pipelines::oscardoc::types::Stats::save_languages]).
+    pub fn with_languages_csv(mut self, languages_csv: bool) -> Self {
+        self.languages_csv = languages_csv;
+        self
+    }
+
+    /// Keep the original bodies of the records that yield documents byte for byte
+    /// (content is otherwise converted to UTF-8 lossily and cleaned) in `<dst>/raw/<shard number>.gz`,
+    /// with an index mapping WARC record ids to their location (see [crate::pipelines::oscardoc::types::RawBodies]).
+    ///
+    /// With [OscarDoc::with_warc_input] or [OscarDoc::with_merge_same_uri], these are the bodies of the converted
+    /// or merged records. Bodies of a shard are held in memory until it is written, so this is opt-in.
+    pub fn with_raw_bodies(mut self, raw_bodies: bool) -> Self {
+        self.raw_bodies = raw_bodies;
+        self
+    }
+}
+
+impl OscarDoc {
+    /// Language files writing in `dir`, as configured.
+    pub(super) fn langfiles(&self, dir: &Path) -> LangFilesDoc {
+        LangFilesDoc::new(dir, self.output.part_size)
+            .with_compression(self.output.compression)
+            .with_naming_scheme(self.output.naming)
+            .with_sorted_keys(self.deterministic)
+            .with_byte_offsets(self.output.byte_offsets)
+            .with_atomic_writes(self.output.atomic_writes)
+    }
+}
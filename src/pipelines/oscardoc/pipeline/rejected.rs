@@ -0,0 +1,265 @@
+//! Rejected records and documents (see [RejectedOptions]).
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use oscar_io::v3::{Document, Metadata, WriterTrait};
+use oxilangtag::LanguageTag;
+use warc::{BufferedBody, Record};
+
+use crate::error::Error;
+use crate::identifiers::identification::Identification;
+use crate::io::{LangFilesDoc, Position};
+use crate::pipelines::oscardoc::types::ExtraMetadata;
+use crate::pipelines::oscardoc::types::WarcMetadata;
+
+use super::{OscarDoc, REJECTED_LANG};
+
+/// What becomes of records and documents that would otherwise be discarded (see [OscarDoc::with_rejected]).
+///
+/// By default, they are discarded, and only counted in the run [crate::pipelines::oscardoc::types::Stats].
+///
+/// # Harvesting
+/// When harvesting (see [RejectedOptions::with_harvest]), records failing the quality filter or with no language
+/// are written in `<dst>/rejected/und_meta.jsonl`, with their reason as `rejected` extra metadata.
+/// Unparseable records can't be rebuilt as documents: their errors are dumped in `<dst>/rejected/unparseable.txt`.
+/// Rejected files are laid out like language files (see [super::OutputOptions]), and truncated as well when resuming.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RejectedOptions {
+    /// keep rejected records in `<dst>/rejected`
+    pub harvest: bool,
+    /// keep documents that fail the quality filter, with their score
+    pub quality_scores: bool,
+    /// keep low-confidence documents, labelled as undetermined
+    pub undetermined: bool,
+}
+
+impl RejectedOptions {
+    /// Keep records that fail the quality filter or have no language in `<dst>/rejected`,
+    /// along with unparseable records errors (see [harvesting](RejectedOptions#harvesting)).
+    ///
+    /// Reasons are `no_language`, and `filtered_short` or `filtered_<filter name>`
+    /// depending on the filter that dropped the record (see [crate::filtering::record::FilterKind::rejection_reason]).
+    pub fn with_harvest(mut self, harvest: bool) -> Self {
+        self.harvest = harvest;
+        self
+    }
+
+    /// Store the quality filter score in document metadata (see [crate::pipelines::oscardoc::types::QualityMetadata]),
+    /// rather than discarding the documents that fail the quality filter.
+    ///
+    /// Filters that do not compute a score (see [crate::filtering::record::FilterKind::score]) still discard documents.
+    pub fn with_quality_scores(mut self, quality_scores: bool) -> Self {
+        self.quality_scores = quality_scores;
+        self
+    }
+
+    /// Label documents below the document confidence threshold (see [OscarDoc::with_doc_threshold])
+    /// as undetermined (`und`) rather than discarding them, keeping their confidence,
+    /// so that they can be reprocessed later (e.g. with another model).
+    ///
+    /// With allowed languages, `und` has to be allowed for them to be written.
+    /// With a multilingual threshold (see [OscarDoc::with_multi_threshold]), they are labelled as multilingual instead.
+    pub fn with_undetermined(mut self, undetermined: bool) -> Self {
+        self.undetermined = undetermined;
+        self
+    }
+}
+
+/// Records discarded while processing a shard, kept when harvesting (see [RejectedOptions::with_harvest]).
+#[derive(Debug, Default)]
+pub(super) struct Rejected {
+    /// discarded documents, with their reason as `rejected` extra metadata
+    pub(super) documents: Vec<Document>,
+    /// unparseable records, as `<record index>\t<error>`
+    pub(super) unparseable: Vec<String>,
+}
+
+/// Files of rejected records, in `<dst>/rejected`.
+pub(super) struct RejectedFiles {
+    langfiles: LangFilesDoc,
+    /// errors of unparseable records, as `<shard>\t<record index>\t<error>`
+    unparseable: File,
+}
+
+impl RejectedFiles {
+    /// Write rejected documents, and dump unparseable records errors.
+    pub(super) fn write(&mut self, shard: &Path, rejected: Rejected) -> Result<(), Error> {
+        for error in rejected.unparseable {
+            writeln!(self.unparseable, "{}\t{}", shard.display(), error)?;
+        }
+
+        if rejected.documents.is_empty() {
+            return Ok(());
+        }
+        let und = LanguageTag::parse(REJECTED_LANG.to_string())?;
+        let writer = self.langfiles.writer(&und)?;
+        writer.lock().unwrap().write(rejected.documents)?;
+
+        Ok(())
+    }
+
+    /// Positions of rejected documents files, to record in the checkpoint.
+    pub(super) fn positions(&self) -> BTreeMap<String, Position> {
+        self.langfiles.positions()
+    }
+
+    /// Flush and sync files.
+    pub(super) fn close(self) -> Result<(), Error> {
+        self.langfiles.close()?;
+        self.unparseable.sync_all()?;
+        Ok(())
+    }
+}
+
+impl OscarDoc {
+    /// Open the files of rejected records, if harvesting.
+    ///
+    /// When resuming, they are appended to, rejected documents files being truncated back to `positions`
+    /// (see [crate::pipelines::oscardoc::types::Checkpoint]).
+    pub(super) fn rejected_files(
+        &self,
+        positions: Option<&BTreeMap<String, Position>>,
+    ) -> Result<Option<RejectedFiles>, Error> {
+        if !self.rejected.harvest {
+            return Ok(None);
+        }
+
+        let dst_rejected = self.dst.join("rejected");
+        std::fs::create_dir_all(&dst_rejected)?;
+        let langfiles = self.langfiles(&dst_rejected);
+        let langfiles = if self.resume {
+            let langfiles = langfiles.appending();
+            if let Some(positions) = positions {
+                langfiles.truncate(positions)?;
+            }
+            langfiles
+        } else {
+            langfiles
+        };
+        let unparseable = OpenOptions::new()
+            .create(true)
+            .append(self.resume)
+            .write(true)
+            .truncate(!self.resume)
+            .open(dst_rejected.join("unparseable.txt"))?;
+
+        Ok(Some(RejectedFiles {
+            langfiles,
+            unparseable,
+        }))
+    }
+
+    /// Build a rejected document, tagged with the rejection `reason`.
+    pub(super) fn rejected_document(
+        record: Record<BufferedBody>,
+        und: &LanguageTag<String>,
+        reason: &str,
+    ) -> Document {
+        let (headers, body) = record.into_raw_parts();
+        let mut metadata = Metadata::new(&Identification::new(und.clone(), 0.0), &[]);
+        metadata.add_extra("rejected", reason);
+        metadata.set_warc_info(&headers.headers);
+        Document::new(
+            String::from_utf8_lossy(&body).into_owned(),
+            headers.headers,
+            metadata,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use oxilangtag::LanguageTag;
+
+    use crate::io::CorpusReader;
+    use crate::pipelines::oscardoc::types::Checkpoint;
+    use crate::pipelines::oscardoc::types::ExtraMetadata;
+
+    use super::super::tests::{gen_pipeline_at, write_shard, MockPredict};
+    use super::RejectedOptions;
+
+    #[test]
+    fn rejected() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let long = format!("Dette er bokmål. {}", "x".repeat(100));
+        write_shard(
+            &src.path().join("0.txt.gz"),
+            &[
+                long.clone(),
+                // mostly short lines: fails the quality filter
+                format!("{long}\n{}{long}", "kort\n".repeat(100)),
+                // no language
+                format!("zzz {}", "x".repeat(100)),
+            ],
+        );
+
+        let p = gen_pipeline_at(src.path(), dst.path())
+            .with_rejected(RejectedOptions::default().with_harvest(true));
+        p.run_with(&MockPredict).unwrap();
+
+        let nb = LanguageTag::parse("nb".to_string()).unwrap();
+        assert_eq!(CorpusReader::new(dst.path(), &nb).unwrap().count(), 1);
+
+        let und = LanguageTag::parse("und".to_string()).unwrap();
+        let mut reasons: Vec<(String, String)> =
+            CorpusReader::new(&dst.path().join("rejected"), &und)
+                .unwrap()
+                .map(|doc| {
+                    let doc = doc.unwrap();
+                    (
+                        doc.metadata().extra("rejected").unwrap().to_string(),
+                        doc.content().lines().next().unwrap().to_string(),
+                    )
+                })
+                .collect();
+        reasons.sort();
+        assert_eq!(
+            reasons,
+            vec![
+                ("filtered_short".to_string(), long),
+                (
+                    "no_language".to_string(),
+                    format!("zzz {}", "x".repeat(100))
+                ),
+            ]
+        );
+        assert!(dst.path().join("rejected/unparseable.txt").exists());
+    }
+
+    #[test]
+    fn rejected_write_error() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let shard = src.path().join("0.txt.gz");
+        write_shard(
+            &shard,
+            &[
+                format!("Dette er bokmål. {}", "x".repeat(100)),
+                format!("zzz {}", "x".repeat(100)),
+            ],
+        );
+        let pipeline = |resume: bool| {
+            gen_pipeline_at(src.path(), dst.path())
+                .with_rejected(RejectedOptions::default().with_harvest(true))
+                .with_resume(resume)
+                .with_quiet(true)
+        };
+
+        // the rejected file can't be opened for writing
+        let und_path = dst.path().join("rejected/und_meta.jsonl");
+        std::fs::create_dir_all(&und_path).unwrap();
+        assert!(pipeline(false).run_with(&MockPredict).is_err());
+        assert!(!Checkpoint::load(dst.path()).unwrap().contains(&shard));
+
+        // the shard is processed again when resuming
+        std::fs::remove_dir(&und_path).unwrap();
+        pipeline(true).run_with(&MockPredict).unwrap();
+        assert!(Checkpoint::load(dst.path()).unwrap().contains(&shard));
+        let und = LanguageTag::parse("und".to_string()).unwrap();
+        let rejected = CorpusReader::new(&dst.path().join("rejected"), &und).unwrap();
+        assert_eq!(rejected.count(), 1);
+    }
+}
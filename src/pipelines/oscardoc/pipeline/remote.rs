@@ -0,0 +1,269 @@
+//! Remote shards, downloaded as processing proceeds (see [RemoteOptions]).
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::SyncSender;
+use std::thread::Scope;
+
+use log::{info, warn};
+use reqwest::Url;
+
+use crate::error::Error;
+use crate::sources::commoncrawl::remote::{self, ShardDownloader};
+
+use super::OscarDoc;
+
+/// Default number of concurrent downloads (see [RemoteOptions::with_download_tasks]).
+const DOWNLOAD_TASKS: usize = 4;
+
+/// Remote shards options (see [OscarDoc::with_remote]).
+///
+/// With [RemoteOptions::with_shard_urls], shards are downloaded as processing proceeds rather than beforehand,
+/// from the URLs listed in a text file (e.g. a CommonCrawl `wet.paths` file, see [remote]).
+/// A number of shards are downloaded concurrently (see [RemoteOptions::with_download_tasks]) into `<dst>/downloads`,
+/// and each is deleted once processed, so that only a handful of shards are on disk at once.
+/// Downloads are blocking, each running on its own thread (there's no async runtime):
+/// the number of download tasks is the number of download threads, and such runs must not be started
+/// from an async context (see [ShardDownloader::new]).
+/// Downloads are retried following [OscarDoc::with_retry], and shards that can't be downloaded are skipped
+/// like unreadable ones: they aren't recorded in the [crate::pipelines::oscardoc::types::Checkpoint].
+///
+/// Downloaded shards are named after their position in the list, which has to stay the same when resuming.
+/// Remote shards have no modification time, so they can't be processed incrementally
+/// (see [OscarDoc::with_incremental]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteOptions {
+    /// list of shard URLs, one per line
+    pub shard_urls: Option<PathBuf>,
+    /// number of concurrent downloads
+    pub download_tasks: usize,
+}
+
+impl Default for RemoteOptions {
+    /// Default is to read shards from the source directory, with 4 download tasks if they were remote.
+    fn default() -> Self {
+        Self {
+            shard_urls: None,
+            download_tasks: DOWNLOAD_TASKS,
+        }
+    }
+}
+
+impl RemoteOptions {
+    /// Download the shards listed in `shard_urls` as they get processed, rather than reading the source directory.
+    /// Shard lists (see [OscarDoc::with_shard_list]) can't be used then.
+    pub fn with_shard_urls(mut self, shard_urls: PathBuf) -> Self {
+        self.shard_urls = Some(shard_urls);
+        self
+    }
+
+    /// Set the number of concurrent downloads of remote shards,
+    /// which is also the number of downloaded shards that can wait to be processed.
+    pub fn with_download_tasks(mut self, download_tasks: usize) -> Self {
+        self.download_tasks = download_tasks;
+        self
+    }
+}
+
+/// Shard index and path, with the result of its download.
+pub(super) type Download = (usize, PathBuf, Result<(), Error>);
+
+/// Remote shards of a run, by local path, and their downloader.
+pub(super) struct Downloads {
+    urls: HashMap<PathBuf, Url>,
+    /// shared by download threads
+    downloader: ShardDownloader,
+}
+
+impl OscarDoc {
+    /// Folder of downloaded shards (see [RemoteOptions]).
+    pub(super) fn download_dir(&self) -> PathBuf {
+        self.dst.join("downloads")
+    }
+
+    /// Get the local paths of the shards listed in `shard_urls`, along with their URL (see [RemoteOptions::with_shard_urls]).
+    ///
+    /// Shards are named after their position in the list, so that they get a shard number.
+    /// URLs that don't have a supported extension are skipped with a warning.
+    pub(super) fn remote_shards(&self, shard_urls: &Path) -> Result<Vec<(PathBuf, Url)>, Error> {
+        let download_dir = self.download_dir();
+        let shards = remote::read_shard_urls(shard_urls)?
+            .into_iter()
+            .enumerate()
+            .filter_map(|(idx, url)| match remote::shard_extension(&url) {
+                Some(extension) => Some((download_dir.join(format!("{idx}.{extension}")), url)),
+                None => {
+                    warn!("skipping shard {} (unsupported extension)", url);
+                    None
+                }
+            })
+            .collect();
+
+        Ok(shards)
+    }
+
+    /// Prepare the downloads of remote shards, if any, creating the download folder.
+    ///
+    /// The downloader is built here so that failing to build it fails the run.
+    pub(super) fn downloads(&self) -> Result<Option<Downloads>, Error> {
+        match &self.remote.shard_urls {
+            Some(shard_urls) => {
+                std::fs::create_dir_all(self.download_dir())?;
+                Ok(Some(Downloads {
+                    urls: self.remote_shards(shard_urls)?.into_iter().collect(),
+                    downloader: ShardDownloader::new(self.retry)?,
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Spawn download threads in `scope` (see [RemoteOptions::with_download_tasks]),
+    /// downloading the shards given by `next_shard` and sending them to `sender`.
+    ///
+    /// Threads block when `sender` is full, and stop once `next_shard` is exhausted or `sender` is disconnected.
+    pub(super) fn spawn_downloads<'scope, 'env, F>(
+        &self,
+        scope: &'scope Scope<'scope, 'env>,
+        downloads: &'env Downloads,
+        next_shard: &'env F,
+        sender: SyncSender<Download>,
+    ) where
+        F: Fn() -> Option<(usize, PathBuf)> + Sync,
+    {
+        info!(
+            "downloading shards with {} tasks",
+            self.remote.download_tasks
+        );
+        for _ in 0..self.remote.download_tasks {
+            let sender = sender.clone();
+            scope.spawn(move || {
+                while let Some((idx, shard)) = next_shard() {
+                    // shards are only renamed once complete, so existing ones can be used as is
+                    let download = if shard.is_file() {
+                        Ok(())
+                    } else {
+                        downloads
+                            .downloader
+                            .download(&downloads.urls[&shard], &shard)
+                    };
+
+                    // blocks if there are too many downloaded shards
+                    if sender.send((idx, shard, download)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    }
+
+    /// Delete a downloaded shard once processed.
+    pub(super) fn remove_download(shard: &Path) {
+        if shard.is_file() {
+            if let Err(e) = std::fs::remove_file(shard) {
+                warn!("could not remove downloaded shard {:?}: {:?}", shard, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        path::Path,
+        time::Duration,
+    };
+
+    use oxilangtag::LanguageTag;
+
+    use crate::io::CorpusReader;
+    use crate::pipelines::oscardoc::types::Checkpoint;
+    use crate::sources::commoncrawl::Retry;
+
+    use super::super::tests::{gen_pipeline_at, write_shard, MockPredict};
+    use super::RemoteOptions;
+
+    /// Serve `files` over HTTP on a local port, failing the first request of `flaky` ones with a server error.
+    /// Returns the server URL.
+    fn serve(files: HashMap<String, Vec<u8>>, flaky: HashSet<String>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut failed = HashSet::new();
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let path = request.split_whitespace().nth(1).unwrap_or("/");
+                let path = path.trim_start_matches('/').to_string();
+                // skip headers
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+
+                let (status, body) = match files.get(&path) {
+                    Some(_) if flaky.contains(&path) && failed.insert(path.clone()) => {
+                        ("500 Internal Server Error", Vec::new())
+                    }
+                    Some(body) => ("200 OK", body.clone()),
+                    None => ("404 Not Found", Vec::new()),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+
+        format!("http://{addr}")
+    }
+
+    #[test]
+    fn shard_urls() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        let mut files = HashMap::new();
+        for shard_id in 0..2 {
+            let name = format!("{shard_id}.txt.gz");
+            let body = format!("Dokument {shard_id} på bokmål. {}", "x".repeat(100));
+            write_shard(&src.path().join(&name), &[body]);
+            files.insert(name.clone(), std::fs::read(src.path().join(&name)).unwrap());
+        }
+        // the second shard only downloads when retrying
+        let server = serve(files, HashSet::from(["1.txt.gz".to_string()]));
+        let shard_urls = src.path().join("wet.paths");
+        std::fs::write(
+            &shard_urls,
+            format!("{server}/0.txt.gz\n{server}/1.txt.gz\n{server}/missing.txt.gz\n"),
+        )
+        .unwrap();
+
+        gen_pipeline_at(Path::new("unused"), dst.path())
+            .with_remote(RemoteOptions::default().with_shard_urls(shard_urls))
+            .with_retry(Retry::new(1, Duration::from_millis(1)))
+            .with_quiet(true)
+            .run_with(&MockPredict)
+            .unwrap();
+
+        let nb = LanguageTag::parse("nb".to_string()).unwrap();
+        assert_eq!(CorpusReader::new(dst.path(), &nb).unwrap().count(), 2);
+
+        // the missing shard is left to resume
+        let downloads = dst.path().join("downloads");
+        let checkpoint = Checkpoint::load(dst.path()).unwrap();
+        let recorded: Vec<bool> = (0..3)
+            .map(|i| checkpoint.contains(&downloads.join(format!("{i}.gz"))))
+            .collect();
+        assert_eq!(recorded, vec![true, true, false]);
+
+        // shards are deleted once processed
+        assert!(!downloads.exists());
+    }
+}
@@ -0,0 +1,58 @@
+/*! Byte offsets.
+
+Stores the byte offset of a document in its (uncompressed) language file in [Metadata],
+so that a reader can seek right to the start of its line rather than scanning the file.
+
+Offsets are relative to the file the document is in, and restart at 0 with each rotated part.
+They are set at write time (see [crate::io::WriterDoc::with_byte_offsets]).
+
+It is stored as [ExtraMetadata] under the `byte_offset` key.
+!*/
+use super::{Document, ExtraMetadata, Metadata};
+
+const BYTE_OFFSET_KEY: &str = "byte_offset";
+
+/// Typed access to the byte offset.
+pub trait ByteOffsetMetadata {
+    /// Offset of the document line in its language file, in bytes.
+    fn byte_offset(&self) -> Option<u64>;
+}
+
+impl ByteOffsetMetadata for Metadata {
+    fn byte_offset(&self) -> Option<u64> {
+        self.extra(BYTE_OFFSET_KEY)?.parse().ok()
+    }
+}
+
+/// Store the byte offset of `doc`, replacing an outdated one (e.g. from a previous corpus).
+pub fn set_byte_offset(doc: &mut Document, offset: u64) {
+    doc.metadata_mut()
+        .set_extra(BYTE_OFFSET_KEY, &offset.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{set_byte_offset, ByteOffsetMetadata};
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+
+    #[test]
+    fn test_byte_offset() {
+        let mut metadata = Metadata::default();
+        metadata.add_annotation("tiny".to_string());
+        let mut doc = Document::new("Hello".to_string(), HashMap::new(), metadata);
+        assert_eq!(doc.metadata().byte_offset(), None);
+
+        set_byte_offset(&mut doc, 42);
+        assert_eq!(doc.metadata().byte_offset(), Some(42));
+
+        // outdated offsets are replaced, other annotations are kept
+        set_byte_offset(&mut doc, 1337);
+        assert_eq!(doc.metadata().byte_offset(), Some(1337));
+        assert_eq!(
+            doc.metadata().annotation(),
+            Some(&vec!["tiny".to_string(), "byte_offset:1337".to_string()])
+        );
+    }
+}
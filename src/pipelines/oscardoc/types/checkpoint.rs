@@ -0,0 +1,173 @@
+/*! Pipeline checkpoint.
+
+Keeps track of the shards that have been fully processed and written,
+so that an interrupted run can be resumed without processing them again.
+
+Along with shards, the checkpoint records where written files were when the last shard was recorded (see [WrittenFiles]):
+a run that dies while writing a shard leaves some of its documents in files,
+which are truncated back to those positions when resuming, before the shard is processed again.
+
+The checkpoint is stored as JSON in `<dst>/checkpoint.json`.
+!*/
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::io::Position;
+
+/// Checkpoint file name.
+const CHECKPOINT_FILENAME: &str = "checkpoint.json";
+
+/// Where written files were when the last shard was recorded.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrittenFiles {
+    /// positions of language files, keyed by language
+    pub langs: BTreeMap<String, Position>,
+    /// positions of rejected documents files, keyed by language
+    pub rejected: BTreeMap<String, Position>,
+    /// sizes of avro rebuild files, keyed by file name
+    pub rebuild: BTreeMap<String, u64>,
+    /// size of the seen hashes file (see [super::SeenHashes]), if any
+    pub seen_hashes: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// paths of completed shards
+    shards: BTreeSet<PathBuf>,
+    /// written files, unknown for checkpoints saved by previous versions
+    #[serde(default)]
+    files: Option<WrittenFiles>,
+}
+
+impl Checkpoint {
+    /// Create the checkpoint of a new run, which hasn't written anything yet.
+    ///
+    /// Contrary to [Checkpoint::default], files are known: there are none.
+    pub fn new() -> Self {
+        Self {
+            shards: BTreeSet::new(),
+            files: Some(WrittenFiles::default()),
+        }
+    }
+
+    /// Get the checkpoint path in the `dst` folder.
+    pub fn path(dst: &Path) -> PathBuf {
+        dst.join(CHECKPOINT_FILENAME)
+    }
+
+    /// Load the checkpoint from the `dst` folder.
+    ///
+    /// Returns an empty checkpoint if there's none.
+    pub fn load(dst: &Path) -> Result<Self, Error> {
+        let path = Self::path(dst);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Save the checkpoint in the `dst` folder.
+    ///
+    /// The checkpoint is first written to a temporary file that is then renamed,
+    /// so that an interruption while saving doesn't corrupt the previous checkpoint.
+    pub fn save(&self, dst: &Path) -> Result<(), Error> {
+        let path = Self::path(dst);
+        let tmp_path = path.with_extension("json.tmp");
+
+        let file = File::create(&tmp_path)?;
+        serde_json::to_writer(&file, self)?;
+        file.sync_all()?;
+        std::fs::rename(tmp_path, path)?;
+
+        Ok(())
+    }
+
+    /// Mark a shard as completed.
+    pub fn add(&mut self, shard_path: PathBuf) {
+        self.shards.insert(shard_path);
+    }
+
+    /// Is the shard completed?
+    pub fn contains(&self, shard_path: &Path) -> bool {
+        self.shards.contains(shard_path)
+    }
+
+    /// Get the written files, if known.
+    pub fn files(&self) -> Option<&WrittenFiles> {
+        self.files.as_ref()
+    }
+
+    /// Get the written files, to update them once a shard is written.
+    pub fn files_mut(&mut self) -> &mut WrittenFiles {
+        self.files.get_or_insert_with(WrittenFiles::default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use tempfile::tempdir;
+
+    use super::{Checkpoint, WrittenFiles};
+    use crate::io::Position;
+
+    #[test]
+    fn test_load_missing() {
+        let dst = tempdir().unwrap();
+        let cp = Checkpoint::load(dst.path()).unwrap();
+        assert_eq!(cp, Checkpoint::default());
+    }
+
+    #[test]
+    fn test_save_load() {
+        let dst = tempdir().unwrap();
+        let mut cp = Checkpoint::default();
+        cp.add(PathBuf::from("shards/0.txt.gz"));
+        cp.add(PathBuf::from("shards/1.txt.gz"));
+        cp.save(dst.path()).unwrap();
+
+        let cp_loaded = Checkpoint::load(dst.path()).unwrap();
+        assert_eq!(cp, cp_loaded);
+        assert!(cp_loaded.contains(Path::new("shards/1.txt.gz")));
+        assert!(!cp_loaded.contains(Path::new("shards/2.txt.gz")));
+    }
+
+    #[test]
+    fn test_files() {
+        let dst = tempdir().unwrap();
+        let mut cp = Checkpoint::new();
+        assert_eq!(cp.files(), Some(&WrittenFiles::default()));
+
+        let position = Position {
+            path: PathBuf::from("fr_meta.jsonl"),
+            part: 1,
+            line: 2,
+            byte: 100,
+            len: 100,
+        };
+        cp.files_mut().langs.insert("fr".to_string(), position);
+        cp.files_mut().rebuild.insert("fr.avro".to_string(), 10);
+        cp.save(dst.path()).unwrap();
+        assert_eq!(Checkpoint::load(dst.path()).unwrap(), cp);
+
+        // checkpoints of previous versions don't know about files
+        std::fs::write(
+            Checkpoint::path(dst.path()),
+            r#"{"shards":["shards/0.txt.gz"]}"#,
+        )
+        .unwrap();
+        let cp = Checkpoint::load(dst.path()).unwrap();
+        assert!(cp.contains(Path::new("shards/0.txt.gz")));
+        assert_eq!(cp.files(), None);
+    }
+}
@@ -0,0 +1,128 @@
+/*! Content hashes.
+
+Stores a hash of the document content in [Metadata], so that downstream tools can deduplicate documents
+across corpora without reading their content.
+
+Hashes are 128-bit XXH3 hashes (see [twox_hash::xxh3::hash128]) of the UTF-8 content,
+the same ones as [super::SeenHashes] and [crate::processing::dedup] use.
+They are written as 32 lowercase hexadecimal digits.
+
+Content can be normalized before being hashed, so that documents that only differ in whitespace
+get the same hash (see [normalize]). Normalized hashes are stored under a different key:
+they are stored as [ExtraMetadata] under the `content_hash` key, or `normalized_content_hash` when normalized.
+!*/
+use itertools::Itertools;
+
+use super::{Document, ExtraMetadata, Metadata};
+
+const CONTENT_HASH_KEY: &str = "content_hash";
+const NORMALIZED_CONTENT_HASH_KEY: &str = "normalized_content_hash";
+
+/// Collapse whitespace runs into single spaces and trim lines, removing blank lines.
+pub fn normalize(content: &str) -> String {
+    content
+        .lines()
+        .map(|line| line.split_whitespace().join(" "))
+        .filter(|line| !line.is_empty())
+        .join("\n")
+}
+
+/// Hash the (possibly normalized) content of a document.
+pub fn hash_content(content: &str, normalized: bool) -> u128 {
+    if normalized {
+        twox_hash::xxh3::hash128(normalize(content).as_bytes())
+    } else {
+        twox_hash::xxh3::hash128(content.as_bytes())
+    }
+}
+
+/// Typed access to content hashes.
+pub trait ContentHashMetadata {
+    /// Hash of the content.
+    fn content_hash(&self) -> Option<u128>;
+
+    /// Hash of the normalized content.
+    fn normalized_content_hash(&self) -> Option<u128>;
+}
+
+impl ContentHashMetadata for Metadata {
+    fn content_hash(&self) -> Option<u128> {
+        u128::from_str_radix(self.extra(CONTENT_HASH_KEY)?, 16).ok()
+    }
+
+    fn normalized_content_hash(&self) -> Option<u128> {
+        u128::from_str_radix(self.extra(NORMALIZED_CONTENT_HASH_KEY)?, 16).ok()
+    }
+}
+
+/// Store the hash of the (possibly normalized) content of `doc`, replacing any previous hash.
+pub fn set_content_hash(doc: &mut Document, normalized: bool) {
+    let key = if normalized {
+        NORMALIZED_CONTENT_HASH_KEY
+    } else {
+        CONTENT_HASH_KEY
+    };
+    let hash = hash_content(doc.content(), normalized);
+    doc.metadata_mut().set_extra(key, &format!("{hash:032x}"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, set_content_hash, ContentHashMetadata};
+    use crate::io::test_utils::gen_doc;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize("  Hello   world \t!\n\n \nNew  line "),
+            "Hello world !\nNew line"
+        );
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let mut docs: Vec<_> = [
+            "Hello world!\nBye.",
+            "Hello world!\nBye.",
+            "Hello  world!\nBye.",
+        ]
+        .iter()
+        .map(|content| gen_doc(content))
+        .collect();
+        for doc in &mut docs {
+            set_content_hash(doc, false);
+            set_content_hash(doc, true);
+        }
+        let hashes: Vec<_> = docs
+            .iter()
+            .map(|doc| doc.metadata().content_hash().unwrap())
+            .collect();
+        let normalized: Vec<_> = docs
+            .iter()
+            .map(|doc| doc.metadata().normalized_content_hash().unwrap())
+            .collect();
+
+        // identical contents get identical hashes, whitespace only matters without normalization
+        assert_eq!(hashes[0], hashes[1]);
+        assert_ne!(hashes[0], hashes[2]);
+        assert!(normalized.iter().all(|hash| *hash == normalized[0]));
+
+        // hashes are stable
+        assert_eq!(
+            docs[0].metadata().annotation().unwrap()[0],
+            "content_hash:7ba8ab0eba11be3ebef1b05c63d027ea"
+        );
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut doc = gen_doc("Hello world!");
+        set_content_hash(&mut doc, false);
+        let hash = doc.metadata().content_hash();
+
+        doc.set_content("Hello again!".to_string());
+        set_content_hash(&mut doc, false);
+        assert_ne!(doc.metadata().content_hash(), hash);
+        assert_eq!(doc.metadata().annotation().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,83 @@
+/*! Content counts.
+
+Stores the size in bytes and the number of lines of the document content in [Metadata],
+so that corpus statistics (e.g. for dataset cards) can be built without reading the content.
+
+Lines are counted the same way as sentence identifications are built (see [str::lines]):
+there is one line per sentence identification, and a trailing newline doesn't start a new line.
+
+They are stored as [ExtraMetadata] under the `content_bytes` and `content_lines` keys.
+!*/
+use super::{Document, ExtraMetadata, Metadata};
+
+const CONTENT_BYTES_KEY: &str = "content_bytes";
+const CONTENT_LINES_KEY: &str = "content_lines";
+
+/// Typed access to content counts.
+pub trait ContentMetadata {
+    /// Size of the content, in bytes.
+    fn content_bytes(&self) -> Option<usize>;
+
+    /// Number of lines of the content.
+    fn content_lines(&self) -> Option<usize>;
+}
+
+impl ContentMetadata for Metadata {
+    fn content_bytes(&self) -> Option<usize> {
+        self.extra(CONTENT_BYTES_KEY)?.parse().ok()
+    }
+
+    fn content_lines(&self) -> Option<usize> {
+        self.extra(CONTENT_LINES_KEY)?.parse().ok()
+    }
+}
+
+/// Store the counts of the content of `doc`, replacing outdated ones.
+pub fn set_content_counts(doc: &mut Document) {
+    let bytes = doc.content().len();
+    let lines = doc.content().lines().count();
+    let metadata = doc.metadata_mut();
+    metadata.set_extra(CONTENT_BYTES_KEY, &bytes.to_string());
+    metadata.set_extra(CONTENT_LINES_KEY, &lines.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{set_content_counts, ContentMetadata};
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+
+    #[test]
+    fn test_counts() {
+        let mut doc = Document::new(
+            "Første linje.\r\nAndre linje.\n".to_string(),
+            HashMap::new(),
+            Metadata::default(),
+        );
+        set_content_counts(&mut doc);
+        assert_eq!(doc.metadata().content_bytes(), Some(doc.content().len()));
+        assert_eq!(doc.metadata().content_lines(), Some(2));
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut metadata = Metadata::default();
+        metadata.add_annotation("tiny".to_string());
+        let mut doc = Document::new("foo\nbar".to_string(), HashMap::new(), metadata);
+        set_content_counts(&mut doc);
+
+        doc.set_content("foo".to_string());
+        set_content_counts(&mut doc);
+        assert_eq!(doc.metadata().content_bytes(), Some(3));
+        assert_eq!(doc.metadata().content_lines(), Some(1));
+        assert_eq!(
+            doc.metadata().annotation(),
+            Some(&vec![
+                "tiny".to_string(),
+                "content_bytes:3".to_string(),
+                "content_lines:1".to_string()
+            ])
+        );
+    }
+}
@@ -0,0 +1,137 @@
+/*! Extra metadata.
+
+[Metadata] comes from `oscar-io` and has a fixed set of fields.
+Pipeline-specific values that do not fit in them are stored as `key:value` annotations,
+the same way [crate::transformers::LSH] prefixes its hashes with `tlsh:`.
+
+Since annotations also hold quality tags (`tiny`, `noisy`...), extras are only added by opt-in options
+of the pipeline, so that default runs keep annotations to quality tags.
+Code reading quality tags of documents that may have extras should use [ExtraMetadata::tags].
+!*/
+use super::Metadata;
+
+/// Separator between key and value.
+const SEPARATOR: char = ':';
+
+/// Read/write access to `key:value` annotations.
+pub trait ExtraMetadata {
+    /// Add a `key:value` annotation.
+    ///
+    /// Keys are not checked for uniqueness: [ExtraMetadata::extra] returns the first one.
+    fn add_extra(&mut self, key: &str, value: &str);
+
+    /// Set the value of `key`, replacing previous annotations with that key (e.g. from a previous corpus).
+    fn set_extra(&mut self, key: &str, value: &str);
+
+    /// Remove the annotations with the provided key.
+    fn remove_extra(&mut self, key: &str);
+
+    /// Get the value of the first annotation with the provided key.
+    fn extra(&self, key: &str) -> Option<&str>;
+
+    /// Get the annotations that are not `key:value` ones (e.g. `tiny`, `noisy`).
+    fn tags(&self) -> Vec<&str>;
+}
+
+impl ExtraMetadata for Metadata {
+    fn add_extra(&mut self, key: &str, value: &str) {
+        self.add_annotation(format!("{key}{SEPARATOR}{value}"));
+    }
+
+    fn set_extra(&mut self, key: &str, value: &str) {
+        if self.extra(key) == Some(value) {
+            return;
+        }
+        self.remove_extra(key);
+        self.add_extra(key, value);
+    }
+
+    fn remove_extra(&mut self, key: &str) {
+        let has_key = |annotation: &str| {
+            annotation
+                .split_once(SEPARATOR)
+                .is_some_and(|(k, _)| k == key)
+        };
+        if !self
+            .annotation()
+            .into_iter()
+            .flatten()
+            .any(|annotation| has_key(annotation))
+        {
+            return;
+        }
+
+        // Metadata can't remove annotations, nor give back its identification to be rebuilt,
+        // so annotations are filtered in its serialized form.
+        let mut value = serde_json::to_value(&*self).expect("metadata is serializable");
+        if let Some(annotations) = value.get_mut("quality_warnings") {
+            if let Some(list) = annotations.as_array_mut() {
+                list.retain(|annotation| !annotation.as_str().is_some_and(has_key));
+                if list.is_empty() {
+                    *annotations = serde_json::Value::Null;
+                }
+            }
+        }
+        *self = serde_json::from_value(value).expect("metadata is deserializable");
+    }
+
+    fn extra(&self, key: &str) -> Option<&str> {
+        self.annotation()?.iter().find_map(|annotation| {
+            annotation
+                .split_once(SEPARATOR)
+                .and_then(|(k, v)| if k == key { Some(v) } else { None })
+        })
+    }
+
+    fn tags(&self) -> Vec<&str> {
+        self.annotation()
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .filter(|annotation| !annotation.contains(SEPARATOR))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExtraMetadata;
+    use crate::pipelines::oscardoc::types::Metadata;
+
+    #[test]
+    fn test_extra() {
+        let mut m = Metadata::default();
+        m.add_annotation("tiny".to_string());
+        m.add_extra("foo", "bar:baz");
+
+        assert_eq!(m.extra("foo"), Some("bar:baz"));
+        assert_eq!(m.extra("tiny"), None);
+        assert_eq!(m.extra("bar"), None);
+        assert_eq!(m.tags(), vec!["tiny"]);
+    }
+
+    #[test]
+    fn test_set_remove_extra() {
+        let mut m = Metadata::default();
+        m.set_harmful_pp(Some(12.5));
+        m.add_extra("foo", "bar");
+        m.add_annotation("tiny".to_string());
+
+        m.set_extra("foo", "baz");
+        assert_eq!(m.extra("foo"), Some("baz"));
+        assert_eq!(m.annotation().unwrap().len(), 2);
+        assert_eq!(m.harmful_pp(), Some(12.5));
+
+        m.remove_extra("foo");
+        m.remove_extra("tiny");
+        assert_eq!(m.extra("foo"), None);
+        assert_eq!(m.tags(), vec!["tiny"]);
+
+        m.remove_extra("bar");
+        assert_eq!(m.tags(), vec!["tiny"]);
+        let mut m = Metadata::default();
+        m.add_extra("foo", "bar");
+        m.remove_extra("foo");
+        assert_eq!(m, Metadata::default());
+    }
+}
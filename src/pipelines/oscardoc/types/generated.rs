@@ -0,0 +1,43 @@
+/*! Generated text score.
+
+Stores the score of the generated text heuristic (see [crate::transformers::GeneratedText])
+in [Metadata], so that documents can be filtered downstream at a custom threshold.
+
+It is stored as [ExtraMetadata] under the `generated_score` key.
+!*/
+use super::{ExtraMetadata, Metadata};
+
+const GENERATED_SCORE_KEY: &str = "generated_score";
+
+/// Typed access to the generated text score.
+pub trait GeneratedMetadata {
+    fn set_generated_score(&mut self, score: f32);
+
+    /// Generated text score. [None] if there's none or if it is invalid.
+    fn generated_score(&self) -> Option<f32>;
+}
+
+impl GeneratedMetadata for Metadata {
+    fn set_generated_score(&mut self, score: f32) {
+        self.add_extra(GENERATED_SCORE_KEY, &score.to_string());
+    }
+
+    fn generated_score(&self) -> Option<f32> {
+        self.extra(GENERATED_SCORE_KEY)?.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GeneratedMetadata;
+    use crate::pipelines::oscardoc::types::Metadata;
+
+    #[test]
+    fn test_generated_score() {
+        let mut m = Metadata::default();
+        assert_eq!(m.generated_score(), None);
+
+        m.set_generated_score(0.75);
+        assert_eq!(m.generated_score(), Some(0.75));
+    }
+}
@@ -0,0 +1,311 @@
+/*! Crawler language hint.
+
+CommonCrawl records may carry the languages identified by the crawler (CLD2)
+in a `WARC-Identified-Content-Language` header, as comma-separated ISO 639-3 codes (e.g. `fra,eng`).
+
+The hint is stored along with whether it agrees with the document language,
+which helps auditing language identification at scale.
+They are stored as [ExtraMetadata] under the `lang_hint` and `lang_hint_agreement` keys.
+Missing hints are not stored.
+
+The document language agrees with the hint if it is the first hinted language.
+ISO 639-3 codes of languages that have an ISO 639-1 code (used by FastText) are converted before comparing.
+!*/
+use std::collections::HashMap;
+
+use oxilangtag::LanguageTag;
+use warc::WarcHeader;
+
+use super::{ExtraMetadata, Metadata};
+
+const LANG_HINT_HEADER: &str = "warc-identified-content-language";
+const LANG_HINT_KEY: &str = "lang_hint";
+const AGREEMENT_KEY: &str = "lang_hint_agreement";
+
+/// ISO 639-3 codes (and individual languages CLD2 reports instead of macrolanguages)
+/// of languages that have an ISO 639-1 code.
+const ISO_639_3_TO_1: &[(&str, &str)] = &[
+    ("aar", "aa"),
+    ("abk", "ab"),
+    ("afr", "af"),
+    ("aka", "ak"),
+    ("amh", "am"),
+    ("ara", "ar"),
+    ("arb", "ar"),
+    ("arg", "an"),
+    ("asm", "as"),
+    ("ava", "av"),
+    ("aym", "ay"),
+    ("aze", "az"),
+    ("azj", "az"),
+    ("bak", "ba"),
+    ("bel", "be"),
+    ("ben", "bn"),
+    ("bis", "bi"),
+    ("bod", "bo"),
+    ("bos", "bs"),
+    ("bre", "br"),
+    ("bul", "bg"),
+    ("cat", "ca"),
+    ("ces", "cs"),
+    ("cha", "ch"),
+    ("che", "ce"),
+    ("chv", "cv"),
+    ("cmn", "zh"),
+    ("cor", "kw"),
+    ("cos", "co"),
+    ("cym", "cy"),
+    ("dan", "da"),
+    ("deu", "de"),
+    ("div", "dv"),
+    ("dzo", "dz"),
+    ("ekk", "et"),
+    ("ell", "el"),
+    ("eng", "en"),
+    ("epo", "eo"),
+    ("est", "et"),
+    ("eus", "eu"),
+    ("ewe", "ee"),
+    ("fao", "fo"),
+    ("fas", "fa"),
+    ("fij", "fj"),
+    ("fin", "fi"),
+    ("fra", "fr"),
+    ("fry", "fy"),
+    ("ful", "ff"),
+    ("gla", "gd"),
+    ("gle", "ga"),
+    ("glg", "gl"),
+    ("glv", "gv"),
+    ("grn", "gn"),
+    ("guj", "gu"),
+    ("hat", "ht"),
+    ("hau", "ha"),
+    ("heb", "he"),
+    ("her", "hz"),
+    ("hin", "hi"),
+    ("hrv", "hr"),
+    ("hun", "hu"),
+    ("hye", "hy"),
+    ("ibo", "ig"),
+    ("ido", "io"),
+    ("iku", "iu"),
+    ("ile", "ie"),
+    ("ina", "ia"),
+    ("ind", "id"),
+    ("ipk", "ik"),
+    ("isl", "is"),
+    ("ita", "it"),
+    ("jav", "jv"),
+    ("jpn", "ja"),
+    ("kal", "kl"),
+    ("kan", "kn"),
+    ("kas", "ks"),
+    ("kat", "ka"),
+    ("kaz", "kk"),
+    ("khk", "mn"),
+    ("khm", "km"),
+    ("kin", "rw"),
+    ("kir", "ky"),
+    ("kor", "ko"),
+    ("kur", "ku"),
+    ("lao", "lo"),
+    ("lat", "la"),
+    ("lav", "lv"),
+    ("lim", "li"),
+    ("lin", "ln"),
+    ("lit", "lt"),
+    ("ltz", "lb"),
+    ("lug", "lg"),
+    ("lvs", "lv"),
+    ("mal", "ml"),
+    ("mar", "mr"),
+    ("mkd", "mk"),
+    ("mlg", "mg"),
+    ("mlt", "mt"),
+    ("mon", "mn"),
+    ("mri", "mi"),
+    ("msa", "ms"),
+    ("mya", "my"),
+    ("nau", "na"),
+    ("nep", "ne"),
+    ("nld", "nl"),
+    ("nno", "nn"),
+    ("nob", "nb"),
+    ("nor", "no"),
+    ("npi", "ne"),
+    ("nya", "ny"),
+    ("oci", "oc"),
+    ("ori", "or"),
+    ("orm", "om"),
+    ("ory", "or"),
+    ("oss", "os"),
+    ("pan", "pa"),
+    ("pes", "fa"),
+    ("plt", "mg"),
+    ("pol", "pl"),
+    ("por", "pt"),
+    ("pus", "ps"),
+    ("que", "qu"),
+    ("roh", "rm"),
+    ("ron", "ro"),
+    ("run", "rn"),
+    ("rus", "ru"),
+    ("sag", "sg"),
+    ("san", "sa"),
+    ("sin", "si"),
+    ("slk", "sk"),
+    ("slv", "sl"),
+    ("sme", "se"),
+    ("smo", "sm"),
+    ("sna", "sn"),
+    ("snd", "sd"),
+    ("som", "so"),
+    ("sot", "st"),
+    ("spa", "es"),
+    ("sqi", "sq"),
+    ("srd", "sc"),
+    ("srp", "sr"),
+    ("ssw", "ss"),
+    ("sun", "su"),
+    ("swa", "sw"),
+    ("swe", "sv"),
+    ("swh", "sw"),
+    ("tah", "ty"),
+    ("tam", "ta"),
+    ("tat", "tt"),
+    ("tel", "te"),
+    ("tgk", "tg"),
+    ("tgl", "tl"),
+    ("tha", "th"),
+    ("tir", "ti"),
+    ("ton", "to"),
+    ("tsn", "tn"),
+    ("tso", "ts"),
+    ("tuk", "tk"),
+    ("tur", "tr"),
+    ("uig", "ug"),
+    ("ukr", "uk"),
+    ("urd", "ur"),
+    ("uzb", "uz"),
+    ("uzn", "uz"),
+    ("ven", "ve"),
+    ("vie", "vi"),
+    ("vol", "vo"),
+    ("wln", "wa"),
+    ("wol", "wo"),
+    ("xho", "xh"),
+    ("yid", "yi"),
+    ("yor", "yo"),
+    ("zha", "za"),
+    ("zho", "zh"),
+    ("zsm", "ms"),
+    ("zul", "zu"),
+];
+
+/// Convert an ISO 639-3 code to the ISO 639-1 one, if there's one.
+fn to_iso_639_1(code: &str) -> &str {
+    ISO_639_3_TO_1
+        .binary_search_by_key(&code, |(iso3, _)| iso3)
+        .map_or(code, |idx| ISO_639_3_TO_1[idx].1)
+}
+
+/// Typed access to the crawler language hint.
+pub trait LangHintMetadata {
+    /// Store the language hint found in `headers`, if any, and whether `lang` agrees with it.
+    fn set_lang_hint(&mut self, headers: &HashMap<WarcHeader, Vec<u8>>, lang: &LanguageTag<String>);
+
+    /// Hinted languages, as found in the header.
+    fn lang_hint(&self) -> Option<&str>;
+
+    /// Does the document language agree with the hint? [None] if there's no hint.
+    fn lang_hint_agreement(&self) -> Option<bool>;
+}
+
+impl LangHintMetadata for Metadata {
+    fn set_lang_hint(
+        &mut self,
+        headers: &HashMap<WarcHeader, Vec<u8>>,
+        lang: &LanguageTag<String>,
+    ) {
+        let hint = match headers.get(&WarcHeader::from(LANG_HINT_HEADER)) {
+            Some(hint) => String::from_utf8_lossy(hint),
+            None => return,
+        };
+        let hint = hint.trim();
+        if hint.is_empty() {
+            return;
+        }
+
+        let agreement = hint
+            .split(',')
+            .next()
+            .map(|primary| to_iso_639_1(primary.trim()) == lang.primary_language())
+            .unwrap_or(false);
+
+        self.add_extra(LANG_HINT_KEY, hint);
+        self.add_extra(AGREEMENT_KEY, &agreement.to_string());
+    }
+
+    fn lang_hint(&self) -> Option<&str> {
+        self.extra(LANG_HINT_KEY)
+    }
+
+    fn lang_hint_agreement(&self) -> Option<bool> {
+        self.extra(AGREEMENT_KEY)?.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oxilangtag::LanguageTag;
+    use warc::WarcHeader;
+
+    use super::{to_iso_639_1, LangHintMetadata, ISO_639_3_TO_1, LANG_HINT_HEADER};
+    use crate::pipelines::oscardoc::types::Metadata;
+
+    fn hinted(hint: &str, lang: &str) -> Metadata {
+        let mut headers = HashMap::new();
+        headers.insert(WarcHeader::from(LANG_HINT_HEADER), hint.as_bytes().to_vec());
+        let mut m = Metadata::default();
+        m.set_lang_hint(&headers, &LanguageTag::parse(lang.to_string()).unwrap());
+        m
+    }
+
+    #[test]
+    fn test_table_sorted() {
+        assert!(ISO_639_3_TO_1.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn test_to_iso_639_1() {
+        assert_eq!(to_iso_639_1("fra"), "fr");
+        assert_eq!(to_iso_639_1("nob"), "nb");
+        // no ISO 639-1 code
+        assert_eq!(to_iso_639_1("ceb"), "ceb");
+    }
+
+    #[test]
+    fn test_agreement() {
+        let m = hinted("fra", "fr");
+        assert_eq!(m.lang_hint(), Some("fra"));
+        assert_eq!(m.lang_hint_agreement(), Some(true));
+
+        let m = hinted("eng,fra", "fr");
+        assert_eq!(m.lang_hint(), Some("eng,fra"));
+        assert_eq!(m.lang_hint_agreement(), Some(false));
+    }
+
+    #[test]
+    fn test_missing() {
+        let mut m = Metadata::default();
+        m.set_lang_hint(
+            &HashMap::new(),
+            &LanguageTag::parse("fr".to_string()).unwrap(),
+        );
+        assert_eq!(m.lang_hint(), None);
+        assert_eq!(m.lang_hint_agreement(), None);
+    }
+}
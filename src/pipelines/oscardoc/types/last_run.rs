@@ -0,0 +1,108 @@
+/*! Last run timestamp.
+
+Incremental runs only process shards that were modified after the previous run started,
+e.g. the shards added to a mirror since last month.
+
+The timestamp is stored as an RFC 3339 date in `<dst>/.last_run`.
+!*/
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::error::Error;
+
+/// Last run file name.
+const LAST_RUN_FILENAME: &str = ".last_run";
+
+/// Start time of the last successful run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LastRun {
+    time: DateTime<Utc>,
+}
+
+impl LastRun {
+    pub fn new(time: SystemTime) -> Self {
+        Self { time: time.into() }
+    }
+
+    /// Get the last run path in the `dst` folder.
+    pub fn path(dst: &Path) -> PathBuf {
+        dst.join(LAST_RUN_FILENAME)
+    }
+
+    /// Load the last run from the `dst` folder.
+    ///
+    /// Returns [None] if there's none, i.e. on the first run.
+    pub fn load(dst: &Path) -> Result<Option<Self>, Error> {
+        let path = Self::path(dst);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        let time = DateTime::parse_from_rfc3339(content.trim())
+            .map_err(|e| Error::Custom(format!("invalid last run timestamp in {path:?}: {e}")))?;
+        Ok(Some(Self {
+            time: time.with_timezone(&Utc),
+        }))
+    }
+
+    /// Save the last run in the `dst` folder.
+    pub fn save(&self, dst: &Path) -> Result<(), Error> {
+        std::fs::write(
+            Self::path(dst),
+            self.time.to_rfc3339_opts(SecondsFormat::Nanos, true),
+        )?;
+        Ok(())
+    }
+
+    /// Is `mtime` newer than the last run, minus `grace`?
+    ///
+    /// The grace window accounts for clocks of network storage being behind the local one.
+    pub fn is_newer(&self, mtime: SystemTime, grace: Duration) -> bool {
+        let since = SystemTime::from(self.time)
+            .checked_sub(grace)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        mtime > since
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use tempfile::tempdir;
+
+    use super::LastRun;
+
+    #[test]
+    fn test_save_load() {
+        let dst = tempdir().unwrap();
+        assert_eq!(LastRun::load(dst.path()).unwrap(), None);
+
+        let last_run = LastRun::new(SystemTime::now());
+        last_run.save(dst.path()).unwrap();
+        assert_eq!(LastRun::load(dst.path()).unwrap(), Some(last_run));
+    }
+
+    #[test]
+    fn test_is_newer() {
+        let now = SystemTime::now();
+        let last_run = LastRun::new(now);
+        let minute = Duration::from_secs(60);
+
+        assert!(last_run.is_newer(now + minute, Duration::ZERO));
+        assert!(!last_run.is_newer(now - minute, Duration::ZERO));
+        assert!(last_run.is_newer(now - minute, 2 * minute));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let dst = tempdir().unwrap();
+        std::fs::write(LastRun::path(dst.path()), "yesterday").unwrap();
+        assert!(LastRun::load(dst.path()).is_err());
+    }
+}
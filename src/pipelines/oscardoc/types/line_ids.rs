@@ -0,0 +1,229 @@
+/*! Line-level identifications.
+
+Documents already hold their line identifications (`sentence_identifications`), in their metadata.
+For line-level work, they can also be written in separate files (`<dst>/<lang>_lines.jsonl`,
+or `<dst>/<lang>/lines.jsonl` for the [NamingScheme::Subdirectory] layout), with one [LineIdentification] per line.
+
+Lines are aligned with documents through the WARC record id and the line index,
+rather than through file offsets, so that alignment holds whatever the rotation of language files
+(and after deduplication or merging).
+The byte offset of each line in the document content is given too.
+
+Files are generated from the language files once they are written,
+and are compressed like them.
+!*/
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use oxilangtag::LanguageTag;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::io::{CorpusReader, NamingScheme};
+
+use super::Document;
+
+/// Number of documents whose lines are compressed together.
+const CHUNK_SIZE: usize = 500;
+
+/// Identification of a line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LineIdentification {
+    /// WARC record id of the document
+    pub id: String,
+    /// index of the line in the document content
+    pub line: usize,
+    /// byte offset of the line in the document content
+    pub offset: usize,
+    /// identified language, if any
+    pub label: Option<String>,
+    pub prob: Option<f32>,
+}
+
+impl LineIdentification {
+    /// Get the line identifications of a document.
+    ///
+    /// Lines that have no identification (i.e. when there are more lines than identifications) get none.
+    fn from_document(doc: &Document) -> Vec<Self> {
+        let content = doc.content();
+        let ids = doc.metadata().sentence_identifications();
+        let id = doc.warc_id();
+        content
+            .lines()
+            .enumerate()
+            .map(|(line, text)| {
+                let identification = ids.get(line).and_then(Option::as_ref);
+                Self {
+                    id: id.to_string(),
+                    line,
+                    offset: text.as_ptr() as usize - content.as_ptr() as usize,
+                    label: identification.map(|id| id.label().to_string()),
+                    prob: identification.map(|id| *id.prob()),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Line identifications files.
+pub struct LineIds;
+
+impl LineIds {
+    /// Get the line identifications file path of `lang` in the `dst` folder.
+    pub fn path(
+        dst: &Path,
+        lang: &LanguageTag<String>,
+        naming: NamingScheme,
+        ext: &str,
+    ) -> PathBuf {
+        match naming {
+            NamingScheme::Flat => dst.join(format!("{lang}_lines.jsonl{ext}")),
+            NamingScheme::Subdirectory => dst.join(lang.as_str()).join(format!("lines.jsonl{ext}")),
+        }
+    }
+
+    /// Write the line identifications file of `lang`, returning the number of lines.
+    fn generate_lang(
+        dst: &Path,
+        lang: &LanguageTag<String>,
+        naming: NamingScheme,
+    ) -> Result<usize, Error> {
+        let reader = CorpusReader::new(dst, lang)?;
+        let compression = reader.compression();
+        let path = Self::path(dst, lang, naming, compression.extension());
+        let mut file = BufWriter::new(File::create(path)?);
+
+        let mut nb_lines = 0;
+        let mut chunk = String::new();
+        for (idx, doc) in reader.enumerate() {
+            for line in LineIdentification::from_document(&doc?) {
+                chunk += &serde_json::to_string(&line)?;
+                chunk.push('\n');
+                nb_lines += 1;
+            }
+            if (idx + 1) % CHUNK_SIZE == 0 {
+                file.write_all(&compression.compress(chunk.as_bytes())?)?;
+                chunk.clear();
+            }
+        }
+        if !chunk.is_empty() {
+            file.write_all(&compression.compress(chunk.as_bytes())?)?;
+        }
+        file.flush()?;
+
+        Ok(nb_lines)
+    }
+
+    /// Write the line identifications files of every language of the corpus in `dst`, in parallel.
+    pub fn generate(dst: &Path, naming: NamingScheme) -> Result<(), Error> {
+        CorpusReader::languages(dst)?
+            .into_par_iter()
+            .try_for_each(|lang| {
+                let nb_lines = Self::generate_lang(dst, &lang, naming)?;
+                log::info!("[{}]: {} line identifications", lang, nb_lines);
+                Ok(())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use oscar_io::common::Identification;
+    use oscar_io::v3::{Document, Metadata, WriterTrait};
+    use oxilangtag::LanguageTag;
+    use tempfile::tempdir;
+    use warc::WarcHeader;
+
+    use super::{LineIdentification, LineIds};
+    use crate::io::{CorpusReader, NamingScheme, WriterDoc};
+
+    fn gen_doc(id: &str, lines: &[(&str, Option<&str>)]) -> Document {
+        let ids: Vec<_> = lines
+            .iter()
+            .map(|(_, label)| {
+                label.map(|label| {
+                    Identification::new(LanguageTag::parse(label.to_string()).unwrap(), 0.9)
+                })
+            })
+            .collect();
+        let doc_id = Identification::new(LanguageTag::parse("fr".to_string()).unwrap(), 0.9);
+        let content = lines
+            .iter()
+            .map(|(line, _)| *line)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut headers = HashMap::new();
+        headers.insert(WarcHeader::RecordID, id.as_bytes().to_vec());
+        Document::new(content, headers, Metadata::new(&doc_id, &ids))
+    }
+
+    fn read_lines(path: &Path) -> Vec<LineIdentification> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_generate() {
+        let dst = tempdir().unwrap();
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let docs = vec![
+            gen_doc(
+                "<urn:uuid:1>",
+                &[
+                    ("Bonjour", Some("fr")),
+                    ("Hello", Some("en")),
+                    ("???", None),
+                ],
+            ),
+            gen_doc("<urn:uuid:2>", &[("Salut", Some("fr"))]),
+        ];
+        // one document per file
+        let mut w = WriterDoc::new(dst.path(), lang.clone(), Some(1)).unwrap();
+        w.write(docs).unwrap();
+        w.close().unwrap();
+
+        LineIds::generate(dst.path(), NamingScheme::Flat).unwrap();
+
+        // the lines file isn't taken for a language file
+        assert_eq!(CorpusReader::languages(dst.path()).unwrap().len(), 1);
+
+        let lines = read_lines(&LineIds::path(dst.path(), &lang, NamingScheme::Flat, ""));
+        let docs: Vec<Document> = CorpusReader::new(dst.path(), &lang)
+            .unwrap()
+            .map(|doc| doc.unwrap())
+            .collect();
+        assert_eq!(
+            lines.len(),
+            docs.iter()
+                .map(|doc| doc.content().lines().count())
+                .sum::<usize>()
+        );
+
+        let labels: Vec<_> = lines
+            .iter()
+            .map(|line| (line.id.as_str(), line.line, line.label.as_deref()))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("<urn:uuid:1>", 0, Some("fr")),
+                ("<urn:uuid:1>", 1, Some("en")),
+                ("<urn:uuid:1>", 2, None),
+                ("<urn:uuid:2>", 0, Some("fr")),
+            ]
+        );
+
+        // offsets point to the lines in the content
+        let content = docs[0].content();
+        assert_eq!(&content[lines[1].offset..lines[2].offset - 1], "Hello");
+    }
+}
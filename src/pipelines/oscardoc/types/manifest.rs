@@ -0,0 +1,216 @@
+/*! Output manifest.
+
+Lists the language files of a generated corpus (rotated parts and harvested rejected documents included),
+with their size, number of lines and SHA-256 checksum,
+so that transfers can be checked and builds compared.
+
+Checksums are computed on the files as written (i.e. compressed, if compression is enabled),
+while lines are counted on the decompressed content, giving the number of documents.
+Files are streamed, so they are never held in memory.
+
+The manifest is stored as JSON in `<dst>/manifest.json`, with paths relative to `<dst>`.
+!*/
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use flate2::read::MultiGzDecoder;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::io::{Compression, CorpusReader};
+
+/// Manifest file name.
+const MANIFEST_FILENAME: &str = "manifest.json";
+
+/// A listed file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// path, relative to the corpus folder
+    pub path: PathBuf,
+    /// size on disk, in bytes
+    pub bytes: u64,
+    /// number of lines of the (decompressed) content
+    pub lines: u64,
+    /// hex-encoded SHA-256 of the file
+    pub sha256: String,
+}
+
+/// Reader that hashes the bytes that go through it.
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl ManifestEntry {
+    /// Build the entry of the file at `dst`/`path`, in a single pass over the file.
+    fn new(dst: &Path, path: PathBuf, compression: Compression) -> Result<Self, Error> {
+        let file = File::open(dst.join(&path))?;
+        let bytes = file.metadata()?.len();
+        let mut raw = HashingReader {
+            inner: BufReader::new(file),
+            hasher: Sha256::new(),
+        };
+
+        let lines = {
+            let mut content: Box<dyn Read + '_> = match compression {
+                Compression::None => Box::new(&mut raw),
+                Compression::Gzip => Box::new(MultiGzDecoder::new(&mut raw)),
+                Compression::Zstd => Box::new(zstd::Decoder::new(&mut raw)?),
+            };
+            let mut lines = 0;
+            let mut buf = [0u8; 1 << 16];
+            loop {
+                let n = content.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                lines += buf[..n].iter().filter(|b| **b == b'\n').count() as u64;
+            }
+            lines
+        };
+
+        // decoders may stop before the end of the file: hash what's left
+        io::copy(&mut raw, &mut io::sink())?;
+
+        Ok(Self {
+            path,
+            bytes,
+            lines,
+            sha256: format!("{:x}", raw.hasher.finalize()),
+        })
+    }
+}
+
+/// List of the files of a corpus.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub files: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Get the manifest path in the `dst` folder.
+    pub fn path(dst: &Path) -> PathBuf {
+        dst.join(MANIFEST_FILENAME)
+    }
+
+    /// List the language files of `dir`, relative to `dst`, with their compression.
+    fn lang_files(dst: &Path, dir: &Path) -> Result<Vec<(PathBuf, Compression)>, Error> {
+        let mut files = Vec::new();
+        for lang in CorpusReader::languages(dir)? {
+            let reader = CorpusReader::new(dir, &lang)?;
+            for path in reader.paths() {
+                // paths are built from dir, that is in dst
+                let path = path.strip_prefix(dst).unwrap_or(path).to_path_buf();
+                files.push((path, reader.compression()));
+            }
+        }
+        Ok(files)
+    }
+
+    /// Build the manifest of the corpus in `dst`, computing checksums in parallel.
+    pub fn generate(dst: &Path) -> Result<Self, Error> {
+        let mut files = Self::lang_files(dst, dst)?;
+        let rejected = dst.join("rejected");
+        if rejected.is_dir() {
+            files.extend(Self::lang_files(dst, &rejected)?);
+        }
+
+        let files = files
+            .into_par_iter()
+            .map(|(path, compression)| ManifestEntry::new(dst, path, compression))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(Self { files })
+    }
+
+    /// Save the manifest in the `dst` folder.
+    pub fn save(&self, dst: &Path) -> Result<(), Error> {
+        let file = File::create(Self::path(dst))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, path::Path};
+
+    use sha2::{Digest, Sha256};
+    use tempfile::tempdir;
+
+    use super::Manifest;
+    use crate::io::test_utils::{gen_docs, write_parts};
+    use crate::io::Compression;
+
+    /// Check that entries match the files on disk.
+    fn check(dst: &Path, manifest: &Manifest) {
+        for entry in &manifest.files {
+            let content = std::fs::read(dst.join(&entry.path)).unwrap();
+            assert_eq!(entry.bytes, content.len() as u64);
+            assert_eq!(entry.sha256, format!("{:x}", Sha256::digest(&content)));
+        }
+    }
+
+    #[test]
+    fn test_manifest() {
+        let dst = tempdir().unwrap();
+        write_parts(
+            dst.path(),
+            "fr",
+            gen_docs(&["foo\nbar", "baz"]),
+            Some(1),
+            Compression::None,
+        );
+        write_parts(
+            dst.path(),
+            "en",
+            gen_docs(&["quux"]),
+            Some(1),
+            Compression::Gzip,
+        );
+        write_parts(
+            dst.path(),
+            "br",
+            gen_docs(&["demat", "kenavo"]),
+            Some(1),
+            Compression::Zstd,
+        );
+
+        let manifest = Manifest::generate(dst.path()).unwrap();
+        manifest.save(dst.path()).unwrap();
+
+        let paths: Vec<_> = manifest
+            .files
+            .iter()
+            .map(|entry| (entry.path.to_str().unwrap(), entry.lines))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("br_meta_part_1.jsonl.zst", 1),
+                ("br_meta_part_2.jsonl.zst", 1),
+                ("en_meta.jsonl.gz", 1),
+                ("fr_meta_part_1.jsonl", 1),
+                ("fr_meta_part_2.jsonl", 1),
+            ]
+        );
+        check(dst.path(), &manifest);
+
+        let saved: Manifest =
+            serde_json::from_reader(File::open(Manifest::path(dst.path())).unwrap()).unwrap();
+        assert_eq!(saved, manifest);
+    }
+}
@@ -2,14 +2,50 @@
 
 * !*/
 // mod document;
+mod byte_offset;
+mod checkpoint;
+mod content_hash;
+mod counts;
+mod extra;
+mod generated;
+mod lang_hint;
+mod last_run;
+mod line_ids;
 mod location;
+mod manifest;
+mod near_duplicates;
+mod progress;
+mod provenance;
+mod quality;
+mod raw_bodies;
 mod rebuild;
+mod seen_hashes;
+mod stats;
+mod warc_info;
 
 // pub use document::Document;
 // pub use document::Metadata;
+pub use byte_offset::{set_byte_offset, ByteOffsetMetadata};
+pub use checkpoint::Checkpoint;
+pub use content_hash::{hash_content, normalize, set_content_hash, ContentHashMetadata};
+pub use counts::{set_content_counts, ContentMetadata};
+pub use extra::ExtraMetadata;
+pub use generated::GeneratedMetadata;
+pub use lang_hint::LangHintMetadata;
+pub use last_run::LastRun;
+pub use line_ids::LineIds;
 pub use location::{IncompleteLocation, Location, LocationBuilder};
+pub use manifest::Manifest;
+pub use near_duplicates::NearDuplicates;
 pub use oscar_io::v3::Document;
 pub use oscar_io::v3::Metadata;
+pub use progress::Progress;
+pub use provenance::Provenance;
+pub use quality::QualityMetadata;
+pub use raw_bodies::RawBodies;
 pub use rebuild::RebuildInformation;
 pub use rebuild::RebuildWriters;
 pub use rebuild::ShardResult;
+pub use seen_hashes::SeenHashes;
+pub use stats::{LangCounts, ShardCounters, ShardStats, Stats, Summary};
+pub use warc_info::WarcMetadata;
@@ -0,0 +1,244 @@
+/*! Near-duplicate documents.
+
+Exact deduplication (see [super::SeenHashes]) misses documents that only differ by a few sentences,
+such as pages sharing most of their boilerplate.
+Those are detected by estimating the [Jaccard similarity](https://en.wikipedia.org/wiki/Jaccard_index)
+of documents with [MinHash](https://en.wikipedia.org/wiki/MinHash) signatures.
+
+Documents are split into shingles, which are n-grams of normalized tokens
+(lowercased, keeping alphanumeric characters only), so that casing, punctuation and whitespace changes don't matter.
+Their signatures are indexed by bands (locality-sensitive hashing), so that only documents sharing at least one band
+are compared, and a document is a near-duplicate of an indexed one if their estimated similarity reaches the threshold.
+
+Indexes are per language, and hold at most a given number of signatures overall (all languages together),
+so that memory is bounded whatever the number of languages:
+once full, documents are still checked against indexed ones, but not indexed themselves.
+!*/
+use std::collections::HashMap;
+
+use oxilangtag::LanguageTag;
+
+use crate::error::Error;
+
+/// Number of hash functions (i.e. size of signatures).
+const NUM_HASHES: usize = 128;
+
+/// Number of rows of a band. The [NUM_HASHES] hashes are split in `NUM_HASHES / BAND_SIZE` bands.
+///
+/// 32 bands of 4 rows make documents with a similarity of 0.5 share a band with a 87% probability,
+/// so that candidates are missed only well below usual thresholds.
+const BAND_SIZE: usize = 4;
+
+/// Number of tokens of shingles.
+const SHINGLE_SIZE: usize = 3;
+
+/// Default maximum number of indexed signatures, over all languages.
+///
+/// A signature takes 512 bytes, plus its band entries: about 1KiB each, so 1GiB at most.
+pub const DEFAULT_CAPACITY: usize = 1_000_000;
+
+/// MinHash signature of a document.
+pub type Signature = [u32; NUM_HASHES];
+
+/// Signatures of a language, indexed by band.
+#[derive(Debug)]
+struct Index {
+    signatures: Vec<Signature>,
+    /// indices of signatures, by band position and hash
+    bands: Vec<HashMap<u64, Vec<usize>>>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Self {
+            signatures: Vec::new(),
+            bands: vec![HashMap::new(); NUM_HASHES / BAND_SIZE],
+        }
+    }
+}
+
+impl Index {
+    fn band_hashes(signature: &Signature) -> impl Iterator<Item = u64> + '_ {
+        signature.chunks_exact(BAND_SIZE).map(|band| {
+            let bytes: Vec<u8> = band.iter().flat_map(|h| h.to_le_bytes()).collect();
+            twox_hash::xxh3::hash64(&bytes)
+        })
+    }
+
+    /// Whether an indexed signature is at least `threshold` similar to `signature`.
+    fn contains_similar(&self, signature: &Signature, threshold: f32) -> bool {
+        Self::band_hashes(signature)
+            .zip(&self.bands)
+            .filter_map(|(hash, band)| band.get(&hash))
+            .flatten()
+            .any(|&idx| similarity(&self.signatures[idx], signature) >= threshold)
+    }
+
+    fn insert(&mut self, signature: Signature) {
+        let idx = self.signatures.len();
+        for (hash, band) in Self::band_hashes(&signature).zip(&mut self.bands) {
+            band.entry(hash).or_default().push(idx);
+        }
+        self.signatures.push(signature);
+    }
+}
+
+/// Estimated Jaccard similarity of the documents of two signatures.
+pub fn similarity(a: &Signature, b: &Signature) -> f32 {
+    let nb_equal = a.iter().zip(b).filter(|(a, b)| a == b).count();
+    nb_equal as f32 / NUM_HASHES as f32
+}
+
+/// Per-language index of document signatures, keeping only the first of near-duplicate documents.
+#[derive(Debug)]
+pub struct NearDuplicates {
+    threshold: f32,
+    capacity: usize,
+    /// number of indexed signatures, over all languages
+    len: usize,
+    indexes: HashMap<LanguageTag<String>, Index>,
+}
+
+impl NearDuplicates {
+    /// Create an index, with documents being near-duplicates above a similarity of `threshold` (between 0 and 1).
+    ///
+    /// Errors if the threshold is out of bounds.
+    pub fn new(threshold: f32) -> Result<Self, Error> {
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(Error::Custom(format!(
+                "near-duplicate threshold must be between 0 and 1 (got {threshold})"
+            )));
+        }
+        Ok(Self {
+            threshold,
+            capacity: DEFAULT_CAPACITY,
+            len: 0,
+            indexes: HashMap::new(),
+        })
+    }
+
+    /// Set the maximum number of indexed signatures, over all languages.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Compute the signature of `content`.
+    ///
+    /// This doesn't need the index to be locked, and can be done in parallel.
+    pub fn signature(&self, content: &str) -> Signature {
+        let tokens: Vec<String> = content
+            .split_whitespace()
+            .map(|token| {
+                token
+                    .chars()
+                    .filter(|c| c.is_alphanumeric())
+                    .flat_map(char::to_lowercase)
+                    .collect::<String>()
+            })
+            .filter(|token| !token.is_empty())
+            .collect();
+
+        let mut signature = [u32::MAX; NUM_HASHES];
+        // documents shorter than a shingle are a single one
+        for shingle in tokens.windows(SHINGLE_SIZE.min(tokens.len()).max(1)) {
+            // derive the hash functions from two hashes (Kirsch-Mitzenmacher)
+            let hash = twox_hash::xxh3::hash128(shingle.join(" ").as_bytes());
+            let (h1, h2) = (hash as u64, (hash >> 64) as u64 | 1);
+            for (i, min) in signature.iter_mut().enumerate() {
+                let h = (h1.wrapping_add((i as u64).wrapping_mul(h2)) >> 32) as u32;
+                *min = (*min).min(h);
+            }
+        }
+
+        signature
+    }
+
+    /// Record a document of `lang` by its `signature`.
+    ///
+    /// Returns `false` if it is a near-duplicate of an already recorded document.
+    pub fn insert(&mut self, lang: &LanguageTag<String>, signature: Signature) -> bool {
+        let index = self.indexes.entry(lang.clone()).or_default();
+        if index.contains_similar(&signature, self.threshold) {
+            return false;
+        }
+        if self.len < self.capacity {
+            index.insert(signature);
+            self.len += 1;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use oxilangtag::LanguageTag;
+
+    use super::{similarity, NearDuplicates};
+
+    /// A document of 20 sentences of 10 distinct words, the sentence `changed` being replaced.
+    fn gen_doc(changed: Option<usize>) -> String {
+        (0..20)
+            .map(|sentence| {
+                let words: Vec<String> = (0..10)
+                    .map(|word| match changed {
+                        Some(changed) if changed == sentence => format!("other{word}"),
+                        _ => format!("word{}", sentence * 10 + word),
+                    })
+                    .collect();
+                format!("{}.", words.join(" "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_near_duplicates() {
+        let mut near_dups = NearDuplicates::new(0.8).unwrap();
+        let en = LanguageTag::parse("en".to_string()).unwrap();
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+
+        let original = near_dups.signature(&gen_doc(None));
+        let changed = near_dups.signature(&gen_doc(Some(5)));
+        assert!(similarity(&original, &changed) >= 0.8);
+
+        assert!(near_dups.insert(&en, original));
+        assert!(!near_dups.insert(&en, changed));
+        // indexes are per language
+        assert!(near_dups.insert(&fr, changed));
+
+        let unrelated = near_dups.signature("a completely different document about something else");
+        assert!(near_dups.insert(&en, unrelated));
+    }
+
+    #[test]
+    fn test_normalization() {
+        let near_dups = NearDuplicates::new(0.8).unwrap();
+        assert_eq!(
+            near_dups.signature("Hello,   World! how are you"),
+            near_dups.signature("hello world\nHow are you?")
+        );
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut near_dups = NearDuplicates::new(0.8).unwrap().with_capacity(1);
+        let en = LanguageTag::parse("en".to_string()).unwrap();
+
+        assert!(near_dups.insert(&en, near_dups.signature("first document here")));
+        // not indexed, so its duplicates aren't detected
+        assert!(near_dups.insert(&en, near_dups.signature("second document there")));
+        assert!(near_dups.insert(&en, near_dups.signature("second document there")));
+        assert!(!near_dups.insert(&en, near_dups.signature("first document here")));
+
+        // the capacity is shared by languages
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        assert!(near_dups.insert(&fr, near_dups.signature("premier document ici")));
+        assert!(near_dups.insert(&fr, near_dups.signature("premier document ici")));
+    }
+
+    #[test]
+    fn test_threshold() {
+        assert!(NearDuplicates::new(1.5).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+/*! Run progress.
+
+Counts completed shards, logging `n/total` along with an estimated time of arrival
+derived from the average shard duration since the start of the run.
+
+Shards complete out of order and from several workers, so completions are counted atomically.
+A run can also be stopped early (see [Progress::stop]), so that workers don't pick new shards,
+either explicitly or once a deadline is reached (see [Progress::with_deadline]).
+!*/
+use std::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+/// Progress of a run over a known number of shards.
+#[derive(Debug)]
+pub struct Progress {
+    total: usize,
+    done: AtomicUsize,
+    stopped: AtomicBool,
+    start: Instant,
+    deadline: Option<Duration>,
+    quiet: bool,
+}
+
+impl Progress {
+    /// Track the progress of `total` shards. Nothing is logged if `quiet` is set.
+    pub fn new(total: usize, quiet: bool) -> Self {
+        if !quiet {
+            info!("{} shards to process", total);
+        }
+        Self {
+            total,
+            done: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+            start: Instant::now(),
+            deadline: None,
+            quiet,
+        }
+    }
+
+    /// Stop the run once `deadline` has elapsed since its start.
+    ///
+    /// The deadline is only checked when asking whether the run is stopped (see [Progress::stopped]),
+    /// i.e. before picking a new shard: shards being processed are not interrupted.
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Number of completed shards.
+    pub fn done(&self) -> usize {
+        self.done.load(Ordering::SeqCst)
+    }
+
+    /// Record the completion of a shard, logging the progress.
+    ///
+    /// Returns the number of completed shards.
+    pub fn incr(&self) -> usize {
+        let done = self.done.fetch_add(1, Ordering::SeqCst) + 1;
+        if !self.quiet {
+            match self.eta(done, self.start.elapsed()) {
+                Some(eta) => info!(
+                    "{}/{} shards processed, ETA {}",
+                    done,
+                    self.total,
+                    format_duration(eta)
+                ),
+                None => info!("{}/{} shards processed", done, self.total),
+            }
+        }
+        done
+    }
+
+    /// Stop the run: remaining shards won't be processed.
+    pub fn stop(&self) {
+        if !self.stopped.swap(true, Ordering::SeqCst) && !self.quiet {
+            info!("stopping after {}/{} shards", self.done(), self.total);
+        }
+    }
+
+    /// Whether the run has been stopped (see [Progress::stop]), or its deadline has been reached.
+    pub fn stopped(&self) -> bool {
+        if self.stopped.load(Ordering::SeqCst) {
+            return true;
+        }
+        match self.deadline {
+            Some(deadline) if self.start.elapsed() >= deadline => {
+                if !self.quiet {
+                    info!("deadline of {} reached", format_duration(deadline));
+                }
+                self.stop();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Log the total duration of the run.
+    pub fn finish(&self) {
+        if !self.quiet {
+            info!(
+                "{}/{} shards processed in {}",
+                self.done(),
+                self.total,
+                format_duration(self.start.elapsed())
+            );
+        }
+    }
+
+    /// Remaining time, given that `done` shards were processed in `elapsed`.
+    fn eta(&self, done: usize, elapsed: Duration) -> Option<Duration> {
+        if done == 0 {
+            return None;
+        }
+        let remaining = self.total.saturating_sub(done) as u32;
+        Some(elapsed / done as u32 * remaining)
+    }
+}
+
+/// Format `duration` as `[<h>h][<m>m]<s>s`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, mins, secs) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    if hours > 0 {
+        format!("{hours}h{mins:02}m{secs:02}s")
+    } else if mins > 0 {
+        format!("{mins}m{secs:02}s")
+    } else {
+        format!("{secs}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{format_duration, Progress};
+
+    #[test]
+    fn test_progress() {
+        let p = Progress::new(4, true);
+        assert_eq!(p.eta(0, Duration::from_secs(10)), None);
+        assert_eq!(
+            p.eta(1, Duration::from_secs(10)),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(p.eta(4, Duration::from_secs(10)), Some(Duration::ZERO));
+
+        assert_eq!(p.incr(), 1);
+        assert_eq!(p.incr(), 2);
+        assert_eq!(p.done(), 2);
+
+        assert!(!p.stopped());
+        p.stop();
+        assert!(p.stopped());
+    }
+
+    #[test]
+    fn test_deadline() {
+        let p = Progress::new(4, true).with_deadline(Duration::from_secs(3600));
+        assert!(!p.stopped());
+
+        let p = Progress::new(4, true).with_deadline(Duration::ZERO);
+        assert!(p.stopped());
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(42)), "42s");
+        assert_eq!(format_duration(Duration::from_secs(125)), "2m05s");
+        assert_eq!(
+            format_duration(Duration::from_secs(3 * 3600 + 61)),
+            "3h01m01s"
+        );
+    }
+}
@@ -0,0 +1,148 @@
+/*! Corpus provenance.
+
+Records how a corpus was identified: the SHA-256 of the FastText model along with the identification parameters,
+so that one can check that two corpora were built with the same model.
+Models are streamed while hashing, so they are never held in memory.
+
+Runs using a custom identifier (see [crate::pipelines::oscardoc::OscarDoc::with_identifier]) have no model to hash,
+and don't use the identification parameters, which are then not recorded either.
+
+The provenance is stored as JSON in `<dst>/provenance.json`.
+!*/
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+
+/// Provenance file name.
+const PROVENANCE_FILENAME: &str = "provenance.json";
+
+/// Language identification model.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Model {
+    /// path of the model, as given to the pipeline
+    pub path: PathBuf,
+    /// hex-encoded SHA-256 of the model file
+    pub sha256: String,
+}
+
+impl Model {
+    /// Hash the model at `path`.
+    pub fn new(path: &Path) -> Result<Self, Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut hasher = Sha256::new();
+        let mut buf = [0u8; 1 << 16];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            sha256: format!("{:x}", hasher.finalize()),
+        })
+    }
+}
+
+/// How a corpus was identified.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// model used, [None] for custom identifiers
+    pub model: Option<Model>,
+    /// number of predictions per line, [None] for custom identifiers
+    pub k: Option<i32>,
+    /// minimum prediction probability, [None] for custom identifiers
+    pub threshold: Option<f32>,
+}
+
+impl Provenance {
+    /// Build the provenance of a run using the model at `model_path`, hashing it.
+    pub fn new(model_path: &Path, k: i32, threshold: f32) -> Result<Self, Error> {
+        Ok(Self {
+            model: Some(Model::new(model_path)?),
+            k: Some(k),
+            threshold: Some(threshold),
+        })
+    }
+
+    /// Build the provenance of a run using a custom identifier.
+    pub fn custom() -> Self {
+        Self {
+            model: None,
+            k: None,
+            threshold: None,
+        }
+    }
+
+    /// Get the provenance path in the `dst` folder.
+    pub fn path(dst: &Path) -> PathBuf {
+        dst.join(PROVENANCE_FILENAME)
+    }
+
+    /// Save the provenance in the `dst` folder.
+    pub fn save(&self, dst: &Path) -> Result<(), Error> {
+        let file = File::create(Self::path(dst))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use sha2::{Digest, Sha256};
+    use tempfile::tempdir;
+
+    use super::Provenance;
+
+    #[test]
+    fn test_provenance() {
+        let dst = tempdir().unwrap();
+        let model_path = dst.path().join("lid.bin");
+        // bigger than the read buffer
+        let content = vec![42u8; 100_000];
+        std::fs::File::create(&model_path)
+            .unwrap()
+            .write_all(&content)
+            .unwrap();
+
+        Provenance::new(&model_path, 1, 0.8)
+            .unwrap()
+            .save(dst.path())
+            .unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_reader(std::fs::File::open(Provenance::path(dst.path())).unwrap())
+                .unwrap();
+        assert_eq!(
+            json["model"]["sha256"],
+            format!("{:x}", Sha256::digest(&content))
+        );
+        assert_eq!(json["model"]["path"], model_path.to_str().unwrap());
+        assert_eq!(json["k"], 1);
+        assert_eq!(json["threshold"], 0.8);
+    }
+
+    #[test]
+    fn test_custom() {
+        let provenance = Provenance::custom();
+        assert!(provenance.model.is_none());
+        assert!(provenance.k.is_none() && provenance.threshold.is_none());
+    }
+
+    #[test]
+    fn test_missing_model() {
+        let dst = tempdir().unwrap();
+        assert!(Provenance::new(&dst.path().join("missing.bin"), 1, 0.8).is_err());
+    }
+}
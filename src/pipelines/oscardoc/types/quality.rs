@@ -0,0 +1,43 @@
+/*! Quality score.
+
+Stores the quality score computed by the quality filter (see [crate::filtering::record::PFilter::score])
+in [Metadata], so that documents can be filtered downstream at a custom threshold.
+
+It is stored as [ExtraMetadata] under the `quality_score` key.
+!*/
+use super::{ExtraMetadata, Metadata};
+
+const QUALITY_SCORE_KEY: &str = "quality_score";
+
+/// Typed access to the quality score.
+pub trait QualityMetadata {
+    fn set_quality_score(&mut self, score: f32);
+
+    /// Quality score. [None] if there's none or if it is invalid.
+    fn quality_score(&self) -> Option<f32>;
+}
+
+impl QualityMetadata for Metadata {
+    fn set_quality_score(&mut self, score: f32) {
+        self.add_extra(QUALITY_SCORE_KEY, &score.to_string());
+    }
+
+    fn quality_score(&self) -> Option<f32> {
+        self.extra(QUALITY_SCORE_KEY)?.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QualityMetadata;
+    use crate::pipelines::oscardoc::types::Metadata;
+
+    #[test]
+    fn test_quality_score() {
+        let mut m = Metadata::default();
+        assert_eq!(m.quality_score(), None);
+
+        m.set_quality_score(0.25);
+        assert_eq!(m.quality_score(), Some(0.25));
+    }
+}
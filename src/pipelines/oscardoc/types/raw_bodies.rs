@@ -0,0 +1,139 @@
+/*! Original record bodies.
+
+Keeps the bodies of the records that yielded documents, byte for byte (i.e. before any cleaning or UTF-8 conversion),
+so that documents can be traced back to what was crawled.
+
+Bodies of a shard are stored in `<dst>/raw/<shard number>.gz`, each body being its own gzip member,
+along with an index in `<dst>/raw/<shard number>.index.tsv` that maps the WARC record id to the (compressed)
+offset and length of its member: a body can then be read without decompressing the whole file (see [RawBodies::read]).
+!*/
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use crate::error::Error;
+
+/// Raw bodies folder name.
+const RAW_DIRNAME: &str = "raw";
+
+/// Original bodies of the records of a shard, by WARC record id.
+#[derive(Debug, Default)]
+pub struct RawBodies {
+    bodies: Vec<(String, Vec<u8>)>,
+}
+
+impl RawBodies {
+    /// Get the raw bodies folder in the `dst` folder.
+    pub fn dir(dst: &Path) -> PathBuf {
+        dst.join(RAW_DIRNAME)
+    }
+
+    fn paths(dst: &Path, shard_id: usize) -> (PathBuf, PathBuf) {
+        let dir = Self::dir(dst);
+        (
+            dir.join(format!("{shard_id}.gz")),
+            dir.join(format!("{shard_id}.index.tsv")),
+        )
+    }
+
+    pub fn push(&mut self, warc_id: String, body: Vec<u8>) {
+        self.bodies.push((warc_id, body));
+    }
+
+    /// Only keep the bodies for which `keep` returns `true`.
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        self.bodies.retain(|(warc_id, _)| keep(warc_id));
+    }
+
+    /// Sort bodies by record id, for reproducible files.
+    pub fn sort(&mut self) {
+        self.bodies.sort_by(|(a, _), (b, _)| a.cmp(b));
+    }
+
+    /// Write the bodies and their index in the `dst` folder, replacing those of a previous run.
+    pub fn write(&self, dst: &Path, shard_id: usize) -> Result<(), Error> {
+        std::fs::create_dir_all(Self::dir(dst))?;
+        let (bodies_path, index_path) = Self::paths(dst, shard_id);
+        let mut bodies = BufWriter::new(File::create(bodies_path)?);
+        let mut index = BufWriter::new(File::create(index_path)?);
+
+        let mut offset = 0;
+        for (warc_id, body) in &self.bodies {
+            let mut member = GzEncoder::new(Vec::new(), Compression::default());
+            member.write_all(body)?;
+            let member = member.finish()?;
+            bodies.write_all(&member)?;
+            writeln!(index, "{}\t{}\t{}", warc_id, offset, member.len())?;
+            offset += member.len() as u64;
+        }
+
+        bodies.flush()?;
+        index.flush()?;
+        Ok(())
+    }
+
+    /// Read the original body of the record `warc_id` of shard `shard_id`, if it has been stored.
+    pub fn read(dst: &Path, shard_id: usize, warc_id: &str) -> Result<Option<Vec<u8>>, Error> {
+        let (bodies_path, index_path) = Self::paths(dst, shard_id);
+        let index = BufReader::new(File::open(index_path)?);
+        for line in index.lines() {
+            let line = line?;
+            let mut fields = line.split('\t');
+            if fields.next() != Some(warc_id) {
+                continue;
+            }
+            let mut location = fields.map(|field| {
+                field.parse::<u64>().map_err(|e| {
+                    Error::Custom(format!("invalid raw bodies index line {line:?}: {e:?}"))
+                })
+            });
+            let (offset, len) = match (location.next(), location.next()) {
+                (Some(offset), Some(len)) => (offset?, len?),
+                _ => {
+                    return Err(Error::Custom(format!(
+                        "invalid raw bodies index line {line:?}"
+                    )))
+                }
+            };
+
+            let mut bodies = File::open(&bodies_path)?;
+            bodies.seek(SeekFrom::Start(offset))?;
+            let mut body = Vec::new();
+            GzDecoder::new(bodies.take(len)).read_to_end(&mut body)?;
+            return Ok(Some(body));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RawBodies;
+
+    #[test]
+    fn test_write_read() {
+        let dst = tempfile::tempdir().unwrap();
+        let mut raw = RawBodies::default();
+        // not valid UTF-8
+        raw.push("<urn:uuid:1>".to_string(), vec![b'a', 0xff, b'\n', b'b']);
+        raw.push("<urn:uuid:2>".to_string(), b"second body".to_vec());
+        raw.write(dst.path(), 3).unwrap();
+
+        assert_eq!(
+            RawBodies::read(dst.path(), 3, "<urn:uuid:2>").unwrap(),
+            Some(b"second body".to_vec())
+        );
+        assert_eq!(
+            RawBodies::read(dst.path(), 3, "<urn:uuid:1>").unwrap(),
+            Some(vec![b'a', 0xff, b'\n', b'b'])
+        );
+        assert_eq!(
+            RawBodies::read(dst.path(), 3, "<urn:uuid:3>").unwrap(),
+            None
+        );
+    }
+}
@@ -12,17 +12,20 @@ Those slim documents contain:
 - line start/end for each WARC Record. Note that `line_start and line_end` are _included_,
 so a document that has `(line_start, line_end) == (10, 10)` has a single line that is at offset 10.
 
+Avro files can't be appended to: resumed runs write new files (see [RebuildWriters::with_dst_resume]),
+after truncating those of the interrupted run back to their recorded sizes (see [RebuildWriters::truncate]).
+
 !*/
 
 use std::{
-    collections::HashMap,
-    fs::File,
+    collections::{BTreeMap, HashMap},
+    fs::{File, OpenOptions},
     path::{Path, PathBuf},
     sync::{Arc, Mutex, RwLock},
 };
 
 use avro_rs::{AvroResult, Codec, Schema, Writer};
-use log::error;
+use log::{error, warn};
 use oxilangtag::LanguageTag;
 use serde::Deserialize;
 use serde::Serialize;
@@ -215,6 +218,8 @@ impl ShardResult {
 pub struct RebuildWriter<'a, T> {
     schema: &'a Schema,
     writer: Writer<'a, T>,
+    /// path of the avro file, if writing to one
+    path: Option<PathBuf>,
 }
 
 impl<'a, T: std::io::Write> RebuildWriter<'a, T> {
@@ -223,6 +228,7 @@ impl<'a, T: std::io::Write> RebuildWriter<'a, T> {
         Self {
             schema,
             writer: Writer::with_codec(schema, writer, Codec::Snappy),
+            path: None,
         }
     }
 
@@ -259,7 +265,14 @@ impl<'a> RebuildWriter<'a, File> {
     pub fn from_path(dst: &Path) -> Result<Self, Error> {
         let schema = &SCHEMA;
         let dest_file = File::create(dst)?;
-        Ok(Self::new(schema, dest_file))
+        let mut writer = Self::new(schema, dest_file);
+        writer.path = Some(dst.to_path_buf());
+        Ok(writer)
+    }
+
+    /// Get the path of the avro file.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
     }
 }
 
@@ -267,6 +280,7 @@ impl<'a> RebuildWriter<'a, File> {
 // pub struct RebuildWriters<'a, T>(HashMap<LanguageTag<String>, Arc<Mutex<RebuildWriter<'a, T>>>>);
 pub struct RebuildWriters<'a, T> {
     inner: Arc<RwLock<HashMap<LanguageTag<String>, Arc<Mutex<RebuildWriter<'a, T>>>>>>,
+    keep_existing: bool,
 }
 
 impl<'a, T> RebuildWriters<'a, T> {
@@ -284,17 +298,33 @@ impl<'a, T> RebuildWriters<'a, T> {
 }
 
 impl<'a> RebuildWriters<'a, File> {
+    /// Get the avro file path for `lang`.
+    ///
+    /// If `keep_existing` is set, the first non-existing path of
+    /// `<dst>/<lang>.avro`, `<dst>/<lang>_1.avro`, `<dst>/<lang>_2.avro`... is used.
     #[inline]
-    fn forge_dst(dst: &Path, lang: &LanguageTag<String>) -> PathBuf {
+    pub(crate) fn forge_dst(
+        dst: &Path,
+        lang: &LanguageTag<String>,
+        keep_existing: bool,
+    ) -> PathBuf {
         let mut p = PathBuf::from(dst);
         p.push(format!("{}.avro", lang.as_str()));
 
+        if keep_existing {
+            let mut part = 1;
+            while p.exists() {
+                p.set_file_name(format!("{}_{part}.avro", lang.as_str()));
+                part += 1;
+            }
+        }
+
         p
     }
 
     pub fn insert(&'a self, root_dir: &Path, k: &LanguageTag<String>) -> Result<(), Error> {
         let mut wlock = self.inner.write().unwrap();
-        let (lang, new_writer) = Self::new_writer_mutex(root_dir, k.clone())?;
+        let (lang, new_writer) = Self::new_writer_mutex(root_dir, k.clone(), self.keep_existing)?;
         wlock.entry(lang).or_insert(new_writer);
         Ok(())
     }
@@ -304,14 +334,73 @@ impl<'a> RebuildWriters<'a, File> {
     fn new_writer_mutex(
         dst: &Path,
         lang: LanguageTag<String>,
+        keep_existing: bool,
     ) -> Result<(LanguageTag<String>, Arc<Mutex<RebuildWriter<'a, File>>>), Error> {
         // let lang = Lang::from_str(lang).unwrap();
-        let path = Self::forge_dst(dst, &lang);
+        let path = Self::forge_dst(dst, &lang, keep_existing);
         let rw = RebuildWriter::from_path(&path)?;
         let rw_mutex = Arc::new(Mutex::new(rw));
         Ok((lang, rw_mutex))
     }
 
+    /// Get the size of the avro file of each language, keyed by file name.
+    ///
+    /// Records that have not been flushed (see [RebuildWriter::flush]) aren't accounted for.
+    pub fn lengths(&self) -> Result<BTreeMap<String, u64>, Error> {
+        let mut lengths = BTreeMap::new();
+        for writer in self.inner.read().unwrap().values() {
+            let writer = writer.lock().unwrap();
+            if let Some(path) = writer.path() {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                lengths.insert(name.to_string(), std::fs::metadata(path)?.len());
+            }
+        }
+        Ok(lengths)
+    }
+
+    /// Truncate the avro files in `dst` back to their recorded size (see [RebuildWriters::lengths]),
+    /// removing those that have none.
+    ///
+    /// Used when resuming, before creating writers, to drop records that were written after the sizes were recorded.
+    pub fn truncate(dst: &Path, lengths: &BTreeMap<String, u64>) -> Result<(), Error> {
+        if !dst.exists() {
+            return Ok(());
+        }
+        for entry in dst.read_dir()? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "avro") {
+                continue;
+            }
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            match lengths.get(name.as_ref()) {
+                Some(len) => {
+                    let actual_len = std::fs::metadata(&path)?.len();
+                    if actual_len < *len {
+                        return Err(Error::Custom(format!(
+                            "{path:?} is shorter ({actual_len} bytes) than its recorded size ({len} bytes)"
+                        )));
+                    }
+                    if actual_len > *len {
+                        warn!(
+                            "{:?}: removing {} bytes written after the last checkpoint",
+                            path,
+                            actual_len - len
+                        );
+                        OpenOptions::new().write(true).open(&path)?.set_len(*len)?;
+                    }
+                }
+                None => {
+                    warn!(
+                        "{:?}: removing file written after the last checkpoint",
+                        path
+                    );
+                    std::fs::remove_file(&path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Use `dst` as a root path for avro files storage.
     ///
     /// Each language will have a possibly empty avro file, at `<dst>/<lang>.avro`.
@@ -329,6 +418,22 @@ impl<'a> RebuildWriters<'a, File> {
 
         Ok(RebuildWriters {
             inner: Arc::new(RwLock::new(HashMap::new())),
+            keep_existing: false,
+        })
+    }
+
+    /// Use `dst` as a root path for avro files storage, keeping existing avro files.
+    ///
+    /// Avro files can't be appended to, so languages that already have a `<dst>/<lang>.avro` file
+    /// get a new one at `<dst>/<lang>_<n>.avro`. Used when resuming a run.
+    pub fn with_dst_resume(dst: &Path) -> Result<Self, Error> {
+        if !dst.exists() {
+            std::fs::create_dir(dst)?;
+        }
+
+        Ok(RebuildWriters {
+            inner: Arc::new(RwLock::new(HashMap::new())),
+            keep_existing: true,
         })
     }
 }
@@ -457,6 +562,7 @@ mod tests {
     fn test_rebuild_writers_contains() {
         let rbw = RebuildWriters::<usize> {
             inner: Arc::new(RwLock::new(HashMap::new())),
+            keep_existing: false,
         };
 
         assert!(!rbw.contains(&LanguageTag::parse("fr".to_string()).unwrap()));
@@ -469,6 +575,7 @@ mod tests {
     fn test_rebuild_writers_insert() {
         let rbw = RebuildWriters::<File> {
             inner: Arc::new(RwLock::new(HashMap::new())),
+            keep_existing: false,
         };
 
         let lang = LanguageTag::parse("fr".to_string()).unwrap();
@@ -478,4 +585,66 @@ mod tests {
         rbw.insert(dir.path(), &lang).unwrap();
         assert!(rbw.contains(&lang));
     }
+
+    #[test]
+    fn test_rebuild_writers_resume() {
+        let lang = LanguageTag::parse("fr".to_string()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let rbw = RebuildWriters::with_dst(dir.path()).unwrap();
+        rbw.insert(dir.path(), &lang).unwrap();
+
+        let rbw = RebuildWriters::with_dst_resume(dir.path()).unwrap();
+        rbw.insert(dir.path(), &lang).unwrap();
+
+        assert!(dir.path().join("fr.avro").exists());
+        assert!(dir.path().join("fr_1.avro").exists());
+    }
+
+    #[test]
+    fn test_rebuild_writers_truncate() {
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        let en = LanguageTag::parse("en".to_string()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        fn append<'a>(
+            rbw: &'a RebuildWriters<'a, File>,
+            lang: &LanguageTag<String>,
+            shard_id: i64,
+        ) {
+            let writer = rbw.writers().get(lang).unwrap().clone();
+            let mut writer = writer.lock().unwrap();
+            let sr = ShardResult::new(
+                shard_id,
+                vec![Location::default()],
+                vec![Metadata::default()],
+            );
+            writer.append_ser(sr).unwrap();
+            writer.flush().unwrap();
+        }
+        let shard_ids = |path: &std::path::Path| -> Vec<i64> {
+            avro_rs::Reader::new(File::open(path).unwrap())
+                .unwrap()
+                .map(|r| {
+                    avro_rs::from_value::<ShardResult>(&r.unwrap())
+                        .unwrap()
+                        .shard_id()
+                })
+                .collect()
+        };
+
+        let lengths = {
+            let rbw = RebuildWriters::with_dst(dir.path()).unwrap();
+            rbw.insert(dir.path(), &fr).unwrap();
+            append(&rbw, &fr, 0);
+            let lengths = rbw.lengths().unwrap();
+            assert_eq!(lengths.keys().collect::<Vec<_>>(), ["fr.avro"]);
+            append(&rbw, &fr, 1);
+            rbw.insert(dir.path(), &en).unwrap();
+            append(&rbw, &en, 1);
+            lengths
+        };
+
+        RebuildWriters::truncate(dir.path(), &lengths).unwrap();
+        assert_eq!(shard_ids(&dir.path().join("fr.avro")), [0]);
+        assert!(!dir.path().join("en.avro").exists());
+    }
 }
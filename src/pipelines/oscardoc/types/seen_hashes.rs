@@ -0,0 +1,281 @@
+/*! Previously seen documents.
+
+Keeps the hashes of the documents written by previous runs (typically previous monthly builds),
+so that documents already present in a previous corpus are not written again.
+
+Hashes are 128-bit XXH3 hashes of document contents: XXH3 is one of the fastest non-cryptographic hashes,
+and 128 bits make collisions negligible even for billions of documents.
+
+They are stored in a sidecar file (usually `hashes.bin`, alongside the output),
+as consecutive 16-byte little-endian integers with no header,
+so that new hashes can simply be appended to it.
+
+Checking documents and recording them are separate steps (see [SeenHashes::contains] and [SeenHashes::extend]),
+so that documents are only recorded once they are written: documents that could not be written
+are not skipped when they are processed again.
+
+Hashes can also be loaded read-only (see [SeenHashes::read_only]), e.g. when documents aren't written:
+new hashes are then only recorded in memory.
+!*/
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{BufWriter, Read, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use log::{info, warn};
+
+use super::hash_content;
+use crate::error::Error;
+
+/// Size of a stored hash, in bytes.
+const HASH_SIZE: usize = 16;
+
+struct Inner {
+    hashes: HashSet<u128>,
+    /// [None] if read-only
+    file: Option<BufWriter<File>>,
+    /// size of the file, in bytes
+    size: u64,
+}
+
+/// Thread-safe set of seen document hashes, backed by an append-only file.
+pub struct SeenHashes {
+    inner: Mutex<Inner>,
+}
+
+impl SeenHashes {
+    /// Load hashes from `path`, creating it if it doesn't exist.
+    ///
+    /// Errors if the file size is not a multiple of the hash size.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let hashes = Self::load(path)?;
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = (hashes.len() * HASH_SIZE) as u64;
+
+        Ok(Self {
+            inner: Mutex::new(Inner {
+                hashes,
+                file: Some(BufWriter::new(file)),
+                size,
+            }),
+        })
+    }
+
+    /// Load hashes from `path` if it exists, without ever writing to it:
+    /// hashes recorded afterwards (see [SeenHashes::extend]) are only kept in memory.
+    ///
+    /// Errors if the file size is not a multiple of the hash size.
+    pub fn read_only(path: &Path) -> Result<Self, Error> {
+        let hashes = Self::load(path)?;
+        let size = (hashes.len() * HASH_SIZE) as u64;
+
+        Ok(Self {
+            inner: Mutex::new(Inner {
+                hashes,
+                file: None,
+                size,
+            }),
+        })
+    }
+
+    /// Load the hashes stored at `path`, if it exists.
+    fn load(path: &Path) -> Result<HashSet<u128>, Error> {
+        let mut hashes = HashSet::new();
+        if path.exists() {
+            let mut buf = Vec::new();
+            File::open(path)?.read_to_end(&mut buf)?;
+            if buf.len() % HASH_SIZE != 0 {
+                return Err(Error::Custom(format!(
+                    "{path:?} is not a valid hash file (size is not a multiple of {HASH_SIZE})"
+                )));
+            }
+            hashes.extend(
+                buf.chunks_exact(HASH_SIZE)
+                    // chunks are necessarily HASH_SIZE long
+                    .map(|chunk| u128::from_le_bytes(chunk.try_into().unwrap())),
+            );
+            info!("loaded {} hashes from {:?}", hashes.len(), path);
+        }
+
+        Ok(hashes)
+    }
+
+    /// Truncate the file at `path` to `size` bytes, dropping the hashes recorded after it was that size,
+    /// e.g. those of documents that were removed when resuming a run.
+    pub fn truncate(path: &Path, size: u64) -> Result<(), Error> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let actual_size = std::fs::metadata(path)?.len();
+        if actual_size > size {
+            warn!(
+                "{:?}: removing {} hashes recorded after the last checkpoint",
+                path,
+                (actual_size - size) / HASH_SIZE as u64
+            );
+            OpenOptions::new().write(true).open(path)?.set_len(size)?;
+        }
+        Ok(())
+    }
+
+    /// Get the hash of a document content.
+    pub fn hash(content: &str) -> u128 {
+        hash_content(content, false)
+    }
+
+    /// Has the content with this hash been seen?
+    pub fn contains(&self, hash: u128) -> bool {
+        self.inner
+            .lock()
+            .expect("Problem locking seen hashes")
+            .hashes
+            .contains(&hash)
+    }
+
+    /// Record hashes as seen, appending new ones to the file (unless read-only) and flushing it.
+    ///
+    /// Returns the number of new hashes.
+    pub fn extend(&self, hashes: impl IntoIterator<Item = u128>) -> Result<usize, Error> {
+        let mut inner = self.inner.lock().expect("Problem locking seen hashes");
+        let mut nb_new = 0;
+        for hash in hashes {
+            if inner.hashes.insert(hash) {
+                if let Some(file) = &mut inner.file {
+                    file.write_all(&hash.to_le_bytes())?;
+                }
+                nb_new += 1;
+            }
+        }
+        if let Some(file) = &mut inner.file {
+            file.flush()?;
+        }
+        inner.size += (nb_new * HASH_SIZE) as u64;
+        Ok(nb_new)
+    }
+
+    /// Get the size of the file, in bytes, including the recorded hashes.
+    pub fn size(&self) -> u64 {
+        self.inner.lock().expect("Problem locking seen hashes").size
+    }
+
+    /// Flush and sync new hashes to disk.
+    pub fn close(self) -> Result<(), Error> {
+        let inner = self
+            .inner
+            .into_inner()
+            .expect("Problem locking seen hashes");
+        if let Some(file) = inner.file {
+            let file = file.into_inner().map_err(|e| e.into_error())?;
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rayon::prelude::*;
+    use tempfile::tempdir;
+
+    use super::SeenHashes;
+
+    #[test]
+    fn test_roundtrip() {
+        let dst = tempdir().unwrap();
+        let path = dst.path().join("hashes.bin");
+        let hash = SeenHashes::hash;
+
+        let seen = SeenHashes::open(&path).unwrap();
+        assert!(!seen.contains(hash("foo")));
+        assert_eq!(
+            seen.extend([hash("foo"), hash("bar"), hash("foo")])
+                .unwrap(),
+            2
+        );
+        assert!(seen.contains(hash("foo")));
+        assert_eq!(seen.size(), 32);
+        seen.close().unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 32);
+
+        let seen = SeenHashes::open(&path).unwrap();
+        assert!(seen.contains(hash("foo")));
+        assert!(seen.contains(hash("bar")));
+        assert!(!seen.contains(hash("baz")));
+        assert_eq!(seen.size(), 32);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let dst = tempdir().unwrap();
+        let path = dst.path().join("hashes.bin");
+        let hash = SeenHashes::hash;
+
+        let seen = SeenHashes::open(&path).unwrap();
+        seen.extend([hash("foo")]).unwrap();
+        let size = seen.size();
+        seen.extend([hash("bar")]).unwrap();
+        seen.close().unwrap();
+
+        SeenHashes::truncate(&path, size).unwrap();
+        let seen = SeenHashes::open(&path).unwrap();
+        assert!(seen.contains(hash("foo")));
+        assert!(!seen.contains(hash("bar")));
+    }
+
+    #[test]
+    fn test_read_only() {
+        let dst = tempdir().unwrap();
+        let path = dst.path().join("hashes.bin");
+        let hash = SeenHashes::hash;
+
+        // missing files are not created
+        let seen = SeenHashes::read_only(&path).unwrap();
+        assert_eq!(seen.extend([hash("foo")]).unwrap(), 1);
+        assert!(seen.contains(hash("foo")));
+        seen.close().unwrap();
+        assert!(!path.exists());
+
+        let seen = SeenHashes::open(&path).unwrap();
+        seen.extend([hash("foo")]).unwrap();
+        seen.close().unwrap();
+
+        // existing ones are left as is
+        let seen = SeenHashes::read_only(&path).unwrap();
+        assert!(seen.contains(hash("foo")));
+        assert_eq!(seen.extend([hash("bar")]).unwrap(), 1);
+        assert!(seen.contains(hash("bar")));
+        seen.close().unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 16);
+    }
+
+    #[test]
+    fn test_concurrent() {
+        let dst = tempdir().unwrap();
+        let path = dst.path().join("hashes.bin");
+
+        let seen = SeenHashes::open(&path).unwrap();
+        let contents: Vec<String> = (0..1000).map(|i| format!("doc {}", i % 100)).collect();
+        let nb_new: usize = contents
+            .par_chunks(10)
+            .map(|chunk| {
+                let hashes = chunk.iter().map(|content| SeenHashes::hash(content));
+                seen.extend(hashes).unwrap()
+            })
+            .sum();
+        assert_eq!(nb_new, 100);
+        seen.close().unwrap();
+
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 1600);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let dst = tempdir().unwrap();
+        let path = dst.path().join("hashes.bin");
+        std::fs::write(&path, b"foo").unwrap();
+        assert!(SeenHashes::open(&path).is_err());
+    }
+}
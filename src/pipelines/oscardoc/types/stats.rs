@@ -0,0 +1,575 @@
+/*! Processing statistics.
+
+Records are dropped at different steps of the pipeline.
+[ShardCounters] counts them while a shard is processed (concurrently),
+and [Stats] aggregates the resulting [ShardStats] of every shard.
+
+The report is stored as JSON in `<dst>/stats.json`.
+Written documents are also counted by identification probability (see [ShardStats::confidence]),
+which reveals languages that are systematically identified with low confidence.
+Sizes of the written languages can also be saved as CSV in `<dst>/languages.csv` (see [Stats::save_languages]).
+
+Dry runs only report documents and sizes per language in a [Summary].
+!*/
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use oxilangtag::LanguageTag;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::io::{Compression, CorpusReader};
+
+use super::Document;
+
+/// Stats file name.
+const STATS_FILENAME: &str = "stats.json";
+
+/// Language sizes file name.
+const LANGUAGES_FILENAME: &str = "languages.csv";
+
+/// Number of buckets of the confidence histograms (see [ShardStats::confidence]).
+pub const CONFIDENCE_BUCKETS: usize = 10;
+
+/// Confidence histogram: bucket `i` counts probabilities in `[i/10, (i+1)/10)`, `1.0` being in the last one.
+pub type ConfidenceHistogram = [usize; CONFIDENCE_BUCKETS];
+
+/// Get the histogram bucket of a probability.
+fn confidence_bucket(prob: f32) -> usize {
+    ((prob.clamp(0.0, 1.0) * CONFIDENCE_BUCKETS as f32) as usize).min(CONFIDENCE_BUCKETS - 1)
+}
+
+/// Thread-safe counters, used while processing a shard.
+#[derive(Debug, Default)]
+pub struct ShardCounters {
+    records_read: AtomicUsize,
+    record_errors: AtomicUsize,
+    no_sentences: AtomicUsize,
+    filtered: AtomicUsize,
+    header_filtered: AtomicUsize,
+    record_type_filtered: AtomicUsize,
+    invalid_utf8: AtomicUsize,
+    min_length_filtered: AtomicUsize,
+    body_regex_filtered: AtomicUsize,
+    no_language: AtomicUsize,
+    identification_errors: AtomicUsize,
+    noisy_tiny: AtomicUsize,
+    no_lang_lines: AtomicUsize,
+    lang_min_bytes_filtered: AtomicUsize,
+    errors: Mutex<BTreeMap<&'static str, usize>>,
+}
+
+impl ShardCounters {
+    /// Increment a counter.
+    #[inline]
+    fn incr(counter: &AtomicUsize) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn incr_records_read(&self) {
+        Self::incr(&self.records_read);
+    }
+    pub fn incr_record_errors(&self) {
+        Self::incr(&self.record_errors);
+    }
+    pub fn incr_no_sentences(&self) {
+        Self::incr(&self.no_sentences);
+    }
+    pub fn incr_filtered(&self) {
+        Self::incr(&self.filtered);
+    }
+    pub fn incr_header_filtered(&self) {
+        Self::incr(&self.header_filtered);
+    }
+    pub fn incr_record_type_filtered(&self) {
+        Self::incr(&self.record_type_filtered);
+    }
+    pub fn incr_invalid_utf8(&self) {
+        Self::incr(&self.invalid_utf8);
+    }
+    pub fn incr_min_length_filtered(&self) {
+        Self::incr(&self.min_length_filtered);
+    }
+    pub fn incr_body_regex_filtered(&self) {
+        Self::incr(&self.body_regex_filtered);
+    }
+    pub fn incr_no_language(&self) {
+        Self::incr(&self.no_language);
+    }
+    pub fn incr_identification_errors(&self) {
+        Self::incr(&self.identification_errors);
+    }
+    pub fn incr_noisy_tiny(&self) {
+        Self::incr(&self.noisy_tiny);
+    }
+    pub fn incr_no_lang_lines(&self) {
+        Self::incr(&self.no_lang_lines);
+    }
+    pub fn incr_lang_min_bytes_filtered(&self) {
+        Self::incr(&self.lang_min_bytes_filtered);
+    }
+
+    /// Count an error by its kind (see [Error::kind]).
+    pub fn count_error(&self, e: &Error) {
+        *self.errors.lock().unwrap().entry(e.kind()).or_default() += 1;
+    }
+
+    /// Get the counts. Filtered out records are attributed to `filter_name`,
+    /// records filtered out on their type to `record_type`, invalid UTF-8 to `invalid_utf8`,
+    /// on their headers to `header`, short records to `min_length`
+    /// records matching excluded patterns to `body_regex`
+    /// and documents too small for their language to `lang_min_bytes`.
+    ///
+    /// Documents per language, excluded languages, seen, near-duplicate and over budget documents are left empty.
+    pub fn into_stats(self, filter_name: &str) -> ShardStats {
+        let mut filtered = BTreeMap::new();
+        let nb_filtered = self.filtered.into_inner();
+        if nb_filtered > 0 {
+            filtered.insert(filter_name.to_string(), nb_filtered);
+        }
+        let nb_record_type_filtered = self.record_type_filtered.into_inner();
+        if nb_record_type_filtered > 0 {
+            filtered.insert("record_type".to_string(), nb_record_type_filtered);
+        }
+        let nb_invalid_utf8 = self.invalid_utf8.into_inner();
+        if nb_invalid_utf8 > 0 {
+            filtered.insert("invalid_utf8".to_string(), nb_invalid_utf8);
+        }
+        let nb_header_filtered = self.header_filtered.into_inner();
+        if nb_header_filtered > 0 {
+            filtered.insert("header".to_string(), nb_header_filtered);
+        }
+        let nb_min_length_filtered = self.min_length_filtered.into_inner();
+        if nb_min_length_filtered > 0 {
+            filtered.insert("min_length".to_string(), nb_min_length_filtered);
+        }
+        let nb_body_regex_filtered = self.body_regex_filtered.into_inner();
+        if nb_body_regex_filtered > 0 {
+            filtered.insert("body_regex".to_string(), nb_body_regex_filtered);
+        }
+        let nb_lang_min_bytes_filtered = self.lang_min_bytes_filtered.into_inner();
+        if nb_lang_min_bytes_filtered > 0 {
+            filtered.insert("lang_min_bytes".to_string(), nb_lang_min_bytes_filtered);
+        }
+
+        ShardStats {
+            records_read: self.records_read.into_inner(),
+            record_errors: self.record_errors.into_inner(),
+            no_sentences: self.no_sentences.into_inner(),
+            filtered,
+            no_language: self.no_language.into_inner(),
+            identification_errors: self.identification_errors.into_inner(),
+            noisy_tiny: self.noisy_tiny.into_inner(),
+            no_lang_lines: self.no_lang_lines.into_inner(),
+            errors: self
+                .errors
+                .into_inner()
+                .unwrap()
+                .into_iter()
+                .map(|(kind, count)| (kind.to_string(), count))
+                .collect(),
+            excluded_lang: 0,
+            seen: 0,
+            near_duplicates: 0,
+            over_budget: 0,
+            over_limit: 0,
+            documents: BTreeMap::new(),
+            lines: BTreeMap::new(),
+            bytes: BTreeMap::new(),
+            confidence: BTreeMap::new(),
+        }
+    }
+}
+
+/// Counts for a shard (or a whole run).
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardStats {
+    /// records read from the shard, including invalid ones
+    pub records_read: usize,
+    /// invalid (corrupt or unparseable) records, skipped
+    pub record_errors: usize,
+    /// records with no sentences left after short sentences removal
+    pub no_sentences: usize,
+    /// records filtered out, by filter kind
+    pub filtered: BTreeMap<String, usize>,
+    /// records with no (or not confident enough) language
+    pub no_language: usize,
+    /// records that errored during identification
+    pub identification_errors: usize,
+    /// invalid records and identification errors, by error kind (see [Error::kind])
+    #[serde(default)]
+    pub errors: BTreeMap<String, usize>,
+    /// documents removed for being both noisy and tiny
+    pub noisy_tiny: usize,
+    /// documents with no long enough line left in their language (see [crate::transformers::KeepDocumentLanguageLines])
+    pub no_lang_lines: usize,
+    /// documents discarded because their language is not allowed
+    pub excluded_lang: usize,
+    /// documents skipped because they had already been seen (see [super::SeenHashes])
+    pub seen: usize,
+    /// documents skipped because they were near-duplicates of previous ones (see [super::NearDuplicates])
+    #[serde(default)]
+    pub near_duplicates: usize,
+    /// documents dropped because their language reached its budget (see [crate::io::LangFilesDoc::with_budgets])
+    pub over_budget: usize,
+    /// documents dropped because the run reached its limit (see [crate::pipelines::oscardoc::OscarDoc::with_limit]),
+    /// the shard being recorded as processed anyway
+    #[serde(default)]
+    pub over_limit: usize,
+    /// documents written, by language
+    pub documents: BTreeMap<String, usize>,
+    /// lines of the documents written, by language
+    #[serde(default)]
+    pub lines: BTreeMap<String, usize>,
+    /// content size (in bytes) of the documents written, by language
+    #[serde(default)]
+    pub bytes: BTreeMap<String, usize>,
+    /// documents written by document identification probability, by language (see [ConfidenceHistogram])
+    #[serde(default)]
+    pub confidence: BTreeMap<String, ConfidenceHistogram>,
+}
+
+/// Counts of the documents written in a language.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LangCounts {
+    pub documents: usize,
+    pub lines: usize,
+    /// content size, in bytes
+    pub bytes: usize,
+    pub confidence: ConfidenceHistogram,
+}
+
+impl LangCounts {
+    /// Account for a document.
+    pub fn add_document(&mut self, doc: &Document) {
+        self.documents += 1;
+        self.lines += doc.content().lines().count();
+        self.bytes += doc.content().len();
+        self.confidence[confidence_bucket(*doc.identification().prob())] += 1;
+    }
+}
+
+impl ShardStats {
+    /// Account for documents written in `lang`.
+    pub fn add_written(&mut self, lang: String, counts: LangCounts) {
+        *self.lines.entry(lang.clone()).or_default() += counts.lines;
+        *self.bytes.entry(lang.clone()).or_default() += counts.bytes;
+        Self::merge_histogram(
+            self.confidence.entry(lang.clone()).or_default(),
+            &counts.confidence,
+        );
+        *self.documents.entry(lang).or_default() += counts.documents;
+    }
+
+    fn merge_histogram(histogram: &mut ConfidenceHistogram, other: &ConfidenceHistogram) {
+        for (count, other) in histogram.iter_mut().zip(other) {
+            *count += other;
+        }
+    }
+
+    /// Add counts of `other` to `self`.
+    pub fn merge(&mut self, other: &ShardStats) {
+        self.records_read += other.records_read;
+        self.record_errors += other.record_errors;
+        self.no_sentences += other.no_sentences;
+        self.no_language += other.no_language;
+        self.identification_errors += other.identification_errors;
+        self.noisy_tiny += other.noisy_tiny;
+        self.no_lang_lines += other.no_lang_lines;
+        self.excluded_lang += other.excluded_lang;
+        self.seen += other.seen;
+        self.near_duplicates += other.near_duplicates;
+        self.over_budget += other.over_budget;
+        self.over_limit += other.over_limit;
+        for (kind, count) in &other.errors {
+            *self.errors.entry(kind.clone()).or_default() += count;
+        }
+        for (filter, count) in &other.filtered {
+            *self.filtered.entry(filter.clone()).or_default() += count;
+        }
+        for (lang, count) in &other.documents {
+            *self.documents.entry(lang.clone()).or_default() += count;
+        }
+        for (lang, count) in &other.lines {
+            *self.lines.entry(lang.clone()).or_default() += count;
+        }
+        for (lang, count) in &other.bytes {
+            *self.bytes.entry(lang.clone()).or_default() += count;
+        }
+        for (lang, histogram) in &other.confidence {
+            Self::merge_histogram(self.confidence.entry(lang.clone()).or_default(), histogram);
+        }
+    }
+}
+
+/// Run report: total counts and counts for each shard.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stats {
+    total: ShardStats,
+    shards: BTreeMap<usize, ShardStats>,
+}
+
+impl Stats {
+    /// Get the stats path in the `dst` folder.
+    pub fn path(dst: &Path) -> PathBuf {
+        dst.join(STATS_FILENAME)
+    }
+
+    /// Load the report from the `dst` folder.
+    ///
+    /// Returns an empty report if there's none.
+    pub fn load(dst: &Path) -> Result<Self, Error> {
+        let path = Self::path(dst);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Add the stats of every shard of `other`.
+    pub fn merge(&mut self, other: &Stats) {
+        for (shard_id, shard_stats) in &other.shards {
+            self.add(*shard_id, shard_stats.clone());
+        }
+    }
+
+    /// Add the stats of a shard.
+    pub fn add(&mut self, shard_id: usize, shard_stats: ShardStats) {
+        self.total.merge(&shard_stats);
+        self.shards.entry(shard_id).or_default().merge(&shard_stats);
+    }
+
+    pub fn total(&self) -> &ShardStats {
+        &self.total
+    }
+
+    pub fn shards(&self) -> &BTreeMap<usize, ShardStats> {
+        &self.shards
+    }
+
+    /// Save the report in the `dst` folder.
+    pub fn save(&self, dst: &Path) -> Result<(), Error> {
+        let file = File::create(Self::path(dst))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Get the language sizes path in the `dst` folder.
+    pub fn languages_path(dst: &Path) -> PathBuf {
+        dst.join(LANGUAGES_FILENAME)
+    }
+
+    /// Save the sizes of the written languages as CSV in the `dst` folder, with a total row.
+    ///
+    /// Columns are `language`, `documents`, `lines` and `bytes` (of content),
+    /// and `compressed_bytes` (size of the language files on disk) if `compression` is enabled.
+    pub fn save_languages(&self, dst: &Path, compression: Compression) -> Result<(), Error> {
+        let compressed = compression != Compression::None;
+        let mut out = csv::Writer::from_path(Self::languages_path(dst))?;
+        let mut header = vec!["language", "documents", "lines", "bytes"];
+        if compressed {
+            header.push("compressed_bytes");
+        }
+        out.write_record(header)?;
+
+        let mut total = [0u64; 4];
+        for (lang, documents) in &self.total.documents {
+            let mut row = [
+                *documents as u64,
+                self.total.lines.get(lang).copied().unwrap_or_default() as u64,
+                self.total.bytes.get(lang).copied().unwrap_or_default() as u64,
+                0,
+            ];
+            if compressed {
+                let reader = CorpusReader::new(dst, &LanguageTag::parse(lang.clone())?)?;
+                for path in reader.paths() {
+                    row[3] += std::fs::metadata(path)?.len();
+                }
+            }
+            for (total, count) in total.iter_mut().zip(row) {
+                *total += count;
+            }
+            Self::write_language_row(&mut out, lang, &row, compressed)?;
+        }
+        Self::write_language_row(&mut out, "total", &total, compressed)?;
+
+        out.flush()?;
+        Ok(())
+    }
+
+    fn write_language_row(
+        out: &mut csv::Writer<File>,
+        lang: &str,
+        counts: &[u64; 4],
+        compressed: bool,
+    ) -> Result<(), Error> {
+        let nb_columns = if compressed { 4 } else { 3 };
+        let mut record = vec![lang.to_string()];
+        record.extend(counts[..nb_columns].iter().map(u64::to_string));
+        out.write_record(record)?;
+        Ok(())
+    }
+}
+
+/// Number of documents and size of a language.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LangSize {
+    pub documents: usize,
+    /// size (in bytes) of the documents once serialized, before compression
+    pub bytes: usize,
+}
+
+/// Dry run report: documents and sizes per language.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Summary {
+    langs: BTreeMap<String, LangSize>,
+}
+
+impl Summary {
+    /// Account for a document of `bytes` bytes in `lang`.
+    pub fn add(&mut self, lang: &str, bytes: usize) {
+        let size = self.langs.entry(lang.to_string()).or_default();
+        size.documents += 1;
+        size.bytes += bytes;
+    }
+
+    pub fn langs(&self) -> &BTreeMap<String, LangSize> {
+        &self.langs
+    }
+
+    /// Share of the total size of each language, by decreasing share.
+    pub fn shares(&self) -> Vec<(&str, f32)> {
+        let total = self.total().bytes.max(1) as f32;
+        let mut shares: Vec<_> = self
+            .langs
+            .iter()
+            .map(|(lang, size)| (lang.as_str(), size.bytes as f32 / total))
+            .collect();
+        shares.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        shares
+    }
+
+    /// Total over all languages.
+    pub fn total(&self) -> LangSize {
+        self.langs
+            .values()
+            .fold(LangSize::default(), |total, size| LangSize {
+                documents: total.documents + size.documents,
+                bytes: total.bytes + size.bytes,
+            })
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<12}{:>12}{:>16}", "lang", "documents", "bytes")?;
+        for (lang, size) in &self.langs {
+            writeln!(f, "{:<12}{:>12}{:>16}", lang, size.documents, size.bytes)?;
+        }
+        let total = self.total();
+        write!(
+            f,
+            "{:<12}{:>12}{:>16}",
+            "total", total.documents, total.bytes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oscar_io::common::Identification;
+    use oxilangtag::LanguageTag;
+
+    use super::{LangCounts, ShardCounters, Stats, Summary};
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+
+    #[test]
+    fn test_counters() {
+        let c = ShardCounters::default();
+        c.incr_records_read();
+        c.incr_records_read();
+        c.incr_filtered();
+        c.incr_header_filtered();
+
+        let s = c.into_stats("pfilter");
+        assert_eq!(s.records_read, 2);
+        assert_eq!(s.filtered.get("pfilter"), Some(&1));
+        assert_eq!(s.filtered.get("header"), Some(&1));
+        assert_eq!(s.no_language, 0);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mut stats = Stats::default();
+        for shard_id in [0, 1] {
+            let c = ShardCounters::default();
+            c.incr_records_read();
+            c.incr_no_language();
+            let mut s = c.into_stats("pfilter");
+            s.documents.insert("fr".to_string(), shard_id + 1);
+            stats.add(shard_id, s);
+        }
+
+        assert_eq!(stats.total().records_read, 2);
+        assert_eq!(stats.total().no_language, 2);
+        assert_eq!(stats.total().documents.get("fr"), Some(&3));
+        assert_eq!(stats.shards().len(), 2);
+        assert_eq!(stats.shards()[&1].documents.get("fr"), Some(&2));
+    }
+
+    #[test]
+    fn test_confidence() {
+        let doc = |prob| {
+            let id = Identification::new(LanguageTag::parse("fr".to_string()).unwrap(), prob);
+            Document::new("foo".to_string(), HashMap::new(), Metadata::new(&id, &[]))
+        };
+
+        // two shards, processed separately
+        let mut stats = Stats::default();
+        for (shard_id, probs) in [(0, vec![0.55, 0.58, 0.95]), (1, vec![0.62, 1.0, 0.05])] {
+            let mut counts = LangCounts::default();
+            for prob in probs {
+                counts.add_document(&doc(prob));
+            }
+            let mut s = ShardCounters::default().into_stats("pfilter");
+            s.add_written("fr".to_string(), counts);
+            stats.add(shard_id, s);
+        }
+
+        assert_eq!(
+            stats.shards()[&0].confidence["fr"],
+            [0, 0, 0, 0, 0, 2, 0, 0, 0, 1]
+        );
+        assert_eq!(
+            stats.total().confidence["fr"],
+            [1, 0, 0, 0, 0, 2, 1, 0, 0, 2]
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut summary = Summary::default();
+        summary.add("fr", 10);
+        summary.add("fr", 5);
+        summary.add("en", 3);
+
+        assert_eq!(summary.langs()["fr"].documents, 2);
+        assert_eq!(summary.langs()["fr"].bytes, 15);
+        assert_eq!(summary.total().documents, 3);
+        assert_eq!(summary.total().bytes, 18);
+        assert_eq!(summary.to_string().lines().count(), 4);
+    }
+}
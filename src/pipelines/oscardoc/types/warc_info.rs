@@ -0,0 +1,104 @@
+/*! Crawl information.
+
+Surfaces the crawl date (`WARC-Date`) and source URL (`WARC-Target-URI`) of a record in [Metadata],
+so that they can be read back without going through the raw WARC headers.
+
+They are stored as [ExtraMetadata] under the `warc_date` and `url` keys.
+Missing headers are not stored.
+!*/
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use warc::WarcHeader;
+
+use super::{ExtraMetadata, Metadata};
+
+const WARC_DATE_KEY: &str = "warc_date";
+const URL_KEY: &str = "url";
+
+/// Typed access to crawl information.
+pub trait WarcMetadata {
+    /// Store the crawl date and URL found in `headers`, if any.
+    fn set_warc_info(&mut self, headers: &HashMap<WarcHeader, Vec<u8>>);
+
+    /// Crawl date. [None] if it is missing or is not a valid RFC 3339 date.
+    fn warc_date(&self) -> Option<DateTime<Utc>>;
+
+    /// Source URL.
+    fn url(&self) -> Option<&str>;
+}
+
+impl WarcMetadata for Metadata {
+    fn set_warc_info(&mut self, headers: &HashMap<WarcHeader, Vec<u8>>) {
+        for (header, key) in [
+            (WarcHeader::Date, WARC_DATE_KEY),
+            (WarcHeader::TargetURI, URL_KEY),
+        ] {
+            if let Some(value) = headers.get(&header) {
+                self.add_extra(key, String::from_utf8_lossy(value).trim());
+            }
+        }
+    }
+
+    fn warc_date(&self) -> Option<DateTime<Utc>> {
+        let date = self.extra(WARC_DATE_KEY)?;
+        DateTime::parse_from_rfc3339(date)
+            .map(|date| date.with_timezone(&Utc))
+            .ok()
+    }
+
+    fn url(&self) -> Option<&str> {
+        self.extra(URL_KEY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{TimeZone, Utc};
+    use warc::WarcHeader;
+
+    use super::WarcMetadata;
+    use crate::pipelines::oscardoc::types::Metadata;
+
+    #[test]
+    fn test_warc_info() {
+        let mut headers = HashMap::new();
+        headers.insert(WarcHeader::Date, b"2021-02-24T17:02:28Z".to_vec());
+        headers.insert(
+            WarcHeader::TargetURI,
+            b"http://example.com/foo?bar=baz".to_vec(),
+        );
+
+        let mut m = Metadata::default();
+        m.set_warc_info(&headers);
+
+        assert_eq!(
+            m.warc_date(),
+            Some(Utc.with_ymd_and_hms(2021, 2, 24, 17, 2, 28).unwrap())
+        );
+        assert_eq!(m.url(), Some("http://example.com/foo?bar=baz"));
+    }
+
+    #[test]
+    fn test_warc_info_missing() {
+        let mut m = Metadata::default();
+        m.set_warc_info(&HashMap::new());
+
+        assert_eq!(m.warc_date(), None);
+        assert_eq!(m.url(), None);
+        assert_eq!(m.annotation(), None);
+    }
+
+    #[test]
+    fn test_warc_info_invalid_date() {
+        let mut headers = HashMap::new();
+        headers.insert(WarcHeader::Date, b"yesterday".to_vec());
+
+        let mut m = Metadata::default();
+        m.set_warc_info(&headers);
+
+        assert_eq!(m.warc_date(), None);
+    }
+}
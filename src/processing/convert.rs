@@ -0,0 +1,280 @@
+/*! Conversion of v1 corpora.
+
+v1 corpora (OSCAR 2019, ungoliant 1.x) split each language in two files:
+
+- `<lang>.txt` holds the content of documents, one sentence per line, documents being separated by an empty line,
+- `<lang>_meta.jsonl` holds the metadata of each document, one per line:
+  its WARC headers (`headers`), the line of its first sentence in the text file (`offset`)
+  and its number of sentences (`nb_sentences`).
+
+Big languages are split in parts, `<lang>_part_1.txt` going with `<lang>_meta_part_1.jsonl`,
+offsets being relative to the part.
+
+Converting a corpus rebuilds self-contained [Document]s (see [oscar_io::v3]) and writes them with [LangFilesDoc]:
+
+- content is rebuilt from the `nb_sentences` lines at `offset`,
+- WARC headers are kept as is,
+- v1 didn't store confidences: the document and each of its lines are identified as the language of the file, with a confidence of 1.
+
+Offsets are only used to locate content, and are not kept.
+Since v2 language files are also named `<lang>_meta.jsonl`, conversion can't be done in place.
+!*/
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use oscar_io::common::Identification;
+use oscar_io::v3::WriterTrait;
+use oxilangtag::LanguageTag;
+use serde::Deserialize;
+use warc::WarcHeader;
+
+use crate::error::Error;
+use crate::io::LangFilesDoc;
+use crate::pipelines::oscardoc::types::{Document, Metadata};
+
+/// Number of documents in a bulk write.
+const BUFSIZE: usize = 500;
+
+/// Metadata of a v1 document.
+#[derive(Debug, Deserialize)]
+struct MetadataV1 {
+    headers: HashMap<WarcHeader, String>,
+    offset: usize,
+    nb_sentences: usize,
+}
+
+/// List the languages of the v1 corpus in `src`.
+fn languages(src: &Path) -> Result<BTreeSet<LanguageTag<String>>, Error> {
+    let mut langs = BTreeSet::new();
+    for entry in std::fs::read_dir(src)? {
+        let filename = entry?.file_name();
+        let filename = filename.to_string_lossy();
+        let lang = match filename.strip_suffix(".txt") {
+            Some(stem) => stem.split_once("_part_").map_or(stem, |(lang, _)| lang),
+            None => continue,
+        };
+        match LanguageTag::parse(lang.to_string()) {
+            Ok(lang) => {
+                langs.insert(lang);
+            }
+            Err(e) => warn!("skipping {:?}: {:?}", filename, e),
+        }
+    }
+
+    Ok(langs)
+}
+
+/// List the (text, metadata) files of a language, in order.
+fn parts(src: &Path, lang: &LanguageTag<String>) -> Vec<(PathBuf, PathBuf)> {
+    let single = (
+        src.join(format!("{lang}.txt")),
+        src.join(format!("{lang}_meta.jsonl")),
+    );
+    if single.0.exists() {
+        return vec![single];
+    }
+
+    (1..)
+        .map(|part| {
+            (
+                src.join(format!("{lang}_part_{part}.txt")),
+                src.join(format!("{lang}_meta_part_{part}.jsonl")),
+            )
+        })
+        .take_while(|(text, _)| text.exists())
+        .collect()
+}
+
+/// Rebuild the documents of a part, writing them in `langfiles`. Returns the number of documents.
+fn convert_part(
+    text: &Path,
+    meta: &Path,
+    lang: &LanguageTag<String>,
+    langfiles: &LangFilesDoc,
+) -> Result<usize, Error> {
+    let id = Identification::new(lang.clone(), 1.0);
+    let mut lines = BufReader::new(File::open(text)?).lines();
+    let mut next_line = |line_nb: usize| {
+        lines.next().unwrap_or_else(|| {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("{text:?} has no line {line_nb}"),
+            ))
+        })
+    };
+    let writer = langfiles.writer(lang)?;
+
+    let mut line_nb = 0;
+    let mut nb_docs = 0;
+    let mut docs = Vec::with_capacity(BUFSIZE);
+    for (index, metadata) in BufReader::new(File::open(meta)?).lines().enumerate() {
+        let metadata: MetadataV1 = serde_json::from_str(&metadata?)?;
+        if metadata.offset < line_nb {
+            return Err(Error::Custom(format!(
+                "{meta:?}: document {index} starts on line {}, in the previous document",
+                metadata.offset
+            )));
+        }
+
+        // skip document separators
+        while line_nb < metadata.offset {
+            if !next_line(line_nb)?.is_empty() {
+                return Err(Error::Custom(format!(
+                    "{text:?}: line {line_nb} is not part of a document"
+                )));
+            }
+            line_nb += 1;
+        }
+
+        let mut sentences = Vec::with_capacity(metadata.nb_sentences);
+        for _ in 0..metadata.nb_sentences {
+            sentences.push(next_line(line_nb)?);
+            line_nb += 1;
+        }
+        let headers = metadata
+            .headers
+            .into_iter()
+            .map(|(header, value)| (header, value.into_bytes()))
+            .collect();
+        let ids = vec![Some(id.clone()); metadata.nb_sentences];
+        docs.push(Document::new(
+            sentences.join("\n"),
+            headers,
+            Metadata::new(&id, &ids),
+        ));
+
+        if docs.len() >= BUFSIZE {
+            nb_docs += docs.len();
+            writer.lock().unwrap().write(std::mem::take(&mut docs))?;
+        }
+    }
+    if !docs.is_empty() {
+        nb_docs += docs.len();
+        writer.lock().unwrap().write(docs)?;
+    }
+
+    Ok(nb_docs)
+}
+
+/// Convert the v1 corpus in `src` into a corpus of documents in `dst`.
+pub fn convert(src: &Path, dst: &Path) -> Result<(), Error> {
+    if src == dst {
+        return Err(Error::Custom(
+            "conversion can't be done in place".to_string(),
+        ));
+    }
+    std::fs::create_dir_all(dst)?;
+
+    let langfiles = LangFilesDoc::new(dst, None);
+    for lang in languages(src)? {
+        let mut nb_docs = 0;
+        for (text, meta) in parts(src, &lang) {
+            nb_docs += convert_part(&text, &meta, &lang, &langfiles)?;
+        }
+        info!("[{}]: converted {} documents", lang, nb_docs);
+    }
+    langfiles.close()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use oxilangtag::LanguageTag;
+    use tempfile::tempdir;
+    use warc::WarcHeader;
+
+    use super::convert;
+    use crate::io::CorpusReader;
+    use crate::processing::validate::validate;
+
+    fn write_v1(src: &Path, name: &str, text: &str, meta: &[(&str, usize, usize)]) {
+        std::fs::write(src.join(format!("{name}.txt")), text).unwrap();
+        let meta: Vec<String> = meta
+            .iter()
+            .map(|(uri, offset, nb_sentences)| {
+                format!(
+                    r#"{{"headers":{{"warc-type":"conversion","warc-target-uri":"{uri}"}},"offset":{offset},"nb_sentences":{nb_sentences}}}"#
+                )
+            })
+            .collect();
+        let meta_name = match name.split_once("_part_") {
+            Some((lang, part)) => format!("{lang}_meta_part_{part}.jsonl"),
+            None => format!("{name}_meta.jsonl"),
+        };
+        std::fs::write(src.join(meta_name), meta.join("\n") + "\n").unwrap();
+    }
+
+    #[test]
+    fn test_convert() {
+        let src = tempdir().unwrap();
+        let dst = tempdir().unwrap();
+        write_v1(
+            src.path(),
+            "fr",
+            "Première phrase.\nDeuxième phrase.\n\nTroisième phrase.\n",
+            &[("http://a.fr/", 0, 2), ("http://b.fr/", 3, 1)],
+        );
+        write_v1(
+            src.path(),
+            "nn_part_1",
+            "Fyrste dokument.\n",
+            &[("http://a.no/", 0, 1)],
+        );
+        write_v1(
+            src.path(),
+            "nn_part_2",
+            "Andre dokument.\nMed to linjer.\n",
+            &[("http://b.no/", 0, 2)],
+        );
+
+        convert(src.path(), dst.path()).unwrap();
+        assert!(validate(dst.path()).unwrap().is_empty());
+
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        let docs: Vec<_> = CorpusReader::new(dst.path(), &fr)
+            .unwrap()
+            .map(|doc| doc.unwrap())
+            .collect();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0].content(), "Première phrase.\nDeuxième phrase.");
+        assert_eq!(docs[1].content(), "Troisième phrase.");
+        assert_eq!(
+            docs[1].warc_headers().get(&WarcHeader::TargetURI),
+            Some(&b"http://b.fr/".to_vec())
+        );
+        assert_eq!(docs[0].identification().label(), &fr);
+        assert_eq!(docs[0].metadata().sentence_identifications().len(), 2);
+
+        let nn = LanguageTag::parse("nn".to_string()).unwrap();
+        let contents: Vec<String> = CorpusReader::new(dst.path(), &nn)
+            .unwrap()
+            .map(|doc| doc.unwrap().content().to_string())
+            .collect();
+        assert_eq!(
+            contents,
+            vec!["Fyrste dokument.", "Andre dokument.\nMed to linjer."]
+        );
+    }
+
+    #[test]
+    fn test_misaligned() {
+        let src = tempdir().unwrap();
+        let dst = tempdir().unwrap();
+        // the second document starts one line too early
+        write_v1(
+            src.path(),
+            "fr",
+            "Première phrase.\nDeuxième phrase.\n\nTroisième phrase.\n",
+            &[("http://a.fr/", 0, 1), ("http://b.fr/", 2, 1)],
+        );
+        assert!(convert(src.path(), dst.path()).is_err());
+    }
+}
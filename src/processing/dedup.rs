@@ -0,0 +1,151 @@
+/*! Document deduplication.
+
+Removes duplicate documents from a document-oriented corpus, keeping the first occurrence.
+
+Languages are processed one at a time, and only a hash of each document content is kept in memory.
+Content can optionally be normalized (whitespace-wise) before being hashed,
+so that documents that only differ in whitespace are considered duplicates.
+Hashes are the ones that can be stored in document metadata (see [crate::pipelines::oscardoc::types::hash_content]).
+
+Output files keep the source files' compression.
+!*/
+use std::{collections::HashSet, path::Path};
+
+use log::info;
+use oscar_io::v3::WriterTrait;
+use oxilangtag::LanguageTag;
+
+use crate::error::Error;
+use crate::io::{CorpusReader, WriterDoc};
+use crate::pipelines::oscardoc::types::hash_content;
+
+/// Default number of documents in a bulk write.
+const DEFAULT_BUFSIZE: usize = 500;
+
+/// Deduplicate the documents of a single language.
+///
+/// Returns the number of kept and removed documents.
+fn dedup_lang(
+    src: &Path,
+    dst: &Path,
+    lang: &LanguageTag<String>,
+    bufsize: usize,
+    normalized: bool,
+) -> Result<(usize, usize), Error> {
+    let reader = CorpusReader::new(src, lang)?;
+    let mut writer =
+        WriterDoc::new(dst, lang.clone(), None)?.with_compression(reader.compression());
+
+    let mut hashes = HashSet::new();
+    let mut removed = 0;
+    let mut buf = Vec::with_capacity(bufsize);
+    for doc in reader {
+        let doc = doc?;
+        if hashes.insert(hash_content(doc.content(), normalized)) {
+            buf.push(doc);
+        } else {
+            removed += 1;
+        }
+
+        if buf.len() >= bufsize {
+            writer.write(std::mem::take(&mut buf))?;
+        }
+    }
+
+    if !buf.is_empty() {
+        writer.write(buf)?;
+    }
+
+    Ok((hashes.len(), removed))
+}
+
+/// Deduplicate the corpus in `src` into `dst`.
+///
+/// `bufsize` is the number of documents in a bulk write (500 by default).
+/// If `normalized` is set, documents that only differ in whitespace are considered duplicates.
+pub fn dedup(
+    src: &Path,
+    dst: &Path,
+    bufsize: Option<usize>,
+    normalized: bool,
+) -> Result<(), Error> {
+    if src == dst {
+        return Err(Error::Custom(
+            "deduplication can't be done in place".to_string(),
+        ));
+    }
+    if !dst.exists() {
+        std::fs::create_dir(dst)?;
+    }
+
+    let bufsize = bufsize.unwrap_or(DEFAULT_BUFSIZE);
+    for lang in CorpusReader::languages(src)? {
+        let (kept, removed) = dedup_lang(src, dst, &lang, bufsize, normalized)?;
+        info!("[{}]: kept {} documents, removed {}", lang, kept, removed);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::dedup;
+    use crate::io::test_utils::{gen_docs, read_contents, write_docs, write_parts};
+    use crate::io::Compression;
+    use crate::pipelines::oscardoc::types::normalize;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("  foo   bar \n\n baz\t"), "foo bar\nbaz");
+    }
+
+    #[test]
+    fn test_dedup() {
+        let src = tempdir().unwrap();
+        let dst = tempdir().unwrap();
+        write_docs(src.path(), "fr", gen_docs(&["foo", "bar", "foo"]));
+        write_docs(src.path(), "en", gen_docs(&["baz"]));
+
+        dedup(src.path(), dst.path(), None, false).unwrap();
+
+        assert_eq!(read_contents(dst.path(), "fr"), vec!["foo", "bar"]);
+        assert_eq!(read_contents(dst.path(), "en"), vec!["baz"]);
+    }
+
+    #[test]
+    fn test_dedup_normalized() {
+        let src = tempdir().unwrap();
+        let dst = tempdir().unwrap();
+        write_docs(
+            src.path(),
+            "fr",
+            gen_docs(&["foo bar", "foo  bar\n", "baz"]),
+        );
+
+        dedup(src.path(), dst.path(), Some(1), false).unwrap();
+        assert_eq!(read_contents(dst.path(), "fr").len(), 3);
+
+        dedup(src.path(), dst.path(), Some(1), true).unwrap();
+        assert_eq!(read_contents(dst.path(), "fr"), vec!["foo bar", "baz"]);
+    }
+
+    #[test]
+    fn test_dedup_compressed() {
+        let src = tempdir().unwrap();
+        let dst = tempdir().unwrap();
+        write_parts(
+            src.path(),
+            "fr",
+            gen_docs(&["foo", "foo"]),
+            None,
+            Compression::Zstd,
+        );
+
+        dedup(src.path(), dst.path(), None, false).unwrap();
+
+        assert!(dst.path().join("fr_meta.jsonl.zst").exists());
+        assert_eq!(read_contents(dst.path(), "fr"), vec!["foo"]);
+    }
+}
@@ -0,0 +1,231 @@
+/*! Corpus merging.
+
+Merges corpora generated on different shard subsets (on different machines for example) into a single one.
+
+For each language, documents of every source are written one source after the other.
+Documents are self-contained, except for their byte offsets (see [crate::pipelines::oscardoc::types::ByteOffsetMetadata]),
+which are relative to their source file: the writer updates them to their position in the merged files.
+Output files use the compression of the first source that has the language.
+
+Rebuild files are per-shard, so they are copied as is in `<dst>/rebuild`,
+being renamed `<lang>_1.avro`, `<lang>_2.avro`... when needed.
+Stats are merged into a single `<dst>/stats.json`.
+!*/
+use std::{collections::BTreeSet, path::Path, path::PathBuf};
+
+use log::info;
+use oscar_io::v3::WriterTrait;
+use oxilangtag::LanguageTag;
+
+use crate::error::Error;
+use crate::io::{CorpusReader, WriterDoc};
+use crate::pipelines::oscardoc::types::{RebuildWriters, Stats};
+
+/// Default number of documents in a bulk write.
+const DEFAULT_BUFSIZE: usize = 500;
+
+/// Merge the documents of a single language.
+///
+/// Returns the number of written documents.
+fn merge_lang(
+    srcs: &[PathBuf],
+    dst: &Path,
+    lang: &LanguageTag<String>,
+    bufsize: usize,
+) -> Result<usize, Error> {
+    let mut writer: Option<WriterDoc> = None;
+    let mut nb_docs = 0;
+    for src in srcs {
+        // not every source has every language
+        let reader = match CorpusReader::new(src, lang) {
+            Ok(reader) => reader,
+            Err(_) => continue,
+        };
+        let writer = match &mut writer {
+            Some(writer) => writer,
+            None => writer.insert(
+                WriterDoc::new(dst, lang.clone(), None)?.with_compression(reader.compression()),
+            ),
+        };
+
+        let mut buf = Vec::with_capacity(bufsize);
+        for doc in reader {
+            buf.push(doc?);
+            if buf.len() >= bufsize {
+                nb_docs += buf.len();
+                writer.write(std::mem::take(&mut buf))?;
+            }
+        }
+        if !buf.is_empty() {
+            nb_docs += buf.len();
+            writer.write(buf)?;
+        }
+    }
+
+    if let Some(writer) = writer {
+        writer.close()?;
+    }
+
+    Ok(nb_docs)
+}
+
+/// Copy rebuild files of `src` into `dst_rebuild`, without overwriting existing ones.
+fn copy_rebuild_files(src: &Path, dst_rebuild: &Path) -> Result<(), Error> {
+    let src_rebuild = src.join("rebuild");
+    if !src_rebuild.is_dir() {
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(src_rebuild)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("avro") {
+            continue;
+        }
+
+        // rebuild files are named <lang>.avro or <lang>_<n>.avro
+        let lang = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.split('_').next())
+            .map(|lang| LanguageTag::parse(lang.to_string()));
+        let lang = match lang {
+            Some(lang) => lang?,
+            None => continue,
+        };
+
+        let dst_path = RebuildWriters::forge_dst(dst_rebuild, &lang, true);
+        info!("copying {:?} to {:?}", path, dst_path);
+        std::fs::copy(path, dst_path)?;
+    }
+
+    Ok(())
+}
+
+/// Merge the corpora in `srcs` into `dst`.
+///
+/// `bufsize` is the number of documents in a bulk write (500 by default).
+pub fn merge(srcs: &[PathBuf], dst: &Path, bufsize: Option<usize>) -> Result<(), Error> {
+    if srcs.iter().any(|src| src == dst) {
+        return Err(Error::Custom("merging can't be done in place".to_string()));
+    }
+    let dst_rebuild = dst.join("rebuild");
+    std::fs::create_dir_all(&dst_rebuild)?;
+
+    let bufsize = bufsize.unwrap_or(DEFAULT_BUFSIZE);
+
+    let mut langs = BTreeSet::new();
+    for src in srcs {
+        langs.extend(CorpusReader::languages(src)?);
+    }
+    for lang in langs {
+        let nb_docs = merge_lang(srcs, dst, &lang, bufsize)?;
+        info!("[{}]: {} documents", lang, nb_docs);
+    }
+
+    let mut stats = Stats::default();
+    for src in srcs {
+        copy_rebuild_files(src, &dst_rebuild)?;
+        stats.merge(&Stats::load(src)?);
+    }
+    stats.save(dst)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use oscar_io::v3::{Document, WriterTrait};
+    use oxilangtag::LanguageTag;
+    use tempfile::tempdir;
+
+    use super::merge;
+    use crate::io::test_utils::{gen_docs, read_contents, write_docs};
+    use crate::io::{CorpusReader, WriterDoc};
+    use crate::pipelines::oscardoc::types::{ShardCounters, Stats};
+
+    fn write_stats(dst: &Path, shard_id: usize) {
+        let c = ShardCounters::default();
+        c.incr_records_read();
+        let mut stats = Stats::default();
+        stats.add(shard_id, c.into_stats("pfilter"));
+        stats.save(dst).unwrap();
+    }
+
+    #[test]
+    fn test_merge() {
+        let src1 = tempdir().unwrap();
+        let src2 = tempdir().unwrap();
+        let dst = tempdir().unwrap();
+
+        write_docs(src1.path(), "fr", gen_docs(&["foo", "bar"]));
+        write_docs(src1.path(), "en", gen_docs(&["baz"]));
+        write_docs(src2.path(), "fr", gen_docs(&["quux"]));
+        write_docs(src2.path(), "br", gen_docs(&["demat"]));
+        for (src, shard_id) in [(&src1, 0), (&src2, 1)] {
+            std::fs::create_dir(src.path().join("rebuild")).unwrap();
+            std::fs::write(src.path().join("rebuild/fr.avro"), b"").unwrap();
+            write_stats(src.path(), shard_id);
+        }
+
+        let srcs = [src1.path().to_path_buf(), src2.path().to_path_buf()];
+        merge(&srcs, dst.path(), Some(1)).unwrap();
+
+        assert_eq!(read_contents(dst.path(), "fr"), vec!["foo", "bar", "quux"]);
+        assert_eq!(read_contents(dst.path(), "en"), vec!["baz"]);
+        assert_eq!(read_contents(dst.path(), "br"), vec!["demat"]);
+
+        assert!(dst.path().join("rebuild/fr.avro").exists());
+        assert!(dst.path().join("rebuild/fr_1.avro").exists());
+
+        let stats = Stats::load(dst.path()).unwrap();
+        assert_eq!(stats.total().records_read, 2);
+        assert_eq!(stats.shards().len(), 2);
+    }
+
+    #[test]
+    fn test_merge_byte_offsets() {
+        use std::io::{BufRead, BufReader, Seek, SeekFrom};
+
+        use crate::pipelines::oscardoc::types::ByteOffsetMetadata;
+
+        let src1 = tempdir().unwrap();
+        let src2 = tempdir().unwrap();
+        let dst = tempdir().unwrap();
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        for (src, contents) in [(&src1, ["foo", "bar"]), (&src2, ["baz", "quux"])] {
+            let mut w = WriterDoc::new(src.path(), fr.clone(), None)
+                .unwrap()
+                .with_byte_offsets(true);
+            w.write(gen_docs(&contents)).unwrap();
+            w.close().unwrap();
+            write_stats(src.path(), 0);
+        }
+
+        let srcs = [src1.path().to_path_buf(), src2.path().to_path_buf()];
+        merge(&srcs, dst.path(), None).unwrap();
+
+        // seeking to the offset of a document lands on its line in the merged file
+        let mut file = std::fs::File::open(dst.path().join("fr_meta.jsonl")).unwrap();
+        let mut nb_docs = 0;
+        for doc in CorpusReader::new(dst.path(), &fr).unwrap() {
+            let doc = doc.unwrap();
+            file.seek(SeekFrom::Start(doc.metadata().byte_offset().unwrap()))
+                .unwrap();
+            let mut line = String::new();
+            BufReader::new(&file).read_line(&mut line).unwrap();
+            let at_offset: Document = serde_json::from_str(&line).unwrap();
+            assert_eq!(at_offset, doc);
+            nb_docs += 1;
+        }
+        assert_eq!(nb_docs, 4);
+    }
+
+    #[test]
+    fn test_merge_in_place() {
+        let src = tempdir().unwrap();
+        let srcs = [src.path().to_path_buf()];
+        assert!(merge(&srcs, src.path(), None).is_err());
+    }
+}
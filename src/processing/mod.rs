@@ -6,7 +6,13 @@ This module is for now only compatible with CommonCrawl extracted content, but w
 !*/
 pub mod check;
 //pub mod compress;
-//pub mod dedup;
+pub mod convert;
+pub mod dedup;
+pub mod merge;
 //pub mod package;
 pub mod rebuild;
-//pub mod split;
+pub mod reindex;
+pub mod reprocess;
+pub mod split;
+pub mod validate;
+pub mod wordcount;
@@ -9,9 +9,12 @@
  * [todo] calls [Iterator::next] on [SRIterator] and uses `n` threads to retrieve [Document]s and do IO to recreate the corpus.
 * !*/
 use crate::pipelines::oscardoc::types::Document;
+use crate::pipelines::oscardoc::types::ExtraMetadata;
 use crate::pipelines::oscardoc::types::RebuildInformation;
 use crate::pipelines::oscardoc::types::ShardResult;
+use crate::pipelines::oscardoc::SEGMENTED_KEY;
 use crate::sources::commoncrawl::Wet;
+use crate::transformers::SentenceSegmenter;
 use oscar_io::v3::Writer;
 use oscar_io::v3::WriterTrait;
 use std::fs::File;
@@ -126,6 +129,16 @@ where
                 .take(nb_take)
                 .join("\n");
 
+            // segmented documents have to be segmented again to match their sentence identifications
+            let body = match rb_info
+                .metadata()
+                .extra(SEGMENTED_KEY)
+                .and_then(|max_chars| max_chars.parse().ok())
+            {
+                Some(max_chars) => SentenceSegmenter::with_max_chars(max_chars).segmented(&body),
+                None => body,
+            };
+
             // compute body length to update content-length
             *headers
                 .headers
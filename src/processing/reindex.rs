@@ -0,0 +1,198 @@
+/*! Corpus reindexing.
+
+Documents hold metadata that depends on their content or on their position in their file:
+content counts (see [crate::pipelines::oscardoc::types::ContentMetadata])
+and byte offsets (see [crate::pipelines::oscardoc::types::ByteOffsetMetadata]).
+Once documents have been curated by hand (removed, or their content edited), these are outdated.
+
+Reindexing rewrites the files of a language in place, recomputing them for every document.
+Byte offsets are set on every document, whether the corpus had them or not.
+
+Documents whose number of lines doesn't match their number of sentence identifications can't be reindexed,
+since their counts would no longer be aligned with their identifications: reindexing then fails,
+before anything is written.
+!*/
+use std::{
+    fs::File,
+    io::{BufRead, BufWriter, Write},
+    path::Path,
+};
+
+use log::info;
+use oxilangtag::LanguageTag;
+
+use crate::error::Error;
+use crate::io::{Compression, CorpusReader};
+use crate::pipelines::oscardoc::types::{set_byte_offset, set_content_counts, Document};
+
+/// Number of documents compressed at once.
+const CHUNK_SIZE: usize = 500;
+
+/// Read the documents of a file, checking that they can be reindexed.
+fn documents(
+    path: &Path,
+    compression: Compression,
+) -> Result<impl Iterator<Item = Result<Document, Error>> + '_, Error> {
+    let lines = compression.reader(path)?.lines().enumerate();
+    Ok(lines.filter_map(move |(index, line)| {
+        let line = match line {
+            Ok(line) if line.is_empty() => return None,
+            Ok(line) => line,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let doc: Document = match serde_json::from_str(&line) {
+            Ok(doc) => doc,
+            Err(e) => {
+                return Some(Err(Error::Custom(format!(
+                    "{path:?}: line {index} is not a document: {e}"
+                ))))
+            }
+        };
+
+        let nb_lines = doc.content().lines().count();
+        let nb_ids = doc.metadata().sentence_identifications().len();
+        if nb_lines != nb_ids {
+            return Some(Err(Error::Custom(format!(
+                "{path:?}: document on line {index} has {nb_lines} lines but {nb_ids} sentence identifications"
+            ))));
+        }
+        Some(Ok(doc))
+    }))
+}
+
+/// Rewrite a file with recomputed metadata, through a temporary file. Returns the number of documents.
+fn reindex_file(path: &Path, compression: Compression) -> Result<usize, Error> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".reindex");
+    let tmp = path.with_file_name(tmp_name);
+
+    let mut out = BufWriter::new(File::create(&tmp)?);
+    let mut chunk = String::new();
+    let mut nb_docs = 0;
+    let mut offset = 0;
+    for doc in documents(path, compression)? {
+        let mut doc = doc?;
+        set_content_counts(&mut doc);
+        set_byte_offset(&mut doc, offset);
+        let line = serde_json::to_string(&doc)? + "\n";
+        offset += line.len() as u64;
+        chunk += &line;
+        nb_docs += 1;
+
+        if nb_docs % CHUNK_SIZE == 0 {
+            out.write_all(&compression.compress(chunk.as_bytes())?)?;
+            chunk.clear();
+        }
+    }
+    if !chunk.is_empty() {
+        out.write_all(&compression.compress(chunk.as_bytes())?)?;
+    }
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+    std::fs::rename(tmp, path)?;
+    Ok(nb_docs)
+}
+
+/// Reindex the documents of `lang` in `dst` in place, returning the number of documents.
+///
+/// Every file is checked before any is rewritten.
+pub fn reindex_lang(dst: &Path, lang: &LanguageTag<String>) -> Result<usize, Error> {
+    let reader = CorpusReader::new(dst, lang)?;
+    let compression = reader.compression();
+    for path in reader.paths() {
+        for doc in documents(path, compression)? {
+            doc?;
+        }
+    }
+
+    let mut nb_docs = 0;
+    for path in reader.paths() {
+        nb_docs += reindex_file(path, compression)?;
+    }
+    Ok(nb_docs)
+}
+
+/// Reindex every language of the corpus in `dst` in place.
+pub fn reindex(dst: &Path) -> Result<(), Error> {
+    for lang in CorpusReader::languages(dst)? {
+        let nb_docs = reindex_lang(dst, &lang)?;
+        info!("[{}]: reindexed {} documents", lang, nb_docs);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Seek, SeekFrom};
+
+    use oscar_io::v3::{Document, WriterTrait};
+    use oxilangtag::LanguageTag;
+    use tempfile::tempdir;
+
+    use super::reindex_lang;
+    use crate::io::test_utils::{gen_identified_doc, write_docs};
+    use crate::io::{CorpusReader, WriterDoc};
+    use crate::pipelines::oscardoc::types::{ByteOffsetMetadata, ContentMetadata};
+
+    #[test]
+    fn test_reindex() {
+        let dst = tempdir().unwrap();
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        let mut w = WriterDoc::new(dst.path(), fr.clone(), None)
+            .unwrap()
+            .with_byte_offsets(true);
+        w.write(vec![
+            gen_identified_doc("foo\nbar", "fr"),
+            gen_identified_doc("removed", "fr"),
+            gen_identified_doc("baz", "fr"),
+        ])
+        .unwrap();
+        w.close().unwrap();
+
+        // remove the second document by hand
+        let path = dst.path().join("fr_meta.jsonl");
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        std::fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+
+        assert_eq!(reindex_lang(dst.path(), &fr).unwrap(), 2);
+
+        // offsets point to the start of the document lines
+        let mut file = std::fs::File::open(&path).unwrap();
+        for doc in CorpusReader::new(dst.path(), &fr).unwrap() {
+            let doc = doc.unwrap();
+            file.seek(SeekFrom::Start(doc.metadata().byte_offset().unwrap()))
+                .unwrap();
+            let mut line = String::new();
+            BufReader::new(&file).read_line(&mut line).unwrap();
+            let at_offset: Document = serde_json::from_str(&line).unwrap();
+            assert_eq!(at_offset, doc);
+            assert_eq!(
+                doc.metadata().content_lines(),
+                Some(doc.content().lines().count())
+            );
+        }
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let dst = tempdir().unwrap();
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        let mut edited = gen_identified_doc("foo", "fr");
+        edited.set_content("foo\nbar".to_string());
+        write_docs(
+            dst.path(),
+            "fr",
+            vec![gen_identified_doc("baz", "fr"), edited],
+        );
+
+        let before = std::fs::read(dst.path().join("fr_meta.jsonl")).unwrap();
+        assert!(reindex_lang(dst.path(), &fr).is_err());
+        // nothing has been written
+        assert_eq!(
+            std::fs::read(dst.path().join("fr_meta.jsonl")).unwrap(),
+            before
+        );
+    }
+}
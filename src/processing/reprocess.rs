@@ -0,0 +1,217 @@
+/*! Corpus reprocessing.
+
+Applies [Transform]s and [Annotate]s to the documents of an existing corpus, writing them into a new one,
+so that new transformers or annotators (a new blocklist, different short sentences thresholds...)
+can be used without identifying WET shards again.
+
+Transforms are applied in order, then annotations.
+Sentence identifications are kept aligned with the transformed content,
+and documents that have no content left are removed.
+
+Rebuild files are not updated, and output files keep the source files' compression.
+!*/
+use std::path::Path;
+
+use log::info;
+use oscar_io::v3::{Document, WriterTrait};
+use oxilangtag::LanguageTag;
+use rayon::prelude::*;
+
+use crate::error::Error;
+use crate::io::{CorpusReader, WriterDoc};
+use crate::pipelines::oscardoc::types::{
+    set_content_counts, set_content_hash, ContentHashMetadata, ContentMetadata,
+};
+use crate::transformers::{rebuild_metadata, Annotate, Annotator, Transform};
+
+/// Default number of documents in a bulk write.
+const DEFAULT_BUFSIZE: usize = 500;
+
+/// A chain of transforms.
+pub type Transforms = Vec<Box<dyn Transform<Document> + Sync>>;
+
+/// Apply `transforms` then `annotator` to `doc`.
+///
+/// Returns `None` if the document has no content left.
+fn reprocess_doc(
+    mut doc: Document,
+    transforms: &Transforms,
+    annotator: &Annotator<Document>,
+) -> Option<Document> {
+    for transform in transforms {
+        let ids = doc.metadata().sentence_identifications().to_vec();
+        let ranges = transform.transform(&mut doc);
+        if ranges.is_empty() || doc.content().is_empty() {
+            return None;
+        }
+
+        // transforms that don't update sentence identifications themselves
+        // leave them misaligned with the kept lines.
+        if doc.metadata().sentence_identifications().len() != doc.content().lines().count() {
+            let kept_ids: Vec<_> = ranges
+                .into_iter()
+                .flat_map(|range| ids.get(range).unwrap_or_default().to_vec())
+                .collect();
+            let metadata = rebuild_metadata(doc.identification(), doc.metadata(), &kept_ids);
+            *doc.metadata_mut() = metadata;
+        }
+    }
+
+    // counts and hashes are only updated if the document had some
+    if doc.metadata().content_bytes().is_some() {
+        set_content_counts(&mut doc);
+    }
+    if doc.metadata().content_hash().is_some() {
+        set_content_hash(&mut doc, false);
+    }
+    if doc.metadata().normalized_content_hash().is_some() {
+        set_content_hash(&mut doc, true);
+    }
+    annotator.annotate(&mut doc);
+    Some(doc)
+}
+
+/// Reprocess the documents of a single language.
+///
+/// Returns the number of kept and removed documents.
+fn reprocess_lang(
+    src: &Path,
+    dst: &Path,
+    lang: &LanguageTag<String>,
+    transforms: &Transforms,
+    annotator: &Annotator<Document>,
+    bufsize: usize,
+) -> Result<(usize, usize), Error> {
+    let reader = CorpusReader::new(src, lang)?;
+    let mut writer =
+        WriterDoc::new(dst, lang.clone(), None)?.with_compression(reader.compression());
+
+    let (mut kept, mut removed) = (0, 0);
+    let mut write = |buf: Vec<Document>| -> Result<(), Error> {
+        let nb_docs = buf.len();
+        let docs: Vec<Document> = buf
+            .into_par_iter()
+            .filter_map(|doc| reprocess_doc(doc, transforms, annotator))
+            .collect();
+        kept += docs.len();
+        removed += nb_docs - docs.len();
+        if !docs.is_empty() {
+            writer.write(docs)?;
+        }
+        Ok(())
+    };
+
+    let mut buf = Vec::with_capacity(bufsize);
+    for doc in reader {
+        buf.push(doc?);
+        if buf.len() >= bufsize {
+            write(std::mem::take(&mut buf))?;
+        }
+    }
+    if !buf.is_empty() {
+        write(buf)?;
+    }
+    writer.close()?;
+
+    Ok((kept, removed))
+}
+
+/// Reprocess the corpus in `src` into `dst`, applying `transforms` and then `annotator` to every document.
+///
+/// `bufsize` is the number of documents in a bulk write (500 by default).
+pub fn reprocess(
+    src: &Path,
+    dst: &Path,
+    transforms: &Transforms,
+    annotator: &Annotator<Document>,
+    bufsize: Option<usize>,
+) -> Result<(), Error> {
+    if src == dst {
+        return Err(Error::Custom(
+            "reprocessing can't be done in place".to_string(),
+        ));
+    }
+    if !dst.exists() {
+        std::fs::create_dir(dst)?;
+    }
+
+    let bufsize = bufsize.unwrap_or(DEFAULT_BUFSIZE);
+    for lang in CorpusReader::languages(src)? {
+        let (kept, removed) = reprocess_lang(src, dst, &lang, transforms, annotator, bufsize)?;
+        info!("[{}]: kept {} documents, removed {}", lang, kept, removed);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use oscar_io::v3::Document;
+    use tempfile::tempdir;
+
+    use super::{reprocess, Transforms};
+    use crate::io::test_utils::{gen_identified_doc, read_docs, write_docs};
+    use crate::transformers::{Annotate, Annotator, RemoveShortSentences};
+
+    fn gen_doc(content: &str) -> Document {
+        let mut doc = gen_identified_doc(content, "fr");
+        doc.metadata_mut().add_annotation("tiny".to_string());
+        doc
+    }
+
+    #[test]
+    fn test_reprocess() {
+        let src = tempdir().unwrap();
+        let dst = tempdir().unwrap();
+        let long = "x".repeat(20);
+        let docs = vec![
+            gen_doc(&format!("foo\nbar\n{long}\nbaz\n{long}\nquux")),
+            gen_doc("foo\nbar"),
+            gen_doc(&long),
+        ];
+        write_docs(src.path(), "fr", docs);
+
+        struct MockAnnotate;
+        impl Annotate<Document> for MockAnnotate {
+            fn annotate(&self, doc: &mut Document) {
+                doc.metadata_mut().add_annotation("reprocessed".to_string());
+            }
+        }
+        let transforms: Transforms = vec![Box::new(RemoveShortSentences::with_min_length(10))];
+        let mut annotator = Annotator::default();
+        annotator.add(Box::new(MockAnnotate));
+
+        reprocess(src.path(), dst.path(), &transforms, &annotator, Some(2)).unwrap();
+
+        // the document with short sentences only is removed
+        let docs = read_docs(dst.path(), "fr");
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0].content(), &format!("{long}\nbaz\n{long}"));
+        assert_eq!(docs[1].content(), &long);
+        for doc in &docs {
+            assert_eq!(
+                doc.metadata().sentence_identifications().len(),
+                doc.content().lines().count()
+            );
+            assert_eq!(doc.identification().label().as_str(), "fr");
+            assert_eq!(
+                doc.metadata().annotation(),
+                Some(&vec!["tiny".to_string(), "reprocessed".to_string()])
+            );
+        }
+    }
+
+    #[test]
+    fn test_reprocess_in_place() {
+        let src = tempdir().unwrap();
+        let transforms: Transforms = Vec::new();
+        assert!(reprocess(
+            src.path(),
+            src.path(),
+            &transforms,
+            &Annotator::default(),
+            None
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,195 @@
+/*! Corpus splitting.
+
+Splits the documents of a corpus into several corpora, for example for ML training:
+
+- [SplitKind::Ratio] puts a share of the documents in a validation corpus (`<dst>/val`) and the rest
+  in a training one (`<dst>/train`). Validation documents are evenly spread over the source corpus
+  rather than taken from its end.
+- [SplitKind::Parts] deals documents round-robin into `n` corpora of roughly equal sizes (`<dst>/part_<i>`, starting at 1).
+
+Documents are never cut, and since text and metadata are stored together, they stay in sync.
+Each output is a regular corpus, that can be read, deduplicated or merged like any other.
+Rebuild files are not split, and output files keep the source files' compression.
+!*/
+use std::path::{Path, PathBuf};
+
+use log::info;
+use oscar_io::v3::{Document, WriterTrait};
+use oxilangtag::LanguageTag;
+
+use crate::error::Error;
+use crate::io::{CorpusReader, WriterDoc};
+
+/// Default number of documents in a bulk write.
+const DEFAULT_BUFSIZE: usize = 500;
+
+/// How documents are split.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitKind {
+    /// Train/validation split, with the share (between 0 and 1) of documents going to validation.
+    Ratio(f32),
+    /// Round-robin split into this number of parts.
+    Parts(usize),
+}
+
+impl SplitKind {
+    /// Output corpora folders, in `dst`.
+    fn outputs(&self, dst: &Path) -> Vec<PathBuf> {
+        match self {
+            Self::Ratio(_) => vec![dst.join("train"), dst.join("val")],
+            Self::Parts(n) => (1..=*n).map(|i| dst.join(format!("part_{i}"))).collect(),
+        }
+    }
+
+    /// Get the output (index in [SplitKind::outputs]) of the `idx`-th document.
+    fn output(&self, idx: usize) -> usize {
+        match self {
+            // a document goes to validation each time the running share crosses an integer
+            Self::Ratio(ratio) => {
+                let crossed = ((idx + 1) as f64 * *ratio as f64).floor()
+                    > (idx as f64 * *ratio as f64).floor();
+                usize::from(crossed)
+            }
+            Self::Parts(n) => idx % n,
+        }
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        match self {
+            Self::Ratio(ratio) if !(0.0..=1.0).contains(ratio) => Err(Error::Custom(format!(
+                "validation ratio should be between 0 and 1 (got {ratio})"
+            ))),
+            Self::Parts(0) => Err(Error::Custom(
+                "number of parts should be at least 1".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Split the documents of a single language.
+///
+/// Returns the number of documents written in each output.
+fn split_lang(
+    src: &Path,
+    outputs: &[PathBuf],
+    lang: &LanguageTag<String>,
+    kind: SplitKind,
+    bufsize: usize,
+) -> Result<Vec<usize>, Error> {
+    let reader = CorpusReader::new(src, lang)?;
+    let mut writers = outputs
+        .iter()
+        .map(|dst| {
+            Ok(WriterDoc::new(dst, lang.clone(), None)?.with_compression(reader.compression()))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut counts = vec![0; outputs.len()];
+    let mut bufs: Vec<Vec<Document>> = vec![Vec::new(); outputs.len()];
+    for (idx, doc) in reader.enumerate() {
+        let output = kind.output(idx);
+        bufs[output].push(doc?);
+        counts[output] += 1;
+        if bufs[output].len() >= bufsize {
+            writers[output].write(std::mem::take(&mut bufs[output]))?;
+        }
+    }
+
+    for (mut writer, buf) in writers.into_iter().zip(bufs) {
+        if !buf.is_empty() {
+            writer.write(buf)?;
+        }
+        writer.close()?;
+    }
+
+    Ok(counts)
+}
+
+/// Split the corpus in `src` into corpora in `dst` (see [the module documentation](self)).
+///
+/// `bufsize` is the number of documents in a bulk write (500 by default).
+pub fn split(src: &Path, dst: &Path, kind: SplitKind, bufsize: Option<usize>) -> Result<(), Error> {
+    kind.check()?;
+    if src == dst {
+        return Err(Error::Custom(
+            "splitting can't be done in place".to_string(),
+        ));
+    }
+
+    let outputs = kind.outputs(dst);
+    for output in &outputs {
+        std::fs::create_dir_all(output)?;
+    }
+
+    let bufsize = bufsize.unwrap_or(DEFAULT_BUFSIZE);
+    for lang in CorpusReader::languages(src)? {
+        let counts = split_lang(src, &outputs, &lang, kind, bufsize)?;
+        info!("[{}]: split into {:?} documents", lang, counts);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use oscar_io::v3::Document;
+    use tempfile::tempdir;
+
+    use super::{split, SplitKind};
+    use crate::io::test_utils::{gen_doc, read_contents, write_docs};
+    use crate::processing::validate::validate;
+
+    fn numbered_docs(nb_docs: usize) -> Vec<Document> {
+        (0..nb_docs)
+            .map(|i| gen_doc(&format!("document {i}")))
+            .collect()
+    }
+
+    #[test]
+    fn test_ratio() {
+        let src = tempdir().unwrap();
+        let dst = tempdir().unwrap();
+        write_docs(src.path(), "en", numbered_docs(10));
+
+        split(src.path(), dst.path(), SplitKind::Ratio(0.2), Some(3)).unwrap();
+
+        let train = dst.path().join("train");
+        let val = dst.path().join("val");
+        for output in [&train, &val] {
+            assert!(validate(output).unwrap().is_empty());
+        }
+
+        let train = read_contents(&train, "en");
+        let val = read_contents(&val, "en");
+        assert_eq!(train.len(), 8);
+        assert_eq!(val, vec!["document 4", "document 9"]);
+        assert!(!train.iter().any(|doc| val.contains(doc)));
+    }
+
+    #[test]
+    fn test_parts() {
+        let src = tempdir().unwrap();
+        let dst = tempdir().unwrap();
+        write_docs(src.path(), "en", numbered_docs(10));
+
+        split(src.path(), dst.path(), SplitKind::Parts(3), None).unwrap();
+
+        let sizes: Vec<usize> = (1..=3)
+            .map(|i| read_contents(&dst.path().join(format!("part_{i}")), "en").len())
+            .collect();
+        assert_eq!(sizes, vec![4, 3, 3]);
+        assert_eq!(
+            read_contents(&dst.path().join("part_2"), "en"),
+            vec!["document 1", "document 4", "document 7"]
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let src = tempdir().unwrap();
+        let dst = tempdir().unwrap();
+        assert!(split(src.path(), dst.path(), SplitKind::Ratio(1.5), None).is_err());
+        assert!(split(src.path(), dst.path(), SplitKind::Parts(0), None).is_err());
+    }
+}
@@ -0,0 +1,291 @@
+/*! Corpus validation.
+
+Checks that a generated corpus is consistent, which usually tells whether the run that generated it has finished.
+
+For each language:
+
+- language files are found, and there's no extra file for the language (a missing part, leftovers of a previous run...),
+- every line is a valid document (a truncated last line usually means that the run has been interrupted),
+- every document has one sentence identification per line.
+
+Partially written last documents can be removed beforehand with [repair].
+!*/
+use std::{collections::BTreeMap, fmt::Display, path::Path, path::PathBuf};
+
+use log::info;
+use oxilangtag::LanguageTag;
+
+use crate::error::Error;
+use crate::io::CorpusReader;
+
+/// A problem found in a language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// no readable files for the language
+    NoFiles(String),
+    /// a file that belongs to the language but wouldn't be read
+    UnexpectedFile(PathBuf),
+    /// a line that can't be read as a document
+    InvalidDocument { index: usize, error: String },
+    /// a document whose number of sentence identifications doesn't match its number of lines
+    SentenceMismatch {
+        index: usize,
+        lines: usize,
+        identifications: usize,
+    },
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoFiles(error) => write!(f, "no files: {error}"),
+            Self::UnexpectedFile(path) => write!(
+                f,
+                "unexpected file {path:?} (missing part or mixed layouts/compressions?)"
+            ),
+            Self::InvalidDocument { index, error } => {
+                write!(f, "document {index}: invalid ({error})")
+            }
+            Self::SentenceMismatch {
+                index,
+                lines,
+                identifications,
+            } => write!(
+                f,
+                "document {index}: {lines} lines but {identifications} sentence identifications"
+            ),
+        }
+    }
+}
+
+/// List the files of `dst` that belong to `lang`, in any layout.
+fn lang_files(dst: &Path, lang: &LanguageTag<String>) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    let prefix = format!("{lang}_meta");
+    for entry in std::fs::read_dir(dst)? {
+        let path = entry?.path();
+        let belongs = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(&prefix));
+        if belongs && path.is_file() {
+            files.push(path);
+        }
+    }
+
+    let subdir = dst.join(lang.as_str());
+    if subdir.is_dir() {
+        for entry in std::fs::read_dir(subdir)? {
+            let path = entry?.path();
+            let belongs = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("text"));
+            if belongs && path.is_file() {
+                files.push(path);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Validate the documents of a language.
+fn validate_lang(dst: &Path, lang: &LanguageTag<String>) -> Result<Vec<Problem>, Error> {
+    let reader = match CorpusReader::new(dst, lang) {
+        Ok(reader) => reader,
+        Err(e) => return Ok(vec![Problem::NoFiles(format!("{e:?}"))]),
+    };
+
+    let mut problems: Vec<Problem> = lang_files(dst, lang)?
+        .into_iter()
+        .filter(|path| !reader.paths().contains(path))
+        .map(Problem::UnexpectedFile)
+        .collect();
+
+    for (index, doc) in reader.enumerate() {
+        let doc = match doc {
+            Ok(doc) => doc,
+            Err(Error::Serde(e)) => {
+                problems.push(Problem::InvalidDocument {
+                    index,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+            // reading errors (e.g. truncated compressed files) prevent reading further
+            Err(e) => {
+                problems.push(Problem::InvalidDocument {
+                    index,
+                    error: format!("{e:?}"),
+                });
+                break;
+            }
+        };
+
+        let lines = doc.content().lines().count();
+        let identifications = doc.metadata().sentence_identifications().len();
+        if lines != identifications {
+            problems.push(Problem::SentenceMismatch {
+                index,
+                lines,
+                identifications,
+            });
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Truncate the last file of each language of the corpus in `dst` to its last complete document
+/// (see [crate::io::Compression::repair]), getting the repaired files and the number of removed bytes.
+///
+/// Previous parts are closed before rotating, so only last files can hold a partial document.
+pub fn repair(dst: &Path) -> Result<Vec<(PathBuf, u64)>, Error> {
+    let mut repaired = Vec::new();
+    for lang in CorpusReader::languages(dst)? {
+        let reader = CorpusReader::new(dst, &lang)?;
+        if let Some(last) = reader.paths().last() {
+            let removed = reader.compression().repair(last)?;
+            if removed > 0 {
+                repaired.push((last.clone(), removed));
+            }
+        }
+    }
+
+    Ok(repaired)
+}
+
+/// Validate the corpus in `dst`, getting the problems of each language that has some.
+pub fn validate(dst: &Path) -> Result<BTreeMap<LanguageTag<String>, Vec<Problem>>, Error> {
+    let mut problems = BTreeMap::new();
+    for lang in CorpusReader::languages(dst)? {
+        info!("[{}]: validating", lang);
+        let lang_problems = validate_lang(dst, &lang)?;
+        if !lang_problems.is_empty() {
+            problems.insert(lang, lang_problems);
+        }
+    }
+
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use oxilangtag::LanguageTag;
+    use tempfile::tempdir;
+
+    use super::{repair, validate, Problem};
+    use crate::io::test_utils::{gen_identified_doc, write_docs, write_parts};
+    use crate::io::Compression;
+
+    fn fr_problems(dst: &Path) -> Vec<Problem> {
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        validate(dst).unwrap().remove(&fr).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_valid() {
+        let dst = tempdir().unwrap();
+        write_parts(
+            dst.path(),
+            "fr",
+            vec![
+                gen_identified_doc("foo\nbar", "fr"),
+                gen_identified_doc("baz", "fr"),
+            ],
+            Some(1),
+            Compression::None,
+        );
+        assert!(validate(dst.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_truncated() {
+        let dst = tempdir().unwrap();
+        write_docs(
+            dst.path(),
+            "fr",
+            vec![
+                gen_identified_doc("foo", "fr"),
+                gen_identified_doc("bar", "fr"),
+            ],
+        );
+
+        // cut the last document in half
+        let path = dst.path().join("fr_meta.jsonl");
+        let content = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &content[..content.len() - 20]).unwrap();
+
+        let problems = fr_problems(dst.path());
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(
+            problems[0],
+            Problem::InvalidDocument { index: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn test_repair() {
+        let dst = tempdir().unwrap();
+        write_docs(
+            dst.path(),
+            "fr",
+            vec![
+                gen_identified_doc("foo", "fr"),
+                gen_identified_doc("bar", "fr"),
+            ],
+        );
+
+        let path = dst.path().join("fr_meta.jsonl");
+        let content = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &content[..content.len() - 20]).unwrap();
+
+        let repaired = repair(dst.path()).unwrap();
+        assert_eq!(repaired.len(), 1);
+        assert_eq!(repaired[0].0, path);
+        assert!(validate(dst.path()).unwrap().is_empty());
+        assert!(repair(dst.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sentence_mismatch() {
+        let dst = tempdir().unwrap();
+        let mut doc = gen_identified_doc("foo", "fr");
+        doc.set_content("foo\nbar\nbaz".to_string());
+        write_docs(dst.path(), "fr", vec![gen_identified_doc("foo", "fr"), doc]);
+
+        assert_eq!(
+            fr_problems(dst.path()),
+            vec![Problem::SentenceMismatch {
+                index: 1,
+                lines: 3,
+                identifications: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn test_missing_part() {
+        let dst = tempdir().unwrap();
+        write_parts(
+            dst.path(),
+            "fr",
+            vec![
+                gen_identified_doc("foo", "fr"),
+                gen_identified_doc("bar", "fr"),
+                gen_identified_doc("baz", "fr"),
+            ],
+            Some(1),
+            Compression::None,
+        );
+        std::fs::remove_file(dst.path().join("fr_meta_part_2.jsonl")).unwrap();
+
+        assert_eq!(
+            fr_problems(dst.path()),
+            vec![Problem::UnexpectedFile(
+                dst.path().join("fr_meta_part_3.jsonl")
+            )]
+        );
+    }
+}
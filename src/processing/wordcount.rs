@@ -0,0 +1,261 @@
+/*! Word frequencies.
+
+Counts the tokens of every language of a corpus, writing the most frequent ones of each language
+in `<dst>/<lang>.tsv` (one `token<TAB>count` line per token, most frequent first).
+
+Documents are streamed, and each language is counted with a bounded number of counters
+using the [Misra-Gries algorithm](https://en.wikipedia.org/wiki/Misra%E2%80%93Gries_summary),
+so that memory doesn't grow with the vocabulary of huge languages.
+Counts are exact as long as a language has fewer distinct tokens than counters.
+Otherwise, they are lower bounds, off by at most [WordCounts::max_error],
+and every token that makes for more than `1 / (capacity + 1)` of the tokens is kept.
+
+Tokens are whitespace-separated by default (see [TokenizerKind]). Other tokenizations can be used
+by implementing [Tokenizer].
+!*/
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use log::{info, warn};
+use oxilangtag::LanguageTag;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::error::Error;
+use crate::io::CorpusReader;
+
+/// Splits text into tokens.
+pub trait Tokenizer {
+    fn tokens<'a>(&self, text: &'a str) -> Box<dyn Iterator<Item = &'a str> + 'a>;
+}
+
+/// Built-in tokenizers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TokenizerKind {
+    /// Split on whitespace, keeping punctuation attached to words.
+    #[default]
+    Whitespace,
+    /// Split on Unicode word boundaries (see [UnicodeSegmentation::unicode_words]), dropping punctuation.
+    /// Scripts that don't use spaces are split per character.
+    UnicodeWords,
+}
+
+impl Tokenizer for TokenizerKind {
+    fn tokens<'a>(&self, text: &'a str) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        match self {
+            Self::Whitespace => Box::new(text.split_whitespace()),
+            Self::UnicodeWords => Box::new(text.unicode_words()),
+        }
+    }
+}
+
+impl FromStr for TokenizerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "whitespace" => Ok(Self::Whitespace),
+            "unicode-words" => Ok(Self::UnicodeWords),
+            other => Err(format!(
+                "unknown tokenizer {other:?} (expected whitespace or unicode-words)"
+            )),
+        }
+    }
+}
+
+/// Token counts of a language, with a bounded number of counters.
+#[derive(Debug)]
+pub struct WordCounts {
+    capacity: usize,
+    counts: HashMap<String, u64>,
+    /// number of times every counter was decremented
+    nb_decrements: u64,
+    nb_tokens: u64,
+}
+
+impl WordCounts {
+    /// Create counts holding at most `capacity` counters.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            counts: HashMap::new(),
+            nb_decrements: 0,
+            nb_tokens: 0,
+        }
+    }
+
+    /// Count a token.
+    ///
+    /// When every counter is taken by other tokens, they are all decremented instead,
+    /// freeing those that reach 0.
+    pub fn add(&mut self, token: &str) {
+        self.nb_tokens += 1;
+        if let Some(count) = self.counts.get_mut(token) {
+            *count += 1;
+        } else if self.counts.len() < self.capacity {
+            self.counts.insert(token.to_string(), 1);
+        } else {
+            self.nb_decrements += 1;
+            self.counts.retain(|_, count| {
+                *count -= 1;
+                *count > 0
+            });
+        }
+    }
+
+    /// Maximum difference between the counts and the actual number of occurrences of tokens.
+    ///
+    /// Counts are exact if it is 0.
+    pub fn max_error(&self) -> u64 {
+        self.nb_decrements
+    }
+
+    /// Number of counted tokens.
+    pub fn nb_tokens(&self) -> u64 {
+        self.nb_tokens
+    }
+
+    /// Get the `n` most frequent tokens with their counts, most frequent first (then in lexicographic order).
+    pub fn top(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut top: Vec<(&str, u64)> = self
+            .counts
+            .iter()
+            .map(|(token, count)| (token.as_str(), *count))
+            .collect();
+        top.sort_unstable_by(|(t1, c1), (t2, c2)| c2.cmp(c1).then(t1.cmp(t2)));
+        top.truncate(n);
+        top
+    }
+}
+
+/// Count the tokens of the documents of `lang` in `src`, with at most `capacity` counters.
+pub fn count_lang(
+    src: &Path,
+    lang: &LanguageTag<String>,
+    capacity: usize,
+    tokenizer: &dyn Tokenizer,
+) -> Result<WordCounts, Error> {
+    let mut counts = WordCounts::new(capacity);
+    for doc in CorpusReader::new(src, lang)? {
+        for token in tokenizer.tokens(doc?.content()) {
+            counts.add(token);
+        }
+    }
+    Ok(counts)
+}
+
+/// Get the path of the frequency table of `lang` in `dst`.
+pub fn path(dst: &Path, lang: &LanguageTag<String>) -> PathBuf {
+    dst.join(format!("{lang}.tsv"))
+}
+
+/// Write the `top` most frequent tokens of every language of the corpus in `src` in `dst`,
+/// counting each language with at most `capacity` counters.
+///
+/// `dst` is created if needed.
+pub fn wordcount(
+    src: &Path,
+    dst: &Path,
+    top: usize,
+    capacity: usize,
+    tokenizer: &dyn Tokenizer,
+) -> Result<(), Error> {
+    if capacity < top {
+        return Err(Error::Custom(format!(
+            "number of counters ({capacity}) should be at least the number of tokens to keep ({top})"
+        )));
+    }
+
+    std::fs::create_dir_all(dst)?;
+    for lang in CorpusReader::languages(src)? {
+        let counts = count_lang(src, &lang, capacity, tokenizer)?;
+        info!("[{}]: counted {} tokens", lang, counts.nb_tokens());
+        if counts.max_error() > 0 {
+            warn!(
+                "[{}]: counts are approximate, and may be up to {} below actual ones",
+                lang,
+                counts.max_error()
+            );
+        }
+
+        let mut w = BufWriter::new(File::create(path(dst, &lang))?);
+        for (token, count) in counts.top(top) {
+            writeln!(w, "{token}\t{count}")?;
+        }
+        w.flush()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use oxilangtag::LanguageTag;
+    use tempfile::tempdir;
+
+    use super::{path, wordcount, Tokenizer, TokenizerKind, WordCounts};
+    use crate::io::test_utils::{gen_docs, write_docs};
+
+    #[test]
+    fn test_wordcount() {
+        let src = tempdir().unwrap();
+        let dst = tempdir().unwrap();
+        write_docs(
+            src.path(),
+            "fr",
+            gen_docs(&["le chat et le chien", "le chat\ndort", "Le chien"]),
+        );
+        write_docs(src.path(), "en", gen_docs(&["the cat and the dog"]));
+
+        wordcount(src.path(), dst.path(), 3, 10, &TokenizerKind::Whitespace).unwrap();
+
+        let fr = LanguageTag::parse("fr".to_string()).unwrap();
+        let en = LanguageTag::parse("en".to_string()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path(dst.path(), &fr)).unwrap(),
+            "le\t3\nchat\t2\nchien\t2\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(path(dst.path(), &en)).unwrap(),
+            "the\t2\nand\t1\ncat\t1\n"
+        );
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut counts = WordCounts::new(2);
+        for token in "a b a c a d a e a b".split_whitespace() {
+            counts.add(token);
+        }
+
+        // the frequent token is kept, with a count within the error bound
+        let top = counts.top(1);
+        assert_eq!(top[0].0, "a");
+        assert!(top[0].1 <= 5 && top[0].1 + counts.max_error() >= 5);
+        assert!(counts.max_error() > 0);
+        assert_eq!(counts.nb_tokens(), 10);
+    }
+
+    #[test]
+    fn test_tokenizers() {
+        let text = "Bonjour, le monde ! 你好世界";
+        let tokens: Vec<&str> = TokenizerKind::Whitespace.tokens(text).collect();
+        assert_eq!(tokens, ["Bonjour,", "le", "monde", "!", "你好世界"]);
+        let tokens: Vec<&str> = TokenizerKind::UnicodeWords.tokens(text).collect();
+        assert_eq!(tokens, ["Bonjour", "le", "monde", "你", "好", "世", "界"]);
+
+        assert_eq!("unicode-words".parse(), Ok(TokenizerKind::UnicodeWords));
+        assert!("bpe".parse::<TokenizerKind>().is_err());
+    }
+
+    #[test]
+    fn test_capacity_below_top() {
+        let src = tempdir().unwrap();
+        let dst = tempdir().unwrap();
+        assert!(wordcount(src.path(), dst.path(), 10, 5, &TokenizerKind::Whitespace).is_err());
+    }
+}
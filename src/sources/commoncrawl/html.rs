@@ -0,0 +1,209 @@
+//! HTML to plain text conversion.
+//!
+//! A lightweight converter that approximates CommonCrawl's WET extraction:
+//! tags are stripped, the contents of non-textual elements (scripts, styles...) are dropped,
+//! block-level elements end lines, and whitespace (including newlines of the source) is collapsed.
+//! Blank lines are removed. Preformatted text isn't kept as is.
+//!
+//! It doesn't build a DOM and is lenient with malformed HTML:
+//! unclosed tags and comments run to the end of the document.
+
+/// Elements whose content is not text.
+const SKIPPED: [&str; 6] = ["script", "style", "noscript", "template", "svg", "iframe"];
+
+/// Elements that are on their own line(s).
+const BLOCKS: [&str; 36] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "caption",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "option",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "title",
+    "tr",
+    "ul",
+];
+
+/// Get the lowercased name of a tag (`tag` being what's between `<` and `>`),
+/// and whether it's a closing tag.
+fn tag_name(tag: &str) -> (String, bool) {
+    let (tag, closing) = match tag.strip_prefix('/') {
+        Some(tag) => (tag, true),
+        None => (tag, false),
+    };
+    let name = tag
+        .split(|c: char| c.is_whitespace() || c == '/')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    (name, closing)
+}
+
+/// Decode a character reference (`entity` being what's between `&` and `;`).
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = entity.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+/// Decode the character references of `text`. Unknown references are kept as is.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        // references are short, don't look for a semicolon too far
+        let end = rest.char_indices().take(12).find(|(_, c)| *c == ';');
+        match end.and_then(|(end, _)| decode_entity(&rest[1..end]).map(|c| (end, c))) {
+            Some((end, c)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Append `chunk` to `text`, turning any whitespace into spaces.
+fn push_text(text: &mut String, chunk: &str) {
+    text.extend(
+        chunk
+            .chars()
+            .map(|c| if c.is_whitespace() { ' ' } else { c }),
+    );
+}
+
+/// Convert `html` to plain text, with one line per block.
+pub fn to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len() / 2);
+    // name of the skipped element we're in, if any
+    let mut skipping: Option<String> = None;
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        if skipping.is_none() {
+            push_text(&mut text, &rest[..start]);
+        }
+        rest = &rest[start..];
+
+        // comments and doctypes
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => {
+                rest = "";
+                break;
+            }
+        };
+        let (name, closing) = tag_name(&rest[1..end]);
+        rest = &rest[end + 1..];
+
+        match &skipping {
+            Some(skipped) if closing && *skipped == name => skipping = None,
+            Some(_) => (),
+            None if !closing && SKIPPED.contains(&name.as_str()) => skipping = Some(name),
+            None if BLOCKS.contains(&name.as_str()) => text.push('\n'),
+            None => (),
+        }
+    }
+    if skipping.is_none() {
+        push_text(&mut text, rest);
+    }
+
+    decode_entities(&text)
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_entities, to_text};
+
+    #[test]
+    fn test_to_text() {
+        let html = r#"<!DOCTYPE html>
+<html>
+<head><title>Une  page</title><style>p { color: red; }</style></head>
+<body>
+<!-- menu -->
+<p>Premier <b>paragraphe</b>,
+sur deux lignes.</p>
+<script type="text/javascript">var x = "<p>pas du texte</p>";</script>
+<ul><li>un</li><li>deux</li></ul>
+Texte<br/>après un saut de ligne
+</body>
+</html>"#;
+        assert_eq!(
+            to_text(html),
+            "Une page\nPremier paragraphe, sur deux lignes.\nun\ndeux\nTexte\naprès un saut de ligne"
+        );
+    }
+
+    #[test]
+    fn test_entities() {
+        assert_eq!(
+            decode_entities("a &amp; b &lt;c&gt; &#233;t&#xE9; &unknown; & done"),
+            "a & b <c> été &unknown; & done"
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(to_text("<p>unclosed <b"), "unclosed");
+        assert_eq!(to_text("text <!-- unclosed comment"), "text");
+        assert_eq!(to_text("<script>never closed"), "");
+    }
+}
@@ -0,0 +1,114 @@
+//! Split records merging.
+//!
+//! The crawler occasionally splits a page into several records.
+//! [MergeSameUri] merges records that are adjacent in a shard and share the exact same `WARC-Target-URI`,
+//! so that their content is identified (and its bytes counted) as a single document.
+//!
+//! Merged records keep the headers and the index of the first one, and their bodies are joined with a newline.
+//! Rebuild locations then only point to the first record.
+use std::iter::Peekable;
+
+use warc::{BufferedBody, Record, WarcHeader};
+
+use super::shard::IndexedRecord;
+
+/// Merges adjacent records that have the same target URI. Records with no target URI are never merged.
+///
+/// Errors are yielded as is, and stop merging.
+pub struct MergeSameUri<I: Iterator> {
+    iter: Peekable<I>,
+}
+
+impl<I: Iterator<Item = IndexedRecord>> MergeSameUri<I> {
+    pub fn new(iter: I) -> Self {
+        Self {
+            iter: iter.peekable(),
+        }
+    }
+}
+
+/// Get the target URI of a record.
+fn target_uri(record: &Record<BufferedBody>) -> Option<String> {
+    record
+        .header(WarcHeader::TargetURI)
+        .map(|uri| uri.into_owned())
+}
+
+impl<I: Iterator<Item = IndexedRecord>> Iterator for MergeSameUri<I> {
+    type Item = IndexedRecord;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (idx, record) = self.iter.next()?;
+        let mut record = match record {
+            Ok(record) => record,
+            Err(e) => return Some((idx, Err(e))),
+        };
+        let uri = match target_uri(&record) {
+            Some(uri) => uri,
+            None => return Some((idx, Ok(record))),
+        };
+
+        let same_uri = |(_, next): &IndexedRecord| matches!(next, Ok(next) if target_uri(next).as_ref() == Some(&uri));
+        let mut body = None;
+        while let Some((_, Ok(next))) = self.iter.next_if(same_uri) {
+            let body = body.get_or_insert_with(|| record.body().to_vec());
+            body.push(b'\n');
+            body.extend_from_slice(next.body());
+        }
+        if let Some(body) = body {
+            record.replace_body(body);
+        }
+
+        Some((idx, Ok(record)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use warc::{BufferedBody, Record, WarcHeader};
+
+    use super::MergeSameUri;
+
+    fn gen_record(uri: Option<&str>, body: &str) -> Record<BufferedBody> {
+        let mut record = Record::default();
+        if let Some(uri) = uri {
+            record.set_header(WarcHeader::TargetURI, uri).unwrap();
+        }
+        record.add_body(body)
+    }
+
+    #[test]
+    fn test_merge() {
+        let records = vec![
+            gen_record(Some("https://example.com/a"), "a1"),
+            gen_record(Some("https://example.com/a"), "a2"),
+            gen_record(Some("https://example.com/b"), "b"),
+            gen_record(Some("https://example.com/a"), "a3"),
+            gen_record(Some("https://example.com/a/"), "a4"),
+            gen_record(None, "none1"),
+            gen_record(None, "none2"),
+        ];
+        let merged: Vec<(usize, String)> =
+            MergeSameUri::new(records.into_iter().map(Ok).enumerate())
+                .map(|(idx, record)| {
+                    (
+                        idx,
+                        String::from_utf8_lossy(record.unwrap().body()).into_owned(),
+                    )
+                })
+                .collect();
+
+        // only adjacent records with the exact same URI are merged, keeping the first index
+        assert_eq!(
+            merged,
+            vec![
+                (0, "a1\na2".to_string()),
+                (2, "b".to_string()),
+                (3, "a3".to_string()),
+                (4, "a4".to_string()),
+                (5, "none1".to_string()),
+                (6, "none2".to_string()),
+            ]
+        );
+    }
+}
@@ -1,6 +1,14 @@
 /*!
 Contains files relative to CommonCrawl.
 !*/
+mod html;
+mod merge;
+mod raw;
+pub mod remote;
+mod retry;
 mod shard;
 
-pub use shard::Wet;
+pub use merge::MergeSameUri;
+pub use raw::Warc;
+pub use retry::Retry;
+pub use shard::{IndexedRecord, ParRecords, Wet};
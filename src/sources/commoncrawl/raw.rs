@@ -0,0 +1,183 @@
+//! Raw WARC utils.
+//!
+//! [Warc] reads raw WARC files (as opposed to WET files, that hold pre-extracted text),
+//! converting HTML `response` records into plain text records shaped like WET `conversion` records,
+//! so that they can go through the same processing.
+//!
+//! Other records (requests, metadata, non-HTML responses...) are skipped.
+//! HTTP payloads are expected to be stored decoded (not chunked nor compressed), as in CommonCrawl archives,
+//! and are read as UTF-8, invalid sequences being replaced.
+//!
+//! Converted records get a new record id and refer to the original one (`WARC-Refers-To`),
+//! keeping its target URI and date.
+use std::{io::BufRead, path::Path};
+
+use warc::{BufferedBody, Record, RecordIter, RecordType, WarcHeader, WarcReader};
+
+use super::html;
+use super::retry::Retry;
+use super::shard::open_compressed;
+use crate::error::Error;
+
+/// Raw WARC reader, generic over reader type. Iterates over converted records.
+pub struct Warc<T> {
+    pub iter: RecordIter<T>,
+}
+
+impl<T: BufRead> Warc<T> {
+    pub fn new(reader: T) -> Self {
+        Self {
+            iter: WarcReader::new(reader).iter_records(),
+        }
+    }
+}
+
+/// Warc reader over a gzip or zstd-compressed file.
+impl Warc<Box<dyn BufRead + Send>> {
+    /// Create a new reader from a compressed WARC file,
+    /// picking the decoder from the file extension (see [super::Wet::EXTENSIONS]).
+    ///
+    /// Opening and reading the file are retried on transient IO errors (see [Retry]).
+    pub fn from_path_with_retry<P: AsRef<Path>>(path: P, retry: Retry) -> Result<Self, Error> {
+        Ok(Self::new(open_compressed(path.as_ref(), retry)?))
+    }
+}
+
+/// Split an HTTP response into its headers and payload.
+///
+/// Returns [None] if there's no header/payload separator.
+fn split_http(response: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (end, sep_len) = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|end| (end, 4))
+        .or_else(|| {
+            response
+                .windows(2)
+                .position(|w| w == b"\n\n")
+                .map(|end| (end, 2))
+        })?;
+    Some((&response[..end], &response[end + sep_len..]))
+}
+
+/// Check that the HTTP `headers` declare an HTML payload.
+fn is_html(headers: &[u8]) -> bool {
+    String::from_utf8_lossy(headers).lines().any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("content-type")
+                && value.to_ascii_lowercase().contains("html")
+        })
+    })
+}
+
+/// Convert an HTML `response` record into a plain text `conversion` record.
+///
+/// Returns [None] for records that hold no HTML.
+fn convert(record: &Record<BufferedBody>) -> Option<Record<BufferedBody>> {
+    if *record.warc_type() != RecordType::Response {
+        return None;
+    }
+    let (headers, payload) = split_http(record.body())?;
+    if !is_html(headers) {
+        return None;
+    }
+
+    let text = html::to_text(&String::from_utf8_lossy(payload));
+    let mut converted = Record::default();
+    converted.set_warc_type(RecordType::Conversion);
+    converted.set_date(*record.date());
+    converted
+        .set_header(WarcHeader::RefersTo, record.warc_id().to_string())
+        .ok()?;
+    converted
+        .set_header(WarcHeader::ContentType, "text/plain")
+        .ok()?;
+    if let Some(uri) = record.header(WarcHeader::TargetURI) {
+        converted
+            .set_header(WarcHeader::TargetURI, uri.into_owned())
+            .ok()?;
+    }
+    Some(converted.add_body(text))
+}
+
+impl<T: BufRead> Iterator for Warc<T> {
+    type Item = Result<Record<BufferedBody>, warc::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.iter.next()? {
+                Ok(record) => {
+                    if let Some(converted) = convert(&record) {
+                        return Some(Ok(converted));
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use warc::{BufferedBody, Record, RecordType, WarcHeader, WarcWriter};
+
+    use super::Warc;
+
+    fn gen_record(warc_type: RecordType, body: &str) -> Record<BufferedBody> {
+        let mut record = Record::default();
+        record.set_warc_type(warc_type);
+        record
+            .set_header(WarcHeader::TargetURI, "http://example.com/")
+            .unwrap();
+        record.add_body(body)
+    }
+
+    #[test]
+    fn test_html_response() {
+        let html = "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=UTF-8\r\n\r\n\
+            <html><head><title>Example</title></head>\
+            <body><h1>Hello</h1><p>This is a <i>tiny</i> page.</p></body></html>";
+        let records = vec![
+            gen_record(RecordType::Request, "GET / HTTP/1.1\r\n\r\n"),
+            gen_record(RecordType::Response, html),
+            gen_record(
+                RecordType::Response,
+                "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\n\r\nnot text",
+            ),
+            gen_record(RecordType::Metadata, "fetchTimeMs: 42"),
+        ];
+        let response_id = records[1].warc_id().to_string();
+
+        let mut raw = Vec::new();
+        let mut writer = WarcWriter::new(&mut raw);
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+
+        let converted: Vec<_> = Warc::new(Cursor::new(raw))
+            .map(|record| record.unwrap())
+            .collect();
+
+        assert_eq!(converted.len(), 1);
+        let record = &converted[0];
+        assert_eq!(
+            String::from_utf8_lossy(record.body()),
+            "Example\nHello\nThis is a tiny page."
+        );
+        assert_eq!(*record.warc_type(), RecordType::Conversion);
+        assert_eq!(
+            record.header(WarcHeader::RefersTo).as_deref(),
+            Some(response_id.as_str())
+        );
+        assert_eq!(
+            record.header(WarcHeader::TargetURI).as_deref(),
+            Some("http://example.com/")
+        );
+        assert_eq!(
+            record.header(WarcHeader::ContentType).as_deref(),
+            Some("text/plain")
+        );
+    }
+}
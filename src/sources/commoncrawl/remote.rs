@@ -0,0 +1,162 @@
+//! Remote shards.
+//!
+//! Rather than downloading every shard before processing them, shards can be downloaded
+//! as they get processed (see [crate::pipelines::oscardoc::RemoteOptions]).
+//! Shard URLs are listed in a `wet.paths`-like file: CommonCrawl paths are relative to [BASE_URL].
+//!
+//! Downloads are retried on transient errors (see [Retry]): connection failures, server errors
+//! and throttling. Other client errors (e.g. a missing shard) are not retried.
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{self, BufRead, BufReader, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+use reqwest::{blocking::Client, StatusCode, Url};
+
+use super::{Retry, Wet};
+use crate::error::Error;
+
+/// Base URL of CommonCrawl paths.
+pub const BASE_URL: &str = "https://data.commoncrawl.org/";
+
+/// Read the shard URLs listed in `path`, one per line.
+///
+/// Lines that are not absolute URLs are CommonCrawl paths (e.g. `crawl-data/CC-MAIN-2023-06/...`).
+/// Blank lines are ignored.
+pub fn read_shard_urls(path: &Path) -> Result<Vec<Url>, Error> {
+    let base = Url::parse(BASE_URL).expect("invalid base URL");
+    let mut urls = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        // joining keeps absolute URLs as is
+        let url = base
+            .join(line)
+            .map_err(|e| Error::Custom(format!("invalid shard URL {line:?}: {e}")))?;
+        urls.push(url);
+    }
+
+    Ok(urls)
+}
+
+/// Extension of the shard at `url`, if it's a supported one (see [Wet::EXTENSIONS]).
+pub fn shard_extension(url: &Url) -> Option<&str> {
+    let (_, extension) = url.path_segments()?.next_back()?.rsplit_once('.')?;
+    Wet::EXTENSIONS.contains(&extension).then_some(extension)
+}
+
+/// Shard downloader, retrying failed downloads.
+pub struct ShardDownloader {
+    client: Client,
+    retry: Retry,
+}
+
+impl ShardDownloader {
+    /// Create a downloader.
+    ///
+    /// Since the underlying client is blocking, this must not be called from an async context.
+    pub fn new(retry: Retry) -> Result<Self, Error> {
+        let client = Client::builder()
+            .build()
+            .map_err(|e| Error::Custom(format!("could not build HTTP client: {e}")))?;
+        Ok(Self { client, retry })
+    }
+
+    /// Download the shard at `url` to `dst`.
+    ///
+    /// The shard is downloaded to a `.part` file next to `dst`, which is renamed once complete:
+    /// `dst` never holds a partial shard. Partial files of failed attempts are removed.
+    pub fn download(&self, url: &Url, dst: &Path) -> Result<(), Error> {
+        let mut part = OsString::from(dst.as_os_str());
+        part.push(".part");
+        let part = PathBuf::from(part);
+
+        debug!("downloading {} to {:?}", url, dst);
+        self.retry.run(url.as_str(), || {
+            let result = self.fetch(url, &part);
+            if result.is_err() {
+                // the file may not have been created
+                let _ = std::fs::remove_file(&part);
+            }
+            result
+        })?;
+        std::fs::rename(&part, dst)?;
+
+        Ok(())
+    }
+
+    /// Fetch `url` into `dst`, mapping HTTP errors to IO ones so that [Retry] tells transient ones apart.
+    fn fetch(&self, url: &Url, dst: &Path) -> io::Result<()> {
+        let to_io = io::Error::other;
+        let mut response = self.client.get(url.clone()).send().map_err(to_io)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let kind = match status {
+                StatusCode::NOT_FOUND | StatusCode::GONE => ErrorKind::NotFound,
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ErrorKind::PermissionDenied,
+                StatusCode::TOO_MANY_REQUESTS => ErrorKind::Other,
+                status if status.is_client_error() => ErrorKind::InvalidInput,
+                _ => ErrorKind::Other,
+            };
+            return Err(io::Error::new(kind, format!("HTTP {status}")));
+        }
+
+        let mut file = File::create(dst)?;
+        response.copy_to(&mut file).map_err(to_io)?;
+        file.sync_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use reqwest::Url;
+
+    use super::{read_shard_urls, shard_extension};
+
+    #[test]
+    fn test_read_shard_urls() {
+        let mut list = tempfile::NamedTempFile::new().unwrap();
+        writeln!(
+            list,
+            "crawl-data/CC-MAIN-2023-06/segments/0/wet/0.warc.wet.gz\n\nhttp://localhost:8000/1.txt.zst"
+        )
+        .unwrap();
+
+        let urls: Vec<String> = read_shard_urls(list.path())
+            .unwrap()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "https://data.commoncrawl.org/crawl-data/CC-MAIN-2023-06/segments/0/wet/0.warc.wet.gz",
+                "http://localhost:8000/1.txt.zst"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shard_extension() {
+        let ext = |url: &str| shard_extension(&Url::parse(url).unwrap()).map(String::from);
+        assert_eq!(
+            ext("http://localhost/0.warc.wet.gz"),
+            Some("gz".to_string())
+        );
+        assert_eq!(
+            ext("http://localhost/0.txt.zst?x=1"),
+            Some("zst".to_string())
+        );
+        assert_eq!(ext("http://localhost/0.txt"), None);
+    }
+}
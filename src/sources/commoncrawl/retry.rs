@@ -0,0 +1,162 @@
+//! Retrying transient IO errors.
+//!
+//! Shards are often stored on network filesystems, where opening or reading a file occasionally fails
+//! for reasons that have nothing to do with the file itself.
+//! [Retry] retries such operations a number of times, doubling the delay between attempts,
+//! and [RetryReader] does the same for each read of a reader.
+//!
+//! Readers are wrapped *under* decoders, so that decoding errors (e.g. a corrupt gzip stream)
+//! are never retried: only errors of the underlying file are.
+use std::{
+    io::{self, ErrorKind, Read},
+    thread,
+    time::Duration,
+};
+
+use log::warn;
+
+/// Retry policy: number of retries after a failed attempt, and delay before the first retry.
+///
+/// The delay is doubled after each retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retry {
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Retry {
+    pub fn new(retries: u32, backoff: Duration) -> Self {
+        Self { retries, backoff }
+    }
+
+    /// Never retry.
+    pub fn none() -> Self {
+        Self::new(0, Duration::ZERO)
+    }
+
+    /// Is the error worth retrying?
+    ///
+    /// Errors about the data or the path itself won't go away by trying again.
+    fn is_transient(e: &io::Error) -> bool {
+        !matches!(
+            e.kind(),
+            ErrorKind::InvalidData
+                | ErrorKind::InvalidInput
+                | ErrorKind::UnexpectedEof
+                | ErrorKind::NotFound
+                | ErrorKind::PermissionDenied
+        )
+    }
+
+    /// Run `op`, retrying on transient errors. `what` is used in warnings.
+    pub fn run<T>(&self, what: &str, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match op() {
+                Err(e) if attempt < self.retries && Self::is_transient(&e) => {
+                    attempt += 1;
+                    warn!(
+                        "{what}: {e} (retry {attempt}/{} in {:?})",
+                        self.retries, backoff
+                    );
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+/// Reader retrying each failed read according to a [Retry] policy.
+pub struct RetryReader<R> {
+    inner: R,
+    retry: Retry,
+    what: String,
+}
+
+impl<R: Read> RetryReader<R> {
+    /// Wrap `inner`. `what` (e.g. a file path) is used in warnings.
+    pub fn new(inner: R, retry: Retry, what: String) -> Self {
+        Self { inner, retry, what }
+    }
+}
+
+impl<R: Read> Read for RetryReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let inner = &mut self.inner;
+        self.retry.run(&self.what, || inner.read(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, ErrorKind, Read};
+    use std::time::Duration;
+
+    use super::{Retry, RetryReader};
+
+    /// Fails `failures` times with `kind`, then reads `content`.
+    struct FlakyReader {
+        failures: usize,
+        kind: ErrorKind,
+        content: io::Cursor<Vec<u8>>,
+    }
+
+    impl Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(io::Error::new(self.kind, "flaky"));
+            }
+            self.content.read(buf)
+        }
+    }
+
+    fn flaky(failures: usize, kind: ErrorKind) -> FlakyReader {
+        FlakyReader {
+            failures,
+            kind,
+            content: io::Cursor::new(b"content".to_vec()),
+        }
+    }
+
+    #[test]
+    fn test_retry() {
+        let retry = Retry::new(3, Duration::from_millis(1));
+        let mut r = RetryReader::new(flaky(2, ErrorKind::TimedOut), retry, "test".to_string());
+        let mut content = String::new();
+        r.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "content");
+
+        // too many failures
+        let retry = Retry::new(1, Duration::from_millis(1));
+        let mut r = RetryReader::new(flaky(2, ErrorKind::TimedOut), retry, "test".to_string());
+        assert!(r.read_to_string(&mut String::new()).is_err());
+
+        // no retry by default
+        let mut r = RetryReader::new(
+            flaky(1, ErrorKind::TimedOut),
+            Retry::none(),
+            "test".to_string(),
+        );
+        assert!(r.read_to_string(&mut String::new()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let retry = Retry::new(3, Duration::from_millis(1));
+        let mut r = RetryReader::new(flaky(1, ErrorKind::InvalidData), retry, "test".to_string());
+        assert_eq!(
+            r.read_to_string(&mut String::new()).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+}
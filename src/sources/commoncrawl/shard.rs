@@ -1,15 +1,45 @@
 //! Shard/WET utils.
 //!
 //! Mainly exists to wrap warc's library [warc::WarcReader] and an efficient gzip library.
+//! zstd-compressed shards are also supported.
 //!
 //! [wet::Wet] implements [Iterator] over contained [warc::RawRecord].
-use std::{fs::File, io::BufReader, path::Path};
+//!
+//! # Parallel iteration
+//! Decompression and parsing are inherently sequential, so records can't be split before they're read.
+//! Rather than bridging the record iterator (see [rayon::iter::ParallelBridge]),
+//! which takes a lock for every record, [Wet] implements [IntoParallelIterator] (see [ParRecords]):
+//! records are read by chunks, each chunk being a regular parallel iterator that rayon splits efficiently.
+//! The lock is taken once per chunk instead of once per record, which lowers contention
+//! when records are cheap to process compared to parsing them (e.g. when most of them are filtered out early),
+//! at the cost of holding a chunk of records in memory per thread.
+//!
+//! A splittable producer (see [rayon::iter::plumbing::Producer]) isn't possible over records,
+//! since their boundaries are only known once they're read.
+//! Nor is one needed over shards: the oscardoc pipeline hands shards out to its own worker threads,
+//! which keep deterministic runs ordered, wait for downloads and bound pending shards.
+//!
+//! `cargo bench --bench shard_records` compares both ways on an in-memory shard of 10k records, with 4 rayon threads.
+//! On a single core, they're on par (32ms) when records are dropped right away,
+//! and chunks are about 10% faster (139ms against 156ms) when records are hashed line by line:
+//! workers spend less time waiting for the next record.
+//! Gains from lower contention are expected to grow with the number of cores, but haven't been measured.
+use std::{fs::File, io::BufReader, iter::Enumerate, path::Path};
 
+use super::retry::{Retry, RetryReader};
 use crate::error::Error;
 use flate2::read::MultiGzDecoder;
+use rayon::iter::plumbing::UnindexedConsumer;
+use rayon::prelude::*;
 use std::io::BufRead;
-use warc::RecordIter;
 use warc::WarcReader;
+use warc::{BufferedBody, Record, RecordIter};
+
+/// Number of records read at once by [ParRecords].
+const CHUNK_SIZE: usize = 64;
+
+/// A record, along with its index in the shard.
+pub type IndexedRecord = (usize, Result<Record<BufferedBody>, warc::Error>);
 
 /// Wet/Shard instance, generic over reader type.
 ///
@@ -47,6 +77,59 @@ impl Wet<BufReader<MultiGzDecoder<File>>> {
     }
 }
 
+/// Wet reader using a zstd decoder over a [File].
+impl Wet<BufReader<zstd::Decoder<'static, BufReader<File>>>> {
+    /// Create a new reader from a zstd-compressed WET file.
+    pub fn from_path_zstd<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let zstd_file = File::open(path)?;
+        let zstd_stream = zstd::Decoder::new(zstd_file)?;
+        let bufreader = BufReader::new(zstd_stream);
+
+        let reader = WarcReader::new(bufreader);
+        Ok(Self {
+            iter: reader.iter_records(),
+        })
+    }
+}
+
+/// Wet reader over a gzip or zstd-compressed [File].
+impl Wet<Box<dyn BufRead + Send>> {
+    /// Shard file extensions supported by [Wet::from_path].
+    pub const EXTENSIONS: [&'static str; 2] = ["gz", "zst"];
+
+    /// Create a new reader from a compressed WET file,
+    /// picking the decoder from the file extension (`.gz` or `.zst`).
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_path_with_retry(path, Retry::none())
+    }
+
+    /// Same as [Wet::from_path], retrying to open and read the file on transient IO errors (see [Retry]).
+    pub fn from_path_with_retry<P: AsRef<Path>>(path: P, retry: Retry) -> Result<Self, Error> {
+        Ok(Self::new(open_compressed(path.as_ref(), retry)?))
+    }
+}
+
+/// Open a compressed shard, picking the decoder from the file extension (see [Wet::EXTENSIONS]).
+///
+/// Opening and reading the file are retried on transient IO errors, decoding errors are not.
+pub(super) fn open_compressed(path: &Path, retry: Retry) -> Result<Box<dyn BufRead + Send>, Error> {
+    let what = format!("{path:?}");
+    let file = retry.run(&what, || File::open(path))?;
+    let file = RetryReader::new(file, retry, what);
+    let reader: Box<dyn BufRead + Send> = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gz") => Box::new(BufReader::new(MultiGzDecoder::new(file))),
+        Some("zst") => Box::new(BufReader::new(zstd::Decoder::new(file)?)),
+        _ => {
+            return Err(Error::Custom(format!(
+                "unsupported shard extension: {:?} (expected one of {:?})",
+                path,
+                Wet::EXTENSIONS
+            )))
+        }
+    };
+    Ok(reader)
+}
+
 #[allow(dead_code)]
 impl<T: BufRead> Wet<T> {
     pub fn new(reader: T) -> Self {
@@ -56,16 +139,76 @@ impl<T: BufRead> Wet<T> {
     }
 }
 
+/// Parallel iterator over the records of a [Wet] (or of any record iterator) and their index,
+/// reading records by chunks.
+///
+/// Records are not yielded in order.
+pub struct ParRecords<I> {
+    iter: I,
+}
+
+impl<I: Iterator> ParRecords<Enumerate<I>> {
+    /// Iterate over the records of `iter` in parallel.
+    pub fn new(iter: I) -> Self {
+        Self {
+            iter: iter.enumerate(),
+        }
+    }
+}
+
+impl<I: Iterator<Item = IndexedRecord>> ParRecords<I> {
+    /// Iterate over records that are already indexed (e.g. by a pre-processing step that drops or merges records).
+    pub fn indexed(iter: I) -> Self {
+        Self { iter }
+    }
+}
+
+impl<I> ParallelIterator for ParRecords<I>
+where
+    I: Iterator<Item = IndexedRecord> + Send,
+{
+    type Item = IndexedRecord;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        let mut iter = self.iter;
+        let chunks = std::iter::from_fn(move || {
+            let chunk: Vec<_> = iter.by_ref().take(CHUNK_SIZE).collect();
+            (!chunk.is_empty()).then_some(chunk)
+        });
+
+        chunks
+            .par_bridge()
+            .flat_map(Vec::into_par_iter)
+            .drive_unindexed(consumer)
+    }
+}
+
+impl<T: BufRead + Send> IntoParallelIterator for Wet<T> {
+    type Iter = ParRecords<Enumerate<RecordIter<T>>>;
+    type Item = IndexedRecord;
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParRecords::new(self.iter)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
+    use rayon::prelude::*;
     use serde_json;
     use std::collections::HashMap;
-    use warc::WarcHeader;
+    use std::io::Cursor;
+    use warc::{BufferedBody, Record, WarcHeader, WarcWriter};
 
-    use super::Wet;
+    use super::{Wet, CHUNK_SIZE};
 
     const SHARD_PATH: &str = "res/shards/0.txt.gz";
+    const TINY_GZIP_PATH: &str = "tests/res/tiny.wet.gz";
+    const TINY_ZSTD_PATH: &str = "tests/res/tiny.wet.zst";
     const SHARD_DIR: &str = "res/shards/";
     #[test]
     fn test_folder() {
@@ -81,6 +224,69 @@ mod tests {
         assert!(w.is_ok());
     }
 
+    #[test]
+    fn test_zstd_same_as_gzip() {
+        let gzip: Vec<_> = Wet::from_path_gzip(TINY_GZIP_PATH)
+            .unwrap()
+            .iter
+            .map(|r| r.unwrap().into_raw_parts())
+            .collect();
+        let zstd: Vec<_> = Wet::from_path_zstd(TINY_ZSTD_PATH)
+            .unwrap()
+            .iter
+            .map(|r| r.unwrap().into_raw_parts())
+            .collect();
+
+        assert_eq!(gzip.len(), 2);
+        assert_eq!(gzip, zstd);
+    }
+
+    #[test]
+    fn test_from_path() {
+        for path in [TINY_GZIP_PATH, TINY_ZSTD_PATH] {
+            let records: Vec<_> = Wet::from_path(path)
+                .unwrap()
+                .iter
+                .map(|r| r.unwrap())
+                .collect();
+            assert_eq!(records.len(), 2);
+            assert_eq!(
+                records[1].header(WarcHeader::TargetURI).as_deref(),
+                Some("http://example.fr/")
+            );
+        }
+    }
+
+    #[test]
+    fn test_par_iter_same_as_iter() {
+        // span several chunks, the last one being partial
+        let mut raw = Vec::new();
+        let mut writer = WarcWriter::new(&mut raw);
+        for i in 0..CHUNK_SIZE * 3 + 5 {
+            let record: Record<BufferedBody> = Record::default().add_body(format!("record {i}"));
+            writer.write(&record).unwrap();
+        }
+
+        let sequential: Vec<_> = Wet::new(Cursor::new(raw.clone()))
+            .iter
+            .enumerate()
+            .map(|(idx, r)| (idx, r.unwrap().into_raw_parts()))
+            .collect();
+        let mut parallel: Vec<_> = Wet::new(Cursor::new(raw))
+            .into_par_iter()
+            .map(|(idx, r)| (idx, r.unwrap().into_raw_parts()))
+            .collect();
+        parallel.sort_by_key(|(idx, _)| *idx);
+
+        assert_eq!(sequential.len(), CHUNK_SIZE * 3 + 5);
+        assert_eq!(parallel, sequential);
+    }
+
+    #[test]
+    fn test_from_path_unsupported() {
+        assert!(Wet::from_path("tests/res/garbage.txt").is_err());
+    }
+
     #[test]
     #[ignore]
     fn test_metadata() {
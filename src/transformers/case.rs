@@ -0,0 +1,116 @@
+//! Case normalization.
+//!
+//! Some downstream uses (e.g. training uncased models) want corpora without case distinctions.
+//! Since language identification can be case-sensitive, this must run once documents are identified.
+use std::{ops::RangeInclusive, str::FromStr};
+
+use crate::pipelines::oscardoc::types::Document;
+
+use super::Transform;
+
+/// How case is normalized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaseMode {
+    /// Unicode lowercasing (see [str::to_lowercase]), e.g. `ß` is kept and final sigmas are `ς`.
+    #[default]
+    Lower,
+    /// Unicode case folding, meant for caseless matching: `ß` becomes `ss`, every sigma becomes `σ`
+    /// and ligatures such as `ﬁ` are expanded.
+    Fold,
+}
+
+impl FromStr for CaseMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lower" => Ok(Self::Lower),
+            "fold" => Ok(Self::Fold),
+            other => Err(format!(
+                "unknown case mode {other:?} (expected lower or fold)"
+            )),
+        }
+    }
+}
+
+/// Lowercases or case-folds document content.
+///
+/// Case mappings can change the length of text (`ß` folds to `ss`),
+/// but never add nor remove line breaks, so lines (and sentence identifications) are kept as is.
+#[derive(Debug, Default)]
+pub struct CaseNormalizer {
+    mode: CaseMode,
+}
+
+impl CaseNormalizer {
+    pub fn new(mode: CaseMode) -> Self {
+        Self { mode }
+    }
+
+    /// Normalize the case of `text`.
+    ///
+    /// Folding uppercases then lowercases each character, without context,
+    /// which gives the full case folding of nearly every character.
+    pub fn normalize(&self, text: &str) -> String {
+        match self.mode {
+            CaseMode::Lower => text.to_lowercase(),
+            CaseMode::Fold => text
+                .chars()
+                .flat_map(char::to_uppercase)
+                .flat_map(char::to_lowercase)
+                .collect(),
+        }
+    }
+}
+
+impl Transform<Document> for CaseNormalizer {
+    /// Every line is kept: returns the range of all lines.
+    fn transform(&self, doc: &mut Document) -> Vec<RangeInclusive<usize>> {
+        let content = self.normalize(doc.content());
+        if &content != doc.content() {
+            doc.set_content(content);
+        }
+
+        match doc.content().lines().count() {
+            0 => Vec::new(),
+            nb_lines => vec![0..=nb_lines - 1],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+    use crate::transformers::Transform;
+
+    use super::{CaseMode, CaseNormalizer};
+
+    #[test]
+    fn test_fold() {
+        let content = "Die Straße\nΟΔΟΣ ὈΔΌΣ\n\nﬁne FINE";
+        let mut doc = Document::new(content.to_string(), HashMap::new(), Metadata::default());
+
+        assert_eq!(
+            CaseNormalizer::new(CaseMode::Fold).transform(&mut doc),
+            vec![0..=3]
+        );
+        assert_eq!(doc.content(), "die strasse\nοδοσ ὀδόσ\n\nfine fine");
+        assert_eq!(doc.content().lines().count(), content.lines().count());
+    }
+
+    #[test]
+    fn test_lower() {
+        let normalizer = CaseNormalizer::new(CaseMode::Lower);
+        assert_eq!(normalizer.normalize("Die Straße"), "die straße");
+        // final sigma
+        assert_eq!(normalizer.normalize("ΟΔΟΣ"), "οδος");
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("fold".parse(), Ok(CaseMode::Fold));
+        assert!("upper".parse::<CaseMode>().is_err());
+    }
+}
@@ -3,25 +3,153 @@
 Adds an `annotation` tag in [Document] depending on possibly harmful/specific content in document.
 
 Currently the approach is to use the [UT1 blocklist](https://dsi.ut-capitole.fr/blacklists/) and to annotate flagged URLs.
+
+Detection can be restricted to some [Category]s (see [ContentDetector::from_categories]),
+in which case categories can also have a `keywords` file (one keyword per line) in their folder.
+Keywords are matched against URLs on word boundaries: `sex` matches `example.com/free-sex` but not `example.com/sussex`.
  * !*/
 
+use std::{
+    collections::{BTreeSet, HashSet},
+    convert::Infallible,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+    str::FromStr,
+};
+
 use log::info;
 use ut1_blocklist::MultipleBlocklist as Blocklist;
 
+use crate::error::Error;
+
 // use crate::pipelines::oscardoc::types::Document;
 use oscar_io::v3::Document;
 
 use super::Annotate;
 
+/// Blocklist category (i.e. the name of its folder).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Category {
+    Adult,
+    Phishing,
+    Malware,
+    /// any other UT1 category
+    Other(String),
+}
+
+impl Category {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Adult => "adult",
+            Self::Phishing => "phishing",
+            Self::Malware => "malware",
+            Self::Other(category) => category,
+        }
+    }
+}
+
+impl FromStr for Category {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "adult" => Self::Adult,
+            "phishing" => Self::Phishing,
+            "malware" => Self::Malware,
+            other => Self::Other(other.to_string()),
+        })
+    }
+}
+
+/// Split a string into lowercase alphanumeric words.
+fn words(s: &str) -> Vec<String> {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
 pub struct ContentDetector {
     bl: Blocklist,
+    /// active categories, every category if [None]
+    categories: Option<HashSet<String>>,
+    /// keywords (as words) and their category
+    keywords: Vec<(Vec<String>, String)>,
 }
 
 impl ContentDetector {
     /// Create a new [ContentDetector] based on a specified [Blocklist].
     pub fn new(bl: Blocklist) -> Self {
         info!("Creating a new ContentDetector");
-        Self { bl }
+        Self {
+            bl,
+            categories: None,
+            keywords: Vec::new(),
+        }
+    }
+
+    /// Create a new [ContentDetector] from a UT1-style blocklist folder
+    /// (one subfolder by category, each having `domains` and/or `urls` files).
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let bl = Blocklist::from_dir(path)?;
+        Ok(Self::new(bl))
+    }
+
+    /// Create a new [ContentDetector] from a UT1-style blocklist folder, only using the provided `categories`.
+    ///
+    /// Keywords are read from the `keywords` file of each category folder, if any.
+    pub fn from_categories(path: &Path, categories: Vec<Category>) -> Result<Self, Error> {
+        let mut cd = Self::from_path(path)?;
+        for category in &categories {
+            let keywords_path = path.join(category.as_str()).join("keywords");
+            if !keywords_path.exists() {
+                continue;
+            }
+            for keyword in BufReader::new(File::open(keywords_path)?).lines() {
+                let keyword = words(&keyword?);
+                if !keyword.is_empty() {
+                    cd.keywords.push((keyword, category.as_str().to_string()));
+                }
+            }
+        }
+        cd.categories = Some(
+            categories
+                .iter()
+                .map(|category| category.as_str().to_string())
+                .collect(),
+        );
+        Ok(cd)
+    }
+
+    /// Get the active categories of `url`.
+    fn detect(&self, url: &str) -> BTreeSet<String> {
+        let mut detected: BTreeSet<String> = self
+            .bl
+            .detect(url)
+            .into_iter()
+            .flatten()
+            .filter(|category| {
+                self.categories
+                    .as_ref()
+                    .is_none_or(|categories| categories.contains(*category))
+            })
+            .cloned()
+            .collect();
+
+        if !self.keywords.is_empty() {
+            let url_words = words(url);
+            for (keyword, category) in &self.keywords {
+                if url_words
+                    .windows(keyword.len())
+                    .any(|window| window == keyword.as_slice())
+                {
+                    detected.insert(category.clone());
+                }
+            }
+        }
+
+        detected
     }
 }
 
@@ -30,10 +158,8 @@ impl Annotate<Document> for ContentDetector {
     /// corresponding to blocklist kind if true.
     fn annotate(&self, doc: &mut Document) {
         if let Some(url) = doc.url() {
-            let categories: Option<Vec<String>> = self
-                .bl
-                .detect(&url)
-                .map(|categories| categories.into_iter().map(String::from).collect());
+            let categories = self.detect(&url);
+            let categories = (!categories.is_empty()).then(|| categories.into_iter().collect());
             doc.metadata_mut().set_categories(categories);
         }
     }
@@ -46,6 +172,8 @@ mod tests {
         path::Path,
     };
 
+    use tempfile::tempdir;
+
     use ut1_blocklist::MultipleBlocklist as Blocklist;
     use warc::WarcHeader;
 
@@ -54,7 +182,7 @@ mod tests {
         transformers::Annotate,
     };
 
-    use super::ContentDetector;
+    use super::{Category, ContentDetector};
 
     fn gen_document(url: &str) -> Document {
         let content = String::new();
@@ -97,4 +225,84 @@ mod tests {
 
         assert!(doc.metadata().annotation().is_none());
     }
+
+    #[test]
+    fn test_from_path() {
+        let bl_dir = tempdir().unwrap();
+        let adult_dir = bl_dir.path().join("adult");
+        std::fs::create_dir(&adult_dir).unwrap();
+        std::fs::write(adult_dir.join("domains"), "foo.bar\n").unwrap();
+
+        let cd = ContentDetector::from_path(bl_dir.path()).unwrap();
+
+        let mut doc = gen_document("https://foo.bar");
+        cd.annotate(&mut doc);
+        assert_eq!(
+            doc.metadata().categories(),
+            Some(vec!["adult".to_string()]).as_ref()
+        );
+
+        let mut doc = gen_document("https://baz.quux");
+        cd.annotate(&mut doc);
+        assert!(doc.metadata().categories().is_none());
+    }
+
+    /// Write a UT1-style blocklist with adult and phishing domains, and adult keywords.
+    fn write_blocklist(dir: &Path) {
+        for (category, domain) in [("adult", "foo.bar"), ("phishing", "baz.quux")] {
+            let category_dir = dir.join(category);
+            std::fs::create_dir(&category_dir).unwrap();
+            std::fs::write(category_dir.join("domains"), format!("{domain}\n")).unwrap();
+        }
+        std::fs::write(dir.join("adult/keywords"), "sex\nhot stuff\n").unwrap();
+    }
+
+    #[test]
+    fn test_category_selection() {
+        let bl_dir = tempdir().unwrap();
+        write_blocklist(bl_dir.path());
+
+        let cd = ContentDetector::from_categories(bl_dir.path(), vec![Category::Phishing]).unwrap();
+
+        let mut doc = gen_document("https://baz.quux");
+        cd.annotate(&mut doc);
+        assert_eq!(
+            doc.metadata().categories(),
+            Some(vec!["phishing".to_string()]).as_ref()
+        );
+
+        // adult is not active
+        let mut doc = gen_document("https://foo.bar/sex");
+        cd.annotate(&mut doc);
+        assert!(doc.metadata().categories().is_none());
+    }
+
+    #[test]
+    fn test_keywords() {
+        let bl_dir = tempdir().unwrap();
+        write_blocklist(bl_dir.path());
+
+        let cd = ContentDetector::from_categories(
+            bl_dir.path(),
+            vec!["adult".parse().unwrap(), Category::Phishing],
+        )
+        .unwrap();
+        let categories = |url: &str| {
+            let mut doc = gen_document(url);
+            cd.annotate(&mut doc);
+            doc.metadata().categories().cloned()
+        };
+
+        let adult = Some(vec!["adult".to_string()]);
+        assert_eq!(categories("https://example.com/free-SEX-videos"), adult);
+        assert_eq!(categories("https://example.com/hot_stuff.html"), adult);
+        assert_eq!(
+            categories("https://baz.quux/sex"),
+            Some(vec!["adult".to_string(), "phishing".to_string()])
+        );
+
+        // keywords inside longer words don't match
+        assert_eq!(categories("https://example.com/sussex"), None);
+        assert_eq!(categories("https://sexton.example.com/hot"), None);
+    }
 }
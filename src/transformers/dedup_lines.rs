@@ -0,0 +1,189 @@
+//! Within-document line deduplication.
+//!
+//! Crawled pages often repeat a line (menu items, disclaimers...) many times.
+//! Removes repeated lines of a document, keeping their first occurrence.
+use std::{collections::HashSet, ops::RangeInclusive, str::FromStr};
+
+use itertools::Itertools;
+
+use crate::pipelines::oscardoc::types::Document;
+
+use super::{rebuild_metadata, Transform};
+
+/// Which repeated lines are removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DedupLinesMode {
+    /// Lines that are the same as the previous one.
+    #[default]
+    Consecutive,
+    /// Lines that already appeared anywhere before in the document.
+    All,
+}
+
+impl FromStr for DedupLinesMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "consecutive" => Ok(Self::Consecutive),
+            "all" => Ok(Self::All),
+            other => Err(format!(
+                "unknown line deduplication mode {other:?} (expected consecutive or all)"
+            )),
+        }
+    }
+}
+
+/// Removes repeated lines, along with their sentence identifications.
+///
+/// Lines are compared without their surrounding whitespace, and blank lines are never removed
+/// since they separate paragraphs. First occurrences are kept, in order.
+///
+/// Note that rebuild locations are not updated: rebuilt documents still have every line.
+#[derive(Debug, Default)]
+pub struct DedupLines {
+    mode: DedupLinesMode,
+}
+
+impl DedupLines {
+    pub fn new(mode: DedupLinesMode) -> Self {
+        Self { mode }
+    }
+}
+
+impl Transform<Document> for DedupLines {
+    /// Returns the ranges of the kept lines, in the original document.
+    fn transform(&self, doc: &mut Document) -> Vec<RangeInclusive<usize>> {
+        let lines: Vec<&str> = doc.content().lines().collect();
+        if lines.is_empty() {
+            return Vec::new();
+        }
+
+        let mut seen = HashSet::new();
+        let mut previous = None;
+        let kept: Vec<usize> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| {
+                let line = line.trim();
+                if line.is_empty() {
+                    previous = None;
+                    return true;
+                }
+                match self.mode {
+                    DedupLinesMode::Consecutive => previous.replace(line) != Some(line),
+                    DedupLinesMode::All => seen.insert(line),
+                }
+            })
+            .map(|(idx, _)| idx)
+            .collect();
+
+        if kept.len() == lines.len() {
+            return vec![0..=lines.len() - 1];
+        }
+
+        // group consecutive kept lines
+        let ranges: Vec<RangeInclusive<usize>> = kept
+            .iter()
+            .enumerate()
+            .group_by(|(pos, idx)| *idx - pos)
+            .into_iter()
+            .map(|(_, mut group)| {
+                let (_, start) = group.next().unwrap();
+                let end = group.last().map_or(start, |(_, end)| end);
+                *start..=*end
+            })
+            .collect();
+
+        // documents identified as a whole have no sentence identifications
+        let ids = doc.metadata().sentence_identifications();
+        let kept_ids: Vec<_> = if ids.is_empty() {
+            Vec::new()
+        } else {
+            kept.iter()
+                .map(|idx| ids.get(*idx).cloned().flatten())
+                .collect()
+        };
+        let content = kept.iter().map(|idx| lines[*idx]).join("\n");
+        let metadata = rebuild_metadata(doc.identification(), doc.metadata(), &kept_ids);
+
+        doc.set_content(content);
+        *doc.metadata_mut() = metadata;
+
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oscar_io::common::Identification;
+    use oxilangtag::LanguageTag;
+
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+    use crate::transformers::Transform;
+
+    use super::{DedupLines, DedupLinesMode};
+
+    fn gen_doc(content: &str) -> Document {
+        let id = Identification::new(LanguageTag::parse("fr".to_string()).unwrap(), 1.0);
+        let ids: Vec<_> = content
+            .lines()
+            .enumerate()
+            .map(|(i, _)| Some(Identification::new(id.label().clone(), i as f32 / 10.0)))
+            .collect();
+        let mut metadata = Metadata::new(&id, &ids);
+        metadata.add_annotation("tiny".to_string());
+        Document::new(content.to_string(), HashMap::new(), metadata)
+    }
+
+    fn probs(doc: &Document) -> Vec<f32> {
+        doc.metadata()
+            .sentence_identifications()
+            .iter()
+            .map(|id| *id.as_ref().unwrap().prob())
+            .collect()
+    }
+
+    const CONTENT: &str = "Accueil\nAccueil\nUne phrase.\n\nAccueil\nUne autre phrase.\nAccueil  ";
+
+    #[test]
+    fn test_consecutive() {
+        let mut doc = gen_doc(CONTENT);
+        let ranges = DedupLines::new(DedupLinesMode::Consecutive).transform(&mut doc);
+
+        assert_eq!(ranges, vec![0..=0, 2..=6]);
+        assert_eq!(
+            doc.content(),
+            "Accueil\nUne phrase.\n\nAccueil\nUne autre phrase.\nAccueil  "
+        );
+        assert_eq!(probs(&doc), vec![0.0, 0.2, 0.3, 0.4, 0.5, 0.6]);
+        assert_eq!(doc.metadata().annotation(), Some(&vec!["tiny".to_string()]));
+    }
+
+    #[test]
+    fn test_all() {
+        let mut doc = gen_doc(CONTENT);
+        let ranges = DedupLines::new(DedupLinesMode::All).transform(&mut doc);
+
+        assert_eq!(ranges, vec![0..=0, 2..=3, 5..=5]);
+        assert_eq!(doc.content(), "Accueil\nUne phrase.\n\nUne autre phrase.");
+        assert_eq!(probs(&doc), vec![0.0, 0.2, 0.3, 0.5]);
+        assert_eq!(
+            doc.metadata().sentence_identifications().len(),
+            doc.content().lines().count()
+        );
+    }
+
+    #[test]
+    fn test_unchanged() {
+        let mut doc = gen_doc("Une phrase.\nUne autre phrase.");
+        let expected = doc.clone();
+        assert_eq!(
+            DedupLines::new(DedupLinesMode::All).transform(&mut doc),
+            vec![0..=1]
+        );
+        assert!(doc == expected);
+    }
+}
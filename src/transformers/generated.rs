@@ -0,0 +1,130 @@
+/*! Annotates likely generated text
+
+Machine-generated and machine-translated pages (spun articles, templated SEO content...) tend to repeat
+the same phrases over and over, much more than human-written text does.
+
+The score of a document is the share of its word n-grams that repeat a previous one, between 0 and 1.
+It only relies on word boundaries (see [UnicodeSegmentation::unicode_words]) rather than on a language model,
+so that it behaves the same for every language: low-resource ones are not penalized for lacking a model,
+and scripts that don't use spaces are split per character.
+!*/
+use std::collections::HashSet;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::Annotate;
+use crate::pipelines::oscardoc::types::{Document, GeneratedMetadata};
+
+/// Annotation of documents whose score reaches the threshold.
+const GENERATED_ANNOTATION: &str = "generated";
+
+/// Scores documents on repeated word n-grams, storing the score in their metadata
+/// (see [GeneratedMetadata]) and annotating them as `generated` above a threshold.
+///
+/// Documents are never dropped: filtering is left to downstream users.
+pub struct GeneratedText {
+    ngram_size: usize,
+    threshold: f32,
+    min_words: usize,
+}
+
+impl Default for GeneratedText {
+    fn default() -> Self {
+        Self {
+            ngram_size: 4,
+            threshold: 0.5,
+            min_words: 20,
+        }
+    }
+}
+
+impl GeneratedText {
+    /// Annotate documents whose score is at least `threshold` (between 0 and 1).
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Share of word n-grams of `text` that repeat a previous one.
+    ///
+    /// Texts with too few words to tell are scored 0.
+    pub fn score(&self, text: &str) -> f32 {
+        let words: Vec<String> = text.unicode_words().map(str::to_lowercase).collect();
+        if words.len() < self.min_words.max(self.ngram_size) {
+            return 0.0;
+        }
+
+        let mut seen = HashSet::new();
+        let ngrams = words.windows(self.ngram_size);
+        let nb_ngrams = ngrams.len();
+        let nb_repeated = ngrams.filter(|ngram| !seen.insert(*ngram)).count();
+        nb_repeated as f32 / nb_ngrams as f32
+    }
+}
+
+impl Annotate<Document> for GeneratedText {
+    fn annotate(&self, doc: &mut Document) {
+        let score = self.score(doc.content());
+        doc.metadata_mut().set_generated_score(score);
+        if score >= self.threshold {
+            doc.metadata_mut()
+                .add_annotation(GENERATED_ANNOTATION.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::pipelines::oscardoc::types::{Document, GeneratedMetadata, Metadata};
+    use crate::transformers::Annotate;
+
+    use super::GeneratedText;
+
+    const NATURAL: &str = "The village lies at the foot of the hills, where the river slows down before reaching the sea. \
+        Most of its inhabitants used to work in the slate quarries, which closed in the sixties. \
+        Nowadays, tourists come in summer to walk along the old railway line, \
+        and a small museum tells the story of the quarrymen and their families.";
+
+    // Breton, which has far less data than English
+    const LOW_RESOURCE: &str = "Ar vro-mañ a zo anavezet evit he aodoù garv hag he enezennoù bihan. \
+        E-pad ar c'hantved diwezhañ e oa bet kuitaet ar maez gant kalz a dud yaouank o vont da labourat e kêr. \
+        Hiziv an deiz e teu en-dro lod anezho, gant c'hoant da gendalc'her gant al labour-douar \
+        ha da zeskiñ ar yezh d'o bugale er skolioù divyezhek.";
+
+    #[test]
+    fn test_score() {
+        let annotator = GeneratedText::default();
+        let repetitive = "Buy the best cheap shoes online today. ".repeat(20);
+
+        assert!(annotator.score(&repetitive) > 0.8);
+        assert!(annotator.score(NATURAL) < 0.1);
+        assert!(annotator.score(LOW_RESOURCE) < 0.1);
+        // too short to tell
+        assert_eq!(annotator.score("buy buy buy buy buy"), 0.0);
+    }
+
+    #[test]
+    fn test_annotate() {
+        let annotator = GeneratedText::default();
+        let content = "Buy the best cheap shoes online today.\n".repeat(20);
+        let mut doc = Document::new(content, HashMap::new(), Metadata::default());
+        annotator.annotate(&mut doc);
+        assert!(doc.metadata().generated_score().unwrap() > 0.8);
+        assert!(doc
+            .metadata()
+            .annotation()
+            .unwrap()
+            .contains(&"generated".to_string()));
+
+        let mut doc = Document::new(NATURAL.to_string(), HashMap::new(), Metadata::default());
+        annotator.annotate(&mut doc);
+        assert!(doc.metadata().generated_score().unwrap() < 0.1);
+        assert!(!doc
+            .metadata()
+            .annotation()
+            .unwrap()
+            .contains(&"generated".to_string()));
+    }
+}
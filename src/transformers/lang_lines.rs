@@ -0,0 +1,200 @@
+//! Line-level language filtering.
+//!
+//! Removes the lines of a document that are not identified in the document language.
+use std::ops::RangeInclusive;
+
+use itertools::Itertools;
+use log::debug;
+
+use crate::{
+    filtering::{sentence::Length, Filter},
+    pipelines::oscardoc::types::Document,
+};
+
+use super::{rebuild_metadata, Transform};
+
+/// Keeps only the lines that are identified in the document language,
+/// along with their sentence identifications.
+///
+/// Lines with no identification are removed too.
+/// Multilingual documents are left untouched.
+///
+/// If no kept line is long enough (see [KeepDocumentLanguageLines::with_min_length]),
+/// no ranges are returned and the document should be discarded.
+///
+/// Note that rebuild locations are not updated: rebuilt documents still have every line.
+#[derive(Default)]
+pub struct KeepDocumentLanguageLines {
+    filter: Length,
+}
+
+impl KeepDocumentLanguageLines {
+    /// Use a custom min_length (in Unicode codepoints) for long sentences.
+    pub fn with_min_length(min_length: usize) -> Self {
+        Self {
+            filter: Length::with_min_size(min_length),
+        }
+    }
+}
+
+impl Transform<Document> for KeepDocumentLanguageLines {
+    /// Returns the ranges of the kept lines, in the original document.
+    fn transform(&self, doc: &mut Document) -> Vec<RangeInclusive<usize>> {
+        let label = doc.identification().label().clone();
+        let lines: Vec<&str> = doc.content().lines().collect();
+        let ids = doc.metadata().sentence_identifications();
+
+        // multilingual documents are labelled as such, there's no line to match.
+        if label.as_str() == "multi" || lines.is_empty() {
+            return vec![0..=lines.len().saturating_sub(1)];
+        }
+
+        let kept: Vec<usize> = ids
+            .iter()
+            .enumerate()
+            .filter(|(_, id)| id.as_ref().map(|id| id.label()) == Some(&label))
+            .map(|(idx, _)| idx)
+            .filter(|idx| *idx < lines.len())
+            .collect();
+
+        if !kept.iter().any(|idx| self.filter.detect(lines[*idx])) {
+            debug!("{}: no long enough line left", doc.warc_id());
+            return Vec::new();
+        }
+
+        if kept.len() == lines.len() {
+            return vec![0..=lines.len() - 1];
+        }
+
+        // group consecutive kept lines
+        let ranges: Vec<RangeInclusive<usize>> = kept
+            .iter()
+            .enumerate()
+            .group_by(|(pos, idx)| *idx - pos)
+            .into_iter()
+            .map(|(_, mut group)| {
+                let (_, start) = group.next().unwrap();
+                let end = group.last().map_or(start, |(_, end)| end);
+                *start..=*end
+            })
+            .collect();
+
+        let content = kept.iter().map(|idx| lines[*idx]).join("\n");
+        let kept_ids: Vec<_> = kept.iter().map(|idx| ids[*idx].clone()).collect();
+        let metadata = rebuild_metadata(doc.identification(), doc.metadata(), &kept_ids);
+
+        doc.set_content(content);
+        *doc.metadata_mut() = metadata;
+
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use oscar_io::common::Identification;
+    use oxilangtag::LanguageTag;
+
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+    use crate::transformers::Transform;
+
+    use super::KeepDocumentLanguageLines;
+
+    fn id(label: &str) -> Option<Identification<String>> {
+        Some(Identification::new(
+            LanguageTag::parse(label.to_string()).unwrap(),
+            1.0,
+        ))
+    }
+
+    fn gen_doc(lines: &[(&str, Option<Identification<String>>)], doc_label: &str) -> Document {
+        let content = lines
+            .iter()
+            .map(|(line, _)| *line)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let ids: Vec<_> = lines.iter().map(|(_, id)| id.clone()).collect();
+        let mut metadata = Metadata::new(&id(doc_label).unwrap(), &ids);
+        metadata.add_annotation("tiny".to_string());
+        Document::new(content, HashMap::new(), metadata)
+    }
+
+    #[test]
+    fn test_mixed() {
+        let mut doc = gen_doc(
+            &[
+                ("Ceci est une phrase en français.", id("fr")),
+                ("This is an English sentence.", id("en")),
+                ("Ceci est une autre phrase en français.", id("fr")),
+                ("Encore du français.", id("fr")),
+                ("???", None),
+            ],
+            "fr",
+        );
+
+        let t = KeepDocumentLanguageLines::with_min_length(10);
+        let ranges = t.transform(&mut doc);
+
+        assert_eq!(ranges, vec![0..=0, 2..=3]);
+        assert_eq!(
+            doc.content(),
+            "Ceci est une phrase en français.\nCeci est une autre phrase en français.\nEncore du français."
+        );
+        assert_eq!(doc.metadata().sentence_identifications().len(), 3);
+        assert!(doc.metadata().sentence_identifications().iter().all(|id| id
+            .as_ref()
+            .unwrap()
+            .label()
+            .as_str()
+            == "fr"));
+        assert_eq!(doc.identification().label().as_str(), "fr");
+        assert_eq!(doc.metadata().annotation(), Some(&vec!["tiny".to_string()]));
+    }
+
+    #[test]
+    fn test_unchanged() {
+        let mut doc = gen_doc(
+            &[
+                ("Ceci est une phrase en français.", id("fr")),
+                ("Encore du français.", id("fr")),
+            ],
+            "fr",
+        );
+        let expected = doc.clone();
+
+        let t = KeepDocumentLanguageLines::with_min_length(10);
+        assert_eq!(t.transform(&mut doc), vec![0..=1]);
+        assert!(doc == expected);
+    }
+
+    #[test]
+    fn test_too_short() {
+        let mut doc = gen_doc(
+            &[
+                ("Court.", id("fr")),
+                ("This is a long enough English sentence.", id("en")),
+            ],
+            "fr",
+        );
+
+        let t = KeepDocumentLanguageLines::with_min_length(10);
+        assert!(t.transform(&mut doc).is_empty());
+    }
+
+    #[test]
+    fn test_multilingual() {
+        let mut doc = gen_doc(
+            &[
+                ("Ceci est une phrase en français.", id("fr")),
+                ("This is an English sentence.", id("en")),
+            ],
+            "multi",
+        );
+
+        let t = KeepDocumentLanguageLines::with_min_length(10);
+        assert_eq!(t.transform(&mut doc), vec![0..=1]);
+        assert_eq!(doc.content().lines().count(), 2);
+    }
+}
@@ -8,32 +8,47 @@ Transformers can either (or both) [Annotate] content or [Transform] it:
 !*/
 
 mod annotate;
+mod case;
 mod content_detector;
+mod dedup_lines;
+mod generated;
 mod header;
+mod lang_lines;
 
 mod lsh;
 mod noisy;
+mod normalize;
+mod pii;
 
 #[cfg(feature = "kenlm")]
 mod kenlm;
 
+mod segment;
 mod sentence_filter;
 mod tiny;
 mod transform;
 pub use annotate::Annotate;
 pub use annotate::Annotator;
-pub use content_detector::ContentDetector;
+pub use case::{CaseMode, CaseNormalizer};
+pub use content_detector::{Category, ContentDetector};
+pub use dedup_lines::{DedupLines, DedupLinesMode};
+pub use generated::GeneratedText;
 pub use header::Header;
-pub use lsh::LSH;
 #[cfg(feature = "kenlm")]
 pub use kenlm::AdultDetector;
 #[cfg(feature = "kenlm")]
 pub use kenlm::AdultDetectorBuilder;
 #[cfg(feature = "kenlm")]
 pub use kenlm::Models;
+pub use lang_lines::KeepDocumentLanguageLines;
+pub use lsh::LSH;
 pub use noisy::Noisy;
+pub use normalize::NfcNormalizer;
+pub use pii::{PiiMode, PiiScrubber};
+pub use segment::SentenceSegmenter;
 pub use sentence_filter::Conv;
-pub use sentence_filter::RemoveShortSentences;
 pub use sentence_filter::ShortSentences;
+pub use sentence_filter::{RemoveShortSentences, TrimMode};
 pub use tiny::TinyDocument;
+pub(crate) use transform::rebuild_metadata;
 pub use transform::Transform;
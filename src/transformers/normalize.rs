@@ -0,0 +1,67 @@
+//! Unicode normalization.
+//!
+//! Web text mixes composed and decomposed forms of the same characters (e.g. `é` as one codepoint or as `e` + `◌́`),
+//! which yields different tokens and hashes for the same text.
+use std::ops::RangeInclusive;
+
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+use crate::pipelines::oscardoc::types::Document;
+
+use super::Transform;
+
+/// Normalizes document content to NFC.
+///
+/// Normalization never adds nor removes line breaks, so lines (and sentence identifications) are kept as is.
+#[derive(Default)]
+pub struct NfcNormalizer;
+
+impl NfcNormalizer {
+    /// Normalize `text` to NFC, returning [None] if it already is.
+    pub fn normalize(text: &str) -> Option<String> {
+        match is_nfc_quick(text.chars()) {
+            IsNormalized::Yes => None,
+            _ => {
+                let normalized: String = text.nfc().collect();
+                (normalized != text).then_some(normalized)
+            }
+        }
+    }
+}
+
+impl Transform<Document> for NfcNormalizer {
+    /// Every line is kept: returns the range of all lines.
+    fn transform(&self, doc: &mut Document) -> Vec<RangeInclusive<usize>> {
+        if let Some(content) = Self::normalize(doc.content()) {
+            doc.set_content(content);
+        }
+
+        match doc.content().lines().count() {
+            0 => Vec::new(),
+            nb_lines => vec![0..=nb_lines - 1],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::pipelines::oscardoc::types::{Document, Metadata};
+    use crate::transformers::Transform;
+
+    use super::NfcNormalizer;
+
+    #[test]
+    fn test_nfc() {
+        // "é" and "ñ" as base letters followed by combining marks
+        let nfd = "cafe\u{301}\nman\u{303}ana\n\nplain";
+        let mut doc = Document::new(nfd.to_string(), HashMap::new(), Metadata::default());
+
+        assert_eq!(NfcNormalizer.transform(&mut doc), vec![0..=3]);
+        assert_eq!(doc.content(), "caf\u{e9}\nma\u{f1}ana\n\nplain");
+        assert_eq!(doc.content().lines().count(), nfd.lines().count());
+
+        assert_eq!(NfcNormalizer::normalize("already composed: \u{e9}"), None);
+    }
+}
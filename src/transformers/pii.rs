@@ -0,0 +1,240 @@
+//! Personal information scrubbing.
+//!
+//! Masks (or removes) email addresses and phone numbers in document contents,
+//! for corpora that shouldn't carry them. Matches are replaced in place and never span lines,
+//! so that lines (and their identifications) are kept as is.
+use std::{ops::RangeInclusive, str::FromStr};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::pipelines::oscardoc::types::{Document, ExtraMetadata};
+
+use super::Transform;
+
+lazy_static! {
+    static ref EMAIL: Regex =
+        Regex::new(r"(?i)[a-z0-9._%+-]+@[a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,}").unwrap();
+    // separators are restricted to spaces, dots, dashes and parentheses: matches never span lines.
+    static ref PHONE: Regex = Regex::new(r"\+?\(?\d[\d .()-]{7,}\d").unwrap();
+}
+
+/// Extra metadata key of the number of masked email addresses.
+pub const EMAILS_KEY: &str = "pii_emails";
+
+/// Extra metadata key of the number of masked phone numbers.
+pub const PHONES_KEY: &str = "pii_phones";
+
+/// Phone numbers have between 9 and 15 digits (E.164), which leaves out most dates and years.
+const PHONE_DIGITS: RangeInclusive<usize> = 9..=15;
+
+/// How matches are replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PiiMode {
+    /// Replace with a placeholder (`[EMAIL]`, `[PHONE]`).
+    #[default]
+    Mask,
+    /// Replace with a placeholder holding a hash of the match (e.g. `[EMAIL:5f0c3e81a2b4d697]`),
+    /// so that identical values can still be told apart from different ones.
+    Hash,
+    /// Remove matches.
+    Remove,
+}
+
+impl FromStr for PiiMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mask" => Ok(Self::Mask),
+            "hash" => Ok(Self::Hash),
+            "remove" => Ok(Self::Remove),
+            other => Err(format!(
+                "unknown pii mode {other:?} (expected mask, hash or remove)"
+            )),
+        }
+    }
+}
+
+/// Numbers of scrubbed matches.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PiiCounts {
+    pub emails: usize,
+    pub phones: usize,
+}
+
+/// Scrubs email addresses and phone numbers from document contents.
+///
+/// Matches that are part of a URL (i.e. in a whitespace-delimited token containing `://`) are left untouched,
+/// so that links with credentials or long numeric ids are not mangled.
+///
+/// Non-zero counts are stored as `pii_emails` and `pii_phones` extra metadata.
+#[derive(Debug, Default)]
+pub struct PiiScrubber {
+    mode: PiiMode,
+}
+
+impl PiiScrubber {
+    pub fn new(mode: PiiMode) -> Self {
+        Self { mode }
+    }
+
+    /// Scrub `text`, returning the scrubbed text (or [None] if there was nothing to scrub) and the counts.
+    pub fn scrub(&self, text: &str) -> (Option<String>, PiiCounts) {
+        let mut counts = PiiCounts::default();
+        let emails = self.replace(text, &EMAIL, "EMAIL", |_| true, &mut counts.emails);
+
+        // emails first, so that digits in addresses are not taken for phone numbers
+        let is_phone =
+            |m: &str| PHONE_DIGITS.contains(&m.chars().filter(char::is_ascii_digit).count());
+        let phones = self.replace(
+            emails.as_deref().unwrap_or(text),
+            &PHONE,
+            "PHONE",
+            is_phone,
+            &mut counts.phones,
+        );
+        (phones.or(emails), counts)
+    }
+
+    /// Replace the matches of `re` in `text` that satisfy `keep`, counting them in `count`.
+    fn replace(
+        &self,
+        text: &str,
+        re: &Regex,
+        label: &str,
+        keep: impl Fn(&str) -> bool,
+        count: &mut usize,
+    ) -> Option<String> {
+        let mut scrubbed = String::with_capacity(text.len());
+        let mut last = 0;
+        let mut replaced = 0;
+        for m in re.find_iter(text) {
+            if !keep(m.as_str())
+                || !is_standalone(text, m.start(), m.end())
+                || in_url(text, m.start())
+            {
+                continue;
+            }
+            scrubbed.push_str(&text[last..m.start()]);
+            scrubbed.push_str(&self.placeholder(label, m.as_str()));
+            last = m.end();
+            replaced += 1;
+        }
+
+        if replaced == 0 {
+            return None;
+        }
+        *count += replaced;
+        scrubbed.push_str(&text[last..]);
+        Some(scrubbed)
+    }
+
+    fn placeholder(&self, label: &str, value: &str) -> String {
+        match self.mode {
+            PiiMode::Mask => format!("[{label}]"),
+            PiiMode::Hash => format!(
+                "[{label}:{:016x}]",
+                twox_hash::xxh3::hash64(value.as_bytes())
+            ),
+            PiiMode::Remove => String::new(),
+        }
+    }
+}
+
+/// Is the match delimited by non-alphanumeric characters (e.g. not the end of a longer number or word)?
+fn is_standalone(text: &str, start: usize, end: usize) -> bool {
+    let before = text[..start].chars().next_back();
+    let after = text[end..].chars().next();
+    !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+}
+
+/// Is the match at `start` part of a URL?
+fn in_url(text: &str, start: usize) -> bool {
+    let token_start = text[..start]
+        .rfind(char::is_whitespace)
+        .map_or(0, |idx| idx + 1);
+    let token_end = text[start..]
+        .find(char::is_whitespace)
+        .map_or(text.len(), |idx| start + idx);
+    text[token_start..token_end].contains("://")
+}
+
+impl Transform<Document> for PiiScrubber {
+    /// Every line is kept: returns the range of all lines.
+    fn transform(&self, doc: &mut Document) -> Vec<RangeInclusive<usize>> {
+        let (scrubbed, counts) = self.scrub(doc.content());
+        if let Some(content) = scrubbed {
+            doc.set_content(content);
+        }
+        if counts.emails > 0 {
+            doc.metadata_mut()
+                .add_extra(EMAILS_KEY, &counts.emails.to_string());
+        }
+        if counts.phones > 0 {
+            doc.metadata_mut()
+                .add_extra(PHONES_KEY, &counts.phones.to_string());
+        }
+
+        match doc.content().lines().count() {
+            0 => Vec::new(),
+            nb_lines => vec![0..=nb_lines - 1],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::pipelines::oscardoc::types::{Document, ExtraMetadata, Metadata};
+    use crate::transformers::Transform;
+
+    use super::{PiiCounts, PiiMode, PiiScrubber, EMAILS_KEY, PHONES_KEY};
+
+    #[test]
+    fn test_mask() {
+        let content =
+            "Contact: jane.doe@example.com\nCall +33 6 12 34 56 78 or (555) 123-4567.\n\nThe end.";
+        let mut doc = Document::new(content.to_string(), HashMap::new(), Metadata::default());
+
+        let ranges = PiiScrubber::default().transform(&mut doc);
+        assert_eq!(
+            doc.content(),
+            "Contact: [EMAIL]\nCall [PHONE] or [PHONE].\n\nThe end."
+        );
+        assert_eq!(ranges, vec![0..=3]);
+        assert_eq!(doc.content().lines().count(), content.lines().count());
+        assert_eq!(doc.metadata().extra(EMAILS_KEY), Some("1"));
+        assert_eq!(doc.metadata().extra(PHONES_KEY), Some("2"));
+    }
+
+    #[test]
+    fn test_untouched() {
+        let s = PiiScrubber::default();
+        // dates, years, short numbers and URLs
+        for text in [
+            "Le 2021-10-15, 1500 personnes.",
+            "ISBN 978-3-16-148410-0-12345",
+            "https://user@example.com/page",
+            "See https://example.com/item/1234567890",
+        ] {
+            assert_eq!(s.scrub(text), (None, PiiCounts::default()), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_modes() {
+        let text = "a@example.org b@example.org a@example.org";
+        let (hashed, counts) = PiiScrubber::new(PiiMode::Hash).scrub(text);
+        let hashed = hashed.unwrap();
+        let hashes: Vec<&str> = hashed.split(' ').collect();
+        assert_eq!(counts.emails, 3);
+        assert!(hashes[0].starts_with("[EMAIL:"));
+        assert_eq!(hashes[0], hashes[2]);
+        assert_ne!(hashes[0], hashes[1]);
+
+        let (removed, _) = PiiScrubber::new(PiiMode::Remove).scrub("mail: a@example.org.");
+        assert_eq!(removed.as_deref(), Some("mail: ."));
+    }
+}
@@ -0,0 +1,228 @@
+//! Sentence segmentation.
+//!
+//! Splits lines into sentences, so that paragraphs that are on a single line
+//! get one sentence (and one sentence identification) per line.
+use std::ops::{Range, RangeInclusive};
+
+use unicode_segmentation::UnicodeSegmentation;
+use warc::{BufferedBody, Record};
+
+use super::Transform;
+
+/// A sentence, located in the original content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sentence {
+    /// index of the line the sentence is in
+    pub line: usize,
+    /// byte offsets of the sentence in its line
+    pub span: Range<usize>,
+}
+
+/// Splits lines into sentences, following Unicode sentence boundaries (UAX #29),
+/// putting each sentence on its own line.
+///
+/// Sentences are trimmed, and blank lines are removed.
+///
+/// Scripts that don't mark sentence ends (e.g. Thai) or that are written without spaces yield
+/// very long "sentences": those longer than `max_chars` (in Unicode codepoints) are cut
+/// at the last whitespace before the limit, or at the limit itself if there's none.
+pub struct SentenceSegmenter {
+    max_chars: usize,
+}
+
+impl SentenceSegmenter {
+    /// Use a custom maximum sentence length (in Unicode codepoints). Default is 500.
+    pub fn with_max_chars(max_chars: usize) -> Self {
+        Self {
+            max_chars: max_chars.max(1),
+        }
+    }
+
+    /// Get the maximum sentence length.
+    pub fn max_chars(&self) -> usize {
+        self.max_chars
+    }
+
+    /// Locate the sentences of `content`.
+    pub fn segment(&self, content: &str) -> Vec<Sentence> {
+        content
+            .lines()
+            .enumerate()
+            .flat_map(|(line, text)| {
+                self.segment_line(text)
+                    .into_iter()
+                    .map(move |span| Sentence { line, span })
+            })
+            .collect()
+    }
+
+    /// Put each sentence of `content` on its own line.
+    pub fn segmented(&self, content: &str) -> String {
+        let lines: Vec<&str> = content.lines().collect();
+        self.segment(content)
+            .into_iter()
+            .map(|sentence| &lines[sentence.line][sentence.span])
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Get the byte spans of the sentences of a line.
+    fn segment_line(&self, line: &str) -> Vec<Range<usize>> {
+        line.split_sentence_bound_indices()
+            .filter_map(|(start, sentence)| trim_span(line, start..start + sentence.len()))
+            .flat_map(|span| self.cut(line, span))
+            .collect()
+    }
+
+    /// Cut `span` of `line` into spans of at most `max_chars` codepoints.
+    fn cut(&self, line: &str, mut span: Range<usize>) -> Vec<Range<usize>> {
+        let mut spans = Vec::new();
+        loop {
+            let text = &line[span.clone()];
+            // byte offset of the codepoint that would exceed the limit
+            let limit = match text.char_indices().nth(self.max_chars) {
+                Some((limit, _)) => limit,
+                None => {
+                    spans.push(span);
+                    return spans;
+                }
+            };
+
+            // cut at the last whitespace before the limit, if any
+            let cut = text[..limit]
+                .char_indices()
+                .rev()
+                .find(|(_, c)| c.is_whitespace())
+                .map(|(idx, _)| idx)
+                .filter(|idx| *idx > 0)
+                .unwrap_or(limit);
+
+            if let Some(head) = trim_span(line, span.start..span.start + cut) {
+                spans.push(head);
+            }
+            match trim_span(line, span.start + cut..span.end) {
+                Some(tail) => span = tail,
+                None => return spans,
+            }
+        }
+    }
+}
+
+/// Remove leading and trailing whitespace of `span` in `line`. Returns [None] if nothing is left.
+fn trim_span(line: &str, span: Range<usize>) -> Option<Range<usize>> {
+    let text = &line[span.clone()];
+    let start = span.start + (text.len() - text.trim_start().len());
+    let end = span.end - (text.len() - text.trim_end().len());
+    (start < end).then_some(start..end)
+}
+
+impl Default for SentenceSegmenter {
+    fn default() -> Self {
+        Self { max_chars: 500 }
+    }
+}
+
+impl Transform<Record<BufferedBody>> for SentenceSegmenter {
+    /// Every non-blank line is kept (split into sentences): returns the range of the original lines.
+    fn transform(&self, doc: &mut Record<BufferedBody>) -> Vec<RangeInclusive<usize>> {
+        let body = String::from_utf8_lossy(doc.body()).into_owned();
+        let nb_lines = body.lines().count();
+        let segmented = self.segmented(&body);
+        doc.replace_body(segmented);
+
+        if nb_lines == 0 {
+            Vec::new()
+        } else {
+            vec![0..=nb_lines - 1]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use warc::{BufferedBody, Record};
+
+    use crate::transformers::Transform;
+
+    use super::{Sentence, SentenceSegmenter};
+
+    #[test]
+    fn test_paragraph() {
+        let content = "Title\nThis is a sentence. Is this another one? Yes!  \n\n  Last line.";
+        let s = SentenceSegmenter::default();
+        let sentences = s.segment(content);
+
+        assert_eq!(
+            sentences,
+            vec![
+                Sentence {
+                    line: 0,
+                    span: 0..5
+                },
+                Sentence {
+                    line: 1,
+                    span: 0..19
+                },
+                Sentence {
+                    line: 1,
+                    span: 20..40
+                },
+                Sentence {
+                    line: 1,
+                    span: 41..45
+                },
+                Sentence {
+                    line: 3,
+                    span: 2..12
+                },
+            ]
+        );
+
+        // offsets point to the sentences in the original lines
+        let lines: Vec<&str> = content.lines().collect();
+        let texts: Vec<&str> = sentences
+            .iter()
+            .map(|s| &lines[s.line][s.span.clone()])
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "Title",
+                "This is a sentence.",
+                "Is this another one?",
+                "Yes!",
+                "Last line."
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ideographic() {
+        let s = SentenceSegmenter::default();
+        assert_eq!(s.segmented("你好。我很好。"), "你好。\n我很好。");
+    }
+
+    #[test]
+    fn test_fallback() {
+        let s = SentenceSegmenter::with_max_chars(4);
+
+        // no whitespace: cut at the limit
+        assert_eq!(s.segmented("กขคงจฉช"), "กขคง\nจฉช");
+
+        // cut at whitespace
+        assert_eq!(s.segmented("ab cd efgh"), "ab\ncd\nefgh");
+    }
+
+    #[test]
+    fn test_transform_record() {
+        let mut record: Record<BufferedBody> =
+            Record::default().add_body("First sentence. Second sentence.\nThird sentence.");
+        let ranges = SentenceSegmenter::default().transform(&mut record);
+
+        assert_eq!(ranges, vec![0..=1]);
+        assert_eq!(
+            String::from_utf8_lossy(record.body()),
+            "First sentence.\nSecond sentence.\nThird sentence."
+        );
+    }
+}
@@ -1,6 +1,7 @@
 //! Sentence transformers
 //!
 use std::ops::RangeInclusive;
+use std::str::FromStr;
 
 use itertools::Itertools;
 use log::debug;
@@ -148,9 +149,38 @@ impl Default for Conv {
     }
 }
 
+/// Which short sentences [RemoveShortSentences] removes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrimMode {
+    /// Short sentences before the first long sentence and after the last one.
+    #[default]
+    Edges,
+    /// Every short sentence.
+    All,
+    /// No sentence.
+    None,
+}
+
+impl FromStr for TrimMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "edges" => Ok(Self::Edges),
+            "all" => Ok(Self::All),
+            "none" => Ok(Self::None),
+            other => Err(format!(
+                "unknown trim mode {other:?} (expected edges, all or none)"
+            )),
+        }
+    }
+}
+
 /// Removes short sentences that are before/after a contiguous chunk of the file.
 ///
 /// The idea is to remove contiguous short sentences that are located before and after a main body.
+/// Other short sentences can be removed too, or none at all (see [TrimMode]).
+/// Whatever the mode, documents that have no long sentence are emptied.
 ///
 /// By default the short sentence threshold is at 100
 /// Example:
@@ -177,45 +207,63 @@ impl Default for Conv {
 /// ```
 pub struct RemoveShortSentences {
     filter: Length,
+    trim_mode: TrimMode,
 }
 
 impl RemoveShortSentences {
-    /// Use a custom min_length for long sentences.
-    fn new(min_length: usize) -> Self {
+    /// Use a custom min_length (in Unicode codepoints) for long sentences.
+    pub fn with_min_length(min_length: usize) -> Self {
         Self {
             filter: Length::with_min_size(min_length),
+            ..Default::default()
         }
     }
 
-    /// extracts indices of the document content, ignoring short lines at start/end.
+    /// Use a custom [TrimMode]. Default is [TrimMode::Edges].
+    pub fn with_trim_mode(mut self, trim_mode: TrimMode) -> Self {
+        self.trim_mode = trim_mode;
+        self
+    }
+
+    /// extracts indices of the kept lines of the document content, in order, following the trim mode.
+    ///
+    /// Nothing is kept if there's no long line.
     fn extract_indices<'a>(&self, lines: std::str::Lines<'a>) -> Vec<(usize, &'a str)> {
-        let s: Vec<(usize, &str)> = lines
-            .enumerate()
-            .skip_while(|(_, sentence)| !self.filter.detect(sentence))
-            .collect();
-        let s: Vec<(usize, &str)> = s
-            .into_iter()
-            .rev()
-            .skip_while(|(_, sentence)| !self.filter.detect(sentence))
-            .collect();
-        s
+        let lines: Vec<(usize, &str)> = lines.enumerate().collect();
+        let is_long = |(_, sentence): &&(usize, &str)| self.filter.detect(sentence);
+        let (first, last) = match (
+            lines.iter().position(|line| is_long(&line)),
+            lines.iter().rposition(|line| is_long(&line)),
+        ) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Vec::new(),
+        };
+
+        match self.trim_mode {
+            TrimMode::Edges => lines[first..=last].to_vec(),
+            TrimMode::All => lines.iter().filter(is_long).copied().collect(),
+            TrimMode::None => lines,
+        }
     }
 
-    /// build content from extracted indices, returning a unique String along with the indices
+    /// build content from extracted indices, returning a unique String along with the ranges of lines
     /// that can be used to rebuild the content.
     fn build_content(s: Vec<(usize, &str)>) -> (String, Vec<RangeInclusive<usize>>) {
-        match (s.first(), s.last()) {
-            (Some(end), Some(start)) => {
-                let ranges = vec![start.0..=end.0];
-                let sentences = s.into_iter().rev().map(|(_, sentence)| sentence).join("\n");
+        // group consecutive lines
+        let ranges: Vec<RangeInclusive<usize>> = s
+            .iter()
+            .enumerate()
+            .group_by(|(pos, (idx, _))| idx - pos)
+            .into_iter()
+            .map(|(_, mut group)| {
+                let (_, (start, _)) = group.next().unwrap();
+                let end = group.last().map_or(*start, |(_, (end, _))| *end);
+                *start..=end
+            })
+            .collect();
+        let sentences = s.into_iter().map(|(_, sentence)| sentence).join("\n");
 
-                (sentences, ranges)
-            }
-            _ => {
-                // set content to empty string
-                (String::new(), Vec::new())
-            }
-        }
+        (sentences, ranges)
     }
     /// get filter detection threshold
     fn filter_min_length(&self) -> &usize {
@@ -255,6 +303,7 @@ impl Default for RemoveShortSentences {
     fn default() -> Self {
         Self {
             filter: Default::default(),
+            trim_mode: TrimMode::default(),
         }
     }
 }
@@ -267,7 +316,7 @@ mod tests {
     use crate::pipelines::oscardoc::types::{Document, Metadata};
     use crate::transformers::{Annotate, Transform};
 
-    use super::{RemoveShortSentences, ShortSentences};
+    use super::{RemoveShortSentences, ShortSentences, TrimMode};
 
     fn gen_valid() -> (Document, String) {
         let content = r"foo
@@ -395,7 +444,7 @@ baz
     #[test]
     fn test_rss() {
         let (mut doc, expected_content) = gen_valid();
-        let rss = RemoveShortSentences::new(10);
+        let rss = RemoveShortSentences::with_min_length(10);
 
         rss.transform(&mut doc);
 
@@ -404,6 +453,24 @@ baz
         assert_eq!(doc.content(), &expected_content);
     }
 
+    #[test]
+    fn test_rss_custom_min_length() {
+        // 12 codepoints, 36 bytes
+        let content = "这是一个很长的中文句子。".to_string();
+        let gen_doc = || Document::new(content.clone(), HashMap::new(), Metadata::default());
+
+        let rss = RemoveShortSentences::with_min_length(10);
+        assert_eq!(rss.filter_min_length(), &10);
+        let mut doc = gen_doc();
+        assert_eq!(rss.transform(&mut doc), vec![0..=0]);
+        assert_eq!(doc.content(), &content);
+
+        let rss = RemoveShortSentences::with_min_length(20);
+        let mut doc = gen_doc();
+        assert!(rss.transform(&mut doc).is_empty());
+        assert_eq!(doc.content(), "");
+    }
+
     #[test]
     fn test_rss_empty() {
         let content = r"foo
@@ -421,7 +488,7 @@ baz
         let metadata = Metadata::default();
         let mut doc = Document::new(content, headers, metadata);
 
-        let rss = RemoveShortSentences::new(10);
+        let rss = RemoveShortSentences::with_min_length(10);
 
         rss.transform(&mut doc);
 
@@ -434,7 +501,7 @@ baz
     fn test_rss_idx() {
         let (mut doc, _) = gen_valid();
         let expected_range = vec![4..=7];
-        let rss = RemoveShortSentences::new(10);
+        let rss = RemoveShortSentences::with_min_length(10);
 
         let range_transformed = rss.transform(&mut doc);
 
@@ -448,7 +515,7 @@ baz
         let mut doc = gen_invalid();
         let expected_ranges = Vec::new();
 
-        let rss = RemoveShortSentences::new(10);
+        let rss = RemoveShortSentences::with_min_length(10);
 
         let range_transformed = rss.transform(&mut doc);
 
@@ -458,7 +525,7 @@ baz
     // #[test]
     // fn test_conv() {
     //     let (doc, expected) = gen_valid_long();
-    //     let c = Conv::new(3, RemoveShortSentences::new(60));
+    //     let c = Conv::new(3, RemoveShortSentences::with_min_length(60));
     //     println!(
     //         "{:?}",
     //         doc.content()
@@ -518,4 +585,49 @@ Long enough sentence here :)"#;
         // this fails if doc is annotated with something else
         assert!(doc.metadata().annotation().is_none())
     }
+
+    #[test]
+    fn test_trim_modes() {
+        let content = "foo\nxxxxxxxxxxx\nbar\nxxxxxxxxxxx\nbaz";
+        let gen_doc = || Document::new(content.to_string(), HashMap::new(), Metadata::default());
+
+        let expected = [
+            (
+                TrimMode::Edges,
+                "xxxxxxxxxxx\nbar\nxxxxxxxxxxx",
+                vec![1..=3],
+            ),
+            (
+                TrimMode::All,
+                "xxxxxxxxxxx\nxxxxxxxxxxx",
+                vec![1..=1, 3..=3],
+            ),
+            (TrimMode::None, content, vec![0..=4]),
+        ];
+        for (trim_mode, expected_content, expected_ranges) in expected {
+            let t = RemoveShortSentences::with_min_length(10).with_trim_mode(trim_mode);
+            let mut doc = gen_doc();
+            assert_eq!(t.transform(&mut doc), expected_ranges, "{trim_mode:?}");
+            assert_eq!(doc.content(), expected_content, "{trim_mode:?}");
+        }
+    }
+
+    #[test]
+    fn test_trim_modes_no_long_sentence() {
+        for trim_mode in [TrimMode::Edges, TrimMode::All, TrimMode::None] {
+            let t = RemoveShortSentences::with_min_length(10).with_trim_mode(trim_mode);
+            let mut doc =
+                Document::new("foo\nbar".to_string(), HashMap::new(), Metadata::default());
+            assert!(t.transform(&mut doc).is_empty());
+            assert!(doc.content().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_trim_mode_from_str() {
+        assert_eq!("edges".parse::<TrimMode>(), Ok(TrimMode::Edges));
+        assert_eq!("all".parse::<TrimMode>(), Ok(TrimMode::All));
+        assert_eq!("none".parse::<TrimMode>(), Ok(TrimMode::None));
+        assert!("foo".parse::<TrimMode>().is_err());
+    }
 }
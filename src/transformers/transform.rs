@@ -2,7 +2,29 @@
 
 use std::ops::RangeInclusive;
 
+use oscar_io::common::Identification;
+
+use crate::pipelines::oscardoc::types::Metadata;
+
 pub trait Transform<T> {
     /// Takes ownership of [Document] and returns it.
     fn transform(&self, doc: &mut T) -> Vec<RangeInclusive<usize>>;
 }
+
+/// Rebuild metadata with the provided sentence identifications, keeping everything else.
+///
+/// Used by transforms that remove lines, to keep sentence identifications aligned with lines.
+pub(crate) fn rebuild_metadata(
+    identification: &Identification<String>,
+    metadata: &Metadata,
+    sentence_identifications: &[Option<Identification<String>>],
+) -> Metadata {
+    let mut new_metadata = Metadata::new(identification, sentence_identifications);
+    for annotation in metadata.annotation().into_iter().flatten() {
+        new_metadata.add_annotation(annotation.clone());
+    }
+    new_metadata.set_categories(metadata.categories().cloned());
+    new_metadata.set_tlsh(metadata.tlsh().cloned());
+    new_metadata.set_harmful_pp(metadata.harmful_pp());
+    new_metadata
+}